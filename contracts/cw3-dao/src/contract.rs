@@ -1,29 +1,46 @@
 use crate::error::ContractError;
 use crate::helpers::{
-    get_and_check_limit, get_deposit_message, get_proposal_deposit_refund_message,
-    get_staked_balance, get_total_staked_supply, get_voting_power_at_height, map_proposal,
+    allocate_voter_rewards, get_and_check_limit, get_ballot_weight, get_deposit_message,
+    get_oldest_open_start_height, get_proposal_deposit_refund_message,
+    get_rejected_deposit_messages, get_staked_balance, get_staking_checkpoint_message,
+    get_total_staked_supply, get_total_weight, get_voter_reward, get_voter_rewards_token,
+    map_proposal, prune_open_proposals, voter_reward_reserve_check_msgs,
+};
+use crate::hooks::{
+    proposal_hook_msgs, remove_failed_hook, vote_hook_msgs, ProposalHookMsg, VoteHookMsg,
+    PROPOSAL_HOOK_REPLY_ID_OFFSET, VOTE_HOOK_REPLY_ID_OFFSET,
+};
+use crate::migrations;
+use crate::msg::{
+    DepositPolicy, DepositToken, ExecuteMsg, GovTokenMsg, InstantiateMsg, MigrateMsg, ProposeMsg,
+    ProposeMultipleChoiceMsg, QueryMsg, ReceiveMsg, VoteMsg, VoteMultipleChoiceMsg,
+    VotingPowerTransform,
 };
-use crate::msg::{ExecuteMsg, GovTokenMsg, InstantiateMsg, ProposeMsg, QueryMsg, VoteMsg};
 use crate::query::{
-    ConfigResponse, Cw20BalancesResponse, ProposalListResponse, ProposalResponse,
-    ThresholdResponse, TokenListResponse, VoteInfo, VoteListResponse, VoteResponse,
-    VoteTallyResponse, VoterResponse,
+    ConfigResponse, Cw20BalancesResponse, DraftListResponse, DraftResponse, ProposalListResponse,
+    ProposalResponse, Status, ThresholdResponse, TokenListResponse, VoteInfo, VoteListResponse,
+    VoteResponse, VoteTallyResponse, VoterResponse, VoterReward, VoterRewardsResponse,
 };
 use crate::state::{
-    next_id, Ballot, Config, Proposal, Votes, BALLOTS, CONFIG, DAO_PAUSED, GOV_TOKEN, PROPOSALS,
-    STAKING_CONTRACT, STAKING_CONTRACT_CODE_ID, STAKING_CONTRACT_UNSTAKING_DURATION,
-    TREASURY_TOKENS,
+    next_draft_id, next_id, Ballot, Config, Draft, MultipleChoiceOption, Proposal, Votes, BALLOTS,
+    CLAIMED_VOTER_REWARDS, CONFIG, DAO_PAUSED, DRAFTS, EXECUTING_PROPOSAL, GOV_TOKEN,
+    OPEN_PROPOSALS, OPEN_PROPOSAL_IDS, OVERRIDDEN_POWER, PROPOSALS, PROPOSAL_HOOKS,
+    PROPOSAL_VOTER_REWARDS, STAKING_CONTRACT, STAKING_CONTRACT_CODE_ID,
+    STAKING_CONTRACT_SNAPSHOT_STRATEGY, STAKING_CONTRACT_UNSTAKING_DURATION, TREASURY_TOKENS,
+    VOTER_REWARD_POOL, VOTER_REWARD_RESERVE, VOTE_HOOKS,
 };
 use cosmwasm_std::{
-    entry_point, to_binary, Addr, Binary, CosmosMsg, Decimal, Deps, DepsMut, Empty, Env,
-    MessageInfo, Order, Reply, Response, StdResult, SubMsg, Uint128, WasmMsg,
+    entry_point, from_binary, to_binary, Addr, Binary, CosmosMsg, Decimal, Deps, DepsMut, Empty,
+    Env, MessageInfo, Order, Reply, Response, StdResult, SubMsg, Uint128, WasmMsg,
 };
 use cw2::set_contract_version;
 use cw20::{
-    BalanceResponse, Cw20Coin, Cw20CoinVerified, Cw20Contract, Cw20QueryMsg, Cw20ReceiveMsg,
-    MinterResponse,
+    BalanceResponse, Cw20Coin, Cw20CoinVerified, Cw20Contract, Cw20ExecuteMsg, Cw20QueryMsg,
+    Cw20ReceiveMsg, MinterResponse,
 };
-use cw3::{Status, Vote};
+use cw3::Vote;
+use cw_controllers::Hooks;
+use cw_migrate::{ensure_upgrade, Version};
 use cw_storage_plus::Bound;
 use cw_utils::{maybe_addr, parse_reply_instantiate_data, Expiration};
 use std::cmp::Ordering;
@@ -37,9 +54,15 @@ pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const MAX_LIMIT: u32 = 30;
 const DEFAULT_LIMIT: u32 = 10;
 
+// Maximum number of options of a multiple choice proposal
+const MAX_CHOICES: usize = 10;
+
 // Reply IDs
 const INSTANTIATE_GOV_TOKEN_REPLY_ID: u64 = 0;
 const INSTANTIATE_STAKING_CONTRACT_REPLY_ID: u64 = 1;
+// The proposal id is added to this for the messages of executed proposals,
+// so that a failing message can be traced back to its proposal
+const EXECUTE_PROPOSAL_REPLY_ID_OFFSET: u64 = 1 << 32;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -58,11 +81,48 @@ pub fn instantiate(
         threshold: msg.threshold,
         max_voting_period: msg.max_voting_period,
         proposal_deposit: msg.proposal_deposit_amount,
-        refund_failed_proposals: msg.refund_failed_proposals,
+        proposal_deposit_token: msg
+            .proposal_deposit_token
+            .unwrap_or(DepositToken::GovToken {}),
+        deposit_policy: msg.deposit_policy.unwrap_or(DepositPolicy::Keep {}),
         image_url: msg.image_url,
         only_members_execute: msg.only_members_execute,
         automatically_add_cw20s: msg.automatically_add_cw20s,
+        allow_revoting: msg.allow_revoting.unwrap_or(false),
+        timelock: msg.timelock,
+        guardian: msg
+            .guardian
+            .map(|guardian| deps.api.addr_validate(&guardian))
+            .transpose()?,
+        cancelled_deposit_policy: msg
+            .cancelled_deposit_policy
+            .unwrap_or(DepositPolicy::Keep {}),
+        catch_execution_failures: msg.catch_execution_failures.unwrap_or(false),
+        min_proposer_stake: msg.min_proposer_stake,
+        proposer_allowlist: msg
+            .proposer_allowlist
+            .map(|allowlist| {
+                allowlist
+                    .iter()
+                    .map(|addr| deps.api.addr_validate(addr))
+                    .collect::<StdResult<Vec<_>>>()
+            })
+            .transpose()?,
+        max_open_proposals: msg.max_open_proposals,
+        proposal_categories: msg.proposal_categories.unwrap_or_default(),
+        voter_rewards: msg.voter_rewards,
+        voting_power_transform: msg
+            .voting_power_transform
+            .unwrap_or(VotingPowerTransform::Linear {}),
+        draft_duration: msg.draft_duration.unwrap_or(msg.max_voting_period),
     };
+    cfg.validate_deposit_policy()?;
+    cfg.validate_timelock()?;
+    cfg.validate_proposal_categories()?;
+    cfg.voting_power_transform.validate()?;
+    if let Some(token) = cfg.voter_rewards.as_ref().and_then(|r| r.token.as_ref()) {
+        deps.api.addr_validate(token.as_str())?;
+    }
     CONFIG.save(deps.storage, &cfg)?;
 
     let mut msgs: Vec<SubMsg> = vec![];
@@ -75,6 +135,7 @@ pub fn instantiate(
             initial_dao_balance,
             msg,
             unstaking_duration,
+            snapshot_strategy,
         } => {
             // Check that someone has an initial balance to be able to vote in the DAO
             if msg.initial_balances.is_empty() {
@@ -94,6 +155,7 @@ pub fn instantiate(
             // Save info for use in reply SubMsgs
             STAKING_CONTRACT_CODE_ID.save(deps.storage, &stake_contract_code_id)?;
             STAKING_CONTRACT_UNSTAKING_DURATION.save(deps.storage, &unstaking_duration)?;
+            STAKING_CONTRACT_SNAPSHOT_STRATEGY.save(deps.storage, &snapshot_strategy)?;
 
             // Instantiate new Gov Token with DAO as admin and minter
             let msg = WasmMsg::Instantiate {
@@ -123,6 +185,7 @@ pub fn instantiate(
             stake_contract_code_id,
             label,
             unstaking_duration,
+            snapshot_strategy,
         } => {
             let cw20_addr = Cw20Contract(
                 deps.api
@@ -145,7 +208,11 @@ pub fn instantiate(
                 msg: to_binary(&stake_cw20::msg::InstantiateMsg {
                     admin: Some(env.contract.address.to_string()),
                     unstaking_duration,
-                    token_address: cw20_addr.addr().to_string(),
+                    token_address: Some(cw20_addr.addr().to_string()),
+                    native_denom: None,
+                    slash_destination: None,
+                    snapshot_strategy,
+                    lockup_tiers: None,
                 })?,
             };
 
@@ -171,12 +238,47 @@ pub fn execute(
             description,
             msgs,
             latest,
-        }) => execute_propose(deps, env, info, title, description, msgs, latest),
+        }) => execute_propose(
+            deps,
+            env,
+            info,
+            title,
+            description,
+            msgs,
+            vec![],
+            latest,
+            None,
+        ),
         ExecuteMsg::Vote(VoteMsg { proposal_id, vote }) => {
-            execute_vote(deps, env, info, proposal_id, vote)
+            execute_vote(deps, env, info, proposal_id, vote, None)
         }
+        ExecuteMsg::ProposeMultipleChoice(ProposeMultipleChoiceMsg {
+            title,
+            description,
+            choices,
+            latest,
+        }) => execute_propose(
+            deps,
+            env,
+            info,
+            title,
+            description,
+            vec![],
+            choices,
+            latest,
+            None,
+        ),
+        ExecuteMsg::VoteMultipleChoice(VoteMultipleChoiceMsg {
+            proposal_id,
+            choice,
+        }) => execute_vote(deps, env, info, proposal_id, Vote::Yes, Some(choice)),
         ExecuteMsg::Execute { proposal_id } => execute_execute(deps, env, info, proposal_id),
         ExecuteMsg::Close { proposal_id } => execute_close(deps, env, info, proposal_id),
+        ExecuteMsg::Cancel {
+            proposal_id,
+            reason,
+        } => execute_cancel(deps, env, info, proposal_id, reason),
+        ExecuteMsg::Withdraw { proposal_id } => execute_withdraw(deps, env, info, proposal_id),
         ExecuteMsg::PauseDAO { expiration } => execute_pause_dao(deps, env, info, expiration),
         ExecuteMsg::UpdateConfig(config) => execute_update_config(deps, env, info, config),
         ExecuteMsg::UpdateCw20TokenList { to_add, to_remove } => {
@@ -186,9 +288,58 @@ pub fn execute(
             new_staking_contract,
         } => execute_update_staking_contract(deps, env, info, new_staking_contract),
         ExecuteMsg::Receive(rec) => execute_receive(deps, env, info, rec),
+        ExecuteMsg::PruneStakingCheckpoints { start_after, limit } => {
+            execute_prune_staking_checkpoints(deps, env, start_after, limit)
+        }
+        ExecuteMsg::AddProposalHook { address } => execute_add_hook(
+            deps,
+            env,
+            info,
+            &PROPOSAL_HOOKS,
+            address,
+            "add_proposal_hook",
+        ),
+        ExecuteMsg::RemoveProposalHook { address } => execute_remove_hook(
+            deps,
+            env,
+            info,
+            &PROPOSAL_HOOKS,
+            address,
+            "remove_proposal_hook",
+        ),
+        ExecuteMsg::AddVoteHook { address } => {
+            execute_add_hook(deps, env, info, &VOTE_HOOKS, address, "add_vote_hook")
+        }
+        ExecuteMsg::RemoveVoteHook { address } => {
+            execute_remove_hook(deps, env, info, &VOTE_HOOKS, address, "remove_vote_hook")
+        }
+        ExecuteMsg::ClaimVoterRewards { proposal_ids } => {
+            execute_claim_voter_rewards(deps, env, info, proposal_ids)
+        }
+        ExecuteMsg::CreateDraft(ProposeMsg {
+            title,
+            description,
+            msgs,
+            latest,
+        }) => execute_create_draft(deps, env, info, title, description, msgs, latest),
+        ExecuteMsg::AmendDraft {
+            draft_id,
+            title,
+            description,
+            msgs,
+        } => execute_amend_draft(deps, env, info, draft_id, title, description, msgs),
+        ExecuteMsg::SponsorDraft { draft_id } => execute_sponsor_draft(deps, env, info, draft_id),
+        ExecuteMsg::RemoveDraft { draft_id } => execute_remove_draft(deps, env, info, draft_id),
+        ExecuteMsg::ExecuteProposalMsgs { proposal_id } => {
+            execute_proposal_msgs(deps, env, info, proposal_id)
+        }
+        ExecuteMsg::CheckVoterRewardReserve {} => {
+            execute_check_voter_reward_reserve(deps, env, info)
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn execute_propose(
     deps: DepsMut,
     env: Env,
@@ -196,8 +347,10 @@ pub fn execute_propose(
     title: String,
     description: String,
     msgs: Vec<CosmosMsg<Empty>>,
+    mut choices: Vec<MultipleChoiceOption>,
     // we ignore earliest
     latest: Option<Expiration>,
+    author: Option<Addr>,
 ) -> Result<Response<Empty>, ContractError> {
     // Check if DAO is Paused
     let paused = DAO_PAUSED.may_load(deps.storage)?;
@@ -210,14 +363,52 @@ pub fn execute_propose(
     let cfg = CONFIG.load(deps.storage)?;
     let gov_token = GOV_TOKEN.load(deps.storage)?;
 
+    if let Some(allowlist) = &cfg.proposer_allowlist {
+        if !allowlist.contains(&info.sender) {
+            return Err(ContractError::ProposerNotAllowed {});
+        }
+    }
+
     // Only owners of the gov token can create a proposal
     let balance = get_staked_balance(deps.as_ref(), info.sender.clone())?;
     if balance == Uint128::zero() {
         return Err(ContractError::Unauthorized {});
     }
+    if let Some(min_proposer_stake) = &cfg.min_proposer_stake {
+        let min = min_proposer_stake.amount(get_total_staked_supply(deps.as_ref())?);
+        if balance < min {
+            return Err(ContractError::InsufficientProposerStake { min });
+        }
+    }
+
+    let open_proposals = prune_open_proposals(deps.storage, &env.block, &info.sender)?;
+    if let Some(max) = cfg.max_open_proposals {
+        if open_proposals >= max {
+            return Err(ContractError::TooManyOpenProposals { max });
+        }
+    }
+
+    // Proposals matching a category use its voting rules
+    let category = cfg.proposal_category(
+        msgs.iter()
+            .chain(choices.iter().flat_map(|choice| &choice.msgs)),
+        &env.contract.address,
+    );
+    let (threshold, max_voting_period, deposit) = match category {
+        Some(category) => (
+            category.threshold.clone(),
+            category.max_voting_period,
+            category.deposit.unwrap_or(cfg.proposal_deposit),
+        ),
+        None => (
+            cfg.threshold.clone(),
+            cfg.max_voting_period,
+            cfg.proposal_deposit,
+        ),
+    };
 
     // Max expires also used as default
-    let max_expires = cfg.max_voting_period.after(&env.block);
+    let max_expires = max_voting_period.after(&env.block);
     let mut expires = latest.unwrap_or(max_expires);
     let comp = expires.partial_cmp(&max_expires);
     if let Some(Ordering::Greater) = comp {
@@ -225,9 +416,34 @@ pub fn execute_propose(
     } else if comp.is_none() {
         return Err(ContractError::WrongExpiration {});
     }
+    let min_voting_end = category
+        .and_then(|category| category.min_voting_period)
+        .map(|min_voting_period| min_voting_period.after(&env.block));
+    if let Some(min_voting_end) = min_voting_end {
+        if let Some(Ordering::Less) = expires.partial_cmp(&min_voting_end) {
+            expires = min_voting_end;
+        }
+    }
+
+    // Multiple choice proposals get a "none of the above" option
+    if !choices.is_empty() {
+        if choices.len() < 2 || choices.len() > MAX_CHOICES {
+            return Err(ContractError::WrongChoiceCount {
+                min: 2,
+                max: MAX_CHOICES as u64,
+            });
+        }
+        choices.push(MultipleChoiceOption {
+            description: "None of the above".to_string(),
+            msgs: vec![],
+        });
+    }
+    let choice_votes = vec![Uint128::zero(); choices.len()];
+
+    let deposit_denom = cfg.proposal_deposit_token.to_denom(&gov_token);
 
-    // Get total supply
-    let total_supply = get_total_staked_supply(deps.as_ref())?;
+    // Get the total weight of the ballots that can be cast
+    let total_weight = get_total_weight(deps.as_ref(), &cfg.voting_power_transform)?;
 
     // Create a proposal
     let mut prop = Proposal {
@@ -244,30 +460,188 @@ pub fn execute_propose(
             abstain: Uint128::zero(),
             veto: Uint128::zero(),
         },
-        threshold: cfg.threshold.clone(),
-        total_weight: total_supply,
-        deposit: cfg.proposal_deposit,
+        threshold,
+        total_weight,
+        deposit,
+        deposit_denom: Some(deposit_denom.clone()),
+        allow_revoting: cfg.allow_revoting,
+        choices,
+        choice_votes,
+        timelock: cfg.timelock,
+        executable_at: None,
+        execution_error: None,
+        category: category.map(|category| category.kind),
+        voting_power_transform: cfg.voting_power_transform.clone(),
+        author,
+        min_voting_end,
+        deposit_pending: false,
     };
     prop.update_status(&env.block);
     let id = next_id(deps.storage)?;
     PROPOSALS.save(deps.storage, id, &prop)?;
-
-    let deposit_msg = get_deposit_message(&env, &info, &cfg.proposal_deposit, &gov_token)?;
+    OPEN_PROPOSALS.save(deps.storage, (&info.sender, id), &Empty {})?;
+    OPEN_PROPOSAL_IDS.save(deps.storage, id, &Empty {})?;
+
+    let deposit_msg = get_deposit_message(&env, &info, &prop.deposit, &deposit_denom)?;
+    let checkpoint_msg = get_staking_checkpoint_message(deps.as_ref())?;
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::NewProposal {
+            proposal_id: id,
+            proposer: info.sender.to_string(),
+        },
+    )?;
 
     Ok(Response::new()
         .add_messages(deposit_msg)
+        .add_messages(checkpoint_msg)
+        .add_submessages(hook_msgs)
         .add_attribute("action", "propose")
         .add_attribute("sender", info.sender)
         .add_attribute("proposal_id", id.to_string())
         .add_attribute("status", format!("{:?}", prop.status)))
 }
 
+pub fn execute_create_draft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    title: String,
+    description: String,
+    msgs: Vec<CosmosMsg<Empty>>,
+    latest: Option<Expiration>,
+) -> Result<Response<Empty>, ContractError> {
+    // Check if DAO is Paused
+    let paused = DAO_PAUSED.may_load(deps.storage)?;
+    if let Some(expiration) = paused {
+        if !expiration.is_expired(&env.block) {
+            return Err(ContractError::Paused {});
+        }
+    }
+
+    let cfg = CONFIG.load(deps.storage)?;
+    let draft = Draft {
+        title,
+        description,
+        author: info.sender.clone(),
+        msgs,
+        latest,
+        expires: cfg.draft_duration.after(&env.block),
+    };
+    let id = next_draft_id(deps.storage)?;
+    DRAFTS.save(deps.storage, id, &draft)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_draft")
+        .add_attribute("sender", info.sender)
+        .add_attribute("draft_id", id.to_string()))
+}
+
+pub fn execute_amend_draft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    draft_id: u64,
+    title: Option<String>,
+    description: Option<String>,
+    msgs: Option<Vec<CosmosMsg<Empty>>>,
+) -> Result<Response<Empty>, ContractError> {
+    // Check if DAO is Paused
+    let paused = DAO_PAUSED.may_load(deps.storage)?;
+    if let Some(expiration) = paused {
+        if !expiration.is_expired(&env.block) {
+            return Err(ContractError::Paused {});
+        }
+    }
+
+    let mut draft = DRAFTS.load(deps.storage, draft_id)?;
+    if draft.author != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if draft.expires.is_expired(&env.block) {
+        return Err(ContractError::DraftExpired {});
+    }
+
+    if let Some(title) = title {
+        draft.title = title;
+    }
+    if let Some(description) = description {
+        draft.description = description;
+    }
+    if let Some(msgs) = msgs {
+        draft.msgs = msgs;
+    }
+    DRAFTS.save(deps.storage, draft_id, &draft)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "amend_draft")
+        .add_attribute("sender", info.sender)
+        .add_attribute("draft_id", draft_id.to_string()))
+}
+
+pub fn execute_sponsor_draft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    draft_id: u64,
+) -> Result<Response<Empty>, ContractError> {
+    let draft = DRAFTS.load(deps.storage, draft_id)?;
+    if draft.expires.is_expired(&env.block) {
+        return Err(ContractError::DraftExpired {});
+    }
+    DRAFTS.remove(deps.storage, draft_id);
+
+    // The sponsor makes the proposal, so it is held to the same
+    // requirements and pays the deposit
+    let res = execute_propose(
+        deps,
+        env,
+        info,
+        draft.title,
+        draft.description,
+        draft.msgs,
+        vec![],
+        draft.latest,
+        Some(draft.author.clone()),
+    )?;
+    Ok(res
+        .add_attribute("draft_id", draft_id.to_string())
+        .add_attribute("author", draft.author))
+}
+
+pub fn execute_remove_draft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    draft_id: u64,
+) -> Result<Response<Empty>, ContractError> {
+    // Check if DAO is Paused
+    let paused = DAO_PAUSED.may_load(deps.storage)?;
+    if let Some(expiration) = paused {
+        if !expiration.is_expired(&env.block) {
+            return Err(ContractError::Paused {});
+        }
+    }
+
+    let draft = DRAFTS.load(deps.storage, draft_id)?;
+    if draft.author != info.sender && !draft.expires.is_expired(&env.block) {
+        return Err(ContractError::Unauthorized {});
+    }
+    DRAFTS.remove(deps.storage, draft_id);
+
+    Ok(Response::new()
+        .add_attribute("action", "remove_draft")
+        .add_attribute("sender", info.sender)
+        .add_attribute("draft_id", draft_id.to_string()))
+}
+
 pub fn execute_vote(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     proposal_id: u64,
     vote: Vote,
+    choice: Option<u32>,
 ) -> Result<Response<Empty>, ContractError> {
     // Check if DAO is Paused
     let paused = DAO_PAUSED.may_load(deps.storage)?;
@@ -285,30 +659,97 @@ pub fn execute_vote(
     if prop.expires.is_expired(&env.block) {
         return Err(ContractError::Expired {});
     }
+    if prop.is_multiple_choice() != choice.is_some() {
+        return Err(ContractError::WrongVoteType {});
+    }
+    if let Some(choice) = choice {
+        if choice as usize >= prop.choices.len() {
+            return Err(ContractError::InvalidChoice { choice });
+        }
+    }
 
-    // Get voter balance at proposal start
-    let vote_power =
-        get_voting_power_at_height(deps.as_ref(), info.sender.clone(), prop.start_height)?;
+    // Get the weight of the voter's ballot at proposal start. A revote
+    // keeps the weight of the previous ballot, which delegators who voted
+    // since may have taken some of.
+    let previous = BALLOTS.may_load(deps.storage, (proposal_id, &info.sender))?;
+    let (vote_power, delegation) = match &previous {
+        Some(ballot) => (ballot.weight, None),
+        None => get_ballot_weight(deps.as_ref(), proposal_id, &prop, &info.sender)?,
+    };
 
     if vote_power == Uint128::zero() {
         return Err(ContractError::Unauthorized {});
     }
 
-    // Cast vote if no vote previously cast
-    BALLOTS.update(deps.storage, (proposal_id, &info.sender), |bal| match bal {
-        Some(_) => Err(ContractError::AlreadyVoted {}),
-        None => Ok(Ballot {
-            weight: vote_power,
-            vote,
-        }),
-    })?;
+    // Cast vote if no vote previously cast, or replace it if revoting is allowed
+    if let Some(ballot) = previous {
+        if !prop.allow_revoting {
+            return Err(ContractError::AlreadyVoted {});
+        }
+        if ballot.vote == vote && ballot.choice == choice {
+            return Err(ContractError::AlreadyCast {});
+        }
+        prop.remove_ballot_weight(&ballot, ballot.weight);
+    } else if let Some(taken) = delegation {
+        // Take the delegator's stake away from their delegate on this proposal
+        let delegate = &taken.delegate;
+        OVERRIDDEN_POWER.update(
+            deps.storage,
+            (proposal_id, delegate),
+            |power| -> StdResult<_> { Ok(power.unwrap_or_default() + taken.power) },
+        )?;
+        if let Some(mut ballot) = BALLOTS.may_load(deps.storage, (proposal_id, delegate))? {
+            prop.remove_ballot_weight(&ballot, taken.weight);
+            ballot.weight -= taken.weight;
+            BALLOTS.save(deps.storage, (proposal_id, delegate), &ballot)?;
+        }
+    }
+    let ballot = Ballot {
+        weight: vote_power,
+        vote,
+        choice,
+    };
+    BALLOTS.save(deps.storage, (proposal_id, &info.sender), &ballot)?;
 
     // Update vote tally
-    prop.votes.add_vote(vote, vote_power);
+    let old_status = prop.status;
+    prop.add_ballot_weight(&ballot, vote_power);
     prop.update_status(&env.block);
+    // The deposit of a proposal rejected early is settled when it is closed,
+    // its voters are rewarded right away as the votes can no longer change
+    prop.deposit_pending = prop.status == Status::Rejected;
     PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+    if prop.deposit_pending {
+        let cfg = CONFIG.load(deps.storage)?;
+        allocate_voter_rewards(deps.storage, &cfg.voter_rewards, proposal_id, &prop)?;
+    }
+
+    let vote_hook_msgs = vote_hook_msgs(
+        deps.as_ref(),
+        VoteHookMsg::NewVote {
+            proposal_id,
+            voter: info.sender.to_string(),
+            vote,
+            choice,
+            weight: vote_power,
+        },
+    )?;
+    let proposal_hook_msgs = if prop.status != old_status {
+        proposal_hook_msgs(
+            deps.as_ref(),
+            ProposalHookMsg::ProposalStatusChanged {
+                proposal_id,
+                old_status,
+                new_status: prop.status,
+            },
+        )?
+    } else {
+        vec![]
+    };
 
     Ok(Response::new()
+        .add_submessages(vote_hook_msgs)
+        .add_submessages(proposal_hook_msgs)
         .add_attribute("action", "vote")
         .add_attribute("sender", info.sender)
         .add_attribute("proposal_id", proposal_id.to_string())
@@ -337,32 +778,137 @@ pub fn execute_execute(
         }
     }
 
-    let gov_token = GOV_TOKEN.load(deps.storage)?;
-
     // Anyone can trigger this if the vote passed
     let mut prop = PROPOSALS.load(deps.storage, proposal_id)?;
     // We allow execution even after the proposal "expiration" as long as all vote come in before
     // that point. If it was approved on time, it can be executed any time.
-    if prop.current_status(&env.block) != Status::Passed {
+    // Proposals whose execution failed can be retried.
+    let status = prop.current_status(&env.block);
+    if ![Status::Passed, Status::ExecutionFailed].contains(&status) {
         return Err(ContractError::WrongExecuteStatus {});
     }
 
+    // Passed proposals may have to wait out the timelock
+    prop.executable_at = prop.executable_at(&env.block);
+    if let Some(executable_at) = prop.executable_at {
+        if !executable_at.is_expired(&env.block) {
+            return Err(ContractError::Timelocked { executable_at });
+        }
+    }
+
     // Set it to executed
     prop.status = Status::Executed;
-    PROPOSALS.save(deps.storage, proposal_id, &prop)?;
-
-    let refund_msg =
-        get_proposal_deposit_refund_message(&prop.proposer, &prop.deposit, &gov_token)?;
+    let response = if cfg.catch_execution_failures {
+        // The messages are dispatched together by a call to the DAO itself,
+        // so that a failing message reverts those before it. The outcome is
+        // handled in the reply, the error of a previous failure is kept
+        // until then.
+        PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+        EXECUTING_PROPOSAL.save(deps.storage, &proposal_id)?;
+        let msg = WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            msg: to_binary(&ExecuteMsg::ExecuteProposalMsgs { proposal_id })?,
+            funds: vec![],
+        };
+        Response::new().add_submessage(SubMsg::reply_always(
+            msg,
+            EXECUTE_PROPOSAL_REPLY_ID_OFFSET + proposal_id,
+        ))
+    } else {
+        prop.execution_error = None;
+        PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+
+        // Hooks are notified before the proposal runs, as it may change them
+        let check_msgs = voter_reward_reserve_check_msgs(deps.storage, &env)?;
+        let (refund_msgs, hook_msgs) = settle_executed_proposal(deps, proposal_id, &prop)?;
+        Response::new()
+            .add_submessages(hook_msgs)
+            .add_messages(refund_msgs)
+            .add_messages(prop.executable_msgs())
+            .add_messages(check_msgs)
+    };
 
-    // Dispatch all proposed messages
-    Ok(Response::new()
-        .add_messages(refund_msg)
-        .add_messages(prop.msgs)
+    Ok(response
         .add_attribute("action", "execute")
         .add_attribute("sender", info.sender)
         .add_attribute("proposal_id", proposal_id.to_string()))
 }
 
+/// Allocates the voter rewards of an executed proposal, and prepares the
+/// refund of its deposit and the `ProposalExecuted` hook messages
+fn settle_executed_proposal(
+    deps: DepsMut,
+    proposal_id: u64,
+    prop: &Proposal,
+) -> Result<(Vec<CosmosMsg>, Vec<SubMsg>), ContractError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    allocate_voter_rewards(deps.storage, &cfg.voter_rewards, proposal_id, prop)?;
+
+    let gov_token = GOV_TOKEN.load(deps.storage)?;
+    let refund_msgs = get_proposal_deposit_refund_message(
+        &prop.proposer,
+        &prop.deposit,
+        &prop.deposit_token(&gov_token),
+    )?;
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::ProposalExecuted { proposal_id },
+    )?;
+    Ok((refund_msgs, hook_msgs))
+}
+
+pub fn execute_proposal_msgs(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+) -> Result<Response, ContractError> {
+    // Only the execution of this proposal may dispatch its messages
+    if env.contract.address != info.sender
+        || EXECUTING_PROPOSAL.may_load(deps.storage)? != Some(proposal_id)
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+    EXECUTING_PROPOSAL.remove(deps.storage);
+
+    let prop = PROPOSALS.load(deps.storage, proposal_id)?;
+    Ok(Response::new()
+        .add_messages(prop.executable_msgs())
+        .add_messages(voter_reward_reserve_check_msgs(deps.storage, &env)?)
+        .add_attribute("action", "execute_proposal_msgs")
+        .add_attribute("proposal_id", proposal_id.to_string()))
+}
+
+pub fn execute_check_voter_reward_reserve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if env.contract.address != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let reserve = VOTER_REWARD_RESERVE
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (token, reserved) in reserve {
+        let balance: BalanceResponse = deps.querier.query_wasm_smart(
+            &token,
+            &Cw20QueryMsg::Balance {
+                address: env.contract.address.to_string(),
+            },
+        )?;
+        if balance.balance < reserved {
+            return Err(ContractError::VoterRewardReserveSpent {
+                token: token.into_string(),
+                reserved,
+            });
+        }
+    }
+
+    Ok(Response::new().add_attribute("action", "check_voter_reward_reserve"))
+}
+
 pub fn execute_close(
     deps: DepsMut,
     env: Env,
@@ -377,41 +923,180 @@ pub fn execute_close(
         }
     }
 
-    let gov_token = GOV_TOKEN.load(deps.storage)?;
-
-    // Anyone can trigger this if the vote passed
     let mut prop = PROPOSALS.load(deps.storage, proposal_id)?;
-    if [Status::Executed, Status::Rejected, Status::Passed]
-        .iter()
-        .any(|x| *x == prop.status)
-    {
-        return Err(ContractError::WrongCloseStatus {});
+
+    // Proposals whose execution failed can be closed right away by their
+    // proposer or the DAO, instead of being executed again. They passed, so
+    // their deposit is refunded.
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::ProposalClosed { proposal_id },
+    )?;
+    if prop.status == Status::ExecutionFailed {
+        if info.sender != prop.proposer && info.sender != env.contract.address {
+            return Err(ContractError::Unauthorized {});
+        }
+        prop.status = Status::Rejected;
+        PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+
+        let cfg = CONFIG.load(deps.storage)?;
+        allocate_voter_rewards(deps.storage, &cfg.voter_rewards, proposal_id, &prop)?;
+        let gov_token = GOV_TOKEN.load(deps.storage)?;
+        let refund_msgs = get_proposal_deposit_refund_message(
+            &prop.proposer,
+            &prop.deposit,
+            &prop.deposit_token(&gov_token),
+        )?;
+        return Ok(Response::new()
+            .add_messages(refund_msgs)
+            .add_submessages(hook_msgs)
+            .add_attribute("action", "close")
+            .add_attribute("sender", info.sender)
+            .add_attribute("proposal_id", proposal_id.to_string())
+            .add_attribute("deposit", "refunded"));
     }
-    if !prop.expires.is_expired(&env.block) {
-        return Err(ContractError::NotExpired {});
+
+    // Anyone can trigger this if the vote failed. Proposals rejected before
+    // they expired only have their deposit left to settle.
+    if !prop.deposit_pending {
+        if [
+            Status::Executed,
+            Status::Rejected,
+            Status::Passed,
+            Status::Cancelled,
+            Status::Withdrawn,
+        ]
+        .contains(&prop.status)
+        {
+            return Err(ContractError::WrongCloseStatus {});
+        }
+        if !prop.expires.is_expired(&env.block) {
+            return Err(ContractError::NotExpired {});
+        }
     }
 
     // Set it to failed
     prop.status = Status::Rejected;
+    prop.deposit_pending = false;
     PROPOSALS.save(deps.storage, proposal_id, &prop)?;
 
     let cfg = CONFIG.load(deps.storage)?;
+    allocate_voter_rewards(deps.storage, &cfg.voter_rewards, proposal_id, &prop)?;
+    let (deposit_msgs, deposit_outcome) =
+        get_rejected_deposit_messages(deps.as_ref(), &prop, &cfg.deposit_policy)?;
 
-    let response_with_optional_refund = match cfg.refund_failed_proposals {
-        Some(true) => Response::new().add_messages(get_proposal_deposit_refund_message(
-            &prop.proposer,
-            &prop.deposit,
-            &gov_token,
-        )?),
-        _ => Response::new(),
-    };
-
-    Ok(response_with_optional_refund
+    Ok(Response::new()
+        .add_messages(deposit_msgs)
+        .add_submessages(hook_msgs)
         .add_attribute("action", "close")
         .add_attribute("sender", info.sender)
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("deposit", deposit_outcome))
+}
+
+pub fn execute_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+) -> Result<Response<Empty>, ContractError> {
+    // Check if DAO is Paused
+    let paused = DAO_PAUSED.may_load(deps.storage)?;
+    if let Some(expiration) = paused {
+        if !expiration.is_expired(&env.block) {
+            return Err(ContractError::Paused {});
+        }
+    }
+
+    let mut prop = PROPOSALS.load(deps.storage, proposal_id)?;
+    if prop.proposer != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if prop.current_status(&env.block) != Status::Open {
+        return Err(ContractError::WrongWithdrawStatus {});
+    }
+    if BALLOTS
+        .prefix(proposal_id)
+        .keys_raw(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_some()
+    {
+        return Err(ContractError::ProposalHasVotes {});
+    }
+
+    prop.status = Status::Withdrawn;
+    PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+    OPEN_PROPOSALS.remove(deps.storage, (&prop.proposer, proposal_id));
+    OPEN_PROPOSAL_IDS.remove(deps.storage, proposal_id);
+
+    // Nobody voted, so the proposer gets their deposit back
+    let gov_token = GOV_TOKEN.load(deps.storage)?;
+    let refund_msgs = get_proposal_deposit_refund_message(
+        &prop.proposer,
+        &prop.deposit,
+        &prop.deposit_token(&gov_token),
+    )?;
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::ProposalStatusChanged {
+            proposal_id,
+            old_status: Status::Open,
+            new_status: Status::Withdrawn,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_messages(refund_msgs)
+        .add_submessages(hook_msgs)
+        .add_attribute("action", "withdraw")
+        .add_attribute("sender", info.sender)
         .add_attribute("proposal_id", proposal_id.to_string()))
 }
 
+pub fn execute_cancel(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+    reason: String,
+) -> Result<Response<Empty>, ContractError> {
+    // The guardian can act even while the DAO is paused
+    let cfg = CONFIG.load(deps.storage)?;
+    if cfg.guardian.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut prop = PROPOSALS.load(deps.storage, proposal_id)?;
+    let old_status = prop.current_status(&env.block);
+    if ![Status::Open, Status::Passed].contains(&old_status) {
+        return Err(ContractError::WrongCancelStatus {});
+    }
+
+    prop.status = Status::Cancelled;
+    prop.executable_at = None;
+    PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+
+    let (deposit_msgs, deposit_outcome) =
+        get_rejected_deposit_messages(deps.as_ref(), &prop, &cfg.cancelled_deposit_policy)?;
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::ProposalStatusChanged {
+            proposal_id,
+            old_status,
+            new_status: Status::Cancelled,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_messages(deposit_msgs)
+        .add_submessages(hook_msgs)
+        .add_attribute("action", "cancel")
+        .add_attribute("sender", info.sender)
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("reason", reason)
+        .add_attribute("deposit", deposit_outcome))
+}
+
 pub fn execute_pause_dao(
     deps: DepsMut,
     env: Env,
@@ -442,6 +1127,25 @@ pub fn execute_update_config(
     }
 
     update_config_msg.threshold.validate()?;
+    update_config_msg.validate_deposit_policy()?;
+    update_config_msg.validate_timelock()?;
+    update_config_msg.validate_proposal_categories()?;
+    update_config_msg.voting_power_transform.validate()?;
+    if let Some(guardian) = &update_config_msg.guardian {
+        deps.api.addr_validate(guardian.as_str())?;
+    }
+    if let Some(token) = update_config_msg
+        .voter_rewards
+        .as_ref()
+        .and_then(|r| r.token.as_ref())
+    {
+        deps.api.addr_validate(token.as_str())?;
+    }
+    if let Some(allowlist) = &update_config_msg.proposer_allowlist {
+        for addr in allowlist {
+            deps.api.addr_validate(addr.as_str())?;
+        }
+    }
 
     CONFIG.save(deps.storage, &update_config_msg)?;
 
@@ -470,6 +1174,72 @@ pub fn execute_update_staking_contract(
         .add_attribute("new_staking_contract", new_staking_contract))
 }
 
+pub fn execute_prune_staking_checkpoints(
+    deps: DepsMut,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response<Empty>, ContractError> {
+    let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
+    // Votes on open proposals still need the checkpoint at their start height
+    let before =
+        get_oldest_open_start_height(deps.storage, &env.block)?.unwrap_or(env.block.height);
+    let msg = WasmMsg::Execute {
+        contract_addr: staking_contract.to_string(),
+        msg: to_binary(&stake_cw20::msg::ExecuteMsg::PruneCheckpoints {
+            before,
+            start_after,
+            limit,
+        })?,
+        funds: vec![],
+    };
+
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "prune_staking_checkpoints")
+        .add_attribute("before", before.to_string()))
+}
+
+pub fn execute_add_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    hooks: &Hooks,
+    address: String,
+    action: &str,
+) -> Result<Response<Empty>, ContractError> {
+    // Only contract can call this method
+    if env.contract.address != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let hook = deps.api.addr_validate(&address)?;
+    hooks.add_hook(deps.storage, hook)?;
+
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("hook", address))
+}
+
+pub fn execute_remove_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    hooks: &Hooks,
+    address: String,
+    action: &str,
+) -> Result<Response<Empty>, ContractError> {
+    // Only contract can call this method
+    if env.contract.address != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let hook = deps.api.addr_validate(&address)?;
+    hooks.remove_hook(deps.storage, hook)?;
+
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("hook", address))
+}
+
 pub fn execute_update_cw20_token_list(
     deps: DepsMut,
     env: Env,
@@ -508,9 +1278,12 @@ pub fn execute_receive(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
-    _wrapped: Cw20ReceiveMsg,
+    wrapped: Cw20ReceiveMsg,
 ) -> Result<Response<Empty>, ContractError> {
     let cfg = CONFIG.load(deps.storage)?;
+    if let Ok(ReceiveMsg::FundVoterRewards {}) = from_binary(&wrapped.msg) {
+        return execute_fund_voter_rewards(deps, info, cfg, wrapped.amount);
+    }
     if !cfg.automatically_add_cw20s {
         return Ok(Response::new());
     }
@@ -522,6 +1295,97 @@ pub fn execute_receive(
         .add_attribute("token", info.sender.to_string()))
 }
 
+pub fn execute_fund_voter_rewards(
+    deps: DepsMut,
+    info: MessageInfo,
+    cfg: Config,
+    amount: Uint128,
+) -> Result<Response<Empty>, ContractError> {
+    let rewards = cfg
+        .voter_rewards
+        .ok_or(ContractError::VoterRewardsDisabled {})?;
+    let token = get_voter_rewards_token(deps.storage, &rewards)?;
+    if info.sender != token {
+        return Err(ContractError::WrongVoterRewardsToken {
+            token: token.into_string(),
+        });
+    }
+
+    // Pool tokens are part of the treasury
+    TREASURY_TOKENS.save(deps.storage, &token, &Empty {})?;
+    let pool = VOTER_REWARD_POOL.update(deps.storage, &token, |pool| -> StdResult<_> {
+        Ok(pool.unwrap_or_default() + amount)
+    })?;
+    VOTER_REWARD_RESERVE.update(deps.storage, &token, |reserve| -> StdResult<_> {
+        Ok(reserve.unwrap_or_default() + amount)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "fund_voter_rewards")
+        .add_attribute("amount", amount)
+        .add_attribute("pool", pool))
+}
+
+pub fn execute_claim_voter_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_ids: Vec<u64>,
+) -> Result<Response<Empty>, ContractError> {
+    // Check if DAO is Paused
+    let paused = DAO_PAUSED.may_load(deps.storage)?;
+    if let Some(expiration) = paused {
+        if !expiration.is_expired(&env.block) {
+            return Err(ContractError::Paused {});
+        }
+    }
+
+    if proposal_ids.len() > MAX_LIMIT as usize {
+        return Err(ContractError::OversizedRequest {
+            size: proposal_ids.len() as u64,
+            max: MAX_LIMIT as u64,
+        });
+    }
+
+    // Rewards may have been paid in different tokens over time
+    let mut claimed: Vec<(Addr, Uint128)> = vec![];
+    for proposal_id in proposal_ids {
+        let (token, amount) = get_voter_reward(deps.storage, proposal_id, &info.sender)?
+            .ok_or(ContractError::NoVoterReward { proposal_id })?;
+        CLAIMED_VOTER_REWARDS.save(deps.storage, (proposal_id, &info.sender), &Empty {})?;
+        match claimed.iter_mut().find(|(claimed, _)| *claimed == token) {
+            Some((_, total)) => *total += amount,
+            None => claimed.push((token, amount)),
+        }
+    }
+
+    for (token, amount) in &claimed {
+        VOTER_REWARD_RESERVE.update(deps.storage, token, |reserve| -> StdResult<_> {
+            Ok(reserve.unwrap_or_default().checked_sub(*amount)?)
+        })?;
+    }
+
+    let msgs = claimed
+        .into_iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(token, amount)| {
+            Ok(WasmMsg::Execute {
+                contract_addr: token.into_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: info.sender.to_string(),
+                    amount,
+                })?,
+                funds: vec![],
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "claim_voter_rewards")
+        .add_attribute("sender", info.sender))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
@@ -550,20 +1414,33 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             to_binary(&query_cw20_balances(deps, env, start_after, limit)?)
         }
         QueryMsg::Cw20TokenList {} => to_binary(&query_cw20_token_list(deps)),
+        QueryMsg::ProposalHooks {} => to_binary(&PROPOSAL_HOOKS.query_hooks(deps)?),
+        QueryMsg::VoteHooks {} => to_binary(&VOTE_HOOKS.query_hooks(deps)?),
+        QueryMsg::VoterRewards {
+            address,
+            start_after,
+            limit,
+        } => to_binary(&query_voter_rewards(deps, address, start_after, limit)?),
+        QueryMsg::VoterRewardPool {} => to_binary(&query_voter_reward_pool(deps)?),
+        QueryMsg::Draft { draft_id } => to_binary(&query_draft(deps, draft_id)?),
+        QueryMsg::ListDrafts { start_after, limit } => {
+            to_binary(&query_list_drafts(deps, env, start_after, limit)?)
+        }
     }
 }
 
 fn query_threshold(deps: Deps) -> StdResult<ThresholdResponse> {
     let cfg = CONFIG.load(deps.storage)?;
-    let total_supply = get_total_staked_supply(deps)?;
-    Ok(cfg.threshold.to_response(total_supply))
+    let total_weight = get_total_weight(deps, &cfg.voting_power_transform)?;
+    Ok(cfg.threshold.to_response(total_weight))
 }
 
 fn query_proposal(deps: Deps, env: Env, id: u64) -> StdResult<ProposalResponse> {
     let prop = PROPOSALS.load(deps.storage, id)?;
     let status = prop.current_status(&env.block);
-    let total_supply = get_total_staked_supply(deps)?;
-    let threshold = prop.threshold.to_response(total_supply);
+    let executable_at = prop.executable_at(&env.block);
+    let threshold = prop.threshold.to_response(prop.total_weight);
+    let deposit_denom = prop.deposit_token(&GOV_TOKEN.load(deps.storage)?);
     Ok(ProposalResponse {
         id,
         title: prop.title,
@@ -574,6 +1451,13 @@ fn query_proposal(deps: Deps, env: Env, id: u64) -> StdResult<ProposalResponse>
         expires: prop.expires,
         threshold,
         deposit_amount: prop.deposit,
+        deposit_denom,
+        allow_revoting: prop.allow_revoting,
+        choices: prop.choices,
+        executable_at,
+        execution_error: prop.execution_error,
+        category: prop.category,
+        author: prop.author,
         start_height: prop.start_height,
     })
 }
@@ -594,6 +1478,7 @@ fn query_proposal_tally(deps: Deps, env: Env, id: u64) -> StdResult<VoteTallyRes
         total_votes,
         total_weight,
         votes: prop.votes,
+        choice_votes: prop.choice_votes,
     })
 }
 
@@ -668,15 +1553,96 @@ fn query_list_proposals(
 ) -> StdResult<ProposalListResponse> {
     let limit = get_and_check_limit(limit, MAX_LIMIT, DEFAULT_LIMIT)? as usize;
     let start = start_after.map(Bound::exclusive_int);
+    let gov_token = GOV_TOKEN.load(deps.storage)?;
     let props: StdResult<Vec<_>> = PROPOSALS
         .range_raw(deps.storage, start, None, Order::Ascending)
         .take(limit)
-        .map(|p| map_proposal(&env.block, p))
+        .map(|p| map_proposal(&env.block, &gov_token, p))
         .collect();
 
     Ok(ProposalListResponse { proposals: props? })
 }
 
+fn map_draft(id: u64, draft: Draft) -> DraftResponse {
+    DraftResponse {
+        id,
+        title: draft.title,
+        description: draft.description,
+        author: draft.author,
+        msgs: draft.msgs,
+        latest: draft.latest,
+        expires: draft.expires,
+    }
+}
+
+fn query_draft(deps: Deps, id: u64) -> StdResult<DraftResponse> {
+    let draft = DRAFTS.load(deps.storage, id)?;
+    Ok(map_draft(id, draft))
+}
+
+fn query_list_drafts(
+    deps: Deps,
+    env: Env,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<DraftListResponse> {
+    let limit = get_and_check_limit(limit, MAX_LIMIT, DEFAULT_LIMIT)? as usize;
+    let start = start_after.map(Bound::exclusive_int);
+    // The limit applies to the drafts scanned, so a page can hold fewer
+    // drafts when some expired
+    let drafts = DRAFTS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .filter(|item| !matches!(item, Ok((_, draft)) if draft.expires.is_expired(&env.block)))
+        .map(|item| item.map(|(id, draft)| map_draft(id, draft)))
+        .collect::<StdResult<_>>()?;
+
+    Ok(DraftListResponse { drafts })
+}
+
+fn query_voter_rewards(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<VoterRewardsResponse> {
+    let voter = deps.api.addr_validate(&address)?;
+    let limit = get_and_check_limit(limit, MAX_LIMIT, DEFAULT_LIMIT)? as usize;
+    let start = start_after.map(Bound::exclusive_int);
+    let rewards = PROPOSAL_VOTER_REWARDS
+        .keys(deps.storage, start, None, Order::Ascending)
+        .map(|proposal_id| {
+            let proposal_id = proposal_id?;
+            Ok(
+                get_voter_reward(deps.storage, proposal_id, &voter)?.map(|(token, amount)| {
+                    VoterReward {
+                        proposal_id,
+                        token,
+                        amount,
+                    }
+                }),
+            )
+        })
+        .filter_map(|reward| reward.transpose())
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(VoterRewardsResponse { rewards })
+}
+
+fn query_voter_reward_pool(deps: Deps) -> StdResult<Uint128> {
+    let cfg = CONFIG.load(deps.storage)?;
+    match cfg.voter_rewards {
+        Some(rewards) => {
+            let token = get_voter_rewards_token(deps.storage, &rewards)?;
+            Ok(VOTER_REWARD_POOL
+                .may_load(deps.storage, &token)?
+                .unwrap_or_default())
+        }
+        None => Ok(Uint128::zero()),
+    }
+}
+
 fn query_reverse_proposals(
     deps: Deps,
     env: Env,
@@ -685,10 +1651,11 @@ fn query_reverse_proposals(
 ) -> StdResult<ProposalListResponse> {
     let limit = get_and_check_limit(limit, MAX_LIMIT, DEFAULT_LIMIT)? as usize;
     let end = start_before.map(Bound::exclusive_int);
+    let gov_token = GOV_TOKEN.load(deps.storage)?;
     let props: StdResult<Vec<_>> = PROPOSALS
         .range_raw(deps.storage, None, end, Order::Descending)
         .take(limit)
-        .map(|p| map_proposal(&env.block, p))
+        .map(|p| map_proposal(&env.block, &gov_token, p))
         .collect();
 
     Ok(ProposalListResponse { proposals: props? })
@@ -706,6 +1673,7 @@ fn query_vote(deps: Deps, proposal_id: u64, voter: String) -> StdResult<VoteResp
     let vote = prop.map(|b| VoteInfo {
         voter,
         vote: b.vote,
+        choice: b.choice,
         weight: b.weight,
     });
     Ok(VoteResponse { vote })
@@ -730,6 +1698,7 @@ fn query_list_votes(
             Ok(VoteInfo {
                 voter: String::from_utf8(voter)?,
                 vote: ballot.vote,
+                choice: ballot.choice,
                 weight: ballot.weight,
             })
         })
@@ -747,6 +1716,21 @@ fn query_voter(deps: Deps, voter: String) -> StdResult<VoterResponse> {
     })
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let previous_version = ensure_upgrade(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    // Storage migrations, oldest first
+    if previous_version < Version::new(0, 3, 0) {
+        migrations::migrate_v0_2(deps.storage)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", previous_version.to_string())
+        .add_attribute("new_version", CONTRACT_VERSION))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
     match msg.id {
@@ -767,6 +1751,9 @@ pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractEr
                     let code_id = STAKING_CONTRACT_CODE_ID.load(deps.storage)?;
                     let unstaking_duration =
                         STAKING_CONTRACT_UNSTAKING_DURATION.load(deps.storage)?;
+                    let snapshot_strategy = STAKING_CONTRACT_SNAPSHOT_STRATEGY
+                        .may_load(deps.storage)?
+                        .flatten();
                     let msg = WasmMsg::Instantiate {
                         code_id,
                         funds: vec![],
@@ -775,7 +1762,11 @@ pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractEr
                         msg: to_binary(&stake_cw20::msg::InstantiateMsg {
                             admin: Some(env.contract.address.to_string()),
                             unstaking_duration,
-                            token_address: cw20_addr.to_string(),
+                            token_address: Some(cw20_addr.to_string()),
+                            native_denom: None,
+                            slash_destination: None,
+                            snapshot_strategy,
+                            lockup_tiers: None,
                         })?,
                     };
                     let msg = SubMsg::reply_on_success(msg, INSTANTIATE_STAKING_CONTRACT_REPLY_ID);
@@ -800,6 +1791,62 @@ pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractEr
                 Err(_) => Err(ContractError::InstantiateGovTokenError {}),
             }
         }
+        id if id >= VOTE_HOOK_REPLY_ID_OFFSET => {
+            let hook = remove_failed_hook(deps, &VOTE_HOOKS, id - VOTE_HOOK_REPLY_ID_OFFSET)?;
+            Ok(Response::new()
+                .add_attribute("action", "remove_failed_vote_hook")
+                .add_attribute("hook", hook.unwrap_or_default()))
+        }
+        id if id >= PROPOSAL_HOOK_REPLY_ID_OFFSET => {
+            let hook =
+                remove_failed_hook(deps, &PROPOSAL_HOOKS, id - PROPOSAL_HOOK_REPLY_ID_OFFSET)?;
+            Ok(Response::new()
+                .add_attribute("action", "remove_failed_proposal_hook")
+                .add_attribute("hook", hook.unwrap_or_default()))
+        }
+        id if id > EXECUTE_PROPOSAL_REPLY_ID_OFFSET => {
+            let proposal_id = id - EXECUTE_PROPOSAL_REPLY_ID_OFFSET;
+            let mut prop = PROPOSALS.load(deps.storage, proposal_id)?;
+            let error = match msg.result.into_result() {
+                Ok(_) => {
+                    prop.execution_error = None;
+                    PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+                    let (refund_msgs, hook_msgs) =
+                        settle_executed_proposal(deps, proposal_id, &prop)?;
+                    return Ok(Response::new()
+                        .add_messages(refund_msgs)
+                        .add_submessages(hook_msgs)
+                        .add_attribute("action", "execution_succeeded")
+                        .add_attribute("proposal_id", proposal_id.to_string()));
+                }
+                Err(error) => error,
+            };
+
+            // None of the proposal's messages were dispatched. A proposal
+            // with the error of a previous attempt was being retried.
+            EXECUTING_PROPOSAL.remove(deps.storage);
+            let old_status = match prop.execution_error {
+                Some(_) => Status::ExecutionFailed,
+                None => Status::Passed,
+            };
+            prop.status = Status::ExecutionFailed;
+            prop.execution_error = Some(error.clone());
+            PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+            let hook_msgs = proposal_hook_msgs(
+                deps.as_ref(),
+                ProposalHookMsg::ProposalStatusChanged {
+                    proposal_id,
+                    old_status,
+                    new_status: Status::ExecutionFailed,
+                },
+            )?;
+
+            Ok(Response::new()
+                .add_submessages(hook_msgs)
+                .add_attribute("action", "execution_failed")
+                .add_attribute("proposal_id", proposal_id.to_string())
+                .add_attribute("error", error))
+        }
         _ => Err(ContractError::UnknownReplyId { id: msg.id }),
     }
 }
@@ -1,4 +1,7 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
+use cw_controllers::HookError;
+use cw_migrate::MigrateError;
+use cw_utils::{Expiration, PaymentError};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -6,6 +9,15 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("{0}")]
+    Payment(#[from] PaymentError),
+
+    #[error("{0}")]
+    Hook(#[from] HookError),
+
     #[error("Failed to instantiate governance token")]
     InstantiateGovTokenError {},
 
@@ -39,12 +51,42 @@ pub enum ContractError {
     #[error("Already voted on this proposal")]
     AlreadyVoted {},
 
+    #[error("Already cast a vote with that option")]
+    AlreadyCast {},
+
+    #[error("Vote type does not match the proposal")]
+    WrongVoteType {},
+
+    #[error("Multiple choice proposals need between {min} and {max} options")]
+    WrongChoiceCount { min: u64, max: u64 },
+
+    #[error("Proposal has no option {choice}")]
+    InvalidChoice { choice: u32 },
+
     #[error("Cannot execute completed or unpassed proposals")]
     WrongExecuteStatus {},
 
     #[error("Cannot close completed or passed proposals")]
     WrongCloseStatus {},
 
+    #[error("Only open or passed proposals can be cancelled")]
+    WrongCancelStatus {},
+
+    #[error("Only open proposals can be withdrawn")]
+    WrongWithdrawStatus {},
+
+    #[error("Proposals can't be withdrawn once voted on")]
+    ProposalHasVotes {},
+
+    #[error("At least {min} staked tokens are required to make a proposal")]
+    InsufficientProposerStake { min: Uint128 },
+
+    #[error("Proposer is not on the proposer allowlist")]
+    ProposerNotAllowed {},
+
+    #[error("Proposer already has the maximum of {max} open proposals")]
+    TooManyOpenProposals { max: u64 },
+
     #[error("Got a submessage reply with unknown id: {id}")]
     UnknownReplyId { id: u64 },
 
@@ -53,4 +95,40 @@ pub enum ContractError {
 
     #[error("DAO is paused")]
     Paused {},
+
+    #[error("Proposal deposit must be exactly {amount}{denom}")]
+    InvalidDeposit { amount: Uint128, denom: String },
+
+    #[error("Deposits can only be sent to the staking contract if paid in the governance token")]
+    InvalidDepositPolicy {},
+
+    #[error("Timelock must use the same unit as the max voting period")]
+    InvalidTimelock {},
+
+    #[error("Min voting period must use the same unit as and not exceed the max voting period")]
+    InvalidVotingPeriods {},
+
+    #[error("Only one proposal category can be set per proposal kind")]
+    DuplicateProposalCategory {},
+
+    #[error("Voting power cap must be more than 0% and at most 100%")]
+    InvalidVotingPowerCap {},
+
+    #[error("Draft has expired")]
+    DraftExpired {},
+
+    #[error("Voter rewards are not enabled")]
+    VoterRewardsDisabled {},
+
+    #[error("Voter rewards are paid in {token}")]
+    WrongVoterRewardsToken { token: String },
+
+    #[error("No voter reward to claim for proposal {proposal_id}")]
+    NoVoterReward { proposal_id: u64 },
+
+    #[error("Proposal would spend {token} reserved for voter rewards ({reserved} reserved)")]
+    VoterRewardReserveSpent { token: String, reserved: Uint128 },
+
+    #[error("Proposal is timelocked until {executable_at}")]
+    Timelocked { executable_at: Expiration },
 }
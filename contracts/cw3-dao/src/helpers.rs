@@ -1,16 +1,27 @@
 use cosmwasm_std::{
-    to_binary, Addr, BlockInfo, CosmosMsg, Deps, Env, MessageInfo, StdError, StdResult, Uint128,
-    WasmMsg,
+    coins, to_binary, Addr, BankMsg, BlockInfo, CosmosMsg, Deps, Env, MessageInfo, Order, StdError,
+    StdResult, Storage, Uint128, WasmMsg,
 };
-use cw20::Cw20ExecuteMsg;
+use cw20::{Cw20ExecuteMsg, Denom};
+use cw_utils::{must_pay, nonpayable};
+use serde::Deserialize;
 
 use stake_cw20::msg::{
-    QueryMsg as StakingContractQueryMsg, StakedBalanceAtHeightResponse, TotalStakedAtHeightResponse,
+    DelegationAtHeightResponse, ExecuteMsg as StakingContractExecuteMsg,
+    QueryMsg as StakingContractQueryMsg, ReceiveMsg as StakingContractReceiveMsg, SnapshotStrategy,
+    StakedBalanceAtHeightResponse, StakerPowerAtHeightResponse, TotalPowerAtHeightResponse,
+    TotalSqrtPowerAtHeightResponse, TotalStakedAtHeightResponse, VotingPowerAtHeightResponse,
 };
 
 use crate::{
+    msg::{DepositPolicy, ExecuteMsg, VoterRewards, VotingPowerTransform},
     query::ProposalResponse,
-    state::{parse_id, Proposal, STAKING_CONTRACT},
+    query::Status,
+    state::{
+        parse_id, Proposal, ProposalVoterRewards, BALLOTS, CLAIMED_VOTER_REWARDS, GOV_TOKEN,
+        OPEN_PROPOSALS, OPEN_PROPOSAL_IDS, OVERRIDDEN_POWER, PROPOSALS, PROPOSAL_VOTER_REWARDS,
+        STAKING_CONTRACT, VOTER_REWARD_POOL, VOTER_REWARD_RESERVE,
+    },
     ContractError,
 };
 
@@ -18,44 +29,135 @@ pub fn get_deposit_message(
     env: &Env,
     info: &MessageInfo,
     amount: &Uint128,
-    gov_token: &Addr,
-) -> StdResult<Vec<CosmosMsg>> {
+    denom: &Denom,
+) -> Result<Vec<CosmosMsg>, ContractError> {
     if *amount == Uint128::zero() {
+        // Nothing is owed, so any funds sent along would be kept silently
+        nonpayable(info)?;
         return Ok(vec![]);
     }
-    let transfer_cw20_msg = Cw20ExecuteMsg::TransferFrom {
-        owner: info.sender.clone().into(),
-        recipient: env.contract.address.clone().into(),
-        amount: *amount,
-    };
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: gov_token.into(),
-        msg: to_binary(&transfer_cw20_msg)?,
-        funds: vec![],
-    };
-    let cw20_transfer_cosmos_msg: CosmosMsg = exec_cw20_transfer.into();
-    Ok(vec![cw20_transfer_cosmos_msg])
+    match denom {
+        Denom::Cw20(gov_token) => {
+            nonpayable(info)?;
+            let transfer_cw20_msg = Cw20ExecuteMsg::TransferFrom {
+                owner: info.sender.clone().into(),
+                recipient: env.contract.address.clone().into(),
+                amount: *amount,
+            };
+            let exec_cw20_transfer = WasmMsg::Execute {
+                contract_addr: gov_token.into(),
+                msg: to_binary(&transfer_cw20_msg)?,
+                funds: vec![],
+            };
+            let cw20_transfer_cosmos_msg: CosmosMsg = exec_cw20_transfer.into();
+            Ok(vec![cw20_transfer_cosmos_msg])
+        }
+        Denom::Native(denom) => {
+            // Native deposits are sent along with the proposal, so there is
+            // nothing to transfer, only the amount to check
+            let paid = must_pay(info, denom)?;
+            if paid != *amount {
+                return Err(ContractError::InvalidDeposit {
+                    amount: *amount,
+                    denom: denom.clone(),
+                });
+            }
+            Ok(vec![])
+        }
+    }
 }
 
 pub fn get_proposal_deposit_refund_message(
     proposer: &Addr,
     amount: &Uint128,
-    gov_token: &Addr,
+    denom: &Denom,
 ) -> StdResult<Vec<CosmosMsg>> {
     if *amount == Uint128::zero() {
         return Ok(vec![]);
     }
-    let transfer_cw20_msg = Cw20ExecuteMsg::Transfer {
-        recipient: proposer.into(),
-        amount: *amount,
-    };
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: gov_token.into(),
-        msg: to_binary(&transfer_cw20_msg)?,
-        funds: vec![],
+    match denom {
+        Denom::Cw20(gov_token) => {
+            let transfer_cw20_msg = Cw20ExecuteMsg::Transfer {
+                recipient: proposer.into(),
+                amount: *amount,
+            };
+            let exec_cw20_transfer = WasmMsg::Execute {
+                contract_addr: gov_token.into(),
+                msg: to_binary(&transfer_cw20_msg)?,
+                funds: vec![],
+            };
+            let cw20_transfer_cosmos_msg: CosmosMsg = exec_cw20_transfer.into();
+            Ok(vec![cw20_transfer_cosmos_msg])
+        }
+        Denom::Native(denom) => {
+            let bank_send = BankMsg::Send {
+                to_address: proposer.into(),
+                amount: coins(amount.u128(), denom),
+            };
+            Ok(vec![bank_send.into()])
+        }
+    }
+}
+
+/// Resolves what happens to the deposit of a rejected proposal under the
+/// given policy. Returns the messages to dispatch along with the outcome,
+/// which is reported in the `deposit` attribute.
+pub fn get_rejected_deposit_messages(
+    deps: Deps,
+    prop: &Proposal,
+    policy: &DepositPolicy,
+) -> StdResult<(Vec<CosmosMsg>, &'static str)> {
+    let refund = match policy {
+        DepositPolicy::Refund {} => true,
+        DepositPolicy::RefundIfQuorum {} => prop.reached_quorum(),
+        _ => false,
     };
-    let cw20_transfer_cosmos_msg: CosmosMsg = exec_cw20_transfer.into();
-    Ok(vec![cw20_transfer_cosmos_msg])
+    let deposit_denom = prop.deposit_token(&GOV_TOKEN.load(deps.storage)?);
+    // Vetoed proposals always forfeit their deposit
+    if refund && !prop.is_vetoed() {
+        let msgs =
+            get_proposal_deposit_refund_message(&prop.proposer, &prop.deposit, &deposit_denom)?;
+        return Ok((msgs, "refunded"));
+    }
+    if prop.deposit == Uint128::zero() {
+        return Ok((vec![], "kept"));
+    }
+
+    match (policy, &deposit_denom) {
+        (DepositPolicy::Burn {}, Denom::Cw20(token)) => {
+            let burn = WasmMsg::Execute {
+                contract_addr: token.into(),
+                msg: to_binary(&Cw20ExecuteMsg::Burn {
+                    amount: prop.deposit,
+                })?,
+                funds: vec![],
+            };
+            Ok((vec![burn.into()], "burned"))
+        }
+        (DepositPolicy::Burn {}, Denom::Native(denom)) => {
+            let burn = BankMsg::Burn {
+                amount: coins(prop.deposit.u128(), denom),
+            };
+            Ok((vec![burn.into()], "burned"))
+        }
+        // The staking contract can only be funded with the governance token,
+        // deposits paid in anything else stay in the treasury
+        (DepositPolicy::SendToStaking {}, Denom::Cw20(token))
+            if *token == GOV_TOKEN.load(deps.storage)? =>
+        {
+            let send = WasmMsg::Execute {
+                contract_addr: token.into(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: STAKING_CONTRACT.load(deps.storage)?.into(),
+                    amount: prop.deposit,
+                    msg: to_binary(&StakingContractReceiveMsg::Fund {})?,
+                })?,
+                funds: vec![],
+            };
+            Ok((vec![send.into()], "sent_to_staking"))
+        }
+        _ => Ok((vec![], "kept")),
+    }
 }
 
 pub fn get_total_staked_supply(deps: Deps) -> StdResult<Uint128> {
@@ -69,6 +171,36 @@ pub fn get_total_staked_supply(deps: Deps) -> StdResult<Uint128> {
     Ok(total.total)
 }
 
+pub fn get_total_voting_power(deps: Deps) -> StdResult<Uint128> {
+    let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
+
+    // Get total voting power, staked supply plus lockup boosts
+    let total: TotalPowerAtHeightResponse = deps.querier.query_wasm_smart(
+        staking_contract,
+        &StakingContractQueryMsg::TotalPowerAtHeight { height: None },
+    )?;
+    Ok(total.power)
+}
+
+/// Total weight of the ballots that can be cast under `transform`
+pub fn get_total_weight(deps: Deps, transform: &VotingPowerTransform) -> StdResult<Uint128> {
+    match transform {
+        VotingPowerTransform::SquareRoot {} => {
+            let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
+
+            // Get the sum of the square roots of the voting power of every
+            // address, kept up to date by the staking contract
+            let total: TotalSqrtPowerAtHeightResponse = deps.querier.query_wasm_smart(
+                staking_contract,
+                &StakingContractQueryMsg::TotalSqrtPowerAtHeight { height: None },
+            )?;
+            Ok(total.power)
+        }
+        // Capped ballots weigh at most the voting power they are cast with
+        _ => get_total_voting_power(deps),
+    }
+}
+
 pub fn get_staked_balance(deps: Deps, address: Addr) -> StdResult<Uint128> {
     let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
 
@@ -83,27 +215,190 @@ pub fn get_staked_balance(deps: Deps, address: Addr) -> StdResult<Uint128> {
     Ok(res.balance)
 }
 
+pub fn get_staker_power_at_height(deps: Deps, address: Addr, height: u64) -> StdResult<Uint128> {
+    let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
+
+    // Get voting power of the address' own stake at height, delegated or not
+    let res: StakerPowerAtHeightResponse = deps.querier.query_wasm_smart(
+        staking_contract,
+        &StakingContractQueryMsg::StakerPowerAtHeight {
+            address: address.to_string(),
+            height: Some(height),
+        },
+    )?;
+    Ok(res.power)
+}
+
 pub fn get_voting_power_at_height(deps: Deps, address: Addr, height: u64) -> StdResult<Uint128> {
     let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
 
-    // Get voting power at height
-    let balance: StakedBalanceAtHeightResponse = deps.querier.query_wasm_smart(
+    // Get voting power at height, including power delegated to the address
+    let res: VotingPowerAtHeightResponse = deps.querier.query_wasm_smart(
         staking_contract,
-        &StakingContractQueryMsg::StakedBalanceAtHeight {
+        &StakingContractQueryMsg::VotingPowerAtHeight {
             address: address.to_string(),
             height: Some(height),
         },
     )?;
-    Ok(balance.balance)
+    Ok(res.power)
+}
+
+/// Weight of the voting power of `address` on a proposal, less the power
+/// taken back by delegators who voted themselves and `taken`
+pub fn get_remaining_weight(
+    deps: Deps,
+    proposal_id: u64,
+    prop: &Proposal,
+    address: &Addr,
+    taken: Uint128,
+) -> StdResult<Uint128> {
+    let overridden = OVERRIDDEN_POWER
+        .may_load(deps.storage, (proposal_id, address))?
+        .unwrap_or_default();
+    let power = get_voting_power_at_height(deps, address.clone(), prop.start_height)?
+        .checked_sub(overridden)?
+        .checked_sub(taken)?;
+    Ok(prop.voting_power_transform.apply(power, prop.total_weight))
+}
+
+/// The part of a delegate's voting power a delegator takes back by voting
+pub struct TakenPower {
+    pub delegate: Addr,
+    /// Power of the delegator's own stake
+    pub power: Uint128,
+    /// Weight the power added to the delegate's ballot
+    pub weight: Uint128,
+}
+
+/// Weight of the first ballot of `voter` on a proposal. Delegators voting
+/// themselves take the weight the power of their own stake adds to their
+/// delegate, so a delegate and their delegators never weigh more together
+/// than the delegate would alone. Also returns what the voter takes from
+/// their delegate if they delegate.
+pub fn get_ballot_weight(
+    deps: Deps,
+    proposal_id: u64,
+    prop: &Proposal,
+    voter: &Addr,
+) -> StdResult<(Uint128, Option<TakenPower>)> {
+    let weight = get_remaining_weight(deps, proposal_id, prop, voter, Uint128::zero())?;
+    let delegate = match get_delegation_at_height(deps, voter.clone(), prop.start_height)? {
+        Some(delegate) => delegate,
+        None => return Ok((weight, None)),
+    };
+    let staked = get_staker_power_at_height(deps, voter.clone(), prop.start_height)?;
+    let taken =
+        get_remaining_weight(deps, proposal_id, prop, &delegate, Uint128::zero())?.checked_sub(
+            get_remaining_weight(deps, proposal_id, prop, &delegate, staked)?,
+        )?;
+    Ok((
+        weight.checked_add(taken)?,
+        Some(TakenPower {
+            delegate,
+            power: staked,
+            weight: taken,
+        }),
+    ))
+}
+
+pub fn get_delegation_at_height(deps: Deps, address: Addr, height: u64) -> StdResult<Option<Addr>> {
+    let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
+
+    // Get the address voting power was delegated to at height
+    let res: DelegationAtHeightResponse = deps.querier.query_wasm_smart(
+        staking_contract,
+        &StakingContractQueryMsg::DelegationAtHeight {
+            address: address.to_string(),
+            height: Some(height),
+        },
+    )?;
+    Ok(res.delegate)
+}
+
+/// The part of the staking contract config the DAO reads. Staking contracts
+/// from before the snapshot strategy was configurable snapshot every block.
+#[derive(Deserialize)]
+struct StakingSnapshotConfig {
+    #[serde(default)]
+    snapshot_strategy: SnapshotStrategy,
+}
+
+/// Checkpoints staked balances at the current height if the staking
+/// contract only snapshots selected heights, so votes on a proposal created
+/// in this block can be weighed at its start height.
+pub fn get_staking_checkpoint_message(deps: Deps) -> StdResult<Vec<CosmosMsg>> {
+    let staking_contract = STAKING_CONTRACT.load(deps.storage)?;
+
+    let config: StakingSnapshotConfig = deps
+        .querier
+        .query_wasm_smart(&staking_contract, &StakingContractQueryMsg::GetConfig {})?;
+    if config.snapshot_strategy != (SnapshotStrategy::Selected {}) {
+        return Ok(vec![]);
+    }
+    let msg = WasmMsg::Execute {
+        contract_addr: staking_contract.into(),
+        msg: to_binary(&StakingContractExecuteMsg::AddCheckpoint {})?,
+        funds: vec![],
+    };
+    Ok(vec![msg.into()])
+}
+
+/// Start height of the oldest proposal that can still be voted on. Removes
+/// the proposals before it from OPEN_PROPOSAL_IDS, as they can't be voted on
+/// again.
+pub fn get_oldest_open_start_height(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+) -> StdResult<Option<u64>> {
+    loop {
+        let id = match OPEN_PROPOSAL_IDS
+            .keys(storage, None, None, Order::Ascending)
+            .next()
+        {
+            Some(id) => id?,
+            None => return Ok(None),
+        };
+        let prop = PROPOSALS.load(storage, id)?;
+        if prop.current_status(block) == Status::Open {
+            return Ok(Some(prop.start_height));
+        }
+        OPEN_PROPOSAL_IDS.remove(storage, id);
+    }
+}
+
+/// Removes the proposals of `proposer` that are no longer open from
+/// OPEN_PROPOSALS and returns how many are left.
+pub fn prune_open_proposals(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    proposer: &Addr,
+) -> StdResult<u64> {
+    let ids = OPEN_PROPOSALS
+        .prefix(proposer)
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<u64>>>()?;
+    let mut open = 0;
+    for id in ids {
+        let prop = PROPOSALS.load(storage, id)?;
+        if prop.current_status(block) == Status::Open {
+            open += 1;
+        } else {
+            OPEN_PROPOSALS.remove(storage, (proposer, id));
+        }
+    }
+    Ok(open)
 }
 
 pub fn map_proposal(
     block: &BlockInfo,
+    gov_token: &Addr,
     item: StdResult<(Vec<u8>, Proposal)>,
 ) -> StdResult<ProposalResponse> {
     let (key, prop) = item?;
     let status = prop.current_status(block);
+    let executable_at = prop.executable_at(block);
     let threshold = prop.threshold.to_response(prop.total_weight);
+    let deposit_denom = prop.deposit_token(gov_token);
     Ok(ProposalResponse {
         id: parse_id(&key)?,
         title: prop.title,
@@ -114,10 +409,105 @@ pub fn map_proposal(
         expires: prop.expires,
         threshold,
         deposit_amount: prop.deposit,
+        deposit_denom,
+        allow_revoting: prop.allow_revoting,
+        choices: prop.choices,
+        executable_at,
+        execution_error: prop.execution_error,
+        category: prop.category,
+        author: prop.author,
         start_height: prop.start_height,
     })
 }
 
+/// The token voter rewards are paid in
+pub fn get_voter_rewards_token(storage: &dyn Storage, rewards: &VoterRewards) -> StdResult<Addr> {
+    match &rewards.token {
+        Some(token) => Ok(token.clone()),
+        None => GOV_TOKEN.load(storage),
+    }
+}
+
+/// Takes the voter rewards of a proposal that is executed, rejected or closed
+/// from the pool. Does nothing if they were already allocated or nobody voted.
+pub fn allocate_voter_rewards(
+    storage: &mut dyn Storage,
+    rewards: &Option<VoterRewards>,
+    proposal_id: u64,
+    prop: &Proposal,
+) -> StdResult<()> {
+    let rewards = match rewards {
+        Some(rewards) => rewards,
+        None => return Ok(()),
+    };
+    let total_weight = prop.votes.total();
+    if total_weight.is_zero() || PROPOSAL_VOTER_REWARDS.has(storage, proposal_id) {
+        return Ok(());
+    }
+
+    let token = get_voter_rewards_token(storage, rewards)?;
+    let pool = VOTER_REWARD_POOL
+        .may_load(storage, &token)?
+        .unwrap_or_default();
+    let amount = pool.min(rewards.reward_per_proposal);
+    if amount.is_zero() {
+        return Ok(());
+    }
+    VOTER_REWARD_POOL.save(storage, &token, &(pool - amount))?;
+    PROPOSAL_VOTER_REWARDS.save(
+        storage,
+        proposal_id,
+        &ProposalVoterRewards {
+            token,
+            amount,
+            total_weight,
+        },
+    )
+}
+
+/// Returns a call from the DAO to itself that checks the voter reward reserve
+/// is still held, to be dispatched after the messages of a proposal
+pub fn voter_reward_reserve_check_msgs(
+    storage: &dyn Storage,
+    env: &Env,
+) -> StdResult<Vec<CosmosMsg>> {
+    let reserved = VOTER_REWARD_RESERVE
+        .range(storage, None, None, Order::Ascending)
+        .any(|item| matches!(item, Ok((_, amount)) if !amount.is_zero()));
+    if !reserved {
+        return Ok(vec![]);
+    }
+    Ok(vec![WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_binary(&ExecuteMsg::CheckVoterRewardReserve {})?,
+        funds: vec![],
+    }
+    .into()])
+}
+
+/// Returns the unclaimed reward of a voter for a proposal, if any
+pub fn get_voter_reward(
+    storage: &dyn Storage,
+    proposal_id: u64,
+    voter: &Addr,
+) -> StdResult<Option<(Addr, Uint128)>> {
+    let rewards = match PROPOSAL_VOTER_REWARDS.may_load(storage, proposal_id)? {
+        Some(rewards) => rewards,
+        None => return Ok(None),
+    };
+    if CLAIMED_VOTER_REWARDS.has(storage, (proposal_id, voter)) {
+        return Ok(None);
+    }
+    Ok(BALLOTS
+        .may_load(storage, (proposal_id, voter))?
+        .map(|ballot| {
+            let amount = rewards
+                .amount
+                .multiply_ratio(ballot.weight, rewards.total_weight);
+            (rewards.token, amount)
+        }))
+}
+
 pub fn get_and_check_limit(limit: Option<u32>, max: u32, default: u32) -> StdResult<u32> {
     match limit {
         Some(l) => {
@@ -0,0 +1,18 @@
+use cosmwasm_std::{Deps, StdResult, SubMsg, Uint128};
+pub use cw3_hooks::{remove_failed_hook, PROPOSAL_HOOK_REPLY_ID_OFFSET, VOTE_HOOK_REPLY_ID_OFFSET};
+
+use crate::query::Status;
+use crate::state::{PROPOSAL_HOOKS, VOTE_HOOKS};
+
+pub type ProposalHookMsg = cw3_hooks::ProposalHookMsg<Status>;
+pub type VoteHookMsg = cw3_hooks::VoteHookMsg<Uint128>;
+
+/// Prepares a `ProposalHookMsg` for every registered proposal hook
+pub fn proposal_hook_msgs(deps: Deps, msg: ProposalHookMsg) -> StdResult<Vec<SubMsg>> {
+    cw3_hooks::proposal_hook_msgs(deps, &PROPOSAL_HOOKS, msg)
+}
+
+/// Prepares a `VoteHookMsg` for every registered vote hook
+pub fn vote_hook_msgs(deps: Deps, msg: VoteHookMsg) -> StdResult<Vec<SubMsg>> {
+    cw3_hooks::vote_hook_msgs(deps, &VOTE_HOOKS, msg)
+}
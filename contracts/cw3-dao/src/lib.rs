@@ -1,6 +1,8 @@
 pub mod contract;
 mod error;
 pub mod helpers;
+pub mod hooks;
+mod migrations;
 pub mod msg;
 pub mod query;
 pub mod state;
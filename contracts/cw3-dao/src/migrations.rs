@@ -0,0 +1,68 @@
+use crate::msg::{DepositPolicy, DepositToken, VotingPowerTransform};
+use crate::state::{Config, CONFIG};
+use cosmwasm_std::{StdResult, Storage};
+
+/// Storage layout of cw3-dao v0.2.x
+pub(crate) mod v0_2 {
+    use crate::msg::Threshold;
+    use cosmwasm_std::Uint128;
+    use cw_storage_plus::Item;
+    use cw_utils::Duration;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+    pub struct Config {
+        pub name: String,
+        pub description: String,
+        pub threshold: Threshold,
+        pub max_voting_period: Duration,
+        pub proposal_deposit: Uint128,
+        pub refund_failed_proposals: Option<bool>,
+        pub image_url: Option<String>,
+        pub only_members_execute: bool,
+        pub automatically_add_cw20s: bool,
+    }
+
+    pub const CONFIG: Item<Config> = Item::new("config");
+}
+
+/// Migrates the config written by v0.2.x, where proposal deposits could
+/// only be paid in the governance token and were either refunded or kept
+/// when a proposal was rejected. Proposals are read as they were stored,
+/// the fields added since default to the behaviour of v0.2. Open proposals
+/// from v0.2 don't count towards the open proposal limit.
+pub fn migrate_v0_2(storage: &mut dyn Storage) -> StdResult<()> {
+    let config = v0_2::CONFIG.load(storage)?;
+    CONFIG.save(
+        storage,
+        &Config {
+            name: config.name,
+            description: config.description,
+            threshold: config.threshold,
+            max_voting_period: config.max_voting_period,
+            proposal_deposit: config.proposal_deposit,
+            proposal_deposit_token: DepositToken::GovToken {},
+            deposit_policy: match config.refund_failed_proposals {
+                Some(true) => DepositPolicy::Refund {},
+                _ => DepositPolicy::Keep {},
+            },
+            image_url: config.image_url,
+            only_members_execute: config.only_members_execute,
+            automatically_add_cw20s: config.automatically_add_cw20s,
+            allow_revoting: false,
+            timelock: None,
+            guardian: None,
+            cancelled_deposit_policy: DepositPolicy::Keep {},
+            catch_execution_failures: false,
+            min_proposer_stake: None,
+            proposer_allowlist: None,
+            max_open_proposals: None,
+            proposal_categories: vec![],
+            voter_rewards: None,
+            voting_power_transform: VotingPowerTransform::Linear {},
+            draft_duration: config.max_voting_period,
+        },
+    )?;
+
+    Ok(())
+}
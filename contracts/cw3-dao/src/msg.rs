@@ -1,13 +1,15 @@
 use crate::error::ContractError;
 use crate::query::ThresholdResponse;
-use crate::state::Config;
-use cosmwasm_std::{CosmosMsg, Decimal, Empty, Uint128};
-use cw20::{Cw20Coin, Cw20ReceiveMsg};
+use crate::state::{Config, MultipleChoiceOption};
+use cosmwasm_std::{from_binary, Addr, CosmosMsg, Decimal, Empty, Uint128, WasmMsg};
+use cw20::{Cw20Coin, Cw20ExecuteMsg, Cw20ReceiveMsg, Denom};
 use cw20_base::msg::InstantiateMarketingInfo;
 use cw3::Vote;
 use cw_utils::{Duration, Expiration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use stake_cw20::msg::SnapshotStrategy;
+use stake_cw20::state::isqrt;
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct InstantiateMsg {
@@ -23,12 +25,46 @@ pub struct InstantiateMsg {
     pub max_voting_period: Duration,
     /// Deposit required to make a proposal
     pub proposal_deposit_amount: Uint128,
-    /// Refund a proposal if it is rejected
-    pub refund_failed_proposals: Option<bool>,
+    /// Token the proposal deposit is paid in, defaults to the governance token
+    pub proposal_deposit_token: Option<DepositToken>,
+    /// What happens to the deposit of a rejected proposal, defaults to
+    /// keeping it in the DAO treasury
+    pub deposit_policy: Option<DepositPolicy>,
     /// Optional Image URL that is used by the contract
     pub image_url: Option<String>,
     pub only_members_execute: bool,
     pub automatically_add_cw20s: bool,
+    /// Allow voters to change their vote while a proposal is open,
+    /// defaults to false
+    pub allow_revoting: Option<bool>,
+    /// Delay between a proposal passing and when it can be executed,
+    /// must use the same unit as max_voting_period
+    pub timelock: Option<Duration>,
+    /// Address allowed to cancel proposals before they are executed
+    pub guardian: Option<String>,
+    /// What happens to the deposit of a proposal cancelled by the guardian,
+    /// defaults to keeping it in the DAO treasury
+    pub cancelled_deposit_policy: Option<DepositPolicy>,
+    /// Mark proposals whose messages fail as ExecutionFailed instead of
+    /// reverting their execution, defaults to false
+    pub catch_execution_failures: Option<bool>,
+    /// Staked balance required to create proposals, any nonzero stake is
+    /// enough if unset
+    pub min_proposer_stake: Option<MinProposerStake>,
+    /// Only these addresses may create proposals if set
+    pub proposer_allowlist: Option<Vec<String>>,
+    /// Maximum number of open proposals an address can have at once
+    pub max_open_proposals: Option<u64>,
+    /// Voting rules for specific kinds of proposals. A proposal matching
+    /// several categories uses the one with the strictest threshold.
+    pub proposal_categories: Option<Vec<ProposalCategory>>,
+    /// Rewards voters from a pool funded with treasury tokens
+    pub voter_rewards: Option<VoterRewards>,
+    /// How voting power translates into ballot weight, defaults to linear
+    pub voting_power_transform: Option<VotingPowerTransform>,
+    /// How long drafts can be amended and sponsored, defaults to
+    /// max_voting_period
+    pub draft_duration: Option<Duration>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -43,6 +79,8 @@ pub enum GovTokenMsg {
         initial_dao_balance: Option<Uint128>,
         msg: GovTokenInstantiateMsg,
         unstaking_duration: Option<Duration>,
+        /// Defaults to snapshotting staked balances at every block
+        snapshot_strategy: Option<SnapshotStrategy>,
     },
     /// Use an existing cw20 token
     UseExistingCw20 {
@@ -50,6 +88,8 @@ pub enum GovTokenMsg {
         label: String,
         stake_contract_code_id: u64,
         unstaking_duration: Option<Duration>,
+        /// Defaults to snapshotting staked balances at every block
+        snapshot_strategy: Option<SnapshotStrategy>,
     },
 }
 
@@ -63,6 +103,222 @@ pub struct GovTokenInstantiateMsg {
     pub marketing: Option<InstantiateMarketingInfo>,
 }
 
+/// The token proposal deposits are paid in.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositToken {
+    /// The DAO's cw20 governance token. Proposers must give the DAO an
+    /// allowance to transfer the deposit before proposing.
+    GovToken {},
+    /// A native denom, sent as funds along with the proposal.
+    Native { denom: String },
+}
+
+impl DepositToken {
+    /// Resolves the deposit token to the denom a new proposal is charged in
+    pub fn to_denom(&self, gov_token: &Addr) -> Denom {
+        match self {
+            DepositToken::GovToken {} => Denom::Cw20(gov_token.clone()),
+            DepositToken::Native { denom } => Denom::Native(denom.clone()),
+        }
+    }
+}
+
+/// What happens to the deposit of a proposal that is rejected.
+///
+/// Proposals rejected by a majority of `Vote::Veto` always forfeit their
+/// deposit. Under the refunding policies a forfeited deposit is kept in
+/// the DAO treasury.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositPolicy {
+    /// Always refund the deposit to the proposer
+    Refund {},
+    /// Refund the deposit only if the proposal reached quorum. Proposals
+    /// with an absolute percentage threshold have no quorum and are
+    /// always refunded.
+    RefundIfQuorum {},
+    /// Keep the deposit in the DAO treasury
+    Keep {},
+    /// Burn the deposit
+    Burn {},
+    /// Send the deposit to the staking contract as `ReceiveMsg::Fund`,
+    /// rewarding stakers. Only valid for governance token deposits.
+    SendToStaking {},
+}
+
+/// The staked balance an address needs to create proposals
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MinProposerStake {
+    /// A fixed amount of staked tokens
+    Absolute { amount: Uint128 },
+    /// A percentage of the total staked supply
+    Percentage { percentage: Decimal },
+}
+
+impl MinProposerStake {
+    pub fn amount(&self, total_staked: Uint128) -> Uint128 {
+        match self {
+            MinProposerStake::Absolute { amount } => *amount,
+            MinProposerStake::Percentage { percentage } => *percentage * total_staked,
+        }
+    }
+}
+
+/// Rewards paid to voters from the voter reward pool. The pool is funded by
+/// sending tokens to the DAO with `ReceiveMsg::FundVoterRewards`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct VoterRewards {
+    /// The cw20 token rewards are paid in, defaults to the governance token
+    pub token: Option<Addr>,
+    /// Taken from the pool when a proposal is executed or closed, and split
+    /// between its voters in proportion to the weight of their ballot
+    pub reward_per_proposal: Uint128,
+}
+
+/// How the voting power of a voter translates into the weight of their
+/// ballot. Delegators who vote themselves take the part of their delegate's
+/// weight their stake adds, so ballots never weigh more than the total
+/// weight of the proposal.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum VotingPowerTransform {
+    /// Ballots weigh the voting power of the voter
+    Linear {},
+    /// Ballots weigh the square root of the voting power of the voter. The
+    /// total weight is the sum of the square roots of the voting power of
+    /// every address, which the staking contract must track.
+    SquareRoot {},
+    /// Ballots weigh at most `percentage` of the total weight, the total
+    /// voting power when the proposal was created. Capped ballots may not
+    /// add up to the total weight, so high thresholds can be out of reach.
+    Capped { percentage: Decimal },
+}
+
+impl Default for VotingPowerTransform {
+    fn default() -> Self {
+        VotingPowerTransform::Linear {}
+    }
+}
+
+impl VotingPowerTransform {
+    pub fn validate(&self) -> Result<(), ContractError> {
+        match self {
+            VotingPowerTransform::Capped { percentage }
+                if percentage.is_zero() || *percentage > Decimal::one() =>
+            {
+                Err(ContractError::InvalidVotingPowerCap {})
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Weight of a ballot cast with `power` on a proposal of `total_weight`
+    pub fn apply(&self, power: Uint128, total_weight: Uint128) -> Uint128 {
+        match self {
+            VotingPowerTransform::Linear {} => power,
+            VotingPowerTransform::SquareRoot {} => isqrt(power),
+            VotingPowerTransform::Capped { percentage } => power.min(*percentage * total_weight),
+        }
+    }
+}
+
+/// A kind of proposal, recognised by the messages it would execute.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalKind {
+    /// Sends native tokens out of the treasury, calls or instantiates a
+    /// contract with funds, moves, mints or burns cw20 tokens, or stakes
+    /// and withdraws staking rewards of native tokens
+    TreasurySpend {},
+    /// Calls the DAO itself, e.g. `UpdateConfig` or `UpdateStakingContract`
+    ConfigChange {},
+    /// Migrates a contract or changes its admin
+    Migration {},
+}
+
+impl ProposalKind {
+    /// Whether executing `msg` makes a proposal of this kind
+    pub fn matches(&self, msg: &CosmosMsg<Empty>, dao: &Addr) -> bool {
+        match (self, msg) {
+            (
+                ProposalKind::TreasurySpend {},
+                CosmosMsg::Bank(_) | CosmosMsg::Staking(_) | CosmosMsg::Distribution(_),
+            ) => true,
+            (
+                ProposalKind::TreasurySpend {},
+                CosmosMsg::Wasm(WasmMsg::Execute { funds, msg, .. }),
+            ) => {
+                !funds.is_empty()
+                    || matches!(
+                        from_binary(msg),
+                        Ok(Cw20ExecuteMsg::Transfer { .. }
+                            | Cw20ExecuteMsg::TransferFrom { .. }
+                            | Cw20ExecuteMsg::Send { .. }
+                            | Cw20ExecuteMsg::SendFrom { .. }
+                            | Cw20ExecuteMsg::IncreaseAllowance { .. }
+                            | Cw20ExecuteMsg::Mint { .. }
+                            | Cw20ExecuteMsg::Burn { .. }
+                            | Cw20ExecuteMsg::BurnFrom { .. })
+                    )
+            }
+            (
+                ProposalKind::TreasurySpend {},
+                CosmosMsg::Wasm(WasmMsg::Instantiate { funds, .. }),
+            ) => !funds.is_empty(),
+            (
+                ProposalKind::ConfigChange {},
+                CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }),
+            ) => contract_addr == dao.as_str(),
+            (
+                ProposalKind::Migration {},
+                CosmosMsg::Wasm(
+                    WasmMsg::Migrate { .. }
+                    | WasmMsg::UpdateAdmin { .. }
+                    | WasmMsg::ClearAdmin { .. },
+                ),
+            ) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Voting rules applied instead of the DAO defaults to proposals of a
+/// given kind.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ProposalCategory {
+    pub kind: ProposalKind,
+    pub threshold: Threshold,
+    /// Proposals of this kind stay open for at least this long
+    pub min_voting_period: Option<Duration>,
+    pub max_voting_period: Duration,
+    /// Deposit required to make a proposal of this kind, defaults to the
+    /// DAO's proposal deposit
+    pub deposit: Option<Uint128>,
+}
+
+impl ProposalCategory {
+    /// The min voting period must not exceed the max voting period, and
+    /// both must use the same unit as the timelock.
+    pub fn validate(&self, timelock: Option<Duration>) -> Result<(), ContractError> {
+        self.threshold.validate()?;
+        match (self.min_voting_period, self.max_voting_period) {
+            (None, _) => {}
+            (Some(Duration::Height(min)), Duration::Height(max))
+            | (Some(Duration::Time(min)), Duration::Time(max))
+                if min <= max => {}
+            _ => return Err(ContractError::InvalidVotingPeriods {}),
+        }
+        match (timelock, self.max_voting_period) {
+            (None, _)
+            | (Some(Duration::Height(_)), Duration::Height(_))
+            | (Some(Duration::Time(_)), Duration::Time(_)) => Ok(()),
+            _ => Err(ContractError::InvalidTimelock {}),
+        }
+    }
+}
+
 /// This defines the different ways tallies can happen.
 ///
 /// The total_weight used for calculating success as well as the weights of each
@@ -102,6 +358,16 @@ impl Threshold {
         }
     }
 
+    /// How hard this threshold is to reach, as the share of the total weight
+    /// that must vote yes if everybody votes followed by the share that
+    /// must vote at all
+    pub fn strictness(&self) -> (Decimal, Decimal) {
+        match self {
+            Threshold::AbsolutePercentage { percentage } => (*percentage, *percentage),
+            Threshold::ThresholdQuorum { threshold, quorum } => (*threshold, *quorum),
+        }
+    }
+
     /// Creates a response from the saved data, just missing the total_weight info
     pub fn to_response(&self, total_weight: Uint128) -> ThresholdResponse {
         match self.clone() {
@@ -140,23 +406,51 @@ pub struct ProposeMsg {
     pub latest: Option<Expiration>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProposeMultipleChoiceMsg {
+    pub title: String,
+    pub description: String,
+    /// The options to choose from, a "none of the above" option is added
+    /// after them
+    pub choices: Vec<MultipleChoiceOption>,
+    pub latest: Option<Expiration>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct VoteMsg {
     pub proposal_id: u64,
     pub vote: Vote,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VoteMultipleChoiceMsg {
+    pub proposal_id: u64,
+    /// Index of the chosen option
+    pub choice: u32,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     /// Makes a new proposal
     Propose(ProposeMsg),
     /// Vote on an open proposal
     Vote(VoteMsg),
+    /// Makes a new proposal where voters pick one of several options
+    ProposeMultipleChoice(ProposeMultipleChoiceMsg),
+    /// Vote for an option of an open multiple choice proposal
+    VoteMultipleChoice(VoteMultipleChoiceMsg),
     /// Execute a passed proposal
     Execute { proposal_id: u64 },
     /// Close a failed proposal
     Close { proposal_id: u64 },
+    /// Cancel an open or passed proposal before it is executed
+    /// (can only be called by the guardian)
+    Cancel { proposal_id: u64, reason: String },
+    /// Withdraw an open proposal nobody voted on yet and refund its
+    /// deposit (can only be called by the proposer)
+    Withdraw { proposal_id: u64 },
     /// Pauses DAO governance (can only be called by DAO contract)
     PauseDAO { expiration: Expiration },
     /// Update DAO config (can only be called by DAO contract)
@@ -172,6 +466,59 @@ pub enum ExecuteMsg {
     /// Wrapper called for automatically adding cw20s
     /// to our tracked balances
     Receive(Cw20ReceiveMsg),
+    /// Removes staking contract checkpoints older than the oldest open
+    /// proposal, along with the history of at most `limit` addresses after
+    /// `start_after`. Only used when the staking contract snapshots selected
+    /// heights, can be called by anyone.
+    PruneStakingCheckpoints {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Registers a contract to receive a `ProposalHookMsg` when proposals
+    /// are created or change status (can only be called by DAO contract)
+    AddProposalHook { address: String },
+    /// Unregisters a proposal hook (can only be called by DAO contract)
+    RemoveProposalHook { address: String },
+    /// Registers a contract to receive a `VoteHookMsg` for every ballot
+    /// cast (can only be called by DAO contract)
+    AddVoteHook { address: String },
+    /// Unregisters a vote hook (can only be called by DAO contract)
+    RemoveVoteHook { address: String },
+    /// Claims the voter rewards of the sender for these proposals
+    ClaimVoterRewards { proposal_ids: Vec<u64> },
+    /// Creates a draft proposal that anyone can make without a deposit. It
+    /// enters voting once it is sponsored.
+    CreateDraft(ProposeMsg),
+    /// Changes an unsponsored draft (can only be called by its author)
+    AmendDraft {
+        draft_id: u64,
+        title: Option<String>,
+        description: Option<String>,
+        msgs: Option<Vec<CosmosMsg<Empty>>>,
+    },
+    /// Turns a draft into a proposal, the sponsor must be allowed to
+    /// propose and pays the deposit
+    SponsorDraft { draft_id: u64 },
+    /// Deletes a draft. Its author can do so at any time, anyone else once
+    /// the draft expired.
+    RemoveDraft { draft_id: u64 },
+    /// Dispatches the messages of the proposal being executed, so that they
+    /// succeed or fail together (can only be called by DAO contract while
+    /// executing that proposal)
+    ExecuteProposalMsgs { proposal_id: u64 },
+    /// Fails if the DAO holds fewer tokens than are reserved for voter
+    /// rewards, runs after the messages of every executed proposal (can only
+    /// be called by DAO contract)
+    CheckVoterRewardReserve {},
+}
+
+/// Messages sent along with cw20 tokens to the DAO
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Adds the tokens to the voter reward pool. Only the voter rewards
+    /// token is accepted.
+    FundVoterRewards {},
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -215,13 +562,38 @@ pub enum QueryMsg {
     },
     /// Return list of cw20 Tokens associated with the DAO Treasury
     Cw20TokenList {},
+    /// Returns HooksResponse
+    ProposalHooks {},
+    /// Returns HooksResponse
+    VoteHooks {},
+    /// Returns the unclaimed rewards of a voter as VoterRewardsResponse
+    VoterRewards {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the unallocated tokens of the voter reward pool (Uint128)
+    VoterRewardPool {},
+    /// Returns DraftResponse
+    Draft { draft_id: u64 },
+    /// Returns DraftListResponse, skipping expired drafts
+    ListDrafts {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrateMsg {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use cosmwasm_std::to_vec;
+    use cosmwasm_std::{
+        coin, coins, to_binary, to_vec, BankMsg, Coin, DistributionMsg, StakingMsg,
+    };
 
     #[test]
     fn vote_encoding() {
@@ -242,6 +614,87 @@ mod tests {
         assert_eq!(r#"{"vote":{"proposal_id":17,"vote":"no"}}"#, json.as_str());
     }
 
+    #[test]
+    fn treasury_spend_matches() {
+        let dao = Addr::unchecked("dao");
+        let kind = ProposalKind::TreasurySpend {};
+        let execute = |msg: &Cw20ExecuteMsg, funds: Vec<Coin>| -> CosmosMsg<Empty> {
+            WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                msg: to_binary(msg).unwrap(),
+                funds,
+            }
+            .into()
+        };
+        let instantiate = |funds: Vec<Coin>| -> CosmosMsg<Empty> {
+            WasmMsg::Instantiate {
+                admin: None,
+                code_id: 1,
+                msg: to_binary(&Empty {}).unwrap(),
+                funds,
+                label: "spend".to_string(),
+            }
+            .into()
+        };
+
+        let spends = vec![
+            BankMsg::Burn {
+                amount: coins(1, "ustars"),
+            }
+            .into(),
+            StakingMsg::Delegate {
+                validator: "validator".to_string(),
+                amount: coin(1, "ustars"),
+            }
+            .into(),
+            DistributionMsg::WithdrawDelegatorReward {
+                validator: "validator".to_string(),
+            }
+            .into(),
+            instantiate(coins(1, "ustars")),
+            execute(
+                &Cw20ExecuteMsg::Mint {
+                    recipient: "somebody".to_string(),
+                    amount: Uint128::new(1),
+                },
+                vec![],
+            ),
+            execute(
+                &Cw20ExecuteMsg::TransferFrom {
+                    owner: "owner".to_string(),
+                    recipient: "somebody".to_string(),
+                    amount: Uint128::new(1),
+                },
+                vec![],
+            ),
+            execute(
+                &Cw20ExecuteMsg::Burn {
+                    amount: Uint128::new(1),
+                },
+                vec![],
+            ),
+        ];
+        for msg in &spends {
+            assert!(kind.matches(msg, &dao), "{:?}", msg);
+        }
+
+        // Contracts can be instantiated and tokens locked down for free
+        let free = vec![
+            instantiate(vec![]),
+            execute(
+                &Cw20ExecuteMsg::DecreaseAllowance {
+                    spender: "somebody".to_string(),
+                    amount: Uint128::new(1),
+                    expires: None,
+                },
+                vec![],
+            ),
+        ];
+        for msg in &free {
+            assert!(!kind.matches(msg, &dao), "{:?}", msg);
+        }
+    }
+
     #[test]
     fn validate_percentage() {
         // 0 is never a valid percentage
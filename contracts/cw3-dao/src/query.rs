@@ -1,7 +1,8 @@
-use crate::state::{Config, Votes};
+use crate::msg::ProposalKind;
+use crate::state::{Config, MultipleChoiceOption, Votes};
 use cosmwasm_std::{Addr, CosmosMsg, Decimal, Empty, Uint128};
-use cw20::Cw20CoinVerified;
-use cw3::{Status, Vote};
+use cw20::{Cw20CoinVerified, Denom};
+use cw3::Vote;
 use cw_utils::Expiration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -79,6 +80,32 @@ pub enum ThresholdResponse {
     },
 }
 
+/// The status of a proposal. This is `cw3::Status` with extra statuses for
+/// proposals cancelled by the guardian, withdrawn by their proposer or whose
+/// execution failed.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum Status {
+    /// proposal was created, but voting has not yet begun for whatever reason
+    Pending = 1,
+    /// you can vote on this
+    Open = 2,
+    /// voting is over and it did not pass
+    Rejected = 3,
+    /// voting is over and it did pass, but has not yet executed
+    Passed = 4,
+    /// voting is over it passed, and the proposal was executed
+    Executed = 5,
+    /// the guardian cancelled the proposal before it was executed
+    Cancelled = 6,
+    /// the proposal was executed but one of its messages failed, it can be
+    /// executed again or closed
+    ExecutionFailed = 7,
+    /// the proposer withdrew the proposal before anybody voted on it
+    Withdrawn = 8,
+}
+
 /// Note, if you are storing custom messages in the proposal,
 /// the querier needs to know what possible custom message types
 /// those are in order to parse the response
@@ -99,6 +126,21 @@ where
     /// that the generic `Threshold{}` query does not provide valid information for existing proposals.
     pub threshold: ThresholdResponse,
     pub deposit_amount: Uint128,
+    /// The token the deposit was paid in, refunds are made in the same token
+    pub deposit_denom: Denom,
+    /// Whether votes can be changed while the proposal is open
+    pub allow_revoting: bool,
+    /// Options of a multiple choice proposal, the last one being "none of
+    /// the above". Empty for yes/no proposals.
+    pub choices: Vec<MultipleChoiceOption>,
+    /// When the proposal can be executed if it passed with a timelock
+    pub executable_at: Option<Expiration>,
+    /// Error returned by the last failed execution of the proposal
+    pub execution_error: Option<String>,
+    /// The category whose voting rules apply, None for the DAO defaults
+    pub category: Option<ProposalKind>,
+    /// Author of the draft the proposal was sponsored from
+    pub author: Option<Addr>,
     /// The block height the proposal was created at. This can be
     /// cross referenced with staked_balance_at_height queries to
     /// determine an addresses's voting power for this proposal.
@@ -130,6 +172,8 @@ pub struct VoteTallyResponse {
     pub total_weight: Uint128,
     /// Tally of the different votes
     pub votes: Votes,
+    /// Tally of each option of a multiple choice proposal
+    pub choice_votes: Vec<Uint128>,
 }
 
 /// Returns the vote (opinion as well as weight counted) as well as
@@ -138,6 +182,8 @@ pub struct VoteTallyResponse {
 pub struct VoteInfo {
     pub voter: String,
     pub vote: Vote,
+    /// The option voted for on a multiple choice proposal
+    pub choice: Option<u32>,
     pub weight: Uint128,
 }
 
@@ -178,3 +224,38 @@ pub struct Cw20BalancesResponse {
 pub struct TokenListResponse {
     pub token_list: Vec<Addr>,
 }
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct VoterReward {
+    pub proposal_id: u64,
+    pub token: Addr,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct VoterRewardsResponse {
+    pub rewards: Vec<VoterReward>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct HooksResponse {
+    pub hooks: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct DraftResponse {
+    pub id: u64,
+    pub title: String,
+    pub description: String,
+    pub author: Addr,
+    pub msgs: Vec<CosmosMsg<Empty>>,
+    /// Expiration of the proposal once the draft is sponsored
+    pub latest: Option<Expiration>,
+    /// The draft can't be amended or sponsored after this
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct DraftListResponse {
+    pub drafts: Vec<DraftResponse>,
+}
@@ -1,12 +1,20 @@
-use crate::msg::Threshold;
+use crate::error::ContractError;
+use crate::msg::{
+    DepositPolicy, DepositToken, MinProposerStake, ProposalCategory, ProposalKind, Threshold,
+    VoterRewards, VotingPowerTransform,
+};
+use crate::query::Status;
 use cosmwasm_std::{
     Addr, BlockInfo, CosmosMsg, Decimal, Empty, StdError, StdResult, Storage, Uint128,
 };
-use cw3::{Status, Vote};
+use cw20::Denom;
+use cw3::Vote;
+use cw_controllers::Hooks;
 use cw_storage_plus::{Item, Map};
 use cw_utils::{Duration, Expiration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use stake_cw20::msg::SnapshotStrategy;
 use std::convert::TryInto;
 
 // we multiply by this when calculating needed_votes in order to round up properly
@@ -20,10 +28,97 @@ pub struct Config {
     pub threshold: Threshold,
     pub max_voting_period: Duration,
     pub proposal_deposit: Uint128,
-    pub refund_failed_proposals: Option<bool>,
+    pub proposal_deposit_token: DepositToken,
+    pub deposit_policy: DepositPolicy,
     pub image_url: Option<String>,
     pub only_members_execute: bool,
     pub automatically_add_cw20s: bool,
+    /// Whether voters may change their vote on new proposals
+    pub allow_revoting: bool,
+    /// Delay between a proposal passing and when it can be executed
+    pub timelock: Option<Duration>,
+    /// Address allowed to cancel proposals before they are executed
+    pub guardian: Option<Addr>,
+    /// What happens to the deposit of a proposal cancelled by the guardian
+    pub cancelled_deposit_policy: DepositPolicy,
+    /// Whether proposal messages are dispatched as submessages so that a
+    /// failing proposal is marked ExecutionFailed instead of reverting
+    pub catch_execution_failures: bool,
+    /// Staked balance required to create proposals
+    pub min_proposer_stake: Option<MinProposerStake>,
+    /// Only these addresses may create proposals if set
+    pub proposer_allowlist: Option<Vec<Addr>>,
+    /// Maximum number of open proposals an address can have at once
+    pub max_open_proposals: Option<u64>,
+    /// Voting rules for specific kinds of proposals
+    pub proposal_categories: Vec<ProposalCategory>,
+    /// Rewards voters from a pool funded with treasury tokens
+    pub voter_rewards: Option<VoterRewards>,
+    /// How voting power translates into ballot weight
+    pub voting_power_transform: VotingPowerTransform,
+    /// How long drafts can be amended and sponsored
+    pub draft_duration: Duration,
+}
+
+impl Config {
+    /// Deposits can only fund the staking contract if they are paid in the
+    /// governance token.
+    pub fn validate_deposit_policy(&self) -> Result<(), ContractError> {
+        for policy in [&self.deposit_policy, &self.cancelled_deposit_policy] {
+            if let (DepositPolicy::SendToStaking {}, DepositToken::Native { .. }) =
+                (policy, &self.proposal_deposit_token)
+            {
+                return Err(ContractError::InvalidDepositPolicy {});
+            }
+        }
+        Ok(())
+    }
+
+    /// The timelock is added to the expiration of proposals passing at the
+    /// end of their voting period, so both must use the same unit.
+    pub fn validate_timelock(&self) -> Result<(), ContractError> {
+        match (self.timelock, self.max_voting_period) {
+            (None, _)
+            | (Some(Duration::Height(_)), Duration::Height(_))
+            | (Some(Duration::Time(_)), Duration::Time(_)) => Ok(()),
+            _ => Err(ContractError::InvalidTimelock {}),
+        }
+    }
+
+    pub fn validate_proposal_categories(&self) -> Result<(), ContractError> {
+        for (i, category) in self.proposal_categories.iter().enumerate() {
+            category.validate(self.timelock)?;
+            if self.proposal_categories[..i]
+                .iter()
+                .any(|other| other.kind == category.kind)
+            {
+                return Err(ContractError::DuplicateProposalCategory {});
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the category with the strictest threshold among those
+    /// matching any of `msgs`, or None if the DAO defaults apply
+    pub fn proposal_category<'a>(
+        &self,
+        msgs: impl IntoIterator<Item = &'a CosmosMsg<Empty>>,
+        dao: &Addr,
+    ) -> Option<&ProposalCategory> {
+        let msgs: Vec<_> = msgs.into_iter().collect();
+        let mut strictest: Option<&ProposalCategory> = None;
+        for category in &self.proposal_categories {
+            if !msgs.iter().any(|msg| category.kind.matches(msg, dao)) {
+                continue;
+            }
+            if strictest.is_none_or(|strictest| {
+                category.threshold.strictness() > strictest.threshold.strictness()
+            }) {
+                strictest = Some(category);
+            }
+        }
+        strictest
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -41,8 +136,69 @@ pub struct Proposal {
     pub total_weight: Uint128,
     /// summary of existing votes
     pub votes: Votes,
-    /// Amount of the native governance token required for voting
+    /// Amount deposited by the proposer
     pub deposit: Uint128,
+    /// The token the deposit was paid in. Not recorded by v0.2, where
+    /// deposits were paid in the governance token.
+    #[serde(default)]
+    pub deposit_denom: Option<Denom>,
+    /// Whether votes can be changed while the proposal is open
+    #[serde(default)]
+    pub allow_revoting: bool,
+    /// Options of a multiple choice proposal, the last one being "none of
+    /// the above". Empty for yes/no proposals.
+    #[serde(default)]
+    pub choices: Vec<MultipleChoiceOption>,
+    /// Weight of the votes cast for each option of a multiple choice proposal
+    #[serde(default)]
+    pub choice_votes: Vec<Uint128>,
+    /// Delay between the proposal passing and when it can be executed
+    #[serde(default)]
+    pub timelock: Option<Duration>,
+    /// Set when the proposal passes before it expires
+    #[serde(default)]
+    pub executable_at: Option<Expiration>,
+    /// Error returned by the last failed execution of the proposal
+    #[serde(default)]
+    pub execution_error: Option<String>,
+    /// The category whose voting rules apply, None for the DAO defaults
+    #[serde(default)]
+    pub category: Option<ProposalKind>,
+    /// How voting power translates into ballot weight
+    #[serde(default)]
+    pub voting_power_transform: VotingPowerTransform,
+    /// Author of the draft the proposal was sponsored from
+    #[serde(default)]
+    pub author: Option<Addr>,
+    /// The proposal can't pass before this, set when its category has a
+    /// min voting period
+    #[serde(default)]
+    pub min_voting_end: Option<Expiration>,
+    /// Set when votes reject the proposal before it expires, until it is
+    /// closed and its deposit settled
+    #[serde(default)]
+    pub deposit_pending: bool,
+}
+
+/// A proposal that has not entered voting yet
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Draft {
+    pub title: String,
+    pub description: String,
+    pub author: Addr,
+    pub msgs: Vec<CosmosMsg<Empty>>,
+    /// Expiration of the proposal once the draft is sponsored
+    pub latest: Option<Expiration>,
+    /// The draft can't be amended or sponsored after this
+    pub expires: Expiration,
+}
+
+/// An option of a multiple choice proposal, with the messages dispatched
+/// if it wins
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct MultipleChoiceOption {
+    pub description: String,
+    pub msgs: Vec<CosmosMsg<Empty>>,
 }
 
 // weight of votes for each option
@@ -78,14 +234,67 @@ impl Votes {
             Vote::Veto => self.veto += weight,
         }
     }
+
+    pub fn remove_vote(&mut self, vote: Vote, weight: Uint128) {
+        match vote {
+            Vote::Yes => self.yes -= weight,
+            Vote::Abstain => self.abstain -= weight,
+            Vote::No => self.no -= weight,
+            Vote::Veto => self.veto -= weight,
+        }
+    }
+}
+
+/// Expiration `duration` after `expiration`
+fn delay(expiration: Expiration, duration: Duration) -> Expiration {
+    match (expiration, duration) {
+        (Expiration::AtHeight(height), Duration::Height(delay)) => {
+            Expiration::AtHeight(height + delay)
+        }
+        (Expiration::AtTime(time), Duration::Time(delay)) => {
+            Expiration::AtTime(time.plus_seconds(delay))
+        }
+        // units are validated to match, so this should never happen
+        _ => Expiration::Never {},
+    }
 }
 
 impl Proposal {
+    /// The token the deposit was paid in
+    pub fn deposit_token(&self, gov_token: &Addr) -> Denom {
+        self.deposit_denom
+            .clone()
+            .unwrap_or_else(|| Denom::Cw20(gov_token.clone()))
+    }
+
     /// current_status is non-mutable and returns what the status should be.
     /// (designed for queries)
     pub fn current_status(&self, block: &BlockInfo) -> Status {
+        let status = self.tally_status(block);
+        // a min voting period keeps the proposal open until it has elapsed
+        if status == Status::Passed
+            && self.status == Status::Open
+            && matches!(self.min_voting_end, Some(end) if !end.is_expired(block))
+        {
+            return Status::Open;
+        }
+        status
+    }
+
+    /// The status the votes alone would give the proposal
+    fn tally_status(&self, block: &BlockInfo) -> Status {
         let mut status = self.status;
 
+        // votes can still change while revoting is allowed, so the outcome
+        // is only known once the proposal expires
+        if status == Status::Open && self.allow_revoting && !self.expires.is_expired(block) {
+            return status;
+        }
+
+        if status == Status::Open && self.is_multiple_choice() {
+            return self.multiple_choice_status(block);
+        }
+
         // if open, check if voting is passed or timed out
         if status == Status::Open && self.is_passed(block) {
             status = Status::Passed;
@@ -101,6 +310,32 @@ impl Proposal {
     /// (designed for handler logic)
     pub fn update_status(&mut self, block: &BlockInfo) {
         self.status = self.current_status(block);
+        if self.status == Status::Passed && self.executable_at.is_none() {
+            self.executable_at = self.executable_at(block);
+        }
+    }
+
+    /// Returns when a passed proposal can be executed, or None if it has
+    /// no timelock or has not passed.
+    pub fn executable_at(&self, block: &BlockInfo) -> Option<Expiration> {
+        let timelock = self.timelock?;
+        if self.executable_at.is_some() {
+            return self.executable_at;
+        }
+        if self.current_status(block) != Status::Passed {
+            return None;
+        }
+        // proposals passing at the end of their voting period are timelocked
+        // from their expiration, those held open by a min voting period from
+        // its end, and others from the block they passed in
+        if self.expires.is_expired(block) {
+            return Some(delay(self.expires, timelock));
+        }
+        match self.min_voting_end {
+            // a vote that passes the proposal updates its status first
+            Some(end) if self.status == Status::Open => Some(delay(end, timelock)),
+            _ => Some(timelock.after(block)),
+        }
     }
 
     /// Helper function to check if a certain vote count has reached threshold.
@@ -150,6 +385,101 @@ impl Proposal {
     pub fn is_rejected(&self, block: &BlockInfo) -> bool {
         self.does_vote_count_reach_threshold(self.votes.no, block)
     }
+
+    pub fn is_multiple_choice(&self) -> bool {
+        !self.choices.is_empty()
+    }
+
+    /// Multiple choice proposals need the quorum, or the absolute percentage,
+    /// of the total weight to vote. The option with the most votes then wins,
+    /// and the proposal is rejected if that is "none of the above" or if there
+    /// is a tie. The proposal passes early once an option has a majority of
+    /// the total weight, as it can no longer be overtaken.
+    fn multiple_choice_status(&self, block: &BlockInfo) -> Status {
+        let expired = self.expires.is_expired(block);
+        let quorum = match self.threshold {
+            Threshold::AbsolutePercentage { percentage } => percentage,
+            Threshold::ThresholdQuorum { quorum, .. } => quorum,
+        };
+        if self.votes.total() < votes_needed(self.total_weight, quorum) {
+            return match expired {
+                true => Status::Rejected,
+                false => Status::Open,
+            };
+        }
+
+        match self.leading_choice() {
+            Some((choice, weight)) if expired || weight + weight > self.total_weight => {
+                if choice == self.choices.len() - 1 {
+                    Status::Rejected
+                } else {
+                    Status::Passed
+                }
+            }
+            _ if expired => Status::Rejected,
+            _ => Status::Open,
+        }
+    }
+
+    /// returns the index and weight of the option with the most votes, or
+    /// None if there is a tie
+    pub fn leading_choice(&self) -> Option<(usize, Uint128)> {
+        let max = *self.choice_votes.iter().max()?;
+        let mut leaders = self
+            .choice_votes
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| **w == max);
+        let (choice, _) = leaders.next()?;
+        match leaders.next() {
+            Some(_) => None,
+            None => Some((choice, max)),
+        }
+    }
+
+    /// The messages dispatched when the proposal is executed, those of the
+    /// winning option for multiple choice proposals
+    pub fn executable_msgs(&self) -> Vec<CosmosMsg<Empty>> {
+        if !self.is_multiple_choice() {
+            return self.msgs.clone();
+        }
+        match self.leading_choice() {
+            Some((choice, _)) => self.choices[choice].msgs.clone(),
+            None => vec![],
+        }
+    }
+
+    /// Adds weight to the tally of a ballot's vote, and to its option on
+    /// multiple choice proposals
+    pub fn add_ballot_weight(&mut self, ballot: &Ballot, weight: Uint128) {
+        self.votes.add_vote(ballot.vote, weight);
+        if let Some(choice) = ballot.choice {
+            self.choice_votes[choice as usize] += weight;
+        }
+    }
+
+    pub fn remove_ballot_weight(&mut self, ballot: &Ballot, weight: Uint128) {
+        self.votes.remove_vote(ballot.vote, weight);
+        if let Some(choice) = ballot.choice {
+            self.choice_votes[choice as usize] -= weight;
+        }
+    }
+
+    /// returns true if enough votes were cast to meet the quorum. Absolute
+    /// percentage thresholds have no quorum, so this is always true for them.
+    pub fn reached_quorum(&self) -> bool {
+        match self.threshold {
+            Threshold::AbsolutePercentage { .. } => true,
+            Threshold::ThresholdQuorum { quorum, .. } => {
+                self.votes.total() >= votes_needed(self.total_weight, quorum)
+            }
+        }
+    }
+
+    /// returns true if a majority of the votes cast were veto votes
+    pub fn is_vetoed(&self) -> bool {
+        self.votes.veto > self.votes.total() - self.votes.veto
+    }
 }
 
 // this is a helper function so Decimal works with u64 rather than Uint128
@@ -157,7 +487,7 @@ impl Proposal {
 fn votes_needed(weight: Uint128, percentage: Decimal) -> Uint128 {
     let applied = percentage * Uint128::from(PRECISION_FACTOR * weight.u128());
     // Divide by PRECISION_FACTOR, rounding up to the nearest integer
-    Uint128::from((applied.u128() + PRECISION_FACTOR - 1) / PRECISION_FACTOR)
+    Uint128::from(applied.u128().div_ceil(PRECISION_FACTOR))
 }
 
 // we cast a ballot with our chosen vote and a given weight
@@ -166,12 +496,28 @@ fn votes_needed(weight: Uint128, percentage: Decimal) -> Uint128 {
 pub struct Ballot {
     pub weight: Uint128,
     pub vote: Vote,
+    /// The option voted for on a multiple choice proposal, in which case
+    /// `vote` is always Yes
+    pub choice: Option<u32>,
+}
+
+/// Voter rewards allocated to a proposal when it was executed or closed
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ProposalVoterRewards {
+    pub token: Addr,
+    pub amount: Uint128,
+    /// Sum of the weight of all ballots on the proposal
+    pub total_weight: Uint128,
 }
 
 // Unique items
 pub const CONFIG: Item<Config> = Item::new("config");
 pub const PROPOSAL_COUNT: Item<u64> = Item::new("proposal_count");
+pub const DRAFT_COUNT: Item<u64> = Item::new("draft_count");
 pub const DAO_PAUSED: Item<Expiration> = Item::new("dao_paused");
+// The proposal whose messages are about to be dispatched by
+// `ExecuteProposalMsgs`, set only during its execution
+pub const EXECUTING_PROPOSAL: Item<u64> = Item::new("executing_proposal");
 
 // Total weight and voters are queried from this contract
 pub const STAKING_CONTRACT: Item<Addr> = Item::new("staking_contract");
@@ -183,11 +529,35 @@ pub const GOV_TOKEN: Item<Addr> = Item::new("gov_token");
 pub const STAKING_CONTRACT_CODE_ID: Item<u64> = Item::new("staking_contract_code_id");
 pub const STAKING_CONTRACT_UNSTAKING_DURATION: Item<Option<Duration>> =
     Item::new("staking_contract_unstaking_duration");
+pub const STAKING_CONTRACT_SNAPSHOT_STRATEGY: Item<Option<SnapshotStrategy>> =
+    Item::new("staking_contract_snapshot_strategy");
+
+// Contracts notified of proposal and vote events
+pub const PROPOSAL_HOOKS: Hooks = Hooks::new("proposal_hooks");
+pub const VOTE_HOOKS: Hooks = Hooks::new("vote_hooks");
 
 // Multiple-item map
 pub const BALLOTS: Map<(u64, &Addr), Ballot> = Map::new("votes");
 pub const PROPOSALS: Map<u64, Proposal> = Map::new("proposals");
+pub const DRAFTS: Map<u64, Draft> = Map::new("drafts");
 pub const TREASURY_TOKENS: Map<&Addr, Empty> = Map::new("treasury_tokens");
+// Voting power taken back from a delegate by delegators who voted on a
+// proposal themselves, keyed by proposal and delegate
+pub const OVERRIDDEN_POWER: Map<(u64, &Addr), Uint128> = Map::new("overridden_power");
+// Proposals that may still be open, keyed by proposer and proposal id.
+// Entries are pruned when the proposer makes a new proposal.
+pub const OPEN_PROPOSALS: Map<(&Addr, u64), Empty> = Map::new("open_proposals");
+// Proposals that may still be open, keyed by proposal id. Entries are pruned
+// when staking checkpoints are.
+pub const OPEN_PROPOSAL_IDS: Map<u64, Empty> = Map::new("open_proposal_ids");
+// Unallocated tokens of the voter reward pool, keyed by token
+pub const VOTER_REWARD_POOL: Map<&Addr, Uint128> = Map::new("voter_reward_pool");
+/// Tokens funded for voter rewards and not claimed yet, whether still in the
+/// pool or allocated to a proposal. Proposals cannot spend them.
+pub const VOTER_REWARD_RESERVE: Map<&Addr, Uint128> = Map::new("voter_reward_reserve");
+pub const PROPOSAL_VOTER_REWARDS: Map<u64, ProposalVoterRewards> =
+    Map::new("proposal_voter_rewards");
+pub const CLAIMED_VOTER_REWARDS: Map<(u64, &Addr), Empty> = Map::new("claimed_voter_rewards");
 
 pub fn next_id(store: &mut dyn Storage) -> StdResult<u64> {
     let id: u64 = PROPOSAL_COUNT.may_load(store)?.unwrap_or_default() + 1;
@@ -195,6 +565,12 @@ pub fn next_id(store: &mut dyn Storage) -> StdResult<u64> {
     Ok(id)
 }
 
+pub fn next_draft_id(store: &mut dyn Storage) -> StdResult<u64> {
+    let id: u64 = DRAFT_COUNT.may_load(store)?.unwrap_or_default() + 1;
+    DRAFT_COUNT.save(store, &id)?;
+    Ok(id)
+}
+
 pub fn parse_id(data: &[u8]) -> StdResult<u64> {
     match data[0..8].try_into() {
         Ok(bytes) => Ok(u64::from_be_bytes(bytes)),
@@ -276,6 +652,18 @@ mod test {
             total_weight,
             votes,
             deposit: Uint128::zero(),
+            deposit_denom: Some(Denom::Cw20(Addr::unchecked("gov_token"))),
+            allow_revoting: false,
+            choices: vec![],
+            choice_votes: vec![],
+            timelock: None,
+            executable_at: None,
+            execution_error: None,
+            category: None,
+            voting_power_transform: VotingPowerTransform::Linear {},
+            author: None,
+            min_voting_end: None,
+            deposit_pending: false,
         };
         (prop, block)
     }
@@ -657,4 +1045,80 @@ mod test {
             true
         ));
     }
+
+    #[test]
+    fn proposal_reached_quorum_and_vetoed() {
+        let quorum = Threshold::ThresholdQuorum {
+            threshold: Decimal::percent(50),
+            quorum: Decimal::percent(40),
+        };
+        let mut votes = Votes::new(Uint128::new(3));
+        votes.add_vote(Vote::Veto, Uint128::new(8));
+
+        // 11 of 30 votes is under quorum, 12 is over
+        let (prop, _) = setup_prop(quorum.clone(), votes.clone(), Uint128::new(30), true);
+        assert!(!prop.reached_quorum());
+        assert!(prop.is_vetoed());
+        votes.add_vote(Vote::No, Uint128::new(1));
+        let (prop, _) = setup_prop(quorum, votes.clone(), Uint128::new(30), true);
+        assert!(prop.reached_quorum());
+        assert!(prop.is_vetoed());
+
+        // absolute percentage thresholds have no quorum
+        let percent = Threshold::AbsolutePercentage {
+            percentage: Decimal::percent(50),
+        };
+        let (prop, _) = setup_prop(percent, Votes::new(Uint128::zero()), Uint128::new(30), true);
+        assert!(prop.reached_quorum());
+        assert!(!prop.is_vetoed());
+
+        // half of the votes is not a veto majority
+        votes.add_vote(Vote::Abstain, Uint128::new(4));
+        assert!(!setup_prop(
+            Threshold::AbsolutePercentage {
+                percentage: Decimal::percent(50)
+            },
+            votes,
+            Uint128::new(30),
+            true
+        )
+        .0
+        .is_vetoed());
+    }
+
+    #[test]
+    fn multiple_choice_status() {
+        let quorum = Threshold::ThresholdQuorum {
+            threshold: Decimal::percent(50),
+            quorum: Decimal::percent(40),
+        };
+        let option = MultipleChoiceOption {
+            description: "Option".to_string(),
+            msgs: vec![],
+        };
+        let status = |choice_votes: [u128; 3], is_expired: bool| -> Status {
+            let (mut prop, block) = setup_prop(
+                quorum.clone(),
+                Votes::new(Uint128::new(choice_votes.iter().sum())),
+                Uint128::new(30),
+                is_expired,
+            );
+            prop.choices = vec![option.clone(); 3];
+            prop.choice_votes = choice_votes.iter().map(|w| Uint128::new(*w)).collect();
+            prop.current_status(&block)
+        };
+
+        // under quorum (40% of 30 = 12)
+        assert_eq!(status([11, 0, 0], false), Status::Open);
+        assert_eq!(status([11, 0, 0], true), Status::Rejected);
+        // over quorum, the leading option wins once expired
+        assert_eq!(status([7, 5, 0], false), Status::Open);
+        assert_eq!(status([7, 5, 0], true), Status::Passed);
+        // ties and "none of the above" are rejected
+        assert_eq!(status([6, 6, 0], true), Status::Rejected);
+        assert_eq!(status([5, 0, 7], true), Status::Rejected);
+        // a majority of the total weight decides early
+        assert_eq!(status([16, 0, 0], false), Status::Passed);
+        assert_eq!(status([0, 0, 16], false), Status::Rejected);
+    }
 }
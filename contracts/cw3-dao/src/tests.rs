@@ -1,27 +1,36 @@
-use crate::contract::{CONTRACT_NAME, CONTRACT_VERSION};
+use crate::contract::{migrate, CONTRACT_NAME, CONTRACT_VERSION};
 use crate::error::ContractError;
+use crate::hooks::{ProposalHookMsg, VoteHookMsg};
+use crate::migrations::v0_2;
 use crate::msg::{
-    ExecuteMsg, GovTokenInstantiateMsg, GovTokenMsg, InstantiateMsg, ProposeMsg, QueryMsg,
-    Threshold, VoteMsg,
+    DepositPolicy, DepositToken, ExecuteMsg, GovTokenInstantiateMsg, GovTokenMsg, InstantiateMsg,
+    MigrateMsg, MinProposerStake, ProposalCategory, ProposalKind, ProposeMsg,
+    ProposeMultipleChoiceMsg, QueryMsg, Threshold, VoteMsg, VoteMultipleChoiceMsg, VoterRewards,
+    VotingPowerTransform,
 };
 use crate::query::{
-    ConfigResponse, Cw20BalancesResponse, ProposalListResponse, ProposalResponse,
-    ThresholdResponse, TokenListResponse, VoteInfo, VoteListResponse, VoteResponse,
-    VoteTallyResponse,
+    ConfigResponse, Cw20BalancesResponse, DraftListResponse, DraftResponse, HooksResponse,
+    ProposalListResponse, ProposalResponse, Status, ThresholdResponse, TokenListResponse, VoteInfo,
+    VoteListResponse, VoteResponse, VoteTallyResponse, VoterReward, VoterRewardsResponse,
 };
-use crate::state::{Config, Votes};
+use crate::state::{
+    Config, MultipleChoiceOption, Votes, BALLOTS, CONFIG, GOV_TOKEN, OPEN_PROPOSAL_IDS, PROPOSALS,
+};
+use cosmwasm_std::testing::{mock_dependencies, mock_env};
 use cosmwasm_std::{
-    coin, coins, to_binary, Addr, BankMsg, BlockInfo, Coin, CosmosMsg, Decimal, Empty, Timestamp,
-    Uint128, WasmMsg,
+    coin, coins, to_binary, Addr, BankMsg, Binary, BlockInfo, Coin, CosmosMsg, Decimal, Empty,
+    Response, StdResult, Timestamp, Uint128, WasmMsg,
 };
-use cw2::{query_contract_info, ContractVersion};
+use cw2::{get_contract_version, query_contract_info, set_contract_version, ContractVersion};
 use cw20::{
-    BalanceResponse, Cw20Coin, Cw20CoinVerified, Cw20Contract, Cw20ExecuteMsg, Cw20QueryMsg,
+    BalanceResponse, Cw20Coin, Cw20CoinVerified, Cw20Contract, Cw20ExecuteMsg, Cw20QueryMsg, Denom,
 };
-use cw3::{Status, Vote};
+use cw3::Vote;
+use cw_migrate::MigrateError;
 use cw_multi_test::{next_block, App, BankSudo, Contract, ContractWrapper, Executor, SudoMsg};
-use cw_utils::{Duration, Expiration};
-use stake_cw20::msg::ReceiveMsg;
+use cw_storage_plus::{Item, Map};
+use cw_utils::{Duration, Expiration, PaymentError};
+use stake_cw20::msg::{LockupTier, ReceiveMsg, SnapshotStrategy};
 use std::borrow::BorrowMut;
 
 const OWNER: &str = "admin0001";
@@ -30,6 +39,7 @@ const VOTER2: &str = "voter0002";
 const VOTER3: &str = "voter0003";
 const SOMEBODY: &str = "somebody";
 const POWER_VOTER: &str = "power-voter";
+const GUARDIAN: &str = "guardian";
 
 const NATIVE_TOKEN_DENOM: &str = "ustars";
 const INITIAL_BALANCE: u128 = 4000000;
@@ -40,7 +50,8 @@ pub fn contract_dao() -> Box<dyn Contract<Empty>> {
         crate::contract::instantiate,
         crate::contract::query,
     )
-    .with_reply(crate::contract::reply);
+    .with_reply(crate::contract::reply)
+    .with_migrate(crate::contract::migrate);
     Box::new(contract)
 }
 
@@ -58,7 +69,9 @@ pub fn contract_staking() -> Box<dyn Contract<Empty>> {
         stake_cw20::contract::execute,
         stake_cw20::contract::instantiate,
         stake_cw20::contract::query,
-    );
+    )
+    .with_reply(stake_cw20::contract::reply)
+    .with_migrate(stake_cw20::contract::migrate);
     Box::new(contract)
 }
 
@@ -95,7 +108,7 @@ fn instantiate_dao(
     threshold: Threshold,
     max_voting_period: Duration,
     proposal_deposit_amount: Option<Uint128>,
-    refund_failed_proposals: Option<bool>,
+    deposit_policy: Option<DepositPolicy>,
 ) -> Addr {
     let dao_code_id = app.store_code(contract_dao());
     let staking_code_id = app.store_code(contract_staking());
@@ -107,14 +120,28 @@ fn instantiate_dao(
             stake_contract_code_id: staking_code_id,
             label: "dao-dao".to_string(),
             unstaking_duration: None,
+            snapshot_strategy: None,
         },
         threshold,
         max_voting_period,
         proposal_deposit_amount: proposal_deposit_amount.unwrap_or_else(Uint128::zero),
-        refund_failed_proposals,
+        proposal_deposit_token: None,
+        deposit_policy,
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        allow_revoting: None,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: None,
+        catch_execution_failures: None,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: None,
+        voter_rewards: None,
+        voting_power_transform: None,
+        draft_duration: None,
     };
     app.instantiate_contract(
         dao_code_id,
@@ -133,7 +160,7 @@ fn setup_test_case(
     max_voting_period: Duration,
     init_funds: Vec<Coin>,
     proposal_deposit_amount: Option<Uint128>,
-    refund_failed_proposals: Option<bool>,
+    deposit_policy: Option<DepositPolicy>,
 ) -> (Addr, Addr, Addr) {
     // Instantiate Gov Token Contract
     let cw20_id = app.store_code(contract_cw20_gov());
@@ -181,7 +208,7 @@ fn setup_test_case(
         threshold,
         max_voting_period,
         proposal_deposit_amount,
-        refund_failed_proposals,
+        deposit_policy,
     );
     app.update_block(next_block);
 
@@ -267,16 +294,30 @@ fn test_instantiate_works() {
             stake_contract_code_id,
             label: "dao-dao".to_string(),
             unstaking_duration: None,
+            snapshot_strategy: None,
         },
         threshold: Threshold::AbsolutePercentage {
             percentage: Decimal::percent(101),
         },
         max_voting_period,
         proposal_deposit_amount: Uint128::zero(),
-        refund_failed_proposals: None,
+        proposal_deposit_token: None,
+        deposit_policy: None,
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        allow_revoting: None,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: None,
+        catch_execution_failures: None,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: None,
+        voter_rewards: None,
+        voting_power_transform: None,
+        draft_duration: None,
     };
     let err = app
         .instantiate_contract(
@@ -328,6 +369,7 @@ fn instantiate_new_gov_token() {
                 marketing: None,
             },
             unstaking_duration: None,
+            snapshot_strategy: None,
         },
         threshold: Threshold::ThresholdQuorum {
             threshold: Decimal::percent(51),
@@ -335,10 +377,23 @@ fn instantiate_new_gov_token() {
         },
         max_voting_period: Duration::Time(1234567),
         proposal_deposit_amount: Uint128::zero(),
-        refund_failed_proposals: None,
+        proposal_deposit_token: None,
+        deposit_policy: None,
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        allow_revoting: None,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: None,
+        catch_execution_failures: None,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: None,
+        voter_rewards: None,
+        voting_power_transform: None,
+        draft_duration: None,
     };
     let res = app.instantiate_contract(
         dao_code_id,
@@ -389,6 +444,7 @@ fn instantiate_new_gov_token() {
                 marketing: None,
             },
             unstaking_duration: None,
+            snapshot_strategy: None,
         },
         threshold: Threshold::ThresholdQuorum {
             threshold: Decimal::percent(51),
@@ -396,10 +452,23 @@ fn instantiate_new_gov_token() {
         },
         max_voting_period: Duration::Time(1234567),
         proposal_deposit_amount: Uint128::zero(),
-        refund_failed_proposals: None,
+        proposal_deposit_token: None,
+        deposit_policy: None,
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        allow_revoting: None,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: None,
+        catch_execution_failures: None,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: None,
+        voter_rewards: None,
+        voting_power_transform: None,
+        draft_duration: None,
     };
     let res = app.instantiate_contract(
         dao_code_id,
@@ -530,6 +599,7 @@ fn instantiate_new_gov_token() {
                 marketing: None,
             },
             unstaking_duration: None,
+            snapshot_strategy: None,
         },
         threshold: Threshold::ThresholdQuorum {
             threshold: Decimal::percent(51),
@@ -537,10 +607,23 @@ fn instantiate_new_gov_token() {
         },
         max_voting_period: Duration::Time(1234567),
         proposal_deposit_amount: Uint128::zero(),
-        refund_failed_proposals: None,
+        proposal_deposit_token: None,
+        deposit_policy: None,
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        allow_revoting: None,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: None,
+        catch_execution_failures: None,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: None,
+        voter_rewards: None,
+        voting_power_transform: None,
+        draft_duration: None,
     };
     let res = app.instantiate_contract(
         dao_code_id,
@@ -681,7 +764,7 @@ fn test_proposal_queries() {
         threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let (dao_addr, _, _) = setup_test_case(
+    let (dao_addr, cw20_addr, _) = setup_test_case(
         &mut app,
         threshold,
         voting_period,
@@ -794,6 +877,13 @@ fn test_proposal_queries() {
             total_weight: Uint128::new(20000000),
         },
         deposit_amount: Uint128::zero(),
+        deposit_denom: Denom::Cw20(cw20_addr),
+        allow_revoting: false,
+        choices: vec![],
+        executable_at: None,
+        execution_error: None,
+        category: None,
+        author: None,
         start_height: first_proposal_start_block,
     };
     assert_eq!(&expected, &res.proposals[0]);
@@ -904,8 +994,8 @@ fn test_token_add_limited() {
 
     // Attempt to add a bunch of nonesense tokens
     let update_token_list_msg = ExecuteMsg::UpdateCw20TokenList {
-        to_add: (0..20).into_iter().map(|i| i.to_string()).collect(),
-        to_remove: (20..31).into_iter().map(|i| i.to_string()).collect(),
+        to_add: (0..20).map(|i| i.to_string()).collect(),
+        to_remove: (20..31).map(|i| i.to_string()).collect(),
     };
     let wasm_msg = WasmMsg::Execute {
         contract_addr: dao_addr.clone().into(),
@@ -1087,6 +1177,7 @@ fn test_vote_works() {
         VoteInfo {
             voter: OWNER.into(),
             vote: Vote::Yes,
+            choice: None,
             weight: Uint128::new(2000000),
         }
     );
@@ -1102,6 +1193,7 @@ fn test_vote_works() {
         VoteInfo {
             voter: VOTER2.into(),
             vote: Vote::No,
+            choice: None,
             weight: Uint128::new(2000000),
         }
     );
@@ -1167,523 +1259,1086 @@ fn test_vote_works() {
 }
 
 #[test]
-fn test_execute_works() {
+fn test_revoting() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
     let threshold = Threshold::ThresholdQuorum {
-        threshold: Decimal::percent(10),
+        threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
     let (dao_addr, _cw20_addr, _) = setup_test_case(
         &mut app,
-        threshold,
+        threshold.clone(),
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
+        coins(100, NATIVE_TOKEN_DENOM),
         None,
         None,
     );
 
-    // ensure we have cash to cover the proposal
-    let contract_bal = app
-        .wrap()
-        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: Uint128::zero(),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: true,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+    app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[])
         .unwrap();
-    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
 
-    // create proposal with 0 vote power
     let proposal = pay_somebody_proposal();
     let res = app
         .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
         .unwrap();
-
-    // Get the proposal id from the logs
     let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
 
-    // Only Passed can be executed
-    let execution = ExecuteMsg::Execute { proposal_id };
-    let err = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
-        .unwrap_err();
-    assert_eq!(
-        ContractError::WrongExecuteStatus {},
-        err.downcast().unwrap()
-    );
-
-    // Vote it, so it passes
-    let vote = ExecuteMsg::Vote(VoteMsg {
+    // 70% of the voting power votes yes, which would normally pass early
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
         proposal_id,
         vote: Vote::Yes,
     });
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
     let res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+        .execute_contract(
+            Addr::unchecked(POWER_VOTER),
+            dao_addr.clone(),
+            &yes_vote,
+            &[],
+        )
         .unwrap();
     assert_eq!(
         res.custom_attrs(1),
         [
             ("action", "vote"),
-            ("sender", VOTER3),
+            ("sender", POWER_VOTER),
             ("proposal_id", proposal_id.to_string().as_str()),
-            ("status", "Passed"),
+            ("status", "Open"),
         ],
     );
 
-    // In passing: Try to close Passed fails
-    let closing = ExecuteMsg::Close { proposal_id };
+    // The outcome is not locked in while votes can still change
+    let execution = ExecuteMsg::Execute { proposal_id };
     let err = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &closing, &[])
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
         .unwrap_err();
-    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+    assert_eq!(
+        ContractError::WrongExecuteStatus {},
+        err.downcast().unwrap()
+    );
 
-    // Accounts without tokens can't execute
+    // Casting the same vote again fails
     let err = app
-        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &execution, &[])
+        .execute_contract(
+            Addr::unchecked(POWER_VOTER),
+            dao_addr.clone(),
+            &yes_vote,
+            &[],
+        )
         .unwrap_err();
-    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
-
-    // Execute works. Token holder can execute Passed proposals
-    let res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
-        .unwrap();
-    assert_eq!(
-        res.custom_attrs(1),
-        [
-            ("action", "execute"),
-            ("sender", VOTER3),
-            ("proposal_id", proposal_id.to_string().as_str()),
-        ],
-    );
+    assert_eq!(ContractError::AlreadyCast {}, err.downcast().unwrap());
 
-    // verify money was transfered
-    let some_bal = app
+    // Changing the vote moves its weight to the new option
+    let no_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::No,
+    });
+    app.execute_contract(
+        Addr::unchecked(POWER_VOTER),
+        dao_addr.clone(),
+        &no_vote,
+        &[],
+    )
+    .unwrap();
+    let tally = get_tally(&app, dao_addr.as_ref(), proposal_id);
+    assert_eq!(tally, Uint128::new(INITIAL_BALANCE));
+    let vote: VoteResponse = app
         .wrap()
-        .query_balance(SOMEBODY, NATIVE_TOKEN_DENOM)
+        .query_wasm_smart(
+            &dao_addr,
+            &QueryMsg::Vote {
+                proposal_id,
+                voter: POWER_VOTER.to_string(),
+            },
+        )
         .unwrap();
-    assert_eq!(some_bal, coin(1, NATIVE_TOKEN_DENOM));
-    let contract_bal = app
+    assert_eq!(vote.vote.unwrap().vote, Vote::No);
+
+    // Once expired the proposal is rejected and votes can no longer change
+    app.update_block(expire(voting_period));
+    let prop: ProposalResponse = app
         .wrap()
-        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
         .unwrap();
-    assert_eq!(contract_bal, coin(9, NATIVE_TOKEN_DENOM));
-
-    // In passing: Try to close Executed fails
-    let err = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &closing, &[])
-        .unwrap_err();
-    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
-
-    // Trying to execute something that was already executed fails
+    assert!(prop.allow_revoting);
+    assert_eq!(prop.status, Status::Rejected);
     let err = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr, &execution, &[])
+        .execute_contract(Addr::unchecked(POWER_VOTER), dao_addr, &yes_vote, &[])
         .unwrap_err();
-    assert_eq!(
-        ContractError::WrongExecuteStatus {},
-        err.downcast().unwrap()
-    )
+    assert_eq!(ContractError::Expired {}, err.downcast().unwrap());
 }
 
 #[test]
-fn test_execute_works_for_anyone() {
+fn test_timelock() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
+    let timelock = Duration::Time(100);
     let threshold = Threshold::ThresholdQuorum {
-        threshold: Decimal::percent(10),
+        threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
     let (dao_addr, _cw20_addr, _) = setup_test_case(
         &mut app,
         threshold.clone(),
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
+        coins(100, NATIVE_TOKEN_DENOM),
         None,
         None,
     );
 
-    // ensure we have cash to cover the proposal
-    let contract_bal = app
-        .wrap()
-        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
-        .unwrap();
-    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
-
-    // Update config to allow anyone to execute
-    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+    let config = |timelock| Config {
         name: "dao-dao".to_string(),
         description: "a great DAO!".to_string(),
-        threshold,
+        threshold: threshold.clone(),
         max_voting_period: voting_period,
         proposal_deposit: Uint128::zero(),
-        refund_failed_proposals: Some(false),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
         image_url: None,
-        only_members_execute: false,
+        only_members_execute: true,
         automatically_add_cw20s: true,
-    });
+        allow_revoting: false,
+        timelock: Some(timelock),
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    };
 
-    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
-    assert!(res.is_ok());
+    // The timelock must use the same unit as the voting period
+    let err = app
+        .execute_contract(
+            dao_addr.clone(),
+            dao_addr.clone(),
+            &ExecuteMsg::UpdateConfig(config(Duration::Height(10))),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::InvalidTimelock {}, err.downcast().unwrap());
 
-    // create proposal with 0 vote power
+    app.execute_contract(
+        dao_addr.clone(),
+        dao_addr.clone(),
+        &ExecuteMsg::UpdateConfig(config(timelock)),
+        &[],
+    )
+    .unwrap();
+
+    // A proposal passing early is timelocked from the block it passed in
     let proposal = pay_somebody_proposal();
     let res = app
         .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
         .unwrap();
-
-    // Get the proposal id from the logs
     let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
+    app.execute_contract(
+        Addr::unchecked(POWER_VOTER),
+        dao_addr.clone(),
+        &yes_vote,
+        &[],
+    )
+    .unwrap();
+    let executable_at = timelock.after(&app.block_info());
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Passed);
+    assert_eq!(prop.executable_at, Some(executable_at));
 
-    // Only Passed can be executed
     let execution = ExecuteMsg::Execute { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::Timelocked { executable_at },
+        err.downcast().unwrap()
+    );
 
-    // Vote sot hat it passes
-    let vote = ExecuteMsg::Vote(VoteMsg {
+    app.update_block(expire(timelock));
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap();
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Executed);
+    assert_eq!(prop.executable_at, Some(executable_at));
+
+    // A proposal passing when it expires is timelocked from its expiration
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
         proposal_id,
         vote: Vote::Yes,
     });
-    let _res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
         .unwrap();
+    assert_eq!(prop.status, Status::Open);
+    assert_eq!(prop.executable_at, None);
 
-    // Execute from account that's not a member
-    let res = app
-        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr, &execution, &[])
+    app.update_block(expire(voting_period));
+    let executable_at = match prop.expires {
+        Expiration::AtTime(time) => Expiration::AtTime(time.plus_seconds(100)),
+        _ => panic!("unexpected expiration"),
+    };
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
         .unwrap();
+    assert_eq!(prop.status, Status::Passed);
+    assert_eq!(prop.executable_at, Some(executable_at));
+
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
     assert_eq!(
-        res.custom_attrs(1),
-        [
-            ("action", "execute"),
-            ("sender", SOMEBODY),
-            ("proposal_id", proposal_id.to_string().as_str()),
-        ],
+        ContractError::Timelocked { executable_at },
+        err.downcast().unwrap()
     );
+
+    app.update_block(expire(timelock));
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr, &execution, &[])
+        .unwrap();
 }
 
 #[test]
-fn proposal_pass_on_expiration() {
+fn test_guardian_cancel() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
+    let timelock = Duration::Time(100);
     let threshold = Threshold::ThresholdQuorum {
         threshold: Decimal::percent(51),
-        quorum: Decimal::percent(1),
+        quorum: Decimal::percent(10),
     };
-    let (dao_addr, _, _) = setup_test_case(
+    let proposal_deposit_amount = Uint128::new(10);
+    let (dao_addr, cw20_addr, _) = setup_test_case(
         &mut app,
-        threshold,
+        threshold.clone(),
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
-        None,
+        coins(100, NATIVE_TOKEN_DENOM),
+        Some(proposal_deposit_amount),
         None,
     );
+    let cw20 = Cw20Contract(cw20_addr.clone());
 
-    // Check balance to ensure we can prop
-    let contract_bal = app
-        .wrap()
-        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
-        .unwrap();
-    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
-
-    let prop = pay_somebody_proposal();
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &prop, &[])
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: proposal_deposit_amount,
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: Some(timelock),
+        guardian: Some(Addr::unchecked(GUARDIAN)),
+        cancelled_deposit_policy: DepositPolicy::Refund {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+    app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[])
         .unwrap();
 
-    // Get prop id
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    let receiver_id = app.store_code(contract_hook_receiver());
+    let receiver_addr = app
+        .instantiate_contract(
+            receiver_id,
+            Addr::unchecked(OWNER),
+            &Empty {},
+            &[],
+            "receiver",
+            None,
+        )
+        .unwrap();
+    app.execute_contract(
+        dao_addr.clone(),
+        dao_addr.clone(),
+        &ExecuteMsg::AddProposalHook {
+            address: receiver_addr.to_string(),
+        },
+        &[],
+    )
+    .unwrap();
 
-    // Vote it, so it passes after voting period is over
-    let vote = ExecuteMsg::Vote(VoteMsg {
+    let propose = |app: &mut App| -> u64 {
+        let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+            spender: dao_addr.clone().into(),
+            amount: proposal_deposit_amount,
+            expires: None,
+        };
+        app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &allowance, &[])
+            .unwrap();
+        let res = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                dao_addr.clone(),
+                &pay_somebody_proposal(),
+                &[],
+            )
+            .unwrap();
+        res.custom_attrs(1)[2].value.parse().unwrap()
+    };
+
+    // Only the guardian can cancel proposals
+    let proposal_id = propose(&mut app);
+    let cancel = ExecuteMsg::Cancel {
         proposal_id,
-        vote: Vote::Yes,
-    });
+        reason: "malicious".to_string(),
+    };
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &cancel, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // Cancelling an open proposal refunds the deposit
     let res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+        .execute_contract(Addr::unchecked(GUARDIAN), dao_addr.clone(), &cancel, &[])
         .unwrap();
     assert_eq!(
         res.custom_attrs(1),
         [
-            ("action", "vote"),
-            ("sender", VOTER3),
+            ("action", "cancel"),
+            ("sender", GUARDIAN),
             ("proposal_id", proposal_id.to_string().as_str()),
-            ("status", "Open")
-        ]
+            ("reason", "malicious"),
+            ("deposit", "refunded"),
+        ],
+    );
+    assert_eq!(
+        cw20.balance(&app, OWNER).unwrap(),
+        Uint128::new(INITIAL_BALANCE / 2)
     );
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Cancelled);
 
-    // Wait until proposal period is done
-    app.update_block(|block| {
-        block.time = block.time.plus_seconds(2000000);
-        block.height = std::cmp::max(1, 2000000 / 5);
+    // Cancelled proposals are final
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
     });
+    let err = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::NotOpen {}, err.downcast().unwrap());
+    let err = app
+        .execute_contract(Addr::unchecked(GUARDIAN), dao_addr.clone(), &cancel, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCancelStatus {}, err.downcast().unwrap());
+    app.update_block(expire(voting_period));
+    let err = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &ExecuteMsg::Close { proposal_id },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
 
-    // Proposal should now be passed
+    // Passed proposals can be cancelled while timelocked
+    let proposal_id = propose(&mut app);
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
+    app.execute_contract(
+        Addr::unchecked(POWER_VOTER),
+        dao_addr.clone(),
+        &yes_vote,
+        &[],
+    )
+    .unwrap();
+    let cancel = ExecuteMsg::Cancel {
+        proposal_id,
+        reason: "drains the treasury".to_string(),
+    };
+    app.execute_contract(Addr::unchecked(GUARDIAN), dao_addr.clone(), &cancel, &[])
+        .unwrap();
     let prop: ProposalResponse = app
         .wrap()
         .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
         .unwrap();
-    assert_eq!(prop.status, Status::Passed);
+    assert_eq!(prop.status, Status::Cancelled);
+    assert_eq!(prop.executable_at, None);
 
-    // Now execute
-    let res = app
+    // Hooks learn about both cancellations
+    let received: Vec<HookReceiverMsg> = app
+        .wrap()
+        .query_wasm_smart(&receiver_addr, &Empty {})
+        .unwrap();
+    let cancellations: Vec<_> = received
+        .into_iter()
+        .filter(|msg| {
+            matches!(
+                msg,
+                HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalStatusChanged {
+                    new_status: Status::Cancelled,
+                    ..
+                })
+            )
+        })
+        .collect();
+    assert_eq!(
+        cancellations,
+        vec![
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalStatusChanged {
+                proposal_id: 1,
+                old_status: Status::Open,
+                new_status: Status::Cancelled,
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalStatusChanged {
+                proposal_id: 2,
+                old_status: Status::Passed,
+                new_status: Status::Cancelled,
+            }),
+        ]
+    );
+
+    app.update_block(expire(timelock));
+    let err = app
         .execute_contract(
             Addr::unchecked(VOTER3),
             dao_addr,
             &ExecuteMsg::Execute { proposal_id },
             &[],
         )
-        .unwrap();
+        .unwrap_err();
     assert_eq!(
-        res.custom_attrs(1),
-        [
-            ("action", "execute"),
-            ("sender", VOTER3),
-            ("proposal_id", proposal_id.to_string().as_str())
-        ]
+        ContractError::WrongExecuteStatus {},
+        err.downcast().unwrap()
     );
 }
 
 #[test]
-fn test_close_works() {
+fn test_execution_failure() {
     let mut app = mock_app();
 
-    let voting_period = Duration::Height(2000000);
+    let voting_period = Duration::Time(2000000);
     let threshold = Threshold::ThresholdQuorum {
         threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let (dao_addr, _cw20_addr, _) = setup_test_case(
+    let proposal_deposit_amount = Uint128::new(10);
+    let (dao_addr, cw20_addr, _) = setup_test_case(
         &mut app,
-        threshold,
+        threshold.clone(),
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
-        None,
+        vec![],
+        Some(proposal_deposit_amount),
         None,
     );
+    let cw20 = Cw20Contract(cw20_addr.clone());
 
-    // create proposal with 0 vote power
-    let proposal = pay_somebody_proposal();
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: proposal_deposit_amount,
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: true,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+    app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[])
+        .unwrap();
+    let receiver_id = app.store_code(contract_hook_receiver());
+    let receiver_addr = app
+        .instantiate_contract(
+            receiver_id,
+            Addr::unchecked(OWNER),
+            &Empty {},
+            &[],
+            "receiver",
+            None,
+        )
         .unwrap();
+    app.execute_contract(
+        dao_addr.clone(),
+        dao_addr.clone(),
+        &ExecuteMsg::AddProposalHook {
+            address: receiver_addr.to_string(),
+        },
+        &[],
+    )
+    .unwrap();
+    let last_hook = |app: &App| -> HookReceiverMsg {
+        let received: Vec<HookReceiverMsg> = app
+            .wrap()
+            .query_wasm_smart(&receiver_addr, &Empty {})
+            .unwrap();
+        received.last().unwrap().clone()
+    };
 
-    // Get the proposal id from the logs
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    let propose = |app: &mut App, proposal: &ExecuteMsg| -> u64 {
+        let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+            spender: dao_addr.clone().into(),
+            amount: proposal_deposit_amount,
+            expires: None,
+        };
+        app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &allowance, &[])
+            .unwrap();
+        let res = app
+            .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), proposal, &[])
+            .unwrap();
+        let proposal_id = res.custom_attrs(1)[2].value.parse().unwrap();
+        let yes_vote = ExecuteMsg::Vote(VoteMsg {
+            proposal_id,
+            vote: Vote::Yes,
+        });
+        app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+            .unwrap();
+        app.execute_contract(
+            Addr::unchecked(POWER_VOTER),
+            dao_addr.clone(),
+            &yes_vote,
+            &[],
+        )
+        .unwrap();
+        proposal_id
+    };
 
-    // Non-expired proposals cannot be closed
+    // The DAO has no funds, so paying somebody fails
+    let proposal_id = propose(&mut app, &pay_somebody_proposal());
+    let execution = ExecuteMsg::Execute { proposal_id };
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap();
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::ExecutionFailed);
+    assert!(prop.execution_error.is_some());
+    assert_eq!(
+        last_hook(&app),
+        HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalStatusChanged {
+            proposal_id,
+            old_status: Status::Passed,
+            new_status: Status::ExecutionFailed,
+        })
+    );
+
+    // The deposit is only refunded once the proposal is executed
+    assert_eq!(
+        cw20.balance(&app, OWNER).unwrap(),
+        Uint128::new(INITIAL_BALANCE / 2) - proposal_deposit_amount
+    );
+
+    // Once funded, the proposal can be executed again
+    app.sudo(SudoMsg::Bank(BankSudo::Mint {
+        to_address: dao_addr.to_string(),
+        amount: coins(1, NATIVE_TOKEN_DENOM),
+    }))
+    .unwrap();
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap();
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Executed);
+    assert_eq!(prop.execution_error, None);
+    let some_bal = app
+        .wrap()
+        .query_balance(SOMEBODY, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(some_bal, coin(1, NATIVE_TOKEN_DENOM));
+    assert_eq!(
+        cw20.balance(&app, OWNER).unwrap(),
+        Uint128::new(INITIAL_BALANCE / 2)
+    );
+    assert_eq!(
+        last_hook(&app),
+        HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalExecuted { proposal_id })
+    );
+
+    // A failed proposal can also be closed before it expires, but only by
+    // its proposer or the DAO
+    let proposal_id = propose(&mut app, &pay_somebody_proposal());
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Execute { proposal_id },
+        &[],
+    )
+    .unwrap();
     let closing = ExecuteMsg::Close { proposal_id };
     let err = app
         .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &closing, &[])
         .unwrap_err();
-    assert_eq!(ContractError::NotExpired {}, err.downcast().unwrap());
-
-    // Expired proposals can be closed
-    app.update_block(expire(voting_period));
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
     let res = app
-        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &closing, &[])
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &closing, &[])
         .unwrap();
     assert_eq!(
         res.custom_attrs(1),
         [
             ("action", "close"),
-            ("sender", SOMEBODY),
+            ("sender", OWNER),
             ("proposal_id", proposal_id.to_string().as_str()),
+            ("deposit", "refunded"),
         ],
     );
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Rejected);
+    assert_eq!(
+        cw20.balance(&app, OWNER).unwrap(),
+        Uint128::new(INITIAL_BALANCE / 2)
+    );
 
-    // Trying to close it again fails
-    let closing = ExecuteMsg::Close { proposal_id };
+    // A failing message reverts the messages dispatched before it, so
+    // executing again doesn't repeat them
+    app.sudo(SudoMsg::Bank(BankSudo::Mint {
+        to_address: dao_addr.to_string(),
+        amount: coins(1, NATIVE_TOKEN_DENOM),
+    }))
+    .unwrap();
+    let (msgs, title, description) = proposal_info();
+    let proposal_id = propose(
+        &mut app,
+        &ExecuteMsg::Propose(ProposeMsg {
+            title,
+            description,
+            msgs: [msgs.clone(), msgs].concat(),
+            latest: None,
+        }),
+    );
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Execute { proposal_id },
+        &[],
+    )
+    .unwrap();
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::ExecutionFailed);
+    let some_bal = app
+        .wrap()
+        .query_balance(SOMEBODY, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(some_bal, coin(1, NATIVE_TOKEN_DENOM));
+    let dao_bal = app
+        .wrap()
+        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(dao_bal, coin(1, NATIVE_TOKEN_DENOM));
+
+    // Proposal messages can only be dispatched by the execution itself
     let err = app
-        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr, &closing, &[])
+        .execute_contract(
+            dao_addr.clone(),
+            dao_addr.clone(),
+            &ExecuteMsg::ExecuteProposalMsgs { proposal_id },
+            &[],
+        )
         .unwrap_err();
-    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
 }
 
 #[test]
-fn test_close_works_with_refund() {
+fn test_proposer_restrictions() {
     let mut app = mock_app();
-    let voting_period = Duration::Height(2000000);
+
+    let voting_period = Duration::Time(2000000);
     let threshold = Threshold::ThresholdQuorum {
         threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let proposal_deposit_amount = Uint128::new(10);
-    let (dao_addr, cw20_addr, _) = setup_test_case(
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
         &mut app,
         threshold.clone(),
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
-        Some(proposal_deposit_amount),
-        Some(true),
-    );
-
-    let cw20 = Cw20Contract(cw20_addr.clone());
-
-    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
-        spender: dao_addr.clone().into(),
-        amount: proposal_deposit_amount,
-        expires: None,
-    };
-
-    let res = app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &allowance, &[]);
-    assert!(res.is_ok());
-
-    let owner_initial_balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
-    let proposal = pay_somebody_proposal();
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
-        .unwrap();
-
-    // Get the proposal id from the logs
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
-
-    let closing = ExecuteMsg::Close { proposal_id };
-
-    // Manually expire proposal to be able to close.
-    app.update_block(expire(voting_period));
-    let res = app
-        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &closing, &[])
-        .unwrap();
-    assert_eq!(
-        res.custom_attrs(1),
-        [
-            ("action", "close"),
-            ("sender", SOMEBODY),
-            ("proposal_id", proposal_id.to_string().as_str()),
-        ],
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
     );
 
-    let owner_balance_after_failed_proposal = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
-    assert_eq!(owner_balance_after_failed_proposal, owner_initial_balance);
-
-    // Update Config to not refund proposals
+    // 15% of the 20M staked tokens is required
     let update_config_msg = ExecuteMsg::UpdateConfig(Config {
         name: "dao-dao".to_string(),
         description: "a great DAO!".to_string(),
         threshold,
         max_voting_period: voting_period,
-        proposal_deposit: proposal_deposit_amount,
-        refund_failed_proposals: Some(false),
+        proposal_deposit: Uint128::zero(),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: Some(MinProposerStake::Percentage {
+            percentage: Decimal::percent(15),
+        }),
+        proposer_allowlist: Some(vec![Addr::unchecked(OWNER), Addr::unchecked(VOTER3)]),
+        max_open_proposals: Some(1),
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
     });
-
-    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
-    assert!(res.is_ok());
-
-    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
-        spender: dao_addr.clone().into(),
-        amount: proposal_deposit_amount,
-        expires: None,
-    };
-
-    let res = app.execute_contract(Addr::unchecked(OWNER), cw20_addr, &allowance, &[]);
-    assert!(res.is_ok());
-
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+    app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[])
         .unwrap();
 
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
-    let closing = ExecuteMsg::Close { proposal_id };
-
-    app.update_block(expire(voting_period));
-    let res = app
-        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr, &closing, &[])
-        .unwrap();
+    let proposal = pay_somebody_proposal();
+    let err = app
+        .execute_contract(
+            Addr::unchecked(POWER_VOTER),
+            dao_addr.clone(),
+            &proposal,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::ProposerNotAllowed {},
+        err.downcast().unwrap()
+    );
 
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap_err();
     assert_eq!(
-        res.custom_attrs(1),
-        [
-            ("action", "close"),
-            ("sender", SOMEBODY),
-            ("proposal_id", proposal_id.to_string().as_str()),
-        ],
+        ContractError::InsufficientProposerStake {
+            min: Uint128::new(3000000)
+        },
+        err.downcast().unwrap()
     );
 
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+    let err = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &proposal, &[])
+        .unwrap_err();
     assert_eq!(
-        owner_initial_balance - proposal_deposit_amount,
-        cw20.balance(&app, Addr::unchecked(OWNER)).unwrap()
+        ContractError::TooManyOpenProposals { max: 1 },
+        err.downcast().unwrap()
     );
+
+    // Expired proposals no longer count as open
+    app.update_block(expire(voting_period));
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr, &proposal, &[])
+        .unwrap();
 }
 
 #[test]
-fn quorum_enforced_even_if_absolute_threshold_met() {
+fn test_proposal_categories() {
     let mut app = mock_app();
 
-    // 33% required for quora, which is 5 of the initial 15
-    // 50% yes required to pass early (8 of the initial 15)
-    let voting_period = Duration::Time(20000);
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
     let (dao_addr, _cw20_addr, _) = setup_test_case(
         &mut app,
-        // note that 60% yes is not enough to pass without 20% no as well
-        Threshold::ThresholdQuorum {
-            threshold: Decimal::percent(50),
-            quorum: Decimal::percent(80),
-        },
+        threshold.clone(),
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
+        coins(100, NATIVE_TOKEN_DENOM),
         None,
         None,
     );
 
-    // create proposal
-    let proposal = pay_somebody_proposal();
-    let res = app
-        .execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &proposal, &[])
-        .unwrap();
-
-    // Get the proposal id from the logs
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
-    let prop_status = |app: &App| -> Status {
-        let query_prop = QueryMsg::Proposal { proposal_id };
-        let prop: ProposalResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
-        prop.status
+    let treasury_spend = ProposalCategory {
+        kind: ProposalKind::TreasurySpend {},
+        threshold: Threshold::ThresholdQuorum {
+            threshold: Decimal::percent(60),
+            quorum: Decimal::percent(20),
+        },
+        min_voting_period: None,
+        max_voting_period: Duration::Time(1000000),
+        deposit: None,
+    };
+    let migration = ProposalCategory {
+        kind: ProposalKind::Migration {},
+        threshold: Threshold::AbsolutePercentage {
+            percentage: Decimal::percent(75),
+        },
+        min_voting_period: Some(Duration::Time(3000000)),
+        max_voting_period: Duration::Time(4000000),
+        deposit: None,
+    };
+    let config = |proposal_categories| Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold: threshold.clone(),
+        max_voting_period: voting_period,
+        proposal_deposit: Uint128::zero(),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories,
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
     };
-    assert_eq!(prop_status(&app), Status::Open);
-    app.update_block(|block| block.height += 3);
 
-    // reach 60% of yes votes, not enough to pass early (or late)
-    let yes_vote = ExecuteMsg::Vote(VoteMsg {
-        proposal_id,
-        vote: Vote::Yes,
-    });
-    app.execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &yes_vote, &[])
-        .unwrap();
-    app.execute_contract(Addr::unchecked(VOTER2), dao_addr.clone(), &yes_vote, &[])
-        .unwrap();
-    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
-        .unwrap();
-    app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &yes_vote, &[])
-        .unwrap();
+    // Each kind can only have one category
+    let err = app
+        .execute_contract(
+            dao_addr.clone(),
+            dao_addr.clone(),
+            &ExecuteMsg::UpdateConfig(config(vec![treasury_spend.clone(), treasury_spend.clone()])),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::DuplicateProposalCategory {},
+        err.downcast().unwrap()
+    );
 
-    // 9 of 15 is 60% absolute threshold, but less than 12 (80% quorum needed)
-    assert_eq!(prop_status(&app), Status::Open);
+    // The min voting period can't exceed the max voting period
+    let err = app
+        .execute_contract(
+            dao_addr.clone(),
+            dao_addr.clone(),
+            &ExecuteMsg::UpdateConfig(config(vec![ProposalCategory {
+                min_voting_period: Some(Duration::Time(5000000)),
+                ..migration.clone()
+            }])),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidVotingPeriods {},
+        err.downcast().unwrap()
+    );
 
-    // add 3 weight no vote and we hit quorum and this passes
-    let no_vote = ExecuteMsg::Vote(VoteMsg {
-        proposal_id,
-        vote: Vote::No,
-    });
     app.execute_contract(
-        Addr::unchecked(POWER_VOTER),
         dao_addr.clone(),
-        &no_vote,
+        dao_addr.clone(),
+        &ExecuteMsg::UpdateConfig(config(vec![treasury_spend, migration])),
         &[],
     )
     .unwrap();
-    assert_eq!(prop_status(&app), Status::Passed);
+
+    let propose = |app: &mut App, msgs: Vec<CosmosMsg>, latest| -> ProposalResponse {
+        let res = app
+            .execute_contract(
+                Addr::unchecked(VOTER3),
+                dao_addr.clone(),
+                &ExecuteMsg::Propose(ProposeMsg {
+                    title: "Categorized".to_string(),
+                    description: "Uses the voting rules of its category".to_string(),
+                    msgs,
+                    latest,
+                }),
+                &[],
+            )
+            .unwrap();
+        let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+        app.wrap()
+            .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+            .unwrap()
+    };
+    let block_time = app.block_info().time;
+
+    // Proposals matching no category use the DAO defaults
+    let prop = propose(&mut app, vec![], None);
+    assert_eq!(prop.category, None);
+    assert_eq!(
+        prop.expires,
+        Expiration::AtTime(block_time.plus_seconds(2000000))
+    );
+
+    // Spending from the treasury is capped by the category's voting period
+    let (msgs, _, _) = proposal_info();
+    let prop = propose(&mut app, msgs.clone(), None);
+    assert_eq!(prop.category, Some(ProposalKind::TreasurySpend {}));
+    assert_eq!(
+        prop.threshold,
+        ThresholdResponse::ThresholdQuorum {
+            threshold: Decimal::percent(60),
+            quorum: Decimal::percent(20),
+            total_weight: Uint128::new(20000000),
+        }
+    );
+    assert_eq!(
+        prop.expires,
+        Expiration::AtTime(block_time.plus_seconds(1000000))
+    );
+
+    // Migrating while spending from the treasury uses the stricter
+    // migration threshold, and stays open for the min voting period
+    let mut msgs = msgs;
+    msgs.push(
+        WasmMsg::Migrate {
+            contract_addr: dao_addr.to_string(),
+            new_code_id: 1,
+            msg: to_binary(&MigrateMsg {}).unwrap(),
+        }
+        .into(),
+    );
+    let prop = propose(
+        &mut app,
+        msgs.clone(),
+        Some(Expiration::AtTime(block_time.plus_seconds(100))),
+    );
+    assert_eq!(prop.category, Some(ProposalKind::Migration {}));
+    assert_eq!(
+        prop.threshold,
+        ThresholdResponse::AbsolutePercentage {
+            percentage: Decimal::percent(75),
+            total_weight: Uint128::new(20000000),
+        }
+    );
+    assert_eq!(
+        prop.expires,
+        Expiration::AtTime(block_time.plus_seconds(3000000))
+    );
+
+    // Enough votes don't pass the proposal before the min voting period
+    let prop = propose(&mut app, msgs, None);
+    assert_eq!(
+        prop.expires,
+        Expiration::AtTime(block_time.plus_seconds(4000000))
+    );
+    for voter in [POWER_VOTER, VOTER3, VOTER1] {
+        app.execute_contract(
+            Addr::unchecked(voter),
+            dao_addr.clone(),
+            &ExecuteMsg::Vote(VoteMsg {
+                proposal_id: prop.id,
+                vote: Vote::Yes,
+            }),
+            &[],
+        )
+        .unwrap();
+    }
+    let query_status = |app: &App| -> Status {
+        let prop: ProposalResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &dao_addr,
+                &QueryMsg::Proposal {
+                    proposal_id: prop.id,
+                },
+            )
+            .unwrap();
+        prop.status
+    };
+    assert_eq!(query_status(&app), Status::Open);
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER3),
+            dao_addr.clone(),
+            &ExecuteMsg::Execute {
+                proposal_id: prop.id,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::WrongExecuteStatus {},
+        err.downcast().unwrap()
+    );
+    app.update_block(|block| block.time = block_time.plus_seconds(3000000));
+    assert_eq!(query_status(&app), Status::Passed);
 }
 
 #[test]
-fn test_burn_does_not_change_proposal_query_response_threshold() {
+fn test_vote_delegation() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
     let threshold = Threshold::ThresholdQuorum {
-        threshold: Decimal::percent(20),
+        threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let (dao_addr, cw20_addr, _) = setup_test_case(
+    let (dao_addr, _cw20_addr, staking_addr) = setup_test_case(
         &mut app,
         threshold,
         voting_period,
@@ -1692,198 +2347,1320 @@ fn test_burn_does_not_change_proposal_query_response_threshold() {
         None,
     );
 
-    let proposal = pay_somebody_proposal();
-    let res = app
-        .execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &proposal, &[])
+    // VOTER1 and VOTER2 delegate their voting power to VOTER3
+    let delegate = stake_cw20::msg::ExecuteMsg::Delegate {
+        address: VOTER3.to_string(),
+    };
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        staking_addr.clone(),
+        &delegate,
+        &[],
+    )
+    .unwrap();
+    app.execute_contract(Addr::unchecked(VOTER2), staking_addr, &delegate, &[])
         .unwrap();
+    app.update_block(next_block);
 
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
-
-    let query_prop = QueryMsg::Proposal { proposal_id };
-    let prop: ProposalResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
-
-    let total_weight = match prop.threshold {
-        ThresholdResponse::AbsoluteCount {
-            weight: _,
-            total_weight,
-        } => total_weight,
-        ThresholdResponse::AbsolutePercentage {
-            percentage: _,
-            total_weight,
-        } => total_weight,
-        ThresholdResponse::ThresholdQuorum {
-            threshold: _,
-            quorum: _,
-            total_weight,
-        } => total_weight,
+    let ballot_weight = |app: &App, proposal_id: u64, voter: &str| -> Uint128 {
+        let vote: VoteResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &dao_addr,
+                &QueryMsg::Vote {
+                    proposal_id,
+                    voter: voter.to_string(),
+                },
+            )
+            .unwrap();
+        vote.vote.unwrap().weight
+    };
+    let yes_vote = |proposal_id| {
+        ExecuteMsg::Vote(VoteMsg {
+            proposal_id,
+            vote: Vote::Yes,
+        })
+    };
+    let no_vote = |proposal_id| {
+        ExecuteMsg::Vote(VoteMsg {
+            proposal_id,
+            vote: Vote::No,
+        })
     };
 
-    assert_eq!(total_weight, Uint128::from(20000000u128));
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
 
-    // Burn some tokens
+    // The delegate votes with the delegated power
     app.execute_contract(
-        Addr::unchecked(VOTER1),
-        cw20_addr,
-        &cw20::Cw20ExecuteMsg::Burn {
-            amount: Uint128::from(1u128),
-        },
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &yes_vote(proposal_id),
         &[],
     )
     .unwrap();
+    assert_eq!(
+        ballot_weight(&app, proposal_id, VOTER3),
+        Uint128::new(INITIAL_BALANCE * 2)
+    );
 
-    let query_prop = QueryMsg::Proposal { proposal_id };
-    let prop: ProposalResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
+    // A delegator voting overrides their delegate
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        dao_addr.clone(),
+        &no_vote(proposal_id),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        ballot_weight(&app, proposal_id, VOTER1),
+        Uint128::new(INITIAL_BALANCE / 2)
+    );
+    assert_eq!(
+        ballot_weight(&app, proposal_id, VOTER3),
+        Uint128::new(INITIAL_BALANCE * 3 / 2)
+    );
+    let tally = get_tally(&app, dao_addr.as_ref(), proposal_id);
+    assert_eq!(tally, Uint128::new(INITIAL_BALANCE * 3 / 2));
 
-    let total_weight = match prop.threshold {
-        ThresholdResponse::AbsoluteCount {
-            weight: _,
-            total_weight,
-        } => total_weight,
-        ThresholdResponse::AbsolutePercentage {
-            percentage: _,
-            total_weight,
-        } => total_weight,
-        ThresholdResponse::ThresholdQuorum {
-            threshold: _,
-            quorum: _,
-            total_weight,
-        } => total_weight,
-    };
+    // The override also applies if the delegator votes first
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    app.execute_contract(
+        Addr::unchecked(VOTER2),
+        dao_addr.clone(),
+        &no_vote(proposal_id),
+        &[],
+    )
+    .unwrap();
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &yes_vote(proposal_id),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        ballot_weight(&app, proposal_id, VOTER2),
+        Uint128::new(INITIAL_BALANCE / 2)
+    );
+    assert_eq!(
+        ballot_weight(&app, proposal_id, VOTER3),
+        Uint128::new(INITIAL_BALANCE * 3 / 2)
+    );
+}
 
-    assert_eq!(total_weight, Uint128::from(20000000u128));
+fn pay_somebody_option(amount: u128) -> MultipleChoiceOption {
+    MultipleChoiceOption {
+        description: format!("Pay somebody {}", amount),
+        msgs: vec![BankMsg::Send {
+            to_address: SOMEBODY.into(),
+            amount: coins(amount, NATIVE_TOKEN_DENOM),
+        }
+        .into()],
+    }
 }
 
 #[test]
-fn query_proposal_tally() {
+fn test_multiple_choice_proposal() {
     let mut app = mock_app();
 
-    let voting_period = Duration::Time(20000);
-    let (dao_addr, _, _) = setup_test_case(
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(30),
+    };
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
         &mut app,
-        Threshold::ThresholdQuorum {
-            threshold: Decimal::percent(50),
-            quorum: Decimal::percent(80),
-        },
+        threshold,
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
+        coins(100, NATIVE_TOKEN_DENOM),
         None,
         None,
     );
 
-    let assert_tally_is = |app: &mut App, proposal_id, expected| {
-        let query_prop = QueryMsg::Tally { proposal_id };
-        let prop: VoteTallyResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
-        assert_eq!(prop, expected);
+    let propose = |choices: Vec<MultipleChoiceOption>| {
+        ExecuteMsg::ProposeMultipleChoice(ProposeMultipleChoiceMsg {
+            title: "Pay somebody".to_string(),
+            description: "How much do I pay her?".to_string(),
+            choices,
+            latest: None,
+        })
+    };
+    let vote = |proposal_id, choice| {
+        ExecuteMsg::VoteMultipleChoice(VoteMultipleChoiceMsg {
+            proposal_id,
+            choice,
+        })
     };
 
-    // create proposal
-    let proposal = pay_somebody_proposal();
+    // At least two options are needed
+    let err = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &propose(vec![pay_somebody_option(1)]),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::WrongChoiceCount { min: 2, max: 10 },
+        err.downcast().unwrap()
+    );
+
     let res = app
-        .execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &proposal, &[])
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &propose(vec![
+                pay_somebody_option(1),
+                pay_somebody_option(5),
+                pay_somebody_option(10),
+            ]),
+            &[],
+        )
         .unwrap();
-
-    // Get the proposal id from the logs
     let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
 
-    assert_tally_is(
-        &mut app,
-        proposal_id,
-        VoteTallyResponse {
-            status: Status::Open,
-            threshold: ThresholdResponse::ThresholdQuorum {
-                threshold: Decimal::percent(50),
-                quorum: Decimal::percent(80),
-                total_weight: Uint128::from(20000000u128),
-            },
-            quorum: Decimal::percent(0),
-            total_votes: Uint128::zero(),
-            total_weight: Uint128::from(20000000u128),
-            votes: Votes::new(Uint128::zero()),
-        },
-    );
+    // A "none of the above" option is added
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.choices.len(), 4);
+    assert_eq!(prop.choices[3].description, "None of the above");
+    assert!(prop.choices[3].msgs.is_empty());
 
-    // Vote yes with 20% of voting power. This will not pass the
-    // proposal.
+    // Yes/no votes and unknown options are rejected
     let yes_vote = ExecuteMsg::Vote(VoteMsg {
         proposal_id,
         vote: Vote::Yes,
     });
-    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
-    assert!(res.is_ok());
-
-    assert_tally_is(
-        &mut app,
-        proposal_id,
-        VoteTallyResponse {
-            status: Status::Open,
-            threshold: ThresholdResponse::ThresholdQuorum {
-                threshold: Decimal::percent(50),
-                quorum: Decimal::percent(80),
-                total_weight: Uint128::from(20000000u128),
-            },
-            quorum: Decimal::percent(20),
-            total_votes: Uint128::from(4000000u128),
-            total_weight: Uint128::from(20000000u128),
-            votes: Votes::new(Uint128::from(4000000u128)),
-        },
-    );
-
-    // Vote abstain with 10% of voting power. This will not pass
-    // the proposal.
-    let yes_vote = ExecuteMsg::Vote(VoteMsg {
-        proposal_id,
-        vote: Vote::Abstain,
-    });
-    let res = app.execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &yes_vote, &[]);
-    assert!(res.is_ok());
-
-    assert_tally_is(
-        &mut app,
-        proposal_id,
-        VoteTallyResponse {
-            status: Status::Open,
-            threshold: ThresholdResponse::ThresholdQuorum {
-                threshold: Decimal::percent(50),
-                quorum: Decimal::percent(80),
-                total_weight: Uint128::from(20000000u128),
-            },
-            quorum: Decimal::percent(30),
-            total_votes: Uint128::from(6000000u128),
-            total_weight: Uint128::from(20000000u128),
-            votes: Votes {
-                yes: Uint128::from(4000000u128),
-                abstain: Uint128::from(2000000u128),
-                no: Uint128::zero(),
-                veto: Uint128::zero(),
-            },
-        },
+    let err = app
+        .execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &yes_vote, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::WrongVoteType {}, err.downcast().unwrap());
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER1),
+            dao_addr.clone(),
+            &vote(proposal_id, 4),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidChoice { choice: 4 },
+        err.downcast().unwrap()
     );
 
-    // Vote yes with and additional 50% of voting power. This will
-    // pass the proposal at 80% quorum and 70% yes.
-    let yes_vote = ExecuteMsg::Vote(VoteMsg {
-        proposal_id,
-        vote: Vote::Yes,
-    });
-    let res = app.execute_contract(
-        Addr::unchecked(POWER_VOTER),
+    // Quorum is met but no option has a majority of the total weight yet
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
         dao_addr.clone(),
-        &yes_vote,
+        &vote(proposal_id, 0),
+        &[],
+    )
+    .unwrap();
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &vote(proposal_id, 1),
         &[],
+    )
+    .unwrap();
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::WrongExecuteStatus {},
+        err.downcast().unwrap()
     );
-    assert!(res.is_ok());
 
-    assert_tally_is(
-        &mut app,
-        proposal_id,
-        VoteTallyResponse {
-            status: Status::Passed,
-            threshold: ThresholdResponse::ThresholdQuorum {
-                threshold: Decimal::percent(50),
-                quorum: Decimal::percent(80),
-                total_weight: Uint128::from(20000000u128),
+    let vote_info: VoteResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &dao_addr,
+            &QueryMsg::Vote {
+                proposal_id,
+                voter: VOTER3.to_string(),
             },
-            quorum: Decimal::percent(80),
+        )
+        .unwrap();
+    assert_eq!(vote_info.vote.unwrap().choice, Some(1));
+
+    // Once expired the leading option wins and its messages are executed
+    app.update_block(expire(voting_period));
+    let tally: VoteTallyResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Tally { proposal_id })
+        .unwrap();
+    assert_eq!(tally.status, Status::Passed);
+    assert_eq!(
+        tally.choice_votes,
+        vec![
+            Uint128::new(INITIAL_BALANCE / 2),
+            Uint128::new(INITIAL_BALANCE),
+            Uint128::zero(),
+            Uint128::zero(),
+        ]
+    );
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap();
+    let balance = app
+        .wrap()
+        .query_balance(SOMEBODY, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(balance, coin(5, NATIVE_TOKEN_DENOM));
+
+    // A majority for "none of the above" rejects the proposal early
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &propose(vec![pay_somebody_option(1), pay_somebody_option(5)]),
+            &[],
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    app.execute_contract(
+        Addr::unchecked(POWER_VOTER),
+        dao_addr.clone(),
+        &vote(proposal_id, 2),
+        &[],
+    )
+    .unwrap();
+    let res = app
+        .execute_contract(
+            Addr::unchecked(VOTER1),
+            dao_addr.clone(),
+            &vote(proposal_id, 2),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(res.custom_attrs(1)[3], ("status", "Rejected"));
+}
+
+#[test]
+fn test_execute_works() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(10),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // ensure we have cash to cover the proposal
+    let contract_bal = app
+        .wrap()
+        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
+
+    // create proposal with 0 vote power
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    // Get the proposal id from the logs
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Only Passed can be executed
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::WrongExecuteStatus {},
+        err.downcast().unwrap()
+    );
+
+    // Vote it, so it passes
+    let vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "vote"),
+            ("sender", VOTER3),
+            ("proposal_id", proposal_id.to_string().as_str()),
+            ("status", "Passed"),
+        ],
+    );
+
+    // In passing: Try to close Passed fails
+    let closing = ExecuteMsg::Close { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &closing, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+
+    // Accounts without tokens can't execute
+    let err = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // Execute works. Token holder can execute Passed proposals
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &execution, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "execute"),
+            ("sender", VOTER3),
+            ("proposal_id", proposal_id.to_string().as_str()),
+        ],
+    );
+
+    // verify money was transfered
+    let some_bal = app
+        .wrap()
+        .query_balance(SOMEBODY, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(some_bal, coin(1, NATIVE_TOKEN_DENOM));
+    let contract_bal = app
+        .wrap()
+        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(contract_bal, coin(9, NATIVE_TOKEN_DENOM));
+
+    // In passing: Try to close Executed fails
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &closing, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+
+    // Trying to execute something that was already executed fails
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr, &execution, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::WrongExecuteStatus {},
+        err.downcast().unwrap()
+    )
+}
+
+#[test]
+fn test_execute_works_for_anyone() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(10),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // ensure we have cash to cover the proposal
+    let contract_bal = app
+        .wrap()
+        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
+
+    // Update config to allow anyone to execute
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: Uint128::zero(),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: false,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+
+    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
+    assert!(res.is_ok());
+
+    // create proposal with 0 vote power
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    // Get the proposal id from the logs
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Only Passed can be executed
+    let execution = ExecuteMsg::Execute { proposal_id };
+
+    // Vote sot hat it passes
+    let vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let _res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+        .unwrap();
+
+    // Execute from account that's not a member
+    let res = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr, &execution, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "execute"),
+            ("sender", SOMEBODY),
+            ("proposal_id", proposal_id.to_string().as_str()),
+        ],
+    );
+}
+
+#[test]
+fn proposal_pass_on_expiration() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(1),
+    };
+    let (dao_addr, _, _) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // Check balance to ensure we can prop
+    let contract_bal = app
+        .wrap()
+        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
+
+    let prop = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &prop, &[])
+        .unwrap();
+
+    // Get prop id
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Vote it, so it passes after voting period is over
+    let vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "vote"),
+            ("sender", VOTER3),
+            ("proposal_id", proposal_id.to_string().as_str()),
+            ("status", "Open")
+        ]
+    );
+
+    // Wait until proposal period is done
+    app.update_block(|block| {
+        block.time = block.time.plus_seconds(2000000);
+        block.height = std::cmp::max(1, 2000000 / 5);
+    });
+
+    // Proposal should now be passed
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Passed);
+
+    // Now execute
+    let res = app
+        .execute_contract(
+            Addr::unchecked(VOTER3),
+            dao_addr,
+            &ExecuteMsg::Execute { proposal_id },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "execute"),
+            ("sender", VOTER3),
+            ("proposal_id", proposal_id.to_string().as_str())
+        ]
+    );
+}
+
+#[test]
+fn test_close_works() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Height(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // create proposal with 0 vote power
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    // Get the proposal id from the logs
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Non-expired proposals cannot be closed
+    let closing = ExecuteMsg::Close { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &closing, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::NotExpired {}, err.downcast().unwrap());
+
+    // Expired proposals can be closed
+    app.update_block(expire(voting_period));
+    let res = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &closing, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "close"),
+            ("sender", SOMEBODY),
+            ("proposal_id", proposal_id.to_string().as_str()),
+            ("deposit", "kept"),
+        ],
+    );
+
+    // Trying to close it again fails
+    let closing = ExecuteMsg::Close { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr, &closing, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+}
+
+#[test]
+fn test_close_works_with_refund() {
+    let mut app = mock_app();
+    let voting_period = Duration::Height(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
+    let proposal_deposit_amount = Uint128::new(10);
+    let (dao_addr, cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        Some(proposal_deposit_amount),
+        Some(DepositPolicy::Refund {}),
+    );
+
+    let cw20 = Cw20Contract(cw20_addr.clone());
+
+    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: dao_addr.clone().into(),
+        amount: proposal_deposit_amount,
+        expires: None,
+    };
+
+    let res = app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &allowance, &[]);
+    assert!(res.is_ok());
+
+    let owner_initial_balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    // Get the proposal id from the logs
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    let closing = ExecuteMsg::Close { proposal_id };
+
+    // Manually expire proposal to be able to close.
+    app.update_block(expire(voting_period));
+    let res = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &closing, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "close"),
+            ("sender", SOMEBODY),
+            ("proposal_id", proposal_id.to_string().as_str()),
+            ("deposit", "refunded"),
+        ],
+    );
+
+    let owner_balance_after_failed_proposal = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
+    assert_eq!(owner_balance_after_failed_proposal, owner_initial_balance);
+
+    // Update Config to not refund proposals
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: proposal_deposit_amount,
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+
+    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
+    assert!(res.is_ok());
+
+    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: dao_addr.clone().into(),
+        amount: proposal_deposit_amount,
+        expires: None,
+    };
+
+    let res = app.execute_contract(Addr::unchecked(OWNER), cw20_addr, &allowance, &[]);
+    assert!(res.is_ok());
+
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    let closing = ExecuteMsg::Close { proposal_id };
+
+    app.update_block(expire(voting_period));
+    let res = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr, &closing, &[])
+        .unwrap();
+
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "close"),
+            ("sender", SOMEBODY),
+            ("proposal_id", proposal_id.to_string().as_str()),
+            ("deposit", "kept"),
+        ],
+    );
+
+    assert_eq!(
+        owner_initial_balance - proposal_deposit_amount,
+        cw20.balance(&app, Addr::unchecked(OWNER)).unwrap()
+    );
+}
+
+// Proposes with a deposit of 10 gov tokens under the given policy, casts the
+// given vote and closes the proposal once expired. Returns the DAO, cw20 and
+// staking contract addresses along with the reported deposit outcome.
+fn close_with_deposit_policy(
+    app: &mut App,
+    deposit_policy: DepositPolicy,
+    vote: Option<(&str, Vote)>,
+) -> (Addr, Addr, Addr, String) {
+    let voting_period = Duration::Height(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
+    let proposal_deposit_amount = Uint128::new(10);
+    let (dao_addr, cw20_addr, staking_addr) = setup_test_case(
+        app,
+        threshold,
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        Some(proposal_deposit_amount),
+        Some(deposit_policy),
+    );
+
+    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: dao_addr.clone().into(),
+        amount: proposal_deposit_amount,
+        expires: None,
+    };
+    app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &allowance, &[])
+        .unwrap();
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &[],
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    if let Some((voter, vote)) = vote {
+        let vote = ExecuteMsg::Vote(VoteMsg { proposal_id, vote });
+        app.execute_contract(Addr::unchecked(voter), dao_addr.clone(), &vote, &[])
+            .unwrap();
+    }
+
+    app.update_block(expire(voting_period));
+    let res = app
+        .execute_contract(
+            Addr::unchecked(SOMEBODY),
+            dao_addr.clone(),
+            &ExecuteMsg::Close { proposal_id },
+            &[],
+        )
+        .unwrap();
+    let outcome = res.custom_attrs(1)[3].value.clone();
+
+    (dao_addr, cw20_addr, staking_addr, outcome)
+}
+
+#[test]
+fn test_close_deposit_policies() {
+    // Owner keeps half of their initial balance after staking
+    let owner_balance = Uint128::new(INITIAL_BALANCE / 2);
+
+    // Refunded regardless of turnout
+    let mut app = mock_app();
+    let (_, cw20_addr, _, outcome) =
+        close_with_deposit_policy(&mut app, DepositPolicy::Refund {}, None);
+    assert_eq!(outcome, "refunded");
+    let cw20 = Cw20Contract(cw20_addr);
+    assert_eq!(cw20.balance(&app, OWNER).unwrap(), owner_balance);
+
+    // Under quorum the deposit is kept by the DAO
+    let mut app = mock_app();
+    let (dao_addr, cw20_addr, _, outcome) =
+        close_with_deposit_policy(&mut app, DepositPolicy::RefundIfQuorum {}, None);
+    assert_eq!(outcome, "kept");
+    let cw20 = Cw20Contract(cw20_addr);
+    assert_eq!(
+        cw20.balance(&app, OWNER).unwrap(),
+        owner_balance - Uint128::new(10)
+    );
+    assert_eq!(cw20.balance(&app, dao_addr).unwrap(), Uint128::new(10));
+
+    // VOTER1 holds 10% of the voting power, which reaches quorum
+    let mut app = mock_app();
+    let (_, cw20_addr, _, outcome) = close_with_deposit_policy(
+        &mut app,
+        DepositPolicy::RefundIfQuorum {},
+        Some((VOTER1, Vote::No)),
+    );
+    assert_eq!(outcome, "refunded");
+    let cw20 = Cw20Contract(cw20_addr);
+    assert_eq!(cw20.balance(&app, OWNER).unwrap(), owner_balance);
+
+    // A veto majority always forfeits the deposit
+    let mut app = mock_app();
+    let (dao_addr, cw20_addr, _, outcome) = close_with_deposit_policy(
+        &mut app,
+        DepositPolicy::Refund {},
+        Some((VOTER1, Vote::Veto)),
+    );
+    assert_eq!(outcome, "kept");
+    let cw20 = Cw20Contract(cw20_addr);
+    assert_eq!(cw20.balance(&app, dao_addr).unwrap(), Uint128::new(10));
+
+    // Burning reduces the token supply
+    let mut app = mock_app();
+    let (dao_addr, cw20_addr, _, outcome) =
+        close_with_deposit_policy(&mut app, DepositPolicy::Burn {}, None);
+    assert_eq!(outcome, "burned");
+    let cw20 = Cw20Contract(cw20_addr);
+    assert_eq!(cw20.balance(&app, dao_addr).unwrap(), Uint128::zero());
+    assert_eq!(
+        cw20.meta(&app).unwrap().total_supply,
+        Uint128::new(INITIAL_BALANCE * 10 - 10)
+    );
+
+    // Funding the staking contract increases the value of staked tokens
+    let mut app = mock_app();
+    let (dao_addr, cw20_addr, staking_addr, outcome) = close_with_deposit_policy(
+        &mut app,
+        DepositPolicy::SendToStaking {},
+        Some((VOTER1, Vote::Veto)),
+    );
+    assert_eq!(outcome, "sent_to_staking");
+    let cw20 = Cw20Contract(cw20_addr);
+    assert_eq!(cw20.balance(&app, dao_addr).unwrap(), Uint128::zero());
+    assert_eq!(
+        cw20.balance(&app, staking_addr).unwrap(),
+        Uint128::new(INITIAL_BALANCE * 5 + 10)
+    );
+}
+
+#[test]
+fn test_close_rejected_early() {
+    let mut app = mock_app();
+    let voting_period = Duration::Height(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
+    let proposal_deposit_amount = Uint128::new(10);
+    let (dao_addr, cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        Some(proposal_deposit_amount),
+        Some(DepositPolicy::Refund {}),
+    );
+    let cw20 = Cw20Contract(cw20_addr.clone());
+
+    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: dao_addr.clone().into(),
+        amount: proposal_deposit_amount,
+        expires: None,
+    };
+    app.execute_contract(Addr::unchecked(OWNER), cw20_addr, &allowance, &[])
+        .unwrap();
+    let owner_initial_balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &[],
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // The proposal is rejected as soon as a majority votes no
+    for voter in [POWER_VOTER, VOTER3] {
+        let vote = ExecuteMsg::Vote(VoteMsg {
+            proposal_id,
+            vote: Vote::No,
+        });
+        app.execute_contract(Addr::unchecked(voter), dao_addr.clone(), &vote, &[])
+            .unwrap();
+    }
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Rejected);
+
+    // It can be closed before it expires, which settles its deposit
+    let closing = ExecuteMsg::Close { proposal_id };
+    let res = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &closing, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "close"),
+            ("sender", SOMEBODY),
+            ("proposal_id", proposal_id.to_string().as_str()),
+            ("deposit", "refunded"),
+        ],
+    );
+    assert_eq!(
+        cw20.balance(&app, Addr::unchecked(OWNER)).unwrap(),
+        owner_initial_balance
+    );
+
+    // The deposit is only settled once
+    let err = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr, &closing, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+}
+
+#[test]
+fn test_send_to_staking_requires_gov_token_deposit() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::AbsolutePercentage {
+        percentage: Decimal::percent(20),
+    };
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: Uint128::new(10),
+        proposal_deposit_token: DepositToken::Native {
+            denom: NATIVE_TOKEN_DENOM.to_string(),
+        },
+        deposit_policy: DepositPolicy::SendToStaking {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+    let err = app
+        .execute_contract(dao_addr.clone(), dao_addr, &update_config_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidDepositPolicy {},
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn quorum_enforced_even_if_absolute_threshold_met() {
+    let mut app = mock_app();
+
+    // 33% required for quora, which is 5 of the initial 15
+    // 50% yes required to pass early (8 of the initial 15)
+    let voting_period = Duration::Time(20000);
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
+        &mut app,
+        // note that 60% yes is not enough to pass without 20% no as well
+        Threshold::ThresholdQuorum {
+            threshold: Decimal::percent(50),
+            quorum: Decimal::percent(80),
+        },
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // create proposal
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    // Get the proposal id from the logs
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    let prop_status = |app: &App| -> Status {
+        let query_prop = QueryMsg::Proposal { proposal_id };
+        let prop: ProposalResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
+        prop.status
+    };
+    assert_eq!(prop_status(&app), Status::Open);
+    app.update_block(|block| block.height += 3);
+
+    // reach 60% of yes votes, not enough to pass early (or late)
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    app.execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
+    app.execute_contract(Addr::unchecked(VOTER2), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
+    app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &yes_vote, &[])
+        .unwrap();
+
+    // 9 of 15 is 60% absolute threshold, but less than 12 (80% quorum needed)
+    assert_eq!(prop_status(&app), Status::Open);
+
+    // add 3 weight no vote and we hit quorum and this passes
+    let no_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::No,
+    });
+    app.execute_contract(
+        Addr::unchecked(POWER_VOTER),
+        dao_addr.clone(),
+        &no_vote,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(prop_status(&app), Status::Passed);
+}
+
+#[test]
+fn test_burn_does_not_change_proposal_query_response_threshold() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(20),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    let query_prop = QueryMsg::Proposal { proposal_id };
+    let prop: ProposalResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
+
+    let total_weight = match prop.threshold {
+        ThresholdResponse::AbsoluteCount {
+            weight: _,
+            total_weight,
+        } => total_weight,
+        ThresholdResponse::AbsolutePercentage {
+            percentage: _,
+            total_weight,
+        } => total_weight,
+        ThresholdResponse::ThresholdQuorum {
+            threshold: _,
+            quorum: _,
+            total_weight,
+        } => total_weight,
+    };
+
+    assert_eq!(total_weight, Uint128::from(20000000u128));
+
+    // Burn some tokens
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        cw20_addr,
+        &cw20::Cw20ExecuteMsg::Burn {
+            amount: Uint128::from(1u128),
+        },
+        &[],
+    )
+    .unwrap();
+
+    let query_prop = QueryMsg::Proposal { proposal_id };
+    let prop: ProposalResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
+
+    let total_weight = match prop.threshold {
+        ThresholdResponse::AbsoluteCount {
+            weight: _,
+            total_weight,
+        } => total_weight,
+        ThresholdResponse::AbsolutePercentage {
+            percentage: _,
+            total_weight,
+        } => total_weight,
+        ThresholdResponse::ThresholdQuorum {
+            threshold: _,
+            quorum: _,
+            total_weight,
+        } => total_weight,
+    };
+
+    assert_eq!(total_weight, Uint128::from(20000000u128));
+}
+
+#[test]
+fn query_proposal_tally() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(20000);
+    let (dao_addr, _, _) = setup_test_case(
+        &mut app,
+        Threshold::ThresholdQuorum {
+            threshold: Decimal::percent(50),
+            quorum: Decimal::percent(80),
+        },
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    let assert_tally_is = |app: &mut App, proposal_id, expected| {
+        let query_prop = QueryMsg::Tally { proposal_id };
+        let prop: VoteTallyResponse = app.wrap().query_wasm_smart(&dao_addr, &query_prop).unwrap();
+        assert_eq!(prop, expected);
+    };
+
+    // create proposal
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    // Get the proposal id from the logs
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    assert_tally_is(
+        &mut app,
+        proposal_id,
+        VoteTallyResponse {
+            status: Status::Open,
+            threshold: ThresholdResponse::ThresholdQuorum {
+                threshold: Decimal::percent(50),
+                quorum: Decimal::percent(80),
+                total_weight: Uint128::from(20000000u128),
+            },
+            quorum: Decimal::percent(0),
+            total_votes: Uint128::zero(),
+            total_weight: Uint128::from(20000000u128),
+            votes: Votes::new(Uint128::zero()),
+            choice_votes: vec![],
+        },
+    );
+
+    // Vote yes with 20% of voting power. This will not pass the
+    // proposal.
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
+    assert!(res.is_ok());
+
+    assert_tally_is(
+        &mut app,
+        proposal_id,
+        VoteTallyResponse {
+            status: Status::Open,
+            threshold: ThresholdResponse::ThresholdQuorum {
+                threshold: Decimal::percent(50),
+                quorum: Decimal::percent(80),
+                total_weight: Uint128::from(20000000u128),
+            },
+            quorum: Decimal::percent(20),
+            total_votes: Uint128::from(4000000u128),
+            total_weight: Uint128::from(20000000u128),
+            votes: Votes::new(Uint128::from(4000000u128)),
+            choice_votes: vec![],
+        },
+    );
+
+    // Vote abstain with 10% of voting power. This will not pass
+    // the proposal.
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Abstain,
+    });
+    let res = app.execute_contract(Addr::unchecked(VOTER1), dao_addr.clone(), &yes_vote, &[]);
+    assert!(res.is_ok());
+
+    assert_tally_is(
+        &mut app,
+        proposal_id,
+        VoteTallyResponse {
+            status: Status::Open,
+            threshold: ThresholdResponse::ThresholdQuorum {
+                threshold: Decimal::percent(50),
+                quorum: Decimal::percent(80),
+                total_weight: Uint128::from(20000000u128),
+            },
+            quorum: Decimal::percent(30),
+            total_votes: Uint128::from(6000000u128),
+            total_weight: Uint128::from(20000000u128),
+            votes: Votes {
+                yes: Uint128::from(4000000u128),
+                abstain: Uint128::from(2000000u128),
+                no: Uint128::zero(),
+                veto: Uint128::zero(),
+            },
+            choice_votes: vec![],
+        },
+    );
+
+    // Vote yes with and additional 50% of voting power. This will
+    // pass the proposal at 80% quorum and 70% yes.
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app.execute_contract(
+        Addr::unchecked(POWER_VOTER),
+        dao_addr.clone(),
+        &yes_vote,
+        &[],
+    );
+    assert!(res.is_ok());
+
+    assert_tally_is(
+        &mut app,
+        proposal_id,
+        VoteTallyResponse {
+            status: Status::Passed,
+            threshold: ThresholdResponse::ThresholdQuorum {
+                threshold: Decimal::percent(50),
+                quorum: Decimal::percent(80),
+                total_weight: Uint128::from(20000000u128),
+            },
+            quorum: Decimal::percent(80),
             total_votes: Uint128::from(16000000u128),
             total_weight: Uint128::from(20000000u128),
             votes: Votes {
@@ -1892,353 +3669,2034 @@ fn query_proposal_tally() {
                 no: Uint128::zero(),
                 veto: Uint128::zero(),
             },
-        },
+            choice_votes: vec![],
+        },
+    );
+}
+
+#[test]
+fn test_update_config() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(20),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, cw20_addr, staking_addr) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // Nobody can call call update config method directly
+    let new_threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
+    let new_voting_period = Duration::Time(5000000);
+    let new_proposal_deposit_amount = Uint128::from(10u8);
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao".to_string(),
+        description: "a really great DAO with emojis 💫 and a name that is really long!"
+            .to_string(),
+        threshold: new_threshold.clone(),
+        max_voting_period: new_voting_period,
+        proposal_deposit: new_proposal_deposit_amount,
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: Some("https://imghostingwebsite.com/fqfpw.jpg".to_string()),
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: new_voting_period,
+    });
+    let res = app.execute_contract(
+        Addr::unchecked(VOTER1),
+        dao_addr.clone(),
+        &update_config_msg,
+        &[],
+    );
+    assert!(res.is_err());
+    let res = app.execute_contract(
+        Addr::unchecked(OWNER),
+        dao_addr.clone(),
+        &update_config_msg,
+        &[],
+    );
+    assert!(res.is_err());
+
+    let wasm_msg = WasmMsg::Execute {
+        contract_addr: dao_addr.clone().into(),
+        msg: to_binary(&update_config_msg).unwrap(),
+        funds: vec![],
+    };
+
+    // Update config proposal must be made
+    let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
+        title: String::from("Change params"),
+        description: String::from("Updates threshold and max voting params"),
+        msgs: vec![wasm_msg.into()],
+        latest: None,
+    });
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Imediately passes on yes vote
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
+    assert!(res.is_ok());
+
+    // Execute
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
+    assert!(res.is_ok());
+
+    // Check that config was updated
+    let res: ConfigResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::GetConfig {})
+        .unwrap();
+
+    assert_eq!(
+        res,
+        ConfigResponse {
+            config: Config {
+                name: "dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao".to_string(),
+                description: "a really great DAO with emojis 💫 and a name that is really long!"
+                    .to_string(),
+                threshold: new_threshold,
+                max_voting_period: new_voting_period,
+                proposal_deposit: new_proposal_deposit_amount,
+                proposal_deposit_token: DepositToken::GovToken {},
+                deposit_policy: DepositPolicy::Keep {},
+                image_url: Some("https://imghostingwebsite.com/fqfpw.jpg".to_string()),
+                only_members_execute: true,
+                automatically_add_cw20s: true,
+                allow_revoting: false,
+                timelock: None,
+                guardian: None,
+                cancelled_deposit_policy: DepositPolicy::Keep {},
+                catch_execution_failures: false,
+                min_proposer_stake: None,
+                proposer_allowlist: None,
+                max_open_proposals: None,
+                proposal_categories: vec![],
+                voter_rewards: None,
+                voting_power_transform: VotingPowerTransform::Linear {},
+                draft_duration: new_voting_period,
+            },
+            gov_token: cw20_addr,
+            staking_contract: staking_addr,
+        }
+    )
+}
+
+#[test]
+fn test_update_staking_contract() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(20),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, cw20_addr, _staking_addr) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // Nobody can call call update staking contract method directly
+    let update_staking_contract_msg = ExecuteMsg::UpdateStakingContract {
+        new_staking_contract: "Better_Staking_Contract".to_string(),
+    };
+    let res = app.execute_contract(
+        Addr::unchecked(VOTER1),
+        dao_addr.clone(),
+        &update_staking_contract_msg,
+        &[],
+    );
+    assert!(res.is_err());
+    let res = app.execute_contract(
+        Addr::unchecked(OWNER),
+        dao_addr.clone(),
+        &update_staking_contract_msg,
+        &[],
+    );
+    assert!(res.is_err());
+
+    let wasm_msg = WasmMsg::Execute {
+        contract_addr: dao_addr.clone().into(),
+        msg: to_binary(&update_staking_contract_msg).unwrap(),
+        funds: vec![],
+    };
+
+    // Update config proposal must be made
+    let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
+        title: String::from("Change params"),
+        description: String::from("Updates threshold and max voting params"),
+        msgs: vec![wasm_msg.into()],
+        latest: None,
+    });
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Imediately passes on yes vote
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
+    assert!(res.is_ok());
+
+    // Execute
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
+    assert!(res.is_ok());
+
+    // Check that config was updated
+    let res: ConfigResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::GetConfig {})
+        .unwrap();
+
+    assert_eq!(
+        res,
+        ConfigResponse {
+            config: Config {
+                name: "dao-dao".to_string(),
+                description: "a great DAO!".to_string(),
+                threshold,
+                max_voting_period: voting_period,
+                proposal_deposit: Uint128::zero(),
+                proposal_deposit_token: DepositToken::GovToken {},
+                deposit_policy: DepositPolicy::Keep {},
+                image_url: None,
+                only_members_execute: true,
+                automatically_add_cw20s: true,
+                allow_revoting: false,
+                timelock: None,
+                guardian: None,
+                cancelled_deposit_policy: DepositPolicy::Keep {},
+                catch_execution_failures: false,
+                min_proposer_stake: None,
+                proposer_allowlist: None,
+                max_open_proposals: None,
+                proposal_categories: vec![],
+                voter_rewards: None,
+                voting_power_transform: VotingPowerTransform::Linear {},
+                draft_duration: voting_period,
+            },
+            gov_token: cw20_addr,
+            staking_contract: Addr::unchecked("Better_Staking_Contract"),
+        }
+    )
+}
+
+#[test]
+fn test_pause_dao() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(20),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, _cw20_addr, _staking_addr) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // Pause DAO until height
+    const PAUSE_HEIGHT: u64 = 100000000;
+    let pause_dao_msg = ExecuteMsg::PauseDAO {
+        expiration: Expiration::AtHeight(PAUSE_HEIGHT),
+    };
+
+    // Nobody can call call update staking contract method directly
+    let res = app.execute_contract(
+        Addr::unchecked(VOTER1),
+        dao_addr.clone(),
+        &pause_dao_msg,
+        &[],
+    );
+    assert!(res.is_err());
+    let res = app.execute_contract(
+        Addr::unchecked(OWNER),
+        dao_addr.clone(),
+        &pause_dao_msg,
+        &[],
+    );
+    assert!(res.is_err());
+
+    let wasm_msg = WasmMsg::Execute {
+        contract_addr: dao_addr.clone().into(),
+        msg: to_binary(&pause_dao_msg).unwrap(),
+        funds: vec![],
+    };
+
+    // Pause DAO proposal must be made
+    let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
+        title: String::from("Change params"),
+        description: String::from("Updates threshold and max voting params"),
+        msgs: vec![wasm_msg.into()],
+        latest: None,
+    });
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Imediately passes on yes vote
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
+    assert!(res.is_ok());
+
+    // Execute
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
+    assert!(res.is_ok());
+
+    // Check that DAO is paused, Propose should fail
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+        .unwrap_err();
+    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
+
+    // Vote should fail
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap_err();
+    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
+
+    // Execute should fail
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
+    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
+
+    // Close should fail
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &ExecuteMsg::Close { proposal_id },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
+
+    // Pause expiration height is reached
+    app.update_block(|b| b.height = PAUSE_HEIGHT + 1);
+
+    // Propose succeeds
+    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[]);
+    assert!(res.is_ok());
+
+    // Vote yields different error
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
+        .unwrap_err();
+    assert_eq!(res.to_string(), ContractError::NotOpen {}.to_string());
+
+    // Expiration yields different error
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
+    assert_eq!(
+        res.to_string(),
+        ContractError::WrongExecuteStatus {}.to_string()
+    );
+
+    // Close yields different error
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr,
+            &ExecuteMsg::Close { proposal_id },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        res.to_string(),
+        ContractError::WrongCloseStatus {}.to_string()
+    );
+}
+
+#[test]
+fn test_config_query() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, cw20_addr, staking_addr) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    let config_query = QueryMsg::GetConfig {};
+    let res: ConfigResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &config_query)
+        .unwrap();
+
+    assert_eq!(
+        res,
+        ConfigResponse {
+            config: Config {
+                name: "dao-dao".to_string(),
+                description: "a great DAO!".to_string(),
+                threshold,
+                max_voting_period: voting_period,
+                proposal_deposit: Uint128::zero(),
+                proposal_deposit_token: DepositToken::GovToken {},
+                deposit_policy: DepositPolicy::Keep {},
+                image_url: None,
+                only_members_execute: true,
+                automatically_add_cw20s: true,
+                allow_revoting: false,
+                timelock: None,
+                guardian: None,
+                cancelled_deposit_policy: DepositPolicy::Keep {},
+                catch_execution_failures: false,
+                min_proposer_stake: None,
+                proposer_allowlist: None,
+                max_open_proposals: None,
+                proposal_categories: vec![],
+                voter_rewards: None,
+                voting_power_transform: VotingPowerTransform::Linear {},
+                draft_duration: voting_period,
+            },
+            gov_token: cw20_addr,
+            staking_contract: staking_addr,
+        }
+    )
+}
+
+#[test]
+fn test_proposal_deposit_works() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::AbsolutePercentage {
+        percentage: Decimal::percent(20),
+    };
+    let (dao_addr, cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    let cw20 = Cw20Contract(cw20_addr.clone());
+
+    let initial_owner_cw20_balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
+
+    // ensure we have cash to cover the proposal
+    let contract_bal = app
+        .wrap()
+        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+        .unwrap();
+    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
+
+    let proposal_deposit_amount = Uint128::new(10);
+
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: proposal_deposit_amount,
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
+    assert!(res.is_ok());
+
+    // Give dao allowance for proposal
+    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: dao_addr.clone().into(),
+        amount: proposal_deposit_amount,
+        expires: None,
+    };
+    let res = app.execute_contract(Addr::unchecked(OWNER), cw20_addr, &allowance, &[]);
+    assert!(res.is_ok());
+
+    // create proposal with 0 vote power
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap();
+
+    // Get the proposal id from the logs
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Check proposal deposit was made
+    let balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
+    let expected_balance = initial_owner_cw20_balance
+        .checked_sub(proposal_deposit_amount)
+        .unwrap();
+    assert_eq!(balance, expected_balance);
+
+    // Only Passed can be executed
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::WrongExecuteStatus {},
+        err.downcast().unwrap()
+    );
+
+    // Vote it, so it passes
+    let vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "vote"),
+            ("sender", VOTER3),
+            ("proposal_id", proposal_id.to_string().as_str()),
+            ("status", "Passed"),
+        ],
+    );
+
+    // Execute works. Anybody can execute Passed proposals
+    let res = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr, &execution, &[])
+        .unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "execute"),
+            ("sender", VOTER3),
+            ("proposal_id", proposal_id.to_string().as_str()),
+        ],
+    );
+
+    // Check deposit has been refunded
+    let balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
+    assert_eq!(balance, initial_owner_cw20_balance);
+}
+
+#[test]
+fn test_native_proposal_deposit_works() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::AbsolutePercentage {
+        percentage: Decimal::percent(20),
+    };
+    let (dao_addr, _cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    app.sudo(SudoMsg::Bank(BankSudo::Mint {
+        to_address: OWNER.to_string(),
+        amount: coins(100, NATIVE_TOKEN_DENOM),
+    }))
+    .unwrap();
+
+    // Without a deposit configured, funds sent with a proposal are refused
+    let err = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &coins(10, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::Payment(PaymentError::NonPayable {}),
+        err.downcast().unwrap()
+    );
+
+    let proposal_deposit_amount = Uint128::new(10);
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: proposal_deposit_amount,
+        proposal_deposit_token: DepositToken::Native {
+            denom: NATIVE_TOKEN_DENOM.to_string(),
+        },
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+    app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[])
+        .unwrap();
+
+    // Proposing without the deposit fails
+    let proposal = pay_somebody_proposal();
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::Payment(PaymentError::NoFunds {}),
+        err.downcast().unwrap()
+    );
+
+    // Proposing with the wrong amount fails
+    let err = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &proposal,
+            &coins(5, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidDeposit {
+            amount: proposal_deposit_amount,
+            denom: NATIVE_TOKEN_DENOM.to_string(),
+        },
+        err.downcast().unwrap()
+    );
+
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &proposal,
+            &coins(10, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    let balance = app.wrap().query_balance(OWNER, NATIVE_TOKEN_DENOM).unwrap();
+    assert_eq!(balance, coin(90, NATIVE_TOKEN_DENOM));
+
+    // The proposal reports the denom of its deposit
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.deposit_amount, proposal_deposit_amount);
+    assert_eq!(
+        prop.deposit_denom,
+        Denom::Native(NATIVE_TOKEN_DENOM.to_string())
+    );
+
+    let vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+        .unwrap();
+    let execution = ExecuteMsg::Execute { proposal_id };
+    app.execute_contract(Addr::unchecked(VOTER3), dao_addr, &execution, &[])
+        .unwrap();
+
+    // Deposit is refunded in the native denom
+    let balance = app.wrap().query_balance(OWNER, NATIVE_TOKEN_DENOM).unwrap();
+    assert_eq!(balance, coin(100, NATIVE_TOKEN_DENOM));
+}
+
+#[test]
+fn test_receive() {
+    let mut app = mock_app();
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::AbsolutePercentage {
+        percentage: Decimal::percent(20),
+    };
+    let (dao_addr, cw20_addr, _) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // Make a new token to send to the DAO
+    let cw20_id = app.store_code(contract_cw20_gov());
+    let msg = cw20_base::msg::InstantiateMsg {
+        name: String::from("NewCoin"),
+        symbol: String::from("COIN"),
+        decimals: 6,
+        initial_balances: vec![
+            Cw20Coin {
+                address: OWNER.to_string(),
+                amount: Uint128::new(INITIAL_BALANCE * 5),
+            },
+            Cw20Coin {
+                address: VOTER1.to_string(),
+                amount: Uint128::new(INITIAL_BALANCE * 5),
+            },
+        ],
+        mint: None,
+        marketing: None,
+    };
+    let res = app.instantiate_contract(cw20_id, Addr::unchecked(OWNER), &msg, &[], "cw20", None);
+    assert!(res.is_ok());
+    let other_cw20_addr = res.unwrap();
+
+    let msg = Cw20ExecuteMsg::Send {
+        contract: dao_addr.to_string(),
+        amount: Uint128::new(200u128),
+        msg: Default::default(),
+    };
+    let res = app.execute_contract(Addr::unchecked(VOTER1), other_cw20_addr.clone(), &msg, &[]);
+    assert!(res.is_ok());
+
+    // Query balances, expecting to see the other cw20 already added
+    let cw20_token_balances_msg = QueryMsg::Cw20Balances {
+        start_after: None,
+        limit: None,
+    };
+    let all_balances: Cw20BalancesResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &cw20_token_balances_msg)
+        .unwrap();
+    assert_eq!(
+        all_balances.cw20_balances,
+        vec![
+            Cw20CoinVerified {
+                address: cw20_addr.clone(),
+                amount: Uint128::zero(),
+            },
+            Cw20CoinVerified {
+                address: other_cw20_addr.clone(),
+                amount: Uint128::new(200u128)
+            }
+        ]
+    );
+
+    // Update to stop auto adding cw20s
+    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: Uint128::new(0),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: false,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
+    });
+    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
+    assert!(res.is_ok());
+
+    // Make another new token with initial balance, this time it won't be added automatically when sent
+    let cw20_id = app.store_code(contract_cw20_gov());
+    let msg = cw20_base::msg::InstantiateMsg {
+        name: String::from("NotAdded"),
+        symbol: String::from("NOT"),
+        decimals: 6,
+        initial_balances: vec![
+            Cw20Coin {
+                address: OWNER.to_string(),
+                amount: Uint128::new(INITIAL_BALANCE * 5),
+            },
+            Cw20Coin {
+                address: VOTER1.to_string(),
+                amount: Uint128::new(INITIAL_BALANCE * 5),
+            },
+        ],
+        mint: None,
+        marketing: None,
+    };
+    let res = app.instantiate_contract(cw20_id, Addr::unchecked(OWNER), &msg, &[], "cw20", None);
+    assert!(res.is_ok());
+    let not_added_cw20_addr = res.unwrap();
+
+    // Send some cw20s that will not be automatically tracked
+    let msg = Cw20ExecuteMsg::Send {
+        contract: dao_addr.to_string(),
+        amount: Uint128::new(200u128),
+        msg: Default::default(),
+    };
+    let res = app.execute_contract(Addr::unchecked(VOTER1), not_added_cw20_addr, &msg, &[]);
+    assert!(res.is_ok());
+
+    // Query all balances, this time will still only have 2
+    // As we have disabled automatic listing of cw20s
+    let cw20_token_balances_msg = QueryMsg::Cw20Balances {
+        start_after: None,
+        limit: None,
+    };
+    let all_balances: Cw20BalancesResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &cw20_token_balances_msg)
+        .unwrap();
+    assert_eq!(
+        all_balances.cw20_balances,
+        vec![
+            Cw20CoinVerified {
+                address: cw20_addr,
+                amount: Uint128::zero(),
+            },
+            Cw20CoinVerified {
+                address: other_cw20_addr,
+                amount: Uint128::new(200u128)
+            }
+        ]
+    );
+}
+
+#[test]
+fn treasury_queries() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::AbsolutePercentage {
+        percentage: Decimal::percent(20),
+    };
+    let (dao_addr, _, _) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(10, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // Query All Treasury Balances
+    let cw20_token_balances_msg = QueryMsg::Cw20Balances {
+        start_after: None,
+        limit: None,
+    };
+    let all_balances: Cw20BalancesResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &cw20_token_balances_msg)
+        .unwrap();
+    assert_eq!(
+        all_balances.cw20_balances,
+        vec![Cw20CoinVerified {
+            address: Addr::unchecked("Contract #0"),
+            amount: Uint128::zero(),
+        }]
+    );
+
+    // Query token list
+    let token_list: TokenListResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Cw20TokenList {})
+        .unwrap();
+    assert_eq!(token_list.token_list.len(), 1);
+
+    // Make a new token with initial balance
+    let cw20_id = app.store_code(contract_cw20_gov());
+    let msg = cw20_base::msg::InstantiateMsg {
+        name: String::from("NewCoin"),
+        symbol: String::from("COIN"),
+        decimals: 6,
+        initial_balances: vec![Cw20Coin {
+            address: OWNER.to_string(),
+            amount: Uint128::new(INITIAL_BALANCE * 5),
+        }],
+        mint: None,
+        marketing: None,
+    };
+    let res = app.instantiate_contract(cw20_id, Addr::unchecked(OWNER), &msg, &[], "cw20", None);
+    assert!(res.is_ok());
+    let other_cw20_addr = res.unwrap();
+
+    // Manually add token to list by voting
+    let update_token_list_msg = ExecuteMsg::UpdateCw20TokenList {
+        to_add: vec!["NEW".to_string(), "NEWNEW".to_string()],
+        to_remove: vec![other_cw20_addr.to_string()],
+    };
+    let wasm_msg = WasmMsg::Execute {
+        contract_addr: dao_addr.clone().into(),
+        msg: to_binary(&update_token_list_msg).unwrap(),
+        funds: vec![],
+    };
+    let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
+        title: String::from("Change params"),
+        description: String::from("Updates threshold and max voting params"),
+        msgs: vec![wasm_msg.into()],
+        latest: None,
+    });
+    let res = app
+        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // Imediately passes on yes vote
+    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+        proposal_id,
+        vote: Vote::Yes,
+    });
+    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
+    assert!(res.is_ok());
+
+    // Execute
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
+    assert!(res.is_ok());
+
+    // Token list should be 3 now
+    let token_list: TokenListResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Cw20TokenList {})
+        .unwrap();
+    assert_eq!(token_list.token_list.len(), 3);
+
+    // Contact #2 token has been removed
+    assert!(!token_list
+        .token_list
+        .contains(&Addr::unchecked("Contract #2")));
+}
+
+#[test]
+fn test_selected_staking_snapshots() {
+    let mut app = mock_app();
+
+    let cw20_id = app.store_code(contract_cw20_gov());
+    let initial_balances = vec![
+        Cw20Coin {
+            address: VOTER1.to_string(),
+            amount: Uint128::new(INITIAL_BALANCE),
+        },
+        Cw20Coin {
+            address: VOTER2.to_string(),
+            amount: Uint128::new(INITIAL_BALANCE),
+        },
+    ];
+    let msg = cw20_base::msg::InstantiateMsg {
+        name: String::from("Test"),
+        symbol: String::from("TEST"),
+        decimals: 6,
+        initial_balances: initial_balances.clone(),
+        mint: None,
+        marketing: None,
+    };
+    let cw20_addr = app
+        .instantiate_contract(cw20_id, Addr::unchecked(OWNER), &msg, &[], "cw20", None)
+        .unwrap();
+
+    let voting_period = Duration::Height(10);
+    let dao_code_id = app.store_code(contract_dao());
+    let staking_code_id = app.store_code(contract_staking());
+    let msg = InstantiateMsg {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        gov_token: GovTokenMsg::UseExistingCw20 {
+            addr: cw20_addr.to_string(),
+            stake_contract_code_id: staking_code_id,
+            label: "dao-dao".to_string(),
+            unstaking_duration: None,
+            snapshot_strategy: Some(SnapshotStrategy::Selected {}),
+        },
+        threshold: Threshold::AbsolutePercentage {
+            percentage: Decimal::percent(90),
+        },
+        max_voting_period: voting_period,
+        proposal_deposit_amount: Uint128::zero(),
+        proposal_deposit_token: None,
+        deposit_policy: None,
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: None,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: None,
+        catch_execution_failures: None,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: None,
+        voter_rewards: None,
+        voting_power_transform: None,
+        draft_duration: None,
+    };
+    let dao_addr = app
+        .instantiate_contract(
+            dao_code_id,
+            Addr::unchecked(OWNER),
+            &msg,
+            &[],
+            "cw3-dao",
+            None,
+        )
+        .unwrap();
+    let res: ConfigResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::GetConfig {})
+        .unwrap();
+    let staking_addr = res.staking_contract;
+    stake_balances(&mut app, initial_balances, &cw20_addr, &staking_addr);
+
+    // Creating a proposal checkpoints staked balances at its start height
+    let res = app
+        .execute_contract(
+            Addr::unchecked(VOTER1),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &[],
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    let start_height = app.block_info().height;
+    app.update_block(next_block);
+
+    // Unstaking after the proposal was created doesn't change the vote weight
+    app.execute_contract(
+        Addr::unchecked(VOTER2),
+        staking_addr.clone(),
+        &stake_cw20::msg::ExecuteMsg::Unstake {
+            amount: Uint128::new(INITIAL_BALANCE / 2),
+        },
+        &[],
+    )
+    .unwrap();
+    app.update_block(next_block);
+    app.execute_contract(
+        Addr::unchecked(VOTER2),
+        dao_addr.clone(),
+        &ExecuteMsg::Vote(VoteMsg {
+            proposal_id,
+            vote: Vote::Yes,
+        }),
+        &[],
+    )
+    .unwrap();
+    let tally = get_tally(&app, dao_addr.as_ref(), proposal_id);
+    assert_eq!(tally, Uint128::new(INITIAL_BALANCE / 2));
+
+    // The checkpoint is kept while the proposal is open
+    let power_at_start = |app: &App| {
+        app.wrap()
+            .query_wasm_smart::<stake_cw20::msg::VotingPowerAtHeightResponse>(
+                &staking_addr,
+                &stake_cw20::msg::QueryMsg::VotingPowerAtHeight {
+                    address: VOTER2.to_string(),
+                    height: Some(start_height),
+                },
+            )
+            .map(|res| res.power)
+    };
+    app.execute_contract(
+        Addr::unchecked(SOMEBODY),
+        dao_addr.clone(),
+        &ExecuteMsg::PruneStakingCheckpoints {
+            start_after: None,
+            limit: None,
+        },
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        power_at_start(&app).unwrap(),
+        Uint128::new(INITIAL_BALANCE / 2)
+    );
+
+    // And pruned once the proposal is no longer open, which also drops it
+    // from the index of open proposals
+    let indexed = |app: &App| {
+        app.wrap()
+            .query_wasm_raw(&dao_addr, OPEN_PROPOSAL_IDS.key(proposal_id).to_vec())
+            .unwrap()
+            .is_some()
+    };
+    assert!(indexed(&app));
+    app.update_block(expire(voting_period));
+    app.execute_contract(
+        Addr::unchecked(SOMEBODY),
+        dao_addr.clone(),
+        &ExecuteMsg::PruneStakingCheckpoints {
+            start_after: None,
+            limit: None,
+        },
+        &[],
+    )
+    .unwrap();
+    power_at_start(&app).unwrap_err();
+    assert!(!indexed(&app));
+}
+
+#[test]
+fn test_lockup_voting_power() {
+    let mut app = mock_app();
+
+    let voting_period = Duration::Time(2000000);
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
+    };
+    let (dao_addr, cw20_addr, staking_addr) = setup_test_case(
+        &mut app,
+        threshold,
+        voting_period,
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    // The DAO administers the staking contract's lockup tiers
+    app.execute_contract(
+        dao_addr.clone(),
+        staking_addr.clone(),
+        &stake_cw20::msg::ExecuteMsg::UpdateLockupTiers {
+            tiers: vec![LockupTier {
+                duration: Duration::Height(100),
+                multiplier: Decimal::percent(200),
+            }],
+        },
+        &[],
+    )
+    .unwrap();
+
+    // VOTER1 locks the other half of their balance
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        cw20_addr,
+        &Cw20ExecuteMsg::Send {
+            contract: staking_addr.to_string(),
+            amount: Uint128::new(INITIAL_BALANCE / 2),
+            msg: to_binary(&ReceiveMsg::StakeLocked {
+                duration: Duration::Height(100),
+            })
+            .unwrap(),
+        },
+        &[],
+    )
+    .unwrap();
+    app.update_block(next_block);
+
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &[],
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        dao_addr.clone(),
+        &ExecuteMsg::Vote(VoteMsg {
+            proposal_id,
+            vote: Vote::Yes,
+        }),
+        &[],
+    )
+    .unwrap();
+    let vote: VoteResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &dao_addr,
+            &QueryMsg::Vote {
+                proposal_id,
+                voter: VOTER1.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        vote.vote.unwrap().weight,
+        Uint128::new(INITIAL_BALANCE * 3 / 2)
+    );
+
+    // The total weight includes the boost
+    let threshold: ThresholdResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Threshold {})
+        .unwrap();
+    assert_eq!(
+        threshold,
+        ThresholdResponse::ThresholdQuorum {
+            threshold: Decimal::percent(51),
+            quorum: Decimal::percent(10),
+            total_weight: Uint128::new(INITIAL_BALANCE * 6),
+        }
     );
 }
 
 #[test]
-fn test_update_config() {
+fn test_voter_rewards() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
     let threshold = Threshold::ThresholdQuorum {
-        threshold: Decimal::percent(20),
+        threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let (dao_addr, cw20_addr, staking_addr) = setup_test_case(
+    let (dao_addr, cw20_addr, _) = setup_test_case(
         &mut app,
-        threshold,
+        threshold.clone(),
         voting_period,
         coins(100, NATIVE_TOKEN_DENOM),
         None,
         None,
     );
+    let cw20 = Cw20Contract(cw20_addr.clone());
 
-    // Nobody can call call update config method directly
-    let new_threshold = Threshold::ThresholdQuorum {
-        threshold: Decimal::percent(51),
-        quorum: Decimal::percent(10),
+    let fund = |amount| Cw20ExecuteMsg::Send {
+        contract: dao_addr.to_string(),
+        amount: Uint128::new(amount),
+        msg: to_binary(&crate::msg::ReceiveMsg::FundVoterRewards {}).unwrap(),
     };
-    let new_voting_period = Duration::Time(5000000);
-    let new_proposal_deposit_amount = Uint128::from(10u8);
+    let err = app
+        .execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &fund(1000), &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::VoterRewardsDisabled {},
+        err.downcast().unwrap()
+    );
+
     let update_config_msg = ExecuteMsg::UpdateConfig(Config {
-        name: "dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao".to_string(),
-        description: "a really great DAO with emojis 💫 and a name that is really long!"
-            .to_string(),
-        threshold: new_threshold.clone(),
-        max_voting_period: new_voting_period,
-        proposal_deposit: new_proposal_deposit_amount,
-        refund_failed_proposals: None,
-        image_url: Some("https://imghostingwebsite.com/fqfpw.jpg".to_string()),
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold,
+        max_voting_period: voting_period,
+        proposal_deposit: Uint128::zero(),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: Some(VoterRewards {
+            token: None,
+            reward_per_proposal: Uint128::new(600),
+        }),
+        voting_power_transform: VotingPowerTransform::Linear {},
+        draft_duration: voting_period,
     });
-    let res = app.execute_contract(
+    app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[])
+        .unwrap();
+    app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &fund(1000), &[])
+        .unwrap();
+    let pool: Uint128 = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::VoterRewardPool {})
+        .unwrap();
+    assert_eq!(pool, Uint128::new(1000));
+
+    // Proposal 1 passes and is executed
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &pay_somebody_proposal(),
+        &[],
+    )
+    .unwrap();
+    for voter in [POWER_VOTER, VOTER3] {
+        app.execute_contract(
+            Addr::unchecked(voter),
+            dao_addr.clone(),
+            &ExecuteMsg::Vote(VoteMsg {
+                proposal_id: 1,
+                vote: Vote::Yes,
+            }),
+            &[],
+        )
+        .unwrap();
+    }
+
+    // Proposal 2 is rejected and closed
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &pay_somebody_proposal(),
+        &[],
+    )
+    .unwrap();
+    app.execute_contract(
         Addr::unchecked(VOTER1),
         dao_addr.clone(),
-        &update_config_msg,
+        &ExecuteMsg::Vote(VoteMsg {
+            proposal_id: 2,
+            vote: Vote::No,
+        }),
+        &[],
+    )
+    .unwrap();
+
+    // Nothing is allocated before proposals are executed or closed
+    let query_rewards = |app: &App, voter: &str| -> Vec<VoterReward> {
+        let res: VoterRewardsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &dao_addr,
+                &QueryMsg::VoterRewards {
+                    address: voter.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        res.rewards
+    };
+    assert_eq!(query_rewards(&app, POWER_VOTER), vec![]);
+
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Execute { proposal_id: 1 },
         &[],
+    )
+    .unwrap();
+    app.update_block(expire(voting_period));
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Close { proposal_id: 2 },
+        &[],
+    )
+    .unwrap();
+
+    // The 600 tokens of proposal 1 are split by ballot weight, proposal 2
+    // gets what is left in the pool
+    assert_eq!(
+        query_rewards(&app, POWER_VOTER),
+        vec![VoterReward {
+            proposal_id: 1,
+            token: cw20_addr.clone(),
+            amount: Uint128::new(428),
+        }]
     );
-    assert!(res.is_err());
-    let res = app.execute_contract(
-        Addr::unchecked(OWNER),
+    assert_eq!(
+        query_rewards(&app, VOTER3),
+        vec![VoterReward {
+            proposal_id: 1,
+            token: cw20_addr.clone(),
+            amount: Uint128::new(171),
+        }]
+    );
+    assert_eq!(
+        query_rewards(&app, VOTER1),
+        vec![VoterReward {
+            proposal_id: 2,
+            token: cw20_addr.clone(),
+            amount: Uint128::new(400),
+        }]
+    );
+    assert_eq!(query_rewards(&app, OWNER), vec![]);
+    let pool: Uint128 = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::VoterRewardPool {})
+        .unwrap();
+    assert_eq!(pool, Uint128::zero());
+
+    // Voters can only claim rewards of proposals they voted on, once
+    let claim = |proposal_ids| ExecuteMsg::ClaimVoterRewards { proposal_ids };
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER1),
+            dao_addr.clone(),
+            &claim(vec![1]),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::NoVoterReward { proposal_id: 1 },
+        err.downcast().unwrap()
+    );
+    let balance = cw20.balance(&app, VOTER1).unwrap();
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
         dao_addr.clone(),
-        &update_config_msg,
+        &claim(vec![2]),
         &[],
+    )
+    .unwrap();
+    assert_eq!(
+        cw20.balance(&app, VOTER1).unwrap(),
+        balance + Uint128::new(400)
+    );
+    assert_eq!(query_rewards(&app, VOTER1), vec![]);
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER1),
+            dao_addr.clone(),
+            &claim(vec![2]),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::NoVoterReward { proposal_id: 2 },
+        err.downcast().unwrap()
     );
-    assert!(res.is_err());
 
-    let wasm_msg = WasmMsg::Execute {
-        contract_addr: dao_addr.clone().into(),
-        msg: to_binary(&update_config_msg).unwrap(),
-        funds: vec![],
+    // The 600 tokens that are not claimed yet cannot be spent by proposals
+    app.execute_contract(
+        Addr::unchecked(OWNER),
+        cw20_addr.clone(),
+        &Cw20ExecuteMsg::Transfer {
+            recipient: dao_addr.to_string(),
+            amount: Uint128::new(500),
+        },
+        &[],
+    )
+    .unwrap();
+    let spendable = cw20.balance(&app, dao_addr.clone()).unwrap() - Uint128::new(600);
+    let pass_transfer = |app: &mut App, amount: Uint128| -> u64 {
+        let res = app
+            .execute_contract(
+                Addr::unchecked(VOTER3),
+                dao_addr.clone(),
+                &ExecuteMsg::Propose(ProposeMsg {
+                    title: "Spend".to_string(),
+                    description: "Spend the treasury".to_string(),
+                    msgs: vec![WasmMsg::Execute {
+                        contract_addr: cw20_addr.to_string(),
+                        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                            recipient: SOMEBODY.to_string(),
+                            amount,
+                        })
+                        .unwrap(),
+                        funds: vec![],
+                    }
+                    .into()],
+                    latest: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+        for voter in [POWER_VOTER, VOTER3] {
+            app.execute_contract(
+                Addr::unchecked(voter),
+                dao_addr.clone(),
+                &ExecuteMsg::Vote(VoteMsg {
+                    proposal_id,
+                    vote: Vote::Yes,
+                }),
+                &[],
+            )
+            .unwrap();
+        }
+        proposal_id
     };
+    let proposal_id = pass_transfer(&mut app, spendable + Uint128::new(1));
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER3),
+            dao_addr.clone(),
+            &ExecuteMsg::Execute { proposal_id },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::VoterRewardReserveSpent {
+            token: cw20_addr.to_string(),
+            reserved: Uint128::new(600),
+        },
+        err.downcast().unwrap()
+    );
+    let proposal_id = pass_transfer(&mut app, spendable);
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Execute { proposal_id },
+        &[],
+    )
+    .unwrap();
+    assert_eq!(cw20.balance(&app, SOMEBODY).unwrap(), spendable);
 
-    // Update config proposal must be made
-    let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
-        title: String::from("Change params"),
-        description: String::from("Updates threshold and max voting params"),
-        msgs: vec![wasm_msg.into()],
-        latest: None,
-    });
+    // Voters on a proposal rejected before it expires are rewarded without
+    // waiting for it to be closed
+    app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &fund(100), &[])
+        .unwrap();
     let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+        .execute_contract(
+            Addr::unchecked(VOTER3),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &[],
+        )
         .unwrap();
     let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
-
-    // Imediately passes on yes vote
-    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+    for voter in [POWER_VOTER, VOTER3] {
+        app.execute_contract(
+            Addr::unchecked(voter),
+            dao_addr.clone(),
+            &ExecuteMsg::Vote(VoteMsg {
+                proposal_id,
+                vote: Vote::No,
+            }),
+            &[],
+        )
+        .unwrap();
+    }
+    let reward = |amount| VoterReward {
         proposal_id,
-        vote: Vote::Yes,
-    });
-    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
-    assert!(res.is_ok());
-
-    // Execute
-    let execution = ExecuteMsg::Execute { proposal_id };
-    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
-    assert!(res.is_ok());
-
-    // Check that config was updated
-    let res: ConfigResponse = app
+        token: cw20_addr.clone(),
+        amount: Uint128::new(amount),
+    };
+    assert!(query_rewards(&app, POWER_VOTER).contains(&reward(71)));
+    assert!(query_rewards(&app, VOTER3).contains(&reward(28)));
+    let pool: Uint128 = app
         .wrap()
-        .query_wasm_smart(&dao_addr, &QueryMsg::GetConfig {})
+        .query_wasm_smart(&dao_addr, &QueryMsg::VoterRewardPool {})
         .unwrap();
+    assert_eq!(pool, Uint128::zero());
 
-    assert_eq!(
-        res,
-        ConfigResponse {
-            config: Config {
-                name: "dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao-dao".to_string(),
-                description: "a really great DAO with emojis 💫 and a name that is really long!"
-                    .to_string(),
-                threshold: new_threshold,
-                max_voting_period: new_voting_period,
-                proposal_deposit: new_proposal_deposit_amount,
-                refund_failed_proposals: None,
-                image_url: Some("https://imghostingwebsite.com/fqfpw.jpg".to_string()),
-                only_members_execute: true,
-                automatically_add_cw20s: true,
-            },
-            gov_token: cw20_addr,
-            staking_contract: staking_addr,
-        }
+    // Closing it does not allocate them twice
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Close { proposal_id },
+        &[],
     )
+    .unwrap();
+    assert!(query_rewards(&app, VOTER3).contains(&reward(28)));
+
+    // Only the DAO can run the check
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER3),
+            dao_addr,
+            &ExecuteMsg::CheckVoterRewardReserve {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
 }
 
 #[test]
-fn test_update_staking_contract() {
+fn test_voting_power_transform() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
     let threshold = Threshold::ThresholdQuorum {
-        threshold: Decimal::percent(20),
+        threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let (dao_addr, cw20_addr, _staking_addr) = setup_test_case(
-        &mut app,
-        threshold.clone(),
-        voting_period,
-        coins(100, NATIVE_TOKEN_DENOM),
-        None,
-        None,
+    let (dao_addr, _cw20_addr, staking_addr) = setup_test_case(
+        &mut app,
+        threshold.clone(),
+        voting_period,
+        coins(100, NATIVE_TOKEN_DENOM),
+        None,
+        None,
+    );
+
+    let config = |voting_power_transform| Config {
+        name: "dao-dao".to_string(),
+        description: "a great DAO!".to_string(),
+        threshold: threshold.clone(),
+        max_voting_period: voting_period,
+        proposal_deposit: Uint128::zero(),
+        proposal_deposit_token: DepositToken::GovToken {},
+        deposit_policy: DepositPolicy::Keep {},
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        allow_revoting: false,
+        timelock: None,
+        guardian: None,
+        cancelled_deposit_policy: DepositPolicy::Keep {},
+        catch_execution_failures: false,
+        min_proposer_stake: None,
+        proposer_allowlist: None,
+        max_open_proposals: None,
+        proposal_categories: vec![],
+        voter_rewards: None,
+        voting_power_transform,
+        draft_duration: voting_period,
+    };
+    let ballot_weight = |app: &App, proposal_id: u64, voter: &str| -> Uint128 {
+        let vote: VoteResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &dao_addr,
+                &QueryMsg::Vote {
+                    proposal_id,
+                    voter: voter.to_string(),
+                },
+            )
+            .unwrap();
+        vote.vote.unwrap().weight
+    };
+    let tally = |app: &App, proposal_id| -> VoteTallyResponse {
+        app.wrap()
+            .query_wasm_smart(&dao_addr, &QueryMsg::Tally { proposal_id })
+            .unwrap()
+    };
+    let vote = |app: &mut App, voter: &str, proposal_id, vote| {
+        app.execute_contract(
+            Addr::unchecked(voter),
+            dao_addr.clone(),
+            &ExecuteMsg::Vote(VoteMsg { proposal_id, vote }),
+            &[],
+        )
+        .unwrap();
+    };
+
+    // The cap must be a valid percentage
+    let err = app
+        .execute_contract(
+            dao_addr.clone(),
+            dao_addr.clone(),
+            &ExecuteMsg::UpdateConfig(config(VotingPowerTransform::Capped {
+                percentage: Decimal::zero(),
+            })),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidVotingPowerCap {},
+        err.downcast().unwrap()
     );
 
-    // Nobody can call call update staking contract method directly
-    let update_staking_contract_msg = ExecuteMsg::UpdateStakingContract {
-        new_staking_contract: "Better_Staking_Contract".to_string(),
-    };
-    let res = app.execute_contract(
+    // VOTER1 delegates their voting power to VOTER2
+    app.execute_contract(
         Addr::unchecked(VOTER1),
-        dao_addr.clone(),
-        &update_staking_contract_msg,
+        staking_addr,
+        &stake_cw20::msg::ExecuteMsg::Delegate {
+            address: VOTER2.to_string(),
+        },
         &[],
-    );
-    assert!(res.is_err());
-    let res = app.execute_contract(
-        Addr::unchecked(OWNER),
+    )
+    .unwrap();
+    app.execute_contract(
         dao_addr.clone(),
-        &update_staking_contract_msg,
+        dao_addr.clone(),
+        &ExecuteMsg::UpdateConfig(config(VotingPowerTransform::SquareRoot {})),
         &[],
-    );
-    assert!(res.is_err());
-
-    let wasm_msg = WasmMsg::Execute {
-        contract_addr: dao_addr.clone().into(),
-        msg: to_binary(&update_staking_contract_msg).unwrap(),
-        funds: vec![],
-    };
+    )
+    .unwrap();
+    app.update_block(next_block);
 
-    // Update config proposal must be made
-    let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
-        title: String::from("Change params"),
-        description: String::from("Updates threshold and max voting params"),
-        msgs: vec![wasm_msg.into()],
-        latest: None,
-    });
+    // The total weight is the sum of the square roots of the voting power
+    // of every address, counting delegated power towards the delegate
     let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &[],
+        )
         .unwrap();
     let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    assert_eq!(
+        tally(&app, proposal_id).total_weight,
+        Uint128::new(1414 + 2000 * 2 + 3162)
+    );
 
-    // Imediately passes on yes vote
-    let yes_vote = ExecuteMsg::Vote(VoteMsg {
-        proposal_id,
-        vote: Vote::Yes,
-    });
-    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
-    assert!(res.is_ok());
-
-    // Execute
-    let execution = ExecuteMsg::Execute { proposal_id };
-    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
-    assert!(res.is_ok());
-
-    // Check that config was updated
-    let res: ConfigResponse = app
-        .wrap()
-        .query_wasm_smart(&dao_addr, &QueryMsg::GetConfig {})
-        .unwrap();
+    // Half of the stake no longer carries half of the weight
+    vote(&mut app, POWER_VOTER, proposal_id, Vote::Yes);
+    assert_eq!(
+        ballot_weight(&app, proposal_id, POWER_VOTER),
+        Uint128::new(3162)
+    );
 
+    // Delegates vote with the square root of the power delegated to them.
+    // A delegator voting themselves takes the weight their stake adds, so
+    // together they never weigh more than the delegate did alone.
+    vote(&mut app, VOTER2, proposal_id, Vote::No);
+    assert_eq!(ballot_weight(&app, proposal_id, VOTER2), Uint128::new(2000));
+    vote(&mut app, VOTER1, proposal_id, Vote::Yes);
     assert_eq!(
-        res,
-        ConfigResponse {
-            config: Config {
-                name: "dao-dao".to_string(),
-                description: "a great DAO!".to_string(),
-                threshold,
-                max_voting_period: voting_period,
-                proposal_deposit: Uint128::zero(),
-                refund_failed_proposals: None,
-                image_url: None,
-                only_members_execute: true,
-                automatically_add_cw20s: true,
-            },
-            gov_token: cw20_addr,
-            staking_contract: Addr::unchecked("Better_Staking_Contract"),
-        }
+        ballot_weight(&app, proposal_id, VOTER1),
+        Uint128::new(2000 - 1414)
+    );
+    assert_eq!(ballot_weight(&app, proposal_id, VOTER2), Uint128::new(1414));
+    let res = tally(&app, proposal_id);
+    assert_eq!(res.votes.yes, Uint128::new(3162 + 586));
+    assert_eq!(res.votes.no, Uint128::new(1414));
+    assert_eq!(res.status, Status::Open);
+
+    // Every address voting adds up to the total weight
+    vote(&mut app, OWNER, proposal_id, Vote::No);
+    vote(&mut app, VOTER3, proposal_id, Vote::Yes);
+    let res = tally(&app, proposal_id);
+    assert_eq!(res.total_votes, res.total_weight);
+    assert_eq!(res.status, Status::Passed);
+
+    // Ballots are capped at 10% of the total weight, the total voting power
+    app.execute_contract(
+        dao_addr.clone(),
+        dao_addr.clone(),
+        &ExecuteMsg::UpdateConfig(config(VotingPowerTransform::Capped {
+            percentage: Decimal::percent(10),
+        })),
+        &[],
     )
+    .unwrap();
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &pay_somebody_proposal(),
+            &[],
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    assert_eq!(
+        tally(&app, proposal_id).total_weight,
+        Uint128::new(INITIAL_BALANCE * 5)
+    );
+    vote(&mut app, POWER_VOTER, proposal_id, Vote::Yes);
+    assert_eq!(
+        ballot_weight(&app, proposal_id, POWER_VOTER),
+        Uint128::new(INITIAL_BALANCE / 2)
+    );
+    vote(&mut app, VOTER3, proposal_id, Vote::Yes);
+    assert_eq!(
+        ballot_weight(&app, proposal_id, VOTER3),
+        Uint128::new(INITIAL_BALANCE / 2)
+    );
+    assert_eq!(tally(&app, proposal_id).status, Status::Open);
 }
 
 #[test]
-fn test_pause_dao() {
+fn test_drafts() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
     let threshold = Threshold::ThresholdQuorum {
-        threshold: Decimal::percent(20),
+        threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let (dao_addr, _cw20_addr, _staking_addr) = setup_test_case(
+    let proposal_deposit_amount = Uint128::new(1000);
+    let (dao_addr, cw20_addr, _) = setup_test_case(
         &mut app,
         threshold,
         voting_period,
         coins(100, NATIVE_TOKEN_DENOM),
-        None,
+        Some(proposal_deposit_amount),
         None,
     );
+    let cw20 = Cw20Contract(cw20_addr.clone());
 
-    // Pause DAO until height
-    const PAUSE_HEIGHT: u64 = 100000000;
-    let pause_dao_msg = ExecuteMsg::PauseDAO {
-        expiration: Expiration::AtHeight(PAUSE_HEIGHT),
+    let query_draft = |app: &App, draft_id| -> StdResult<DraftResponse> {
+        app.wrap()
+            .query_wasm_smart(&dao_addr, &QueryMsg::Draft { draft_id })
     };
-
-    // Nobody can call call update staking contract method directly
-    let res = app.execute_contract(
-        Addr::unchecked(VOTER1),
-        dao_addr.clone(),
-        &pause_dao_msg,
-        &[],
-    );
-    assert!(res.is_err());
-    let res = app.execute_contract(
-        Addr::unchecked(OWNER),
-        dao_addr.clone(),
-        &pause_dao_msg,
-        &[],
-    );
-    assert!(res.is_err());
-
-    let wasm_msg = WasmMsg::Execute {
-        contract_addr: dao_addr.clone().into(),
-        msg: to_binary(&pause_dao_msg).unwrap(),
-        funds: vec![],
+    let sponsor = |app: &mut App, sponsor: &str, draft_id| {
+        app.execute_contract(
+            Addr::unchecked(sponsor),
+            dao_addr.clone(),
+            &ExecuteMsg::SponsorDraft { draft_id },
+            &[],
+        )
+    };
+    let amend = |title: &str| ExecuteMsg::AmendDraft {
+        draft_id: 1,
+        title: Some(title.to_string()),
+        description: None,
+        msgs: None,
     };
 
-    // Pause DAO proposal must be made
-    let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
-        title: String::from("Change params"),
-        description: String::from("Updates threshold and max voting params"),
-        msgs: vec![wasm_msg.into()],
+    // Anyone can create a draft without a deposit
+    let (msgs, title, description) = proposal_info();
+    let draft = ProposeMsg {
+        title,
+        description,
+        msgs: vec![],
         latest: None,
-    });
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+    };
+    for _ in 0..2 {
+        let res = app
+            .execute_contract(
+                Addr::unchecked(SOMEBODY),
+                dao_addr.clone(),
+                &ExecuteMsg::CreateDraft(draft.clone()),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(res.custom_attrs(1)[0].value, "create_draft");
+    }
+    let drafts: DraftListResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &dao_addr,
+            &QueryMsg::ListDrafts {
+                start_after: None,
+                limit: None,
+            },
+        )
         .unwrap();
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
-
-    // Imediately passes on yes vote
-    let yes_vote = ExecuteMsg::Vote(VoteMsg {
-        proposal_id,
-        vote: Vote::Yes,
-    });
-    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
-    assert!(res.is_ok());
-
-    // Execute
-    let execution = ExecuteMsg::Execute { proposal_id };
-    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
-    assert!(res.is_ok());
+    assert_eq!(
+        drafts
+            .drafts
+            .iter()
+            .map(|draft| draft.id)
+            .collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    let proposals: ProposalListResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &dao_addr,
+            &QueryMsg::ListProposals {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert!(proposals.proposals.is_empty());
 
-    // Check that DAO is paused, Propose should fail
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[])
+    // Only the author can amend the draft
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER3),
+            dao_addr.clone(),
+            &amend("Hijacked"),
+            &[],
+        )
         .unwrap_err();
-    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    app.execute_contract(
+        Addr::unchecked(SOMEBODY),
+        dao_addr.clone(),
+        &ExecuteMsg::AmendDraft {
+            draft_id: 1,
+            title: Some("Amended".to_string()),
+            description: None,
+            msgs: Some(msgs.clone()),
+        },
+        &[],
+    )
+    .unwrap();
+    let amended = query_draft(&app, 1).unwrap();
+    assert_eq!(amended.title, "Amended");
+    assert_eq!(amended.description, draft.description);
+    assert_eq!(amended.msgs, msgs);
 
-    // Vote should fail
-    let res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
-        .unwrap_err();
-    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
+    // Sponsors must be able to make proposals
+    let err = sponsor(&mut app, SOMEBODY, 1).unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
 
-    // Execute should fail
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
-        .unwrap_err();
-    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
+    // Sponsoring turns the draft into a proposal that starts at that height,
+    // with the deposit paid by the sponsor
+    app.update_block(next_block);
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        cw20_addr,
+        &Cw20ExecuteMsg::IncreaseAllowance {
+            spender: dao_addr.to_string(),
+            amount: proposal_deposit_amount,
+            expires: None,
+        },
+        &[],
+    )
+    .unwrap();
+    let balance = cw20.balance(&app, VOTER3).unwrap();
+    let res = sponsor(&mut app, VOTER3, 1).unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+    assert_eq!(
+        cw20.balance(&app, VOTER3).unwrap(),
+        balance - proposal_deposit_amount
+    );
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.title, "Amended");
+    assert_eq!(prop.msgs, msgs);
+    assert_eq!(prop.proposer, Addr::unchecked(VOTER3));
+    assert_eq!(prop.author, Some(Addr::unchecked(SOMEBODY)));
+    assert_eq!(prop.deposit_amount, proposal_deposit_amount);
+    assert_eq!(prop.start_height, app.block_info().height);
+
+    // Sponsored drafts are gone
+    query_draft(&app, 1).unwrap_err();
+    sponsor(&mut app, VOTER3, 1).unwrap_err();
+    app.execute_contract(
+        Addr::unchecked(SOMEBODY),
+        dao_addr.clone(),
+        &amend("Too late"),
+        &[],
+    )
+    .unwrap_err();
 
-    // Close should fail
-    let res = app
+    // Expired drafts can't be amended or sponsored
+    app.update_block(expire(voting_period));
+    let err = app
         .execute_contract(
-            Addr::unchecked(OWNER),
+            Addr::unchecked(SOMEBODY),
             dao_addr.clone(),
-            &ExecuteMsg::Close { proposal_id },
+            &ExecuteMsg::AmendDraft {
+                draft_id: 2,
+                title: None,
+                description: None,
+                msgs: None,
+            },
             &[],
         )
         .unwrap_err();
-    assert_eq!(res.to_string(), ContractError::Paused {}.to_string());
-
-    // Pause expiration height is reached
-    app.update_block(|b| b.height = PAUSE_HEIGHT + 1);
-
-    // Propose succeeds
-    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal_msg, &[]);
-    assert!(res.is_ok());
-
-    // Vote yields different error
-    let res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[])
-        .unwrap_err();
-    assert_eq!(res.to_string(), ContractError::NotOpen {}.to_string());
+    assert_eq!(ContractError::DraftExpired {}, err.downcast().unwrap());
+    let err = sponsor(&mut app, VOTER3, 2).unwrap_err();
+    assert_eq!(ContractError::DraftExpired {}, err.downcast().unwrap());
+    assert!(query_draft(&app, 2)
+        .unwrap()
+        .expires
+        .is_expired(&app.block_info()));
+
+    // Expired drafts are no longer listed, and anyone can remove them
+    let list_drafts = |app: &App| -> Vec<u64> {
+        let drafts: DraftListResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &dao_addr,
+                &QueryMsg::ListDrafts {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        drafts.drafts.iter().map(|draft| draft.id).collect()
+    };
+    assert!(list_drafts(&app).is_empty());
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::RemoveDraft { draft_id: 2 },
+        &[],
+    )
+    .unwrap();
+    query_draft(&app, 2).unwrap_err();
 
-    // Expiration yields different error
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
+    // Only the author can remove a draft before it expires
+    app.execute_contract(
+        Addr::unchecked(SOMEBODY),
+        dao_addr.clone(),
+        &ExecuteMsg::CreateDraft(draft),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(list_drafts(&app), vec![3]);
+    let remove = ExecuteMsg::RemoveDraft { draft_id: 3 };
+    let err = app
+        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &remove, &[])
         .unwrap_err();
-    assert_eq!(
-        res.to_string(),
-        ContractError::WrongExecuteStatus {}.to_string()
-    );
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
 
-    // Close yields different error
-    let res = app
+    // Drafts can't be amended while the DAO is paused
+    app.execute_contract(
+        dao_addr.clone(),
+        dao_addr.clone(),
+        &ExecuteMsg::PauseDAO {
+            expiration: Expiration::AtHeight(app.block_info().height + 10),
+        },
+        &[],
+    )
+    .unwrap();
+    let err = app
         .execute_contract(
-            Addr::unchecked(OWNER),
-            dao_addr,
-            &ExecuteMsg::Close { proposal_id },
+            Addr::unchecked(SOMEBODY),
+            dao_addr.clone(),
+            &ExecuteMsg::AmendDraft {
+                draft_id: 3,
+                title: None,
+                description: None,
+                msgs: None,
+            },
             &[],
         )
         .unwrap_err();
-    assert_eq!(
-        res.to_string(),
-        ContractError::WrongCloseStatus {}.to_string()
-    );
+    assert_eq!(ContractError::Paused {}, err.downcast().unwrap());
+    let err = app
+        .execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &remove, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Paused {}, err.downcast().unwrap());
+
+    app.update_block(|block| block.height += 10);
+    app.execute_contract(Addr::unchecked(SOMEBODY), dao_addr.clone(), &remove, &[])
+        .unwrap();
+    query_draft(&app, 3).unwrap_err();
 }
 
 #[test]
-fn test_config_query() {
+fn test_withdraw() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
@@ -2246,307 +5704,431 @@ fn test_config_query() {
         threshold: Decimal::percent(51),
         quorum: Decimal::percent(10),
     };
-    let (dao_addr, cw20_addr, staking_addr) = setup_test_case(
+    let proposal_deposit_amount = Uint128::new(1000);
+    let (dao_addr, cw20_addr, _) = setup_test_case(
         &mut app,
-        threshold.clone(),
+        threshold,
         voting_period,
         coins(100, NATIVE_TOKEN_DENOM),
-        None,
+        Some(proposal_deposit_amount),
         None,
     );
+    let cw20 = Cw20Contract(cw20_addr.clone());
 
-    let config_query = QueryMsg::GetConfig {};
-    let res: ConfigResponse = app
+    let propose = |app: &mut App| -> u64 {
+        let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+            spender: dao_addr.clone().into(),
+            amount: proposal_deposit_amount,
+            expires: None,
+        };
+        app.execute_contract(Addr::unchecked(OWNER), cw20_addr.clone(), &allowance, &[])
+            .unwrap();
+        let res = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                dao_addr.clone(),
+                &pay_somebody_proposal(),
+                &[],
+            )
+            .unwrap();
+        res.custom_attrs(1)[2].value.parse().unwrap()
+    };
+    let withdraw = |app: &mut App, sender: &str, proposal_id| {
+        app.execute_contract(
+            Addr::unchecked(sender),
+            dao_addr.clone(),
+            &ExecuteMsg::Withdraw { proposal_id },
+            &[],
+        )
+    };
+
+    // Only the proposer can withdraw their proposal
+    let balance = cw20.balance(&app, OWNER).unwrap();
+    let proposal_id = propose(&mut app);
+    let err = withdraw(&mut app, VOTER1, proposal_id).unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // Withdrawing refunds the deposit and ends the proposal
+    let res = withdraw(&mut app, OWNER, proposal_id).unwrap();
+    assert_eq!(res.custom_attrs(1)[0].value, "withdraw");
+    assert_eq!(cw20.balance(&app, OWNER).unwrap(), balance);
+    let prop: ProposalResponse = app
         .wrap()
-        .query_wasm_smart(&dao_addr, &config_query)
+        .query_wasm_smart(&dao_addr, &QueryMsg::Proposal { proposal_id })
         .unwrap();
+    assert_eq!(prop.status, Status::Withdrawn);
 
+    let err = withdraw(&mut app, OWNER, proposal_id).unwrap_err();
     assert_eq!(
-        res,
-        ConfigResponse {
-            config: Config {
-                name: "dao-dao".to_string(),
-                description: "a great DAO!".to_string(),
-                threshold,
-                max_voting_period: voting_period,
-                proposal_deposit: Uint128::zero(),
-                refund_failed_proposals: None,
-                image_url: None,
-                only_members_execute: true,
-                automatically_add_cw20s: true,
-            },
-            gov_token: cw20_addr,
-            staking_contract: staking_addr,
-        }
+        ContractError::WrongWithdrawStatus {},
+        err.downcast().unwrap()
+    );
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER1),
+            dao_addr.clone(),
+            &ExecuteMsg::Vote(VoteMsg {
+                proposal_id,
+                vote: Vote::Yes,
+            }),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::NotOpen {}, err.downcast().unwrap());
+    app.update_block(expire(voting_period));
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER1),
+            dao_addr.clone(),
+            &ExecuteMsg::Close { proposal_id },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+
+    // Proposals can't be withdrawn once somebody voted
+    let proposal_id = propose(&mut app);
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        dao_addr.clone(),
+        &ExecuteMsg::Vote(VoteMsg {
+            proposal_id,
+            vote: Vote::No,
+        }),
+        &[],
     )
+    .unwrap();
+    let err = withdraw(&mut app, OWNER, proposal_id).unwrap_err();
+    assert_eq!(ContractError::ProposalHasVotes {}, err.downcast().unwrap());
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum HookReceiverMsg {
+    ProposalHook(ProposalHookMsg),
+    VoteHook(VoteHookMsg),
+}
+
+const RECEIVED_HOOKS: Item<Vec<HookReceiverMsg>> = Item::new("received_hooks");
+
+/// Records every proposal and vote hook it receives
+fn contract_hook_receiver() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |deps, _env, _info, msg: HookReceiverMsg| -> StdResult<Response> {
+            let mut hooks = RECEIVED_HOOKS.may_load(deps.storage)?.unwrap_or_default();
+            hooks.push(msg);
+            RECEIVED_HOOKS.save(deps.storage, &hooks)?;
+            Ok(Response::new())
+        },
+        |_deps, _env, _info, _msg: Empty| -> StdResult<Response> { Ok(Response::new()) },
+        |deps, _env, _msg: Empty| -> StdResult<Binary> {
+            to_binary(&RECEIVED_HOOKS.may_load(deps.storage)?.unwrap_or_default())
+        },
+    );
+    Box::new(contract)
 }
 
 #[test]
-fn test_proposal_deposit_works() {
+fn test_hooks() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
-    let threshold = Threshold::AbsolutePercentage {
-        percentage: Decimal::percent(20),
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(10),
     };
-    let (dao_addr, cw20_addr, _) = setup_test_case(
+    let (dao_addr, cw20_addr, staking_addr) = setup_test_case(
         &mut app,
-        threshold.clone(),
+        threshold,
         voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
+        coins(100, NATIVE_TOKEN_DENOM),
         None,
         None,
     );
 
-    let cw20 = Cw20Contract(cw20_addr.clone());
-
-    let initial_owner_cw20_balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
-
-    // ensure we have cash to cover the proposal
-    let contract_bal = app
-        .wrap()
-        .query_balance(&dao_addr, NATIVE_TOKEN_DENOM)
+    let receiver_id = app.store_code(contract_hook_receiver());
+    let receiver_addr = app
+        .instantiate_contract(
+            receiver_id,
+            Addr::unchecked(OWNER),
+            &Empty {},
+            &[],
+            "receiver",
+            None,
+        )
         .unwrap();
-    assert_eq!(contract_bal, coin(10, NATIVE_TOKEN_DENOM));
-
-    let proposal_deposit_amount = Uint128::new(10);
-
-    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
-        name: "dao-dao".to_string(),
-        description: "a great DAO!".to_string(),
-        threshold,
-        max_voting_period: voting_period,
-        proposal_deposit: proposal_deposit_amount,
-        refund_failed_proposals: None,
-        image_url: None,
-        only_members_execute: true,
-        automatically_add_cw20s: true,
-    });
-    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
-    assert!(res.is_ok());
 
-    // Give dao allowance for proposal
-    let allowance = Cw20ExecuteMsg::IncreaseAllowance {
-        spender: dao_addr.clone().into(),
-        amount: proposal_deposit_amount,
-        expires: None,
+    // Only the DAO can manage its hooks
+    let add_vote_hook = ExecuteMsg::AddVoteHook {
+        address: receiver_addr.to_string(),
     };
-    let res = app.execute_contract(Addr::unchecked(OWNER), cw20_addr, &allowance, &[]);
-    assert!(res.is_ok());
+    let err = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            dao_addr.clone(),
+            &add_vote_hook,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
 
-    // create proposal with 0 vote power
-    let proposal = pay_somebody_proposal();
-    let res = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &proposal, &[])
+    // The cw20 and staking contracts fail to handle hooks
+    app.execute_contract(dao_addr.clone(), dao_addr.clone(), &add_vote_hook, &[])
         .unwrap();
+    for hook in [&cw20_addr, &receiver_addr, &staking_addr] {
+        app.execute_contract(
+            dao_addr.clone(),
+            dao_addr.clone(),
+            &ExecuteMsg::AddProposalHook {
+                address: hook.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    }
 
-    // Get the proposal id from the logs
-    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
-
-    // Check proposal deposit was made
-    let balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
-    let expected_balance = initial_owner_cw20_balance
-        .checked_sub(proposal_deposit_amount)
+    // Failing hooks are removed without failing the proposal
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &pay_somebody_proposal(),
+        &[],
+    )
+    .unwrap();
+    let hooks: HooksResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::ProposalHooks {})
         .unwrap();
-    assert_eq!(balance, expected_balance);
+    assert_eq!(hooks.hooks, vec![receiver_addr.to_string()]);
 
-    // Only Passed can be executed
-    let execution = ExecuteMsg::Execute { proposal_id };
-    let err = app
-        .execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[])
-        .unwrap_err();
-    assert_eq!(
-        ContractError::WrongExecuteStatus {},
-        err.downcast().unwrap()
-    );
+    // The first vote leaves the proposal open, the second one passes it
+    for voter in [POWER_VOTER, VOTER3] {
+        app.execute_contract(
+            Addr::unchecked(voter),
+            dao_addr.clone(),
+            &ExecuteMsg::Vote(VoteMsg {
+                proposal_id: 1,
+                vote: Vote::Yes,
+            }),
+            &[],
+        )
+        .unwrap();
+    }
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Execute { proposal_id: 1 },
+        &[],
+    )
+    .unwrap();
 
-    // Vote it, so it passes
-    let vote = ExecuteMsg::Vote(VoteMsg {
-        proposal_id,
-        vote: Vote::Yes,
-    });
-    let res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &vote, &[])
+    // Expired proposals are closed
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &pay_somebody_proposal(),
+        &[],
+    )
+    .unwrap();
+    app.update_block(expire(voting_period));
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        dao_addr.clone(),
+        &ExecuteMsg::Close { proposal_id: 2 },
+        &[],
+    )
+    .unwrap();
+
+    let received: Vec<HookReceiverMsg> = app
+        .wrap()
+        .query_wasm_smart(&receiver_addr, &Empty {})
         .unwrap();
     assert_eq!(
-        res.custom_attrs(1),
-        [
-            ("action", "vote"),
-            ("sender", VOTER3),
-            ("proposal_id", proposal_id.to_string().as_str()),
-            ("status", "Passed"),
-        ],
+        received,
+        vec![
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::NewProposal {
+                proposal_id: 1,
+                proposer: VOTER3.to_string(),
+            }),
+            HookReceiverMsg::VoteHook(VoteHookMsg::NewVote {
+                proposal_id: 1,
+                voter: POWER_VOTER.to_string(),
+                vote: Vote::Yes,
+                choice: None,
+                weight: Uint128::new(10000000),
+            }),
+            HookReceiverMsg::VoteHook(VoteHookMsg::NewVote {
+                proposal_id: 1,
+                voter: VOTER3.to_string(),
+                vote: Vote::Yes,
+                choice: None,
+                weight: Uint128::new(4000000),
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalStatusChanged {
+                proposal_id: 1,
+                old_status: Status::Open,
+                new_status: Status::Passed,
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalExecuted { proposal_id: 1 }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::NewProposal {
+                proposal_id: 2,
+                proposer: VOTER3.to_string(),
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalClosed { proposal_id: 2 }),
+        ]
     );
 
-    // Execute works. Anybody can execute Passed proposals
-    let res = app
-        .execute_contract(Addr::unchecked(VOTER3), dao_addr, &execution, &[])
+    app.execute_contract(
+        dao_addr.clone(),
+        dao_addr.clone(),
+        &ExecuteMsg::RemoveVoteHook {
+            address: receiver_addr.to_string(),
+        },
+        &[],
+    )
+    .unwrap();
+    let hooks: HooksResponse = app
+        .wrap()
+        .query_wasm_smart(&dao_addr, &QueryMsg::VoteHooks {})
         .unwrap();
+    assert!(hooks.hooks.is_empty());
+}
+
+#[test]
+fn test_migrate() {
+    let mut deps = mock_dependencies();
+
+    // Only the same contract type can be migrated
+    set_contract_version(deps.as_mut().storage, "crates.io:cw3_multisig", "0.2.5").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
     assert_eq!(
-        res.custom_attrs(1),
-        [
-            ("action", "execute"),
-            ("sender", VOTER3),
-            ("proposal_id", proposal_id.to_string().as_str()),
-        ],
+        err,
+        ContractError::Migrate(MigrateError::CannotMigrate {
+            previous_contract: "crates.io:cw3_multisig".to_string()
+        })
     );
 
-    // Check deposit has been refunded
-    let balance = cw20.balance(&app, Addr::unchecked(OWNER)).unwrap();
-    assert_eq!(balance, initial_owner_cw20_balance);
-}
+    // Downgrades are refused
+    set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Migrate(MigrateError::CannotMigrateVersion {
+            previous_version: "99.0.0".to_string(),
+            new_version: CONTRACT_VERSION.to_string(),
+        })
+    );
 
-#[test]
-fn test_receive() {
-    let mut app = mock_app();
-    let voting_period = Duration::Time(2000000);
+    // Upgrading from v0.2 rewrites the config, proposals are read as they
+    // were stored
+    let gov_token = Addr::unchecked("gov_token");
     let threshold = Threshold::AbsolutePercentage {
         percentage: Decimal::percent(20),
     };
-    let (dao_addr, cw20_addr, _) = setup_test_case(
-        &mut app,
-        threshold.clone(),
-        voting_period,
-        coins(10, NATIVE_TOKEN_DENOM),
-        None,
-        None,
-    );
-
-    // Make a new token to send to the DAO
-    let cw20_id = app.store_code(contract_cw20_gov());
-    let msg = cw20_base::msg::InstantiateMsg {
-        name: String::from("NewCoin"),
-        symbol: String::from("COIN"),
-        decimals: 6,
-        initial_balances: vec![
-            Cw20Coin {
-                address: OWNER.to_string(),
-                amount: Uint128::new(INITIAL_BALANCE * 5),
-            },
-            Cw20Coin {
-                address: VOTER1.to_string(),
-                amount: Uint128::new(INITIAL_BALANCE * 5),
+    set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.2.6").unwrap();
+    GOV_TOKEN.save(deps.as_mut().storage, &gov_token).unwrap();
+    v0_2::CONFIG
+        .save(
+            deps.as_mut().storage,
+            &v0_2::Config {
+                name: "dao-dao".to_string(),
+                description: "a great DAO!".to_string(),
+                threshold: threshold.clone(),
+                max_voting_period: Duration::Time(2000000),
+                proposal_deposit: Uint128::new(10),
+                refund_failed_proposals: Some(true),
+                image_url: None,
+                only_members_execute: true,
+                automatically_add_cw20s: true,
             },
-        ],
-        mint: None,
-        marketing: None,
-    };
-    let res = app.instantiate_contract(cw20_id, Addr::unchecked(OWNER), &msg, &[], "cw20", None);
-    assert!(res.is_ok());
-    let other_cw20_addr = res.unwrap();
-
-    let msg = Cw20ExecuteMsg::Send {
-        contract: dao_addr.to_string(),
-        amount: Uint128::new(200u128),
-        msg: Default::default(),
-    };
-    let res = app.execute_contract(Addr::unchecked(VOTER1), other_cw20_addr.clone(), &msg, &[]);
-    assert!(res.is_ok());
-
-    // Query balances, expecting to see the other cw20 already added
-    let cw20_token_balances_msg = QueryMsg::Cw20Balances {
-        start_after: None,
-        limit: None,
-    };
-    let all_balances: Cw20BalancesResponse = app
-        .wrap()
-        .query_wasm_smart(&dao_addr, &cw20_token_balances_msg)
+        )
         .unwrap();
-    assert_eq!(
-        all_balances.cw20_balances,
-        vec![
-            Cw20CoinVerified {
-                address: cw20_addr.clone(),
-                amount: Uint128::zero(),
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct LegacyProposal {
+        title: String,
+        description: String,
+        proposer: Addr,
+        start_height: u64,
+        expires: Expiration,
+        msgs: Vec<CosmosMsg>,
+        status: Status,
+        threshold: Threshold,
+        total_weight: Uint128,
+        votes: Votes,
+        deposit: Uint128,
+    }
+    Map::new("proposals")
+        .save(
+            deps.as_mut().storage,
+            1u64,
+            &LegacyProposal {
+                title: "Pay somebody".to_string(),
+                description: "Do I pay her?".to_string(),
+                proposer: Addr::unchecked(OWNER),
+                start_height: 100,
+                expires: Expiration::AtHeight(200),
+                msgs: vec![],
+                status: Status::Open,
+                threshold,
+                total_weight: Uint128::new(100),
+                votes: Votes::new(Uint128::zero()),
+                deposit: Uint128::new(10),
             },
-            Cw20CoinVerified {
-                address: other_cw20_addr.clone(),
-                amount: Uint128::new(200u128)
-            }
-        ]
-    );
-
-    // Update to stop auto adding cw20s
-    let update_config_msg = ExecuteMsg::UpdateConfig(Config {
-        name: "dao-dao".to_string(),
-        description: "a great DAO!".to_string(),
-        threshold,
-        max_voting_period: voting_period,
-        proposal_deposit: Uint128::new(0),
-        refund_failed_proposals: None,
-        image_url: None,
-        only_members_execute: true,
-        automatically_add_cw20s: false,
-    });
-    let res = app.execute_contract(dao_addr.clone(), dao_addr.clone(), &update_config_msg, &[]);
-    assert!(res.is_ok());
+        )
+        .unwrap();
 
-    // Make another new token with initial balance, this time it won't be added automatically when sent
-    let cw20_id = app.store_code(contract_cw20_gov());
-    let msg = cw20_base::msg::InstantiateMsg {
-        name: String::from("NotAdded"),
-        symbol: String::from("NOT"),
-        decimals: 6,
-        initial_balances: vec![
-            Cw20Coin {
-                address: OWNER.to_string(),
-                amount: Uint128::new(INITIAL_BALANCE * 5),
-            },
-            Cw20Coin {
-                address: VOTER1.to_string(),
-                amount: Uint128::new(INITIAL_BALANCE * 5),
+    // Ballots from v0.2 have no choice and are read as-is
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct LegacyBallot {
+        weight: Uint128,
+        vote: Vote,
+    }
+    let legacy_ballots: Map<(u64, &Addr), LegacyBallot> = Map::new("votes");
+    legacy_ballots
+        .save(
+            deps.as_mut().storage,
+            (1, &Addr::unchecked(VOTER1)),
+            &LegacyBallot {
+                weight: Uint128::new(5),
+                vote: Vote::No,
             },
-        ],
-        mint: None,
-        marketing: None,
-    };
-    let res = app.instantiate_contract(cw20_id, Addr::unchecked(OWNER), &msg, &[], "cw20", None);
-    assert!(res.is_ok());
-    let not_added_cw20_addr = res.unwrap();
+        )
+        .unwrap();
 
-    // Send some cw20s that will not be automatically tracked
-    let msg = Cw20ExecuteMsg::Send {
-        contract: dao_addr.to_string(),
-        amount: Uint128::new(200u128),
-        msg: Default::default(),
-    };
-    let res = app.execute_contract(Addr::unchecked(VOTER1), not_added_cw20_addr, &msg, &[]);
-    assert!(res.is_ok());
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    let version = get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(version.version, CONTRACT_VERSION);
 
-    // Query all balances, this time will still only have 2
-    // As we have disabled automatic listing of cw20s
-    let cw20_token_balances_msg = QueryMsg::Cw20Balances {
-        start_after: None,
-        limit: None,
-    };
-    let all_balances: Cw20BalancesResponse = app
-        .wrap()
-        .query_wasm_smart(&dao_addr, &cw20_token_balances_msg)
+    let ballot = BALLOTS
+        .load(deps.as_ref().storage, (1, &Addr::unchecked(VOTER1)))
         .unwrap();
-    assert_eq!(
-        all_balances.cw20_balances,
-        vec![
-            Cw20CoinVerified {
-                address: cw20_addr,
-                amount: Uint128::zero(),
-            },
-            Cw20CoinVerified {
-                address: other_cw20_addr,
-                amount: Uint128::new(200u128)
-            }
-        ]
-    );
+    assert_eq!(ballot.choice, None);
+    assert_eq!(ballot.vote, Vote::No);
+
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.proposal_deposit_token, DepositToken::GovToken {});
+    assert_eq!(config.deposit_policy, DepositPolicy::Refund {});
+    assert!(!config.allow_revoting);
+    assert_eq!(config.timelock, None);
+    assert_eq!(config.guardian, None);
+    let prop = PROPOSALS.load(deps.as_ref().storage, 1).unwrap();
+    assert_eq!(prop.deposit, Uint128::new(10));
+    assert_eq!(prop.deposit_denom, None);
+    assert_eq!(prop.deposit_token(&gov_token), Denom::Cw20(gov_token));
+    assert!(!prop.allow_revoting);
+    assert_eq!(prop.timelock, None);
+    assert_eq!(prop.voting_power_transform, VotingPowerTransform::Linear {});
 }
 
 #[test]
-fn treasury_queries() {
+fn test_migrate_staking_contract_by_proposal() {
     let mut app = mock_app();
 
     let voting_period = Duration::Time(2000000);
     let threshold = Threshold::AbsolutePercentage {
         percentage: Decimal::percent(20),
     };
-    let (dao_addr, _, _) = setup_test_case(
+    let (dao_addr, _cw20_addr, staking_addr) = setup_test_case(
         &mut app,
         threshold,
         voting_period,
@@ -2555,61 +6137,22 @@ fn treasury_queries() {
         None,
     );
 
-    // Query All Treasury Balances
-    let cw20_token_balances_msg = QueryMsg::Cw20Balances {
-        start_after: None,
-        limit: None,
+    // The DAO is the admin of the staking contract it instantiated
+    let new_code_id = app.store_code(contract_staking());
+    let migrate_msg = WasmMsg::Migrate {
+        contract_addr: staking_addr.to_string(),
+        new_code_id,
+        msg: to_binary(&stake_cw20::msg::MigrateMsg {}).unwrap(),
     };
-    let all_balances: Cw20BalancesResponse = app
-        .wrap()
-        .query_wasm_smart(&dao_addr, &cw20_token_balances_msg)
-        .unwrap();
-    assert_eq!(
-        all_balances.cw20_balances,
-        vec![Cw20CoinVerified {
-            address: Addr::unchecked("Contract #0"),
-            amount: Uint128::zero(),
-        }]
-    );
-
-    // Query token list
-    let token_list: TokenListResponse = app
-        .wrap()
-        .query_wasm_smart(&dao_addr, &QueryMsg::Cw20TokenList {})
-        .unwrap();
-    assert_eq!(token_list.token_list.len(), 1);
 
-    // Make a new token with initial balance
-    let cw20_id = app.store_code(contract_cw20_gov());
-    let msg = cw20_base::msg::InstantiateMsg {
-        name: String::from("NewCoin"),
-        symbol: String::from("COIN"),
-        decimals: 6,
-        initial_balances: vec![Cw20Coin {
-            address: OWNER.to_string(),
-            amount: Uint128::new(INITIAL_BALANCE * 5),
-        }],
-        mint: None,
-        marketing: None,
-    };
-    let res = app.instantiate_contract(cw20_id, Addr::unchecked(OWNER), &msg, &[], "cw20", None);
-    assert!(res.is_ok());
-    let other_cw20_addr = res.unwrap();
+    // Nobody but the DAO can migrate it
+    let err = app.execute(Addr::unchecked(OWNER), migrate_msg.clone().into());
+    assert!(err.is_err());
 
-    // Manually add token to list by voting
-    let update_token_list_msg = ExecuteMsg::UpdateCw20TokenList {
-        to_add: vec!["NEW".to_string(), "NEWNEW".to_string()],
-        to_remove: vec![other_cw20_addr.to_string()],
-    };
-    let wasm_msg = WasmMsg::Execute {
-        contract_addr: dao_addr.clone().into(),
-        msg: to_binary(&update_token_list_msg).unwrap(),
-        funds: vec![],
-    };
     let proposal_msg = ExecuteMsg::Propose(ProposeMsg {
-        title: String::from("Change params"),
-        description: String::from("Updates threshold and max voting params"),
-        msgs: vec![wasm_msg.into()],
+        title: String::from("Upgrade staking"),
+        description: String::from("Migrate the staking contract to new code"),
+        msgs: vec![migrate_msg.into()],
         latest: None,
     });
     let res = app
@@ -2617,28 +6160,17 @@ fn treasury_queries() {
         .unwrap();
     let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
 
-    // Imediately passes on yes vote
-    let yes_vote = ExecuteMsg::Vote(VoteMsg {
+    let vote = ExecuteMsg::Vote(VoteMsg {
         proposal_id,
         vote: Vote::Yes,
     });
-    let res = app.execute_contract(Addr::unchecked(VOTER3), dao_addr.clone(), &yes_vote, &[]);
-    assert!(res.is_ok());
+    app.execute_contract(Addr::unchecked(POWER_VOTER), dao_addr.clone(), &vote, &[])
+        .unwrap();
 
-    // Execute
     let execution = ExecuteMsg::Execute { proposal_id };
-    let res = app.execute_contract(Addr::unchecked(OWNER), dao_addr.clone(), &execution, &[]);
-    assert!(res.is_ok());
-
-    // Token list should be 3 now
-    let token_list: TokenListResponse = app
-        .wrap()
-        .query_wasm_smart(&dao_addr, &QueryMsg::Cw20TokenList {})
+    app.execute_contract(Addr::unchecked(POWER_VOTER), dao_addr, &execution, &[])
         .unwrap();
-    assert_eq!(token_list.token_list.len(), 3);
 
-    // Contact #2 token has been removed
-    assert!(!token_list
-        .token_list
-        .contains(&Addr::unchecked("Contract #2")));
+    let contract_data = app.contract_data(&staking_addr).unwrap();
+    assert_eq!(contract_data.code_id as u64, new_code_id);
 }
@@ -11,24 +11,29 @@ use cosmwasm_std::{
 use cw2::set_contract_version;
 use cw20::{BalanceResponse, Cw20CoinVerified, Cw20QueryMsg, Cw20ReceiveMsg};
 use cw3::{
-    Status, Vote, VoteInfo, VoteListResponse, VoteResponse, VoterDetail, VoterListResponse,
-    VoterResponse,
+    Vote, VoteInfo, VoteListResponse, VoteResponse, VoterDetail, VoterListResponse, VoterResponse,
 };
 use cw4::{Cw4Contract, MemberChangedHookMsg, MemberDiff};
 use cw4_group::msg::InstantiateMsg as Cw4InstantiateMsg;
+use cw_controllers::Hooks;
+use cw_migrate::ensure_upgrade;
 use cw_storage_plus::Bound;
 use cw_utils::{maybe_addr, parse_reply_instantiate_data, Expiration, ThresholdResponse};
 
 use crate::error::ContractError;
 use crate::helpers::{get_and_check_limit, map_proposal};
-use crate::msg::{ExecuteMsg, GroupMsg, InstantiateMsg, QueryMsg};
+use crate::hooks::{
+    proposal_hook_msgs, remove_failed_hook, vote_hook_msgs, ProposalHookMsg, VoteHookMsg,
+    PROPOSAL_HOOK_REPLY_ID_OFFSET, VOTE_HOOK_REPLY_ID_OFFSET,
+};
+use crate::msg::{ExecuteMsg, GroupMsg, InstantiateMsg, MigrateMsg, QueryMsg};
 use crate::query::{
-    ConfigResponse, Cw20BalancesResponse, ProposalListResponse, ProposalResponse,
+    ConfigResponse, Cw20BalancesResponse, ProposalListResponse, ProposalResponse, Status,
     TokenListResponse, VoteTallyResponse,
 };
 use crate::state::{
     next_id, Ballot, Config, Proposal, Votes, BALLOTS, CONFIG, GROUP_ADDRESS, PROPOSALS,
-    TREASURY_TOKENS,
+    PROPOSAL_HOOKS, TREASURY_TOKENS, VOTE_HOOKS,
 };
 
 // Version info for migration info
@@ -59,7 +64,9 @@ pub fn instantiate(
         image_url: msg.image_url,
         only_members_execute: msg.only_members_execute,
         automatically_add_cw20s: msg.automatically_add_cw20s,
+        timelock: msg.timelock,
     };
+    cfg.validate_timelock()?;
     CONFIG.save(deps.storage, &cfg)?;
 
     let mut msgs: Vec<SubMsg> = vec![];
@@ -129,6 +136,7 @@ pub fn execute(
         ExecuteMsg::Vote { proposal_id, vote } => execute_vote(deps, env, info, proposal_id, vote),
         ExecuteMsg::Execute { proposal_id } => execute_execute(deps, env, info, proposal_id),
         ExecuteMsg::Close { proposal_id } => execute_close(deps, env, info, proposal_id),
+        ExecuteMsg::Withdraw { proposal_id } => execute_withdraw(deps, env, info, proposal_id),
         ExecuteMsg::MemberChangedHook(MemberChangedHookMsg { diffs }) => {
             execute_membership_hook(deps, env, info, diffs)
         }
@@ -137,6 +145,28 @@ pub fn execute(
             execute_update_cw20_token_list(deps, env, info, to_add, to_remove)
         }
         ExecuteMsg::Receive(rec) => execute_receive(deps, env, info, rec),
+        ExecuteMsg::AddProposalHook { address } => execute_add_hook(
+            deps,
+            env,
+            info,
+            &PROPOSAL_HOOKS,
+            address,
+            "add_proposal_hook",
+        ),
+        ExecuteMsg::RemoveProposalHook { address } => execute_remove_hook(
+            deps,
+            env,
+            info,
+            &PROPOSAL_HOOKS,
+            address,
+            "remove_proposal_hook",
+        ),
+        ExecuteMsg::AddVoteHook { address } => {
+            execute_add_hook(deps, env, info, &VOTE_HOOKS, address, "add_vote_hook")
+        }
+        ExecuteMsg::RemoveVoteHook { address } => {
+            execute_remove_hook(deps, env, info, &VOTE_HOOKS, address, "remove_vote_hook")
+        }
     }
 }
 
@@ -185,6 +215,8 @@ pub fn execute_propose(
         votes: Votes::yes(vote_power),
         threshold: cfg.threshold,
         total_weight: group_addr.total_weight(&deps.querier)?,
+        timelock: cfg.timelock,
+        executable_at: None,
     };
 
     // Ensure that the incoming update config message doesn't propose
@@ -203,7 +235,41 @@ pub fn execute_propose(
     };
     BALLOTS.save(deps.storage, (id, &info.sender), &ballot)?;
 
+    let new_proposal_hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::NewProposal {
+            proposal_id: id,
+            proposer: info.sender.to_string(),
+        },
+    )?;
+    let vote_hook_msgs = vote_hook_msgs(
+        deps.as_ref(),
+        VoteHookMsg::NewVote {
+            proposal_id: id,
+            voter: info.sender.to_string(),
+            vote: Vote::Yes,
+            choice: None,
+            weight: vote_power,
+        },
+    )?;
+    // The proposer's own vote may already decide the proposal
+    let status_hook_msgs = if prop.status != Status::Open {
+        proposal_hook_msgs(
+            deps.as_ref(),
+            ProposalHookMsg::ProposalStatusChanged {
+                proposal_id: id,
+                old_status: Status::Open,
+                new_status: prop.status,
+            },
+        )?
+    } else {
+        vec![]
+    };
+
     Ok(Response::new()
+        .add_submessages(new_proposal_hook_msgs)
+        .add_submessages(vote_hook_msgs)
+        .add_submessages(status_hook_msgs)
         .add_attribute("action", "propose")
         .add_attribute("sender", info.sender)
         .add_attribute("proposal_id", id.to_string())
@@ -246,11 +312,37 @@ pub fn execute_vote(
     })?;
 
     // update vote tally
+    let old_status = prop.status;
     prop.votes.add_vote(vote, vote_power);
     prop.update_status(&env.block);
     PROPOSALS.save(deps.storage, proposal_id, &prop)?;
 
+    let vote_hook_msgs = vote_hook_msgs(
+        deps.as_ref(),
+        VoteHookMsg::NewVote {
+            proposal_id,
+            voter: info.sender.to_string(),
+            vote,
+            choice: None,
+            weight: vote_power,
+        },
+    )?;
+    let proposal_hook_msgs = if prop.status != old_status {
+        proposal_hook_msgs(
+            deps.as_ref(),
+            ProposalHookMsg::ProposalStatusChanged {
+                proposal_id,
+                old_status,
+                new_status: prop.status,
+            },
+        )?
+    } else {
+        vec![]
+    };
+
     Ok(Response::new()
+        .add_submessages(vote_hook_msgs)
+        .add_submessages(proposal_hook_msgs)
         .add_attribute("action", "vote")
         .add_attribute("sender", info.sender)
         .add_attribute("proposal_id", proposal_id.to_string())
@@ -280,12 +372,27 @@ pub fn execute_execute(
         return Err(ContractError::WrongExecuteStatus {});
     }
 
+    // passed proposals may have to wait out the timelock
+    prop.executable_at = prop.executable_at(&env.block);
+    if let Some(executable_at) = prop.executable_at {
+        if !executable_at.is_expired(&env.block) {
+            return Err(ContractError::Timelocked { executable_at });
+        }
+    }
+
     // set it to executed
     prop.status = Status::Executed;
     PROPOSALS.save(deps.storage, proposal_id, &prop)?;
 
+    // hooks are notified before the proposal runs, as it may change them
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::ProposalExecuted { proposal_id },
+    )?;
+
     // dispatch all proposed messages
     Ok(Response::new()
+        .add_submessages(hook_msgs)
         .add_messages(prop.msgs)
         .add_attribute("action", "execute")
         .add_attribute("sender", info.sender)
@@ -301,9 +408,13 @@ pub fn execute_close(
     // anyone can trigger this if the vote passed
 
     let mut prop = PROPOSALS.load(deps.storage, proposal_id)?;
-    if [Status::Executed, Status::Rejected, Status::Passed]
-        .iter()
-        .any(|x| *x == prop.status)
+    if [
+        Status::Executed,
+        Status::Rejected,
+        Status::Passed,
+        Status::Withdrawn,
+    ]
+    .contains(&prop.status)
     {
         return Err(ContractError::WrongCloseStatus {});
     }
@@ -315,12 +426,60 @@ pub fn execute_close(
     prop.status = Status::Rejected;
     PROPOSALS.save(deps.storage, proposal_id, &prop)?;
 
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::ProposalClosed { proposal_id },
+    )?;
+
     Ok(Response::new()
+        .add_submessages(hook_msgs)
         .add_attribute("action", "close")
         .add_attribute("sender", info.sender)
         .add_attribute("proposal_id", proposal_id.to_string()))
 }
 
+pub fn execute_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+) -> Result<Response<Empty>, ContractError> {
+    let mut prop = PROPOSALS.load(deps.storage, proposal_id)?;
+    if prop.proposer != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if prop.current_status(&env.block) != Status::Open {
+        return Err(ContractError::WrongWithdrawStatus {});
+    }
+    // The proposer voted yes when proposing, nobody else may have voted
+    for voter in BALLOTS
+        .prefix(proposal_id)
+        .keys(deps.storage, None, None, Order::Ascending)
+    {
+        if voter? != prop.proposer {
+            return Err(ContractError::ProposalHasVotes {});
+        }
+    }
+
+    prop.status = Status::Withdrawn;
+    PROPOSALS.save(deps.storage, proposal_id, &prop)?;
+
+    let hook_msgs = proposal_hook_msgs(
+        deps.as_ref(),
+        ProposalHookMsg::ProposalStatusChanged {
+            proposal_id,
+            old_status: Status::Open,
+            new_status: Status::Withdrawn,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_submessages(hook_msgs)
+        .add_attribute("action", "withdraw")
+        .add_attribute("sender", info.sender)
+        .add_attribute("proposal_id", proposal_id.to_string()))
+}
+
 pub fn execute_membership_hook(
     deps: DepsMut,
     _env: Env,
@@ -352,6 +511,7 @@ pub fn execute_update_config(
 
     let total_weight = group_addr.total_weight(&deps.querier)?;
     new_config.threshold.validate(total_weight)?;
+    new_config.validate_timelock()?;
 
     CONFIG.save(deps.storage, &new_config)?;
 
@@ -360,6 +520,46 @@ pub fn execute_update_config(
         .add_attribute("sender", info.sender))
 }
 
+pub fn execute_add_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    hooks: &Hooks,
+    address: String,
+    action: &str,
+) -> Result<Response<Empty>, ContractError> {
+    // Only contract can call this method
+    if env.contract.address != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let hook = deps.api.addr_validate(&address)?;
+    hooks.add_hook(deps.storage, hook)?;
+
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("hook", address))
+}
+
+pub fn execute_remove_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    hooks: &Hooks,
+    address: String,
+    action: &str,
+) -> Result<Response<Empty>, ContractError> {
+    // Only contract can call this method
+    if env.contract.address != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let hook = deps.api.addr_validate(&address)?;
+    hooks.remove_hook(deps.storage, hook)?;
+
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("hook", address))
+}
+
 pub fn execute_update_cw20_token_list(
     deps: DepsMut,
     env: Env,
@@ -443,6 +643,8 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             to_binary(&query_cw20_balances(deps, env, start_after, limit)?)
         }
         QueryMsg::Cw20TokenList {} => to_binary(&query_cw20_token_list(deps)),
+        QueryMsg::ProposalHooks {} => to_binary(&PROPOSAL_HOOKS.query_hooks(deps)?),
+        QueryMsg::VoteHooks {} => to_binary(&VOTE_HOOKS.query_hooks(deps)?),
     }
 }
 
@@ -456,6 +658,7 @@ fn query_threshold(deps: Deps) -> StdResult<ThresholdResponse> {
 fn query_proposal(deps: Deps, env: Env, id: u64) -> StdResult<ProposalResponse> {
     let prop = PROPOSALS.load(deps.storage, id)?;
     let status = prop.current_status(&env.block);
+    let executable_at = prop.executable_at(&env.block);
     let threshold = prop.threshold.to_response(prop.total_weight);
     Ok(ProposalResponse {
         id,
@@ -466,6 +669,7 @@ fn query_proposal(deps: Deps, env: Env, id: u64) -> StdResult<ProposalResponse>
         status,
         expires: prop.expires,
         threshold,
+        executable_at,
     })
 }
 
@@ -653,31 +857,58 @@ fn query_list_voters(
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
-    if msg.id != INSTANTIATE_CW4_GROUP_REPLY_ID {
-        return Err(ContractError::UnknownReplyId { id: msg.id });
-    };
-    let res = parse_reply_instantiate_data(msg);
-    match res {
-        Ok(res) => {
-            // Get group contract
-            let group_addr =
-                Cw4Contract(deps.api.addr_validate(&res.contract_address).map_err(|_| {
-                    ContractError::InvalidGroup {
-                        addr: res.contract_address.clone(),
-                    }
-                })?);
-
-            // Validate threshold
-            let cfg = CONFIG.load(deps.storage)?;
-            let total_weight = group_addr.total_weight(&deps.querier)?;
-            cfg.threshold.validate(total_weight)?;
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    // Proposals and the config from v0.2 are read as they were stored, the
+    // fields added since default to the behaviour of v0.2
+    let previous_version = ensure_upgrade(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-            // Save group address
-            GROUP_ADDRESS.save(deps.storage, &group_addr)?;
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", previous_version.to_string())
+        .add_attribute("new_version", CONTRACT_VERSION))
+}
 
-            Ok(Response::new())
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        INSTANTIATE_CW4_GROUP_REPLY_ID => {
+            let res = parse_reply_instantiate_data(msg);
+            match res {
+                Ok(res) => {
+                    // Get group contract
+                    let group_addr =
+                        Cw4Contract(deps.api.addr_validate(&res.contract_address).map_err(
+                            |_| ContractError::InvalidGroup {
+                                addr: res.contract_address.clone(),
+                            },
+                        )?);
+
+                    // Validate threshold
+                    let cfg = CONFIG.load(deps.storage)?;
+                    let total_weight = group_addr.total_weight(&deps.querier)?;
+                    cfg.threshold.validate(total_weight)?;
+
+                    // Save group address
+                    GROUP_ADDRESS.save(deps.storage, &group_addr)?;
+
+                    Ok(Response::new())
+                }
+                Err(_) => Err(ContractError::InstantiateGroupContractError {}),
+            }
+        }
+        id if id >= VOTE_HOOK_REPLY_ID_OFFSET => {
+            let hook = remove_failed_hook(deps, &VOTE_HOOKS, id - VOTE_HOOK_REPLY_ID_OFFSET)?;
+            Ok(Response::new()
+                .add_attribute("action", "remove_failed_vote_hook")
+                .add_attribute("hook", hook.unwrap_or_default()))
+        }
+        id if id >= PROPOSAL_HOOK_REPLY_ID_OFFSET => {
+            let hook =
+                remove_failed_hook(deps, &PROPOSAL_HOOKS, id - PROPOSAL_HOOK_REPLY_ID_OFFSET)?;
+            Ok(Response::new()
+                .add_attribute("action", "remove_failed_proposal_hook")
+                .add_attribute("hook", hook.unwrap_or_default()))
         }
-        Err(_) => Err(ContractError::InstantiateGroupContractError {}),
+        id => Err(ContractError::UnknownReplyId { id }),
     }
 }
@@ -1,4 +1,7 @@
 use cosmwasm_std::StdError;
+use cw_controllers::HookError;
+use cw_migrate::MigrateError;
+use cw_utils::Expiration;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -6,6 +9,12 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("{0}")]
+    Hook(#[from] HookError),
+
     #[error("Invalid voting threshold percentage, must be in the 0.5-1.0 range")]
     InvalidThreshold {},
 
@@ -51,6 +60,18 @@ pub enum ContractError {
     #[error("Cannot close completed or passed proposals")]
     WrongCloseStatus {},
 
+    #[error("Only open proposals can be withdrawn")]
+    WrongWithdrawStatus {},
+
+    #[error("Proposals can't be withdrawn once voted on")]
+    ProposalHasVotes {},
+
+    #[error("Timelock must use the same unit as the max voting period")]
+    InvalidTimelock {},
+
+    #[error("Proposal is timelocked until {executable_at}")]
+    Timelocked { executable_at: Expiration },
+
     #[error("Unable to instantiate the group contract")]
     InstantiateGroupContractError {},
 
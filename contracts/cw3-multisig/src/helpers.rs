@@ -11,6 +11,7 @@ pub fn map_proposal(
 ) -> StdResult<ProposalResponse> {
     let (key, prop) = item?;
     let status = prop.current_status(block);
+    let executable_at = prop.executable_at(block);
     let threshold = prop.threshold.to_response(prop.total_weight);
     Ok(ProposalResponse {
         id: parse_id(&key)?,
@@ -21,6 +22,7 @@ pub fn map_proposal(
         status,
         expires: prop.expires,
         threshold,
+        executable_at,
     })
 }
 
@@ -1,6 +1,7 @@
 pub mod contract;
 mod error;
 pub mod helpers;
+pub mod hooks;
 pub mod msg;
 pub mod query;
 pub mod state;
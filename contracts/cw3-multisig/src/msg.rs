@@ -21,6 +21,9 @@ pub struct InstantiateMsg {
     pub image_url: Option<String>,
     pub only_members_execute: bool,
     pub automatically_add_cw20s: bool,
+    /// Delay between a proposal passing and when it can be executed,
+    /// must use the same unit as max_voting_period
+    pub timelock: Option<Duration>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -153,6 +156,11 @@ pub enum ExecuteMsg {
     Close {
         proposal_id: u64,
     },
+    /// Withdraw an open proposal nobody else voted on yet (can only be
+    /// called by the proposer)
+    Withdraw {
+        proposal_id: u64,
+    },
     /// Handles update hook messages from the group contract
     MemberChangedHook(MemberChangedHookMsg),
     /// Update the multisg config.
@@ -165,6 +173,24 @@ pub enum ExecuteMsg {
     /// Wrapper called for automatically adding cw20s
     /// to our tracked balances
     Receive(Cw20ReceiveMsg),
+    /// Registers a contract to receive a `ProposalHookMsg` when proposals
+    /// are created or change status (can only be called by the multisig)
+    AddProposalHook {
+        address: String,
+    },
+    /// Unregisters a proposal hook (can only be called by the multisig)
+    RemoveProposalHook {
+        address: String,
+    },
+    /// Registers a contract to receive a `VoteHookMsg` for every ballot
+    /// cast (can only be called by the multisig)
+    AddVoteHook {
+        address: String,
+    },
+    /// Unregisters a vote hook (can only be called by the multisig)
+    RemoveVoteHook {
+        address: String,
+    },
 }
 
 // We can also add this as a cw3 extension
@@ -215,8 +241,16 @@ pub enum QueryMsg {
     },
     /// Return list of cw20 Tokens associated with the DAO Treasury
     Cw20TokenList {},
+    /// Returns HooksResponse
+    ProposalHooks {},
+    /// Returns HooksResponse
+    VoteHooks {},
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrateMsg {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
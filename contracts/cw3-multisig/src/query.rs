@@ -1,6 +1,5 @@
 use cosmwasm_std::{Addr, CosmosMsg, Decimal, Empty, Uint128};
 use cw20::Cw20CoinVerified;
-use cw3::Status;
 use cw4::Cw4Contract;
 use cw_utils::{Expiration, ThresholdResponse};
 use schemars::JsonSchema;
@@ -9,6 +8,26 @@ use std::fmt;
 
 use crate::state::{Config, Votes};
 
+/// The status of a proposal. This is `cw3::Status` with an extra status for
+/// proposals withdrawn by their proposer.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum Status {
+    /// proposal was created, but voting has not yet begun for whatever reason
+    Pending = 1,
+    /// you can vote on this
+    Open = 2,
+    /// voting is over and it did not pass
+    Rejected = 3,
+    /// voting is over and it did pass, but has not yet executed
+    Passed = 4,
+    /// voting is over it passed, and the proposal was executed
+    Executed = 5,
+    /// the proposer withdrew the proposal before anybody else voted on it
+    Withdrawn = 6,
+}
+
 /// Our own custom proposal response class, implements
 /// all attributes specified in CW3. Extended as we
 /// have a proposer field and want to test if it is set
@@ -29,6 +48,8 @@ where
     /// as well as the total_weight of the voting group may have changed since this time. That means
     /// that the generic `Threshold{}` query does not provide valid information for existing proposals.
     pub threshold: ThresholdResponse,
+    /// When the proposal can be executed if it passed with a timelock
+    pub executable_at: Option<Expiration>,
 }
 
 /// As above, implement our own proposal list response
@@ -74,3 +95,8 @@ pub struct Cw20BalancesResponse {
 pub struct TokenListResponse {
     pub token_list: Vec<Addr>,
 }
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct HooksResponse {
+    pub hooks: Vec<String>,
+}
@@ -1,10 +1,13 @@
+use crate::error::ContractError;
 use crate::msg::{ExecuteMsg, Threshold};
+use crate::query::Status;
 use cosmwasm_std::{
     from_binary, Addr, BlockInfo, CosmosMsg, Decimal, Empty, QuerierWrapper, StdError, StdResult,
     Storage, Uint128, WasmMsg::Execute,
 };
-use cw3::{Status, Vote};
+use cw3::Vote;
 use cw4::Cw4Contract;
+use cw_controllers::Hooks;
 use cw_storage_plus::{Item, Map};
 use cw_utils::{Duration, Expiration};
 use schemars::JsonSchema;
@@ -29,6 +32,22 @@ pub struct Config {
     pub image_url: Option<String>,
     pub only_members_execute: bool,
     pub automatically_add_cw20s: bool,
+    /// Delay between a proposal passing and when it can be executed.
+    #[serde(default)]
+    pub timelock: Option<Duration>,
+}
+
+impl Config {
+    /// The timelock is added to the expiration of proposals passing at the
+    /// end of their voting period, so both must use the same unit.
+    pub fn validate_timelock(&self) -> Result<(), ContractError> {
+        match (self.timelock, self.max_voting_period) {
+            (None, _)
+            | (Some(Duration::Height(_)), Duration::Height(_))
+            | (Some(Duration::Time(_)), Duration::Time(_)) => Ok(()),
+            _ => Err(ContractError::InvalidTimelock {}),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -46,6 +65,12 @@ pub struct Proposal {
     pub total_weight: u64,
     // summary of existing votes
     pub votes: Votes,
+    /// delay between the proposal passing and when it can be executed
+    #[serde(default)]
+    pub timelock: Option<Duration>,
+    /// set when the proposal passes before it expires
+    #[serde(default)]
+    pub executable_at: Option<Expiration>,
 }
 
 // weight of votes for each option
@@ -104,6 +129,36 @@ impl Proposal {
     /// (designed for handler logic)
     pub fn update_status(&mut self, block: &BlockInfo) {
         self.status = self.current_status(block);
+        if self.status == Status::Passed && self.executable_at.is_none() {
+            self.executable_at = self.executable_at(block);
+        }
+    }
+
+    /// Returns when a passed proposal can be executed, or None if it has
+    /// no timelock or has not passed.
+    pub fn executable_at(&self, block: &BlockInfo) -> Option<Expiration> {
+        let timelock = self.timelock?;
+        if self.executable_at.is_some() {
+            return self.executable_at;
+        }
+        if self.current_status(block) != Status::Passed {
+            return None;
+        }
+        // proposals passing at the end of their voting period are timelocked
+        // from their expiration, others from the block they passed in
+        if !self.expires.is_expired(block) {
+            return Some(timelock.after(block));
+        }
+        match (self.expires, timelock) {
+            (Expiration::AtHeight(height), Duration::Height(delay)) => {
+                Some(Expiration::AtHeight(height + delay))
+            }
+            (Expiration::AtTime(time), Duration::Time(delay)) => {
+                Some(Expiration::AtTime(time.plus_seconds(delay)))
+            }
+            // units are validated to match, so this should never happen
+            _ => Some(Expiration::Never {}),
+        }
     }
 
     /// Helper function to check if a certain vote count has reached threshold.
@@ -197,7 +252,7 @@ impl Proposal {
 fn votes_needed(weight: u64, percentage: Decimal) -> u64 {
     let applied = percentage * Uint128::new(PRECISION_FACTOR * weight as u128);
     // Divide by PRECISION_FACTOR, rounding up to the nearest integer
-    ((applied.u128() + PRECISION_FACTOR - 1) / PRECISION_FACTOR) as u64
+    applied.u128().div_ceil(PRECISION_FACTOR) as u64
 }
 
 // we cast a ballot with our chosen vote and a given weight
@@ -215,6 +270,10 @@ pub const PROPOSAL_COUNT: Item<u64> = Item::new("proposal_count");
 // Total weight and voters are queried from this contract
 pub const GROUP_ADDRESS: Item<Cw4Contract> = Item::new("group_address");
 
+// Contracts notified of proposal and vote events
+pub const PROPOSAL_HOOKS: Hooks = Hooks::new("proposal_hooks");
+pub const VOTE_HOOKS: Hooks = Hooks::new("vote_hooks");
+
 // multiple-item map
 pub const BALLOTS: Map<(u64, &Addr), Ballot> = Map::new("votes");
 pub const PROPOSALS: Map<u64, Proposal> = Map::new("proposals");
@@ -291,6 +350,8 @@ mod test {
             threshold,
             total_weight,
             votes,
+            timelock: None,
+            executable_at: None,
         };
 
         (prop, block)
@@ -1,23 +1,27 @@
-use crate::msg::{ExecuteMsg, GroupMsg, InstantiateMsg, QueryMsg, Threshold};
+use crate::contract::migrate;
+use crate::hooks::{ProposalHookMsg, VoteHookMsg};
+use crate::msg::{ExecuteMsg, GroupMsg, InstantiateMsg, MigrateMsg, QueryMsg, Threshold};
 use crate::query::{
-    ConfigResponse, Cw20BalancesResponse, ProposalListResponse, ProposalResponse,
-    TokenListResponse, VoteTallyResponse,
+    ConfigResponse, Cw20BalancesResponse, HooksResponse, ProposalListResponse, ProposalResponse,
+    Status, TokenListResponse, VoteTallyResponse,
 };
-use crate::state::{Config, Votes};
+use crate::state::{Config, Votes, CONFIG, PROPOSALS};
 use crate::ContractError;
+use cosmwasm_std::testing::{mock_dependencies, mock_env};
 use cosmwasm_std::{
-    coin, coins, to_binary, Addr, BankMsg, BlockInfo, Coin, CosmosMsg, Decimal, Empty, Timestamp,
-    Uint128, WasmMsg,
+    coin, coins, to_binary, Addr, BankMsg, Binary, BlockInfo, Coin, CosmosMsg, Decimal, Empty,
+    Response, StdResult, Timestamp, Uint128, WasmMsg,
 };
-use cw2::{query_contract_info, ContractVersion};
+use cw2::{get_contract_version, query_contract_info, set_contract_version, ContractVersion};
 use cw20::{Cw20Coin, Cw20CoinVerified, Cw20ExecuteMsg};
 use cw3::{
-    Status, Vote, VoteInfo, VoteListResponse, VoteResponse, VoterDetail, VoterListResponse,
-    VoterResponse,
+    Vote, VoteInfo, VoteListResponse, VoteResponse, VoterDetail, VoterListResponse, VoterResponse,
 };
 use cw4::{Cw4Contract, Cw4ExecuteMsg, Member, MemberChangedHookMsg, MemberDiff};
 use cw4_group::helpers::Cw4GroupContract;
+use cw_migrate::MigrateError;
 use cw_multi_test::{next_block, App, AppBuilder, Contract, ContractWrapper, Executor};
+use cw_storage_plus::{Item, Map};
 use cw_utils::{Duration, Expiration, ThresholdResponse};
 
 const OWNER: &str = "admin0001";
@@ -107,6 +111,7 @@ fn instantiate_multisig(
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        timelock: None,
     };
     app.instantiate_contract(
         multisig_id,
@@ -247,6 +252,7 @@ fn test_instantiate_works() {
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        timelock: None,
     };
     let err = app
         .instantiate_contract(
@@ -272,6 +278,7 @@ fn test_instantiate_works() {
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        timelock: None,
     };
     let err = app
         .instantiate_contract(
@@ -297,6 +304,7 @@ fn test_instantiate_works() {
         image_url: None,
         only_members_execute: true,
         automatically_add_cw20s: true,
+        timelock: None,
     };
     let multisig_addr = app
         .instantiate_contract(
@@ -336,6 +344,7 @@ fn test_instantiate_works() {
                 image_url: None,
                 only_members_execute: true,
                 automatically_add_cw20s: true,
+                timelock: None,
             },
             group_address: Cw4Contract::new(Addr::unchecked(group_addr)),
         }
@@ -376,6 +385,7 @@ fn test_instantiate_works() {
         image_url: Some("https://imgur.com/someElmo.png".to_string()),
         only_members_execute: true,
         automatically_add_cw20s: true,
+        timelock: None,
     };
     let res = app.instantiate_contract(
         multisig_id,
@@ -486,6 +496,7 @@ fn test_update_config() {
             image_url: Some("https://someUrl.com/image.png".to_string()),
             only_members_execute: true,
             automatically_add_cw20s: true,
+            timelock: None,
         },
         multisig_addr.to_string(),
     );
@@ -528,6 +539,7 @@ fn test_update_config() {
                 image_url: Some("https://someUrl.com/image.png".to_string()),
                 only_members_execute: true,
                 automatically_add_cw20s: true,
+                timelock: None,
             },
             group_address: Cw4Contract::new(Addr::unchecked(group_addr)),
         }
@@ -543,6 +555,7 @@ fn test_update_config() {
             image_url: None,
             only_members_execute: true,
             automatically_add_cw20s: true,
+            timelock: None,
         },
         multisig_addr.to_string(),
     );
@@ -696,6 +709,7 @@ fn test_proposal_queries() {
             threshold: Decimal::percent(80),
             quorum: Decimal::percent(20),
         },
+        executable_at: None,
     };
     assert_eq!(&expected, &res.proposals[0]);
 }
@@ -867,8 +881,8 @@ fn test_token_add_limited() {
 
     // Attempt to add a bunch of nonesense tokens
     let update_token_list_msg = ExecuteMsg::UpdateCw20TokenList {
-        to_add: (0..20).into_iter().map(|i| i.to_string()).collect(),
-        to_remove: (20..31).into_iter().map(|i| i.to_string()).collect(),
+        to_add: (0..20).map(|i| i.to_string()).collect(),
+        to_remove: (20..31).map(|i| i.to_string()).collect(),
     };
     let wasm_msg = WasmMsg::Execute {
         contract_addr: multisig_addr.clone().into(),
@@ -1353,6 +1367,7 @@ fn test_execute_works_for_anyone() {
             image_url: Some("https://someUrl.com/image.png".to_string()),
             only_members_execute: false,
             automatically_add_cw20s: true,
+            timelock: None,
         },
         multisig_addr.to_string(),
     );
@@ -1494,6 +1509,103 @@ fn proposal_pass_on_expiration() {
     );
 }
 
+#[test]
+fn test_execute_timelocked() {
+    let init_funds = coins(10, "BTC");
+    let mut app = mock_app(&init_funds);
+
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(1),
+    };
+    let voting_period = Duration::Height(10);
+    let timelock = Duration::Height(5);
+    let (multisig_addr, _) =
+        setup_test_case(&mut app, threshold.clone(), voting_period, init_funds, true);
+
+    let config = |timelock| Config {
+        name: "fishsig".to_string(),
+        description: "🐟".to_string(),
+        threshold: threshold.clone(),
+        max_voting_period: voting_period,
+        image_url: None,
+        only_members_execute: true,
+        automatically_add_cw20s: true,
+        timelock: Some(timelock),
+    };
+
+    // the timelock must use the same unit as the voting period
+    let err = app
+        .execute_contract(
+            multisig_addr.clone(),
+            multisig_addr.clone(),
+            &ExecuteMsg::UpdateConfig(config(Duration::Time(5))),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::InvalidTimelock {}, err.downcast().unwrap());
+
+    app.execute_contract(
+        multisig_addr.clone(),
+        multisig_addr.clone(),
+        &ExecuteMsg::UpdateConfig(config(timelock)),
+        &[],
+    )
+    .unwrap();
+
+    let proposal = pay_somebody_proposal();
+    let res = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            multisig_addr.clone(),
+            &proposal,
+            &[],
+        )
+        .unwrap();
+    let proposal_id: u64 = res.custom_attrs(1)[2].value.parse().unwrap();
+
+    // pass it before it expires, starting the timelock
+    let vote = ExecuteMsg::Vote {
+        proposal_id,
+        vote: Vote::Yes,
+    };
+    app.execute_contract(Addr::unchecked(VOTER4), multisig_addr.clone(), &vote, &[])
+        .unwrap();
+    let executable_at = Expiration::AtHeight(app.block_info().height + 5);
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&multisig_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Passed);
+    assert_eq!(prop.executable_at, Some(executable_at));
+
+    let execution = ExecuteMsg::Execute { proposal_id };
+    let err = app
+        .execute_contract(
+            Addr::unchecked(VOTER3),
+            multisig_addr.clone(),
+            &execution,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::Timelocked { executable_at },
+        err.downcast().unwrap()
+    );
+
+    // once the timelock is over it can be executed
+    app.update_block(|block| block.height += 5);
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        multisig_addr.clone(),
+        &execution,
+        &[],
+    )
+    .unwrap();
+    let some_bal = app.wrap().query_balance(SOMEBODY, "BTC").unwrap();
+    assert_eq!(some_bal, coin(1, "BTC"));
+}
+
 #[test]
 fn test_close_works() {
     let init_funds = coins(10, "BTC");
@@ -1559,6 +1671,91 @@ fn test_close_works() {
     assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
 }
 
+#[test]
+fn test_withdraw() {
+    let init_funds = coins(10, "BTC");
+    let mut app = mock_app(&init_funds);
+
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(1),
+    };
+    let voting_period = Duration::Height(2000000);
+    let (multisig_addr, _) = setup_test_case(&mut app, threshold, voting_period, init_funds, true);
+
+    let propose = |app: &mut App| -> u64 {
+        let res = app
+            .execute_contract(
+                Addr::unchecked(VOTER1),
+                multisig_addr.clone(),
+                &pay_somebody_proposal(),
+                &[],
+            )
+            .unwrap();
+        res.custom_attrs(1)[2].value.parse().unwrap()
+    };
+    let withdraw = |app: &mut App, sender: &str, proposal_id| {
+        app.execute_contract(
+            Addr::unchecked(sender),
+            multisig_addr.clone(),
+            &ExecuteMsg::Withdraw { proposal_id },
+            &[],
+        )
+    };
+
+    // Only the proposer can withdraw their proposal
+    let proposal_id = propose(&mut app);
+    let err = withdraw(&mut app, VOTER2, proposal_id).unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // The yes vote of the proposer doesn't prevent withdrawing
+    let res = withdraw(&mut app, VOTER1, proposal_id).unwrap();
+    assert_eq!(
+        res.custom_attrs(1),
+        [
+            ("action", "withdraw"),
+            ("sender", VOTER1),
+            ("proposal_id", proposal_id.to_string().as_str()),
+        ],
+    );
+    let prop: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&multisig_addr, &QueryMsg::Proposal { proposal_id })
+        .unwrap();
+    assert_eq!(prop.status, Status::Withdrawn);
+
+    let err = withdraw(&mut app, VOTER1, proposal_id).unwrap_err();
+    assert_eq!(
+        ContractError::WrongWithdrawStatus {},
+        err.downcast().unwrap()
+    );
+    app.update_block(expire(voting_period));
+    let err = app
+        .execute_contract(
+            Addr::unchecked(SOMEBODY),
+            multisig_addr.clone(),
+            &ExecuteMsg::Close { proposal_id },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::WrongCloseStatus {}, err.downcast().unwrap());
+
+    // Proposals can't be withdrawn once somebody else voted
+    let proposal_id = propose(&mut app);
+    app.execute_contract(
+        Addr::unchecked(VOTER2),
+        multisig_addr.clone(),
+        &ExecuteMsg::Vote {
+            proposal_id,
+            vote: Vote::No,
+        },
+        &[],
+    )
+    .unwrap();
+    let err = withdraw(&mut app, VOTER1, proposal_id).unwrap_err();
+    assert_eq!(ContractError::ProposalHasVotes {}, err.downcast().unwrap());
+}
+
 // uses the power from the beginning of the voting period
 #[test]
 fn execute_group_changes_from_external() {
@@ -2172,6 +2369,7 @@ fn test_receive() {
         image_url: None,
         only_members_execute: false,
         automatically_add_cw20s: false,
+        timelock: None,
     });
     let res = app.execute_contract(
         multisig_addr.clone(),
@@ -2337,3 +2535,317 @@ fn treasury_queries() {
         .token_list
         .contains(&Addr::unchecked("Contract #2")));
 }
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum HookReceiverMsg {
+    ProposalHook(ProposalHookMsg),
+    VoteHook(VoteHookMsg),
+}
+
+const RECEIVED_HOOKS: Item<Vec<HookReceiverMsg>> = Item::new("received_hooks");
+
+/// Records every proposal and vote hook it receives
+fn contract_hook_receiver() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |deps, _env, _info, msg: HookReceiverMsg| -> StdResult<Response> {
+            let mut hooks = RECEIVED_HOOKS.may_load(deps.storage)?.unwrap_or_default();
+            hooks.push(msg);
+            RECEIVED_HOOKS.save(deps.storage, &hooks)?;
+            Ok(Response::new())
+        },
+        |_deps, _env, _info, _msg: Empty| -> StdResult<Response> { Ok(Response::new()) },
+        |deps, _env, _msg: Empty| -> StdResult<Binary> {
+            to_binary(&RECEIVED_HOOKS.may_load(deps.storage)?.unwrap_or_default())
+        },
+    );
+    Box::new(contract)
+}
+
+#[test]
+fn test_hooks() {
+    let init_funds = coins(10, "BTC");
+    let mut app = mock_app(&init_funds);
+
+    let threshold = Threshold::ThresholdQuorum {
+        threshold: Decimal::percent(51),
+        quorum: Decimal::percent(1),
+    };
+    let voting_period = Duration::Time(2000000);
+    let (multisig_addr, group_addr) =
+        setup_test_case(&mut app, threshold, voting_period, init_funds, true);
+
+    let receiver_id = app.store_code(contract_hook_receiver());
+    let receiver_addr = app
+        .instantiate_contract(
+            receiver_id,
+            Addr::unchecked(OWNER),
+            &Empty {},
+            &[],
+            "receiver",
+            None,
+        )
+        .unwrap();
+
+    // Only the multisig can manage its hooks
+    let add_vote_hook = ExecuteMsg::AddVoteHook {
+        address: receiver_addr.to_string(),
+    };
+    let err = app
+        .execute_contract(
+            Addr::unchecked(OWNER),
+            multisig_addr.clone(),
+            &add_vote_hook,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // The group contract fails to handle hooks
+    app.execute_contract(
+        multisig_addr.clone(),
+        multisig_addr.clone(),
+        &add_vote_hook,
+        &[],
+    )
+    .unwrap();
+    for hook in [&group_addr, &receiver_addr] {
+        app.execute_contract(
+            multisig_addr.clone(),
+            multisig_addr.clone(),
+            &ExecuteMsg::AddProposalHook {
+                address: hook.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    }
+
+    // Failing hooks are removed without failing the proposal
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        multisig_addr.clone(),
+        &pay_somebody_proposal(),
+        &[],
+    )
+    .unwrap();
+    let hooks: HooksResponse = app
+        .wrap()
+        .query_wasm_smart(&multisig_addr, &QueryMsg::ProposalHooks {})
+        .unwrap();
+    assert_eq!(hooks.hooks, vec![receiver_addr.to_string()]);
+
+    let vote = ExecuteMsg::Vote {
+        proposal_id: 1,
+        vote: Vote::Yes,
+    };
+    app.execute_contract(Addr::unchecked(VOTER4), multisig_addr.clone(), &vote, &[])
+        .unwrap();
+    app.execute_contract(
+        Addr::unchecked(VOTER3),
+        multisig_addr.clone(),
+        &ExecuteMsg::Execute { proposal_id: 1 },
+        &[],
+    )
+    .unwrap();
+
+    // Expired proposals are closed
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        multisig_addr.clone(),
+        &pay_somebody_proposal(),
+        &[],
+    )
+    .unwrap();
+    app.update_block(expire(voting_period));
+    app.execute_contract(
+        Addr::unchecked(VOTER1),
+        multisig_addr.clone(),
+        &ExecuteMsg::Close { proposal_id: 2 },
+        &[],
+    )
+    .unwrap();
+
+    // A proposal passed by the proposer's own vote reports its status
+    app.execute_contract(
+        Addr::unchecked(VOTER4),
+        multisig_addr.clone(),
+        &pay_somebody_proposal(),
+        &[],
+    )
+    .unwrap();
+
+    let received: Vec<HookReceiverMsg> = app
+        .wrap()
+        .query_wasm_smart(&receiver_addr, &Empty {})
+        .unwrap();
+    assert_eq!(
+        received,
+        vec![
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::NewProposal {
+                proposal_id: 1,
+                proposer: VOTER1.to_string(),
+            }),
+            HookReceiverMsg::VoteHook(VoteHookMsg::NewVote {
+                proposal_id: 1,
+                voter: VOTER1.to_string(),
+                vote: Vote::Yes,
+                choice: None,
+                weight: 1,
+            }),
+            HookReceiverMsg::VoteHook(VoteHookMsg::NewVote {
+                proposal_id: 1,
+                voter: VOTER4.to_string(),
+                vote: Vote::Yes,
+                choice: None,
+                weight: 12,
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalStatusChanged {
+                proposal_id: 1,
+                old_status: Status::Open,
+                new_status: Status::Passed,
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalExecuted { proposal_id: 1 }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::NewProposal {
+                proposal_id: 2,
+                proposer: VOTER1.to_string(),
+            }),
+            HookReceiverMsg::VoteHook(VoteHookMsg::NewVote {
+                proposal_id: 2,
+                voter: VOTER1.to_string(),
+                vote: Vote::Yes,
+                choice: None,
+                weight: 1,
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalClosed { proposal_id: 2 }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::NewProposal {
+                proposal_id: 3,
+                proposer: VOTER4.to_string(),
+            }),
+            HookReceiverMsg::VoteHook(VoteHookMsg::NewVote {
+                proposal_id: 3,
+                voter: VOTER4.to_string(),
+                vote: Vote::Yes,
+                choice: None,
+                weight: 12,
+            }),
+            HookReceiverMsg::ProposalHook(ProposalHookMsg::ProposalStatusChanged {
+                proposal_id: 3,
+                old_status: Status::Open,
+                new_status: Status::Passed,
+            }),
+        ]
+    );
+
+    app.execute_contract(
+        multisig_addr.clone(),
+        multisig_addr.clone(),
+        &ExecuteMsg::RemoveVoteHook {
+            address: receiver_addr.to_string(),
+        },
+        &[],
+    )
+    .unwrap();
+    let hooks: HooksResponse = app
+        .wrap()
+        .query_wasm_smart(&multisig_addr, &QueryMsg::VoteHooks {})
+        .unwrap();
+    assert!(hooks.hooks.is_empty());
+}
+
+#[test]
+fn test_migrate() {
+    let mut deps = mock_dependencies();
+
+    // Only the same contract type can be migrated
+    set_contract_version(deps.as_mut().storage, "crates.io:cw3_dao", "0.2.6").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Migrate(MigrateError::CannotMigrate {
+            previous_contract: "crates.io:cw3_dao".to_string()
+        })
+    );
+
+    // Downgrades are refused
+    set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Migrate(MigrateError::CannotMigrateVersion {
+            previous_version: "99.0.0".to_string(),
+            new_version: CONTRACT_VERSION.to_string(),
+        })
+    );
+
+    // The config and proposals of v0.2 are read as they were stored
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct LegacyConfig {
+        name: String,
+        description: String,
+        threshold: Threshold,
+        max_voting_period: Duration,
+        image_url: Option<String>,
+        only_members_execute: bool,
+        automatically_add_cw20s: bool,
+    }
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct LegacyProposal {
+        proposer: Addr,
+        title: String,
+        description: String,
+        start_height: u64,
+        expires: Expiration,
+        msgs: Vec<CosmosMsg>,
+        status: Status,
+        threshold: Threshold,
+        total_weight: u64,
+        votes: Votes,
+    }
+    let threshold = Threshold::AbsoluteCount { weight: 2 };
+    set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.2.5").unwrap();
+    Item::new("config")
+        .save(
+            deps.as_mut().storage,
+            &LegacyConfig {
+                name: "multisig".to_string(),
+                description: "a great multisig!".to_string(),
+                threshold: threshold.clone(),
+                max_voting_period: Duration::Time(2000000),
+                image_url: None,
+                only_members_execute: true,
+                automatically_add_cw20s: true,
+            },
+        )
+        .unwrap();
+    Map::new("proposals")
+        .save(
+            deps.as_mut().storage,
+            1u64,
+            &LegacyProposal {
+                proposer: Addr::unchecked(OWNER),
+                title: "Pay somebody".to_string(),
+                description: "Do I pay her?".to_string(),
+                start_height: 100,
+                expires: Expiration::AtHeight(200),
+                msgs: vec![],
+                status: Status::Open,
+                threshold,
+                total_weight: 3,
+                votes: Votes::yes(1),
+            },
+        )
+        .unwrap();
+
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    let version = get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(version.version, CONTRACT_VERSION);
+
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.name, "multisig");
+    assert_eq!(config.timelock, None);
+    let prop = PROPOSALS.load(deps.as_ref().storage, 1).unwrap();
+    assert_eq!(prop.votes, Votes::yes(1));
+    assert_eq!(prop.timelock, None);
+    assert_eq!(prop.executable_at, None);
+}
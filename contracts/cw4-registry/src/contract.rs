@@ -5,12 +5,13 @@ use cosmwasm_std::{
 };
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, ListGroupsResponse, QueryMsg};
+use crate::msg::{ExecuteMsg, InstantiateMsg, ListGroupsResponse, MigrateMsg, QueryMsg};
 use crate::state::{EMPTY, MEMBER_INDEX};
 
 use cw2::set_contract_version;
 use cw4::MemberChangedHookMsg;
 use cw4_group::helpers::Cw4GroupContract;
+use cw_migrate::ensure_upgrade;
 use cw_storage_plus::Bound;
 
 const CONTRACT_NAME: &str = "crates.io:cw4-registry";
@@ -130,16 +131,29 @@ pub fn query_groups(
     Ok(ListGroupsResponse { groups: groups_str })
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let previous_version = ensure_upgrade(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", previous_version.to_string())
+        .add_attribute("new_version", CONTRACT_VERSION))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::contract::{execute, instantiate, query};
-    use crate::msg::{ExecuteMsg, InstantiateMsg};
+    use crate::contract::{execute, instantiate, migrate, query, CONTRACT_NAME, CONTRACT_VERSION};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg};
     use crate::ContractError;
     use anyhow::Error;
+    use cw_migrate::MigrateError;
 
     use crate::helpers::Cw4RegistryContract;
     use assert_matches::assert_matches;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env};
     use cosmwasm_std::{to_binary, Addr, Empty, WasmMsg};
+    use cw2::{get_contract_version, set_contract_version};
     use cw4::Member;
     use cw4_group::helpers::Cw4GroupContract;
     use cw_multi_test::{App, BasicApp, Contract, ContractWrapper, Executor};
@@ -336,4 +350,31 @@ mod tests {
         let groups = registry_contract.list_group(&router, ADDR1).unwrap();
         assert_eq!(groups.groups, vec![group_contract.addr()])
     }
+
+    #[test]
+    fn test_migrate() {
+        let mut deps = mock_dependencies();
+
+        // Only the same contract type can be migrated
+        set_contract_version(deps.as_mut().storage, "crates.io:cw4-group", "0.11.1").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_matches!(
+            err,
+            ContractError::Migrate(MigrateError::CannotMigrate { .. })
+        );
+
+        // Downgrades are refused
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_matches!(
+            err,
+            ContractError::Migrate(MigrateError::CannotMigrateVersion { .. })
+        );
+
+        // Upgrading stores the new version
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        let version = get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, CONTRACT_VERSION);
+    }
 }
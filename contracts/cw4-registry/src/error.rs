@@ -1,4 +1,5 @@
 use cosmwasm_std::StdError;
+use cw_migrate::MigrateError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +7,9 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Migrate(#[from] MigrateError),
+
     #[error("Unauthorized")]
     Unauthorized {},
 }
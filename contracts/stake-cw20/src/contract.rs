@@ -2,18 +2,30 @@
 use cosmwasm_std::entry_point;
 
 use cosmwasm_std::{
-    from_binary, to_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response,
-    StdError, StdResult, Uint128,
+    coins, from_binary, to_binary, Addr, BankMsg, Binary, CosmosMsg, Decimal, Deps, DepsMut, Empty,
+    Env, MessageInfo, Order, Reply, Response, StdError, StdResult, Storage, Uint128, WasmMsg,
 };
 
-use cw20::Cw20ReceiveMsg;
+use cw20::{Cw20ReceiveMsg, Denom};
 
+use crate::hooks::{
+    remove_failed_hook, stake_changed_hook_msgs, STAKE_CHANGED_HOOK_REPLY_ID_OFFSET,
+};
+use crate::migrations;
 use crate::msg::{
-    ExecuteMsg, GetConfigResponse, InstantiateMsg, QueryMsg, ReceiveMsg,
-    StakedBalanceAtHeightResponse, StakedValueResponse, TotalStakedAtHeightResponse,
-    TotalValueResponse,
+    DelegatedPowerAtHeightResponse, DelegationAtHeightResponse, ExchangeRateAtHeightResponse,
+    ExecuteMsg, GetConfigResponse, InstantiateMsg, ListStakersAtHeightResponse,
+    ListStakersResponse, LocksResponse, LockupTier, MigrateMsg, PendingReward,
+    PendingRewardsResponse, QueryMsg, ReceiveMsg, SnapshotStrategy, StakedBalanceAtHeightResponse,
+    StakedValueAtHeightResponse, StakedValueResponse, StakerBalance, StakerCountResponse,
+    StakerPowerAtHeightResponse, TotalPowerAtHeightResponse, TotalSqrtPowerAtHeightResponse,
+    TotalStakedAtHeightResponse, TotalValueResponse, VotingPowerAtHeightResponse,
+};
+use crate::state::{
+    isqrt, reward_token_key, Config, Lock, RewardToken, Snapshots, CHECKPOINTS, CLAIMS, CONFIG,
+    HOOKS, LOCKS, LOCK_EXPIRIES, MAX_CLAIMS, MAX_LOCKS, MAX_RELEASED_LOCKS, RELEASE_CURSOR,
+    REWARD_TOKENS, STAKER_COUNT, STAKER_REWARDS, UNRECORDED_POWER,
 };
-use crate::state::{Config, BALANCE, CLAIMS, CONFIG, MAX_CLAIMS, STAKED_BALANCES, STAKED_TOTAL};
 use crate::ContractError;
 use cw2::set_contract_version;
 pub use cw20_base::allowances::{
@@ -26,16 +38,23 @@ pub use cw20_base::contract::{
     query_token_info,
 };
 pub use cw20_base::enumerable::{query_all_accounts, query_all_allowances};
-use cw_controllers::ClaimsResponse;
-use cw_utils::Duration;
+use cw_controllers::{Claim, ClaimsResponse};
+use cw_migrate::{ensure_upgrade, Version};
+use cw_storage_plus::{Bound, Prefixer, PrimaryKey};
+use cw_utils::{maybe_addr, must_pay, Duration, Expiration};
 
 const CONTRACT_NAME: &str = "crates.io:stake_cw20";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_PRUNE_LIMIT: u32 = 100;
+const DEFAULT_PRUNE_LIMIT: u32 = 30;
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response<Empty>, ContractError> {
@@ -44,13 +63,29 @@ pub fn instantiate(
         None => None,
     };
 
-    let token_address = deps.api.addr_validate(&msg.token_address)?;
+    let token = match (msg.token_address, msg.native_denom) {
+        (Some(token_address), None) => Denom::Cw20(deps.api.addr_validate(&token_address)?),
+        (None, Some(denom)) => Denom::Native(denom),
+        _ => return Err(ContractError::InvalidTokenConfig {}),
+    };
     let config = Config {
         admin,
-        token_address,
+        token,
         unstaking_duration: msg.unstaking_duration,
+        slash_destination: msg
+            .slash_destination
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        snapshot_strategy: msg.snapshot_strategy.unwrap_or_default(),
+        lockup_tiers: validate_lockup_tiers(msg.lockup_tiers.unwrap_or_default())?,
     };
     CONFIG.save(deps.storage, &config)?;
+    Snapshots::load(deps.storage)?.sqrt_power.save(
+        deps.storage,
+        &Uint128::zero(),
+        env.block.height,
+    )?;
+    STAKER_COUNT.save(deps.storage, &0)?;
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     Ok(Response::new())
@@ -65,11 +100,56 @@ pub fn execute(
 ) -> Result<Response<Empty>, ContractError> {
     match msg {
         ExecuteMsg::Receive(msg) => execute_receive(deps, env, info, msg),
+        ExecuteMsg::Stake {} => {
+            let amount = must_pay_staking_denom(deps.storage, &info)?;
+            execute_stake(deps, env, &info.sender, amount)
+        }
+        ExecuteMsg::StakeLocked { duration } => {
+            let amount = must_pay_staking_denom(deps.storage, &info)?;
+            execute_stake_locked(deps, env, &info.sender, amount, duration)
+        }
+        ExecuteMsg::Fund {} => {
+            let amount = must_pay_staking_denom(deps.storage, &info)?;
+            execute_fund(deps, env, &info.sender, amount)
+        }
         ExecuteMsg::Unstake { amount } => execute_unstake(deps, env, info, amount),
-        ExecuteMsg::Claim {} => execute_claim(deps, env, info),
-        ExecuteMsg::UpdateConfig { admin, duration } => {
-            execute_update_config(info, deps, admin, duration)
+        ExecuteMsg::Claim {} => execute_claim(deps, env, info, None, None),
+        ExecuteMsg::ClaimAt { release_at } => {
+            execute_claim(deps, env, info, Some(release_at), None)
         }
+        ExecuteMsg::ClaimAmount { amount } => execute_claim(deps, env, info, None, Some(amount)),
+        ExecuteMsg::Restake { release_at } => execute_restake(deps, env, info, release_at),
+        ExecuteMsg::Delegate { address } => execute_delegate(deps, env, info, address),
+        ExecuteMsg::Undelegate {} => execute_undelegate(deps, env, info),
+        ExecuteMsg::FundRewards {} => {
+            let funds = info
+                .funds
+                .iter()
+                .map(|coin| (Denom::Native(coin.denom.clone()), coin.amount))
+                .collect();
+            execute_fund_rewards(deps, env, &info.sender, funds)
+        }
+        ExecuteMsg::ClaimRewards {} => execute_claim_rewards(deps, env, info),
+        ExecuteMsg::AddRewardToken {
+            denom,
+            reward_duration,
+        } => execute_add_reward_token(deps, env, info, denom, reward_duration),
+        ExecuteMsg::UpdateConfig {
+            admin,
+            duration,
+            slash_destination,
+        } => execute_update_config(info, deps, admin, duration, slash_destination),
+        ExecuteMsg::Slash { addr, percentage } => execute_slash(deps, env, info, addr, percentage),
+        ExecuteMsg::AddHook { addr } => execute_add_hook(deps, info, addr),
+        ExecuteMsg::RemoveHook { addr } => execute_remove_hook(deps, info, addr),
+        ExecuteMsg::AddCheckpoint {} => execute_add_checkpoint(deps, env, info),
+        ExecuteMsg::PruneCheckpoints {
+            before,
+            start_after,
+            limit,
+        } => execute_prune_checkpoints(deps, info, before, start_after, limit),
+        ExecuteMsg::UpdateLockupTiers { tiers } => execute_update_lockup_tiers(deps, info, tiers),
+        ExecuteMsg::ReleaseLocks { limit } => execute_release_locks(deps, env, limit),
     }
 }
 
@@ -78,6 +158,7 @@ pub fn execute_update_config(
     deps: DepsMut,
     new_admin: Option<String>,
     duration: Option<Duration>,
+    slash_destination: Option<String>,
 ) -> Result<Response, ContractError> {
     let mut config: Config = CONFIG.load(deps.storage)?;
     match config.admin {
@@ -92,6 +173,9 @@ pub fn execute_update_config(
 
             config.admin = new_admin.map(|a| deps.api.addr_validate(&a)).transpose()?;
             config.unstaking_duration = duration;
+            config.slash_destination = slash_destination
+                .map(|addr| deps.api.addr_validate(&addr))
+                .transpose()?;
 
             CONFIG.save(deps.storage, &config)?;
             Ok(Response::new().add_attribute(
@@ -105,6 +189,40 @@ pub fn execute_update_config(
     }
 }
 
+/// Returns the amount of the staked native denom sent with `info`
+fn must_pay_staking_denom(
+    storage: &dyn Storage,
+    info: &MessageInfo,
+) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(storage)?;
+    match config.token {
+        Denom::Native(denom) => Ok(must_pay(info, &denom)?),
+        Denom::Cw20(_) => Err(ContractError::WrongStakingToken {
+            denom: reward_token_key(&config.token),
+        }),
+    }
+}
+
+/// Message sending `amount` of `denom` to `recipient`
+fn send_tokens_msg(denom: &Denom, recipient: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+    Ok(match denom {
+        Denom::Cw20(addr) => WasmMsg::Execute {
+            contract_addr: addr.to_string(),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        }
+        .into(),
+        Denom::Native(denom) => BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: coins(amount.u128(), denom),
+        }
+        .into(),
+    })
+}
+
 pub fn execute_receive(
     deps: DepsMut,
     env: Env,
@@ -112,17 +230,37 @@ pub fn execute_receive(
     wrapper: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    if info.sender != config.token_address {
-        return Err(ContractError::InvalidToken {
-            received: info.sender,
-            expected: config.token_address,
-        });
-    }
     let msg: ReceiveMsg = from_binary(&wrapper.msg)?;
+    // Rewards can be funded with any cw20 reward token
+    if msg != (ReceiveMsg::FundRewards {}) {
+        match config.token {
+            Denom::Cw20(token_address) if token_address != info.sender => {
+                return Err(ContractError::InvalidToken {
+                    received: info.sender,
+                    expected: token_address,
+                })
+            }
+            Denom::Native(_) => {
+                return Err(ContractError::WrongStakingToken {
+                    denom: reward_token_key(&config.token),
+                })
+            }
+            _ => {}
+        }
+    }
     let sender = deps.api.addr_validate(&wrapper.sender)?;
     match msg {
         ReceiveMsg::Stake {} => execute_stake(deps, env, &sender, wrapper.amount),
+        ReceiveMsg::StakeLocked { duration } => {
+            execute_stake_locked(deps, env, &sender, wrapper.amount, duration)
+        }
         ReceiveMsg::Fund {} => execute_fund(deps, env, &sender, wrapper.amount),
+        ReceiveMsg::FundRewards {} => execute_fund_rewards(
+            deps,
+            env,
+            &sender,
+            vec![(Denom::Cw20(info.sender), wrapper.amount)],
+        ),
     }
 }
 
@@ -132,8 +270,29 @@ pub fn execute_stake(
     sender: &Addr,
     amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let balance = BALANCE.load(deps.storage).unwrap_or_default();
-    let staked_total = STAKED_TOTAL.load(deps.storage).unwrap_or_default();
+    let snapshots = Snapshots::load(deps.storage)?;
+    let old = snapshots
+        .staked_balances
+        .may_load(deps.storage, sender)?
+        .unwrap_or_default();
+    stake(deps.storage, env.block.height, sender, amount)?;
+    let new = snapshots.staked_balances.load(deps.storage, sender)?;
+    let hook_msgs = stake_changed_hook_msgs(deps.as_ref(), sender.clone(), old, new)?;
+    Ok(Response::new()
+        .add_submessages(hook_msgs)
+        .add_attribute("action", "stake")
+        .add_attribute("from", sender)
+        .add_attribute("amount", amount))
+}
+
+/// Stakes `amount` tokens held by the contract for `sender` at the current
+/// share price
+fn stake(storage: &mut dyn Storage, height: u64, sender: &Addr, amount: Uint128) -> StdResult<()> {
+    let snapshots = Snapshots::load(storage)?;
+    release_locks(storage, height, sender)?;
+    update_rewards(storage, height, sender)?;
+    let balance = snapshots.balance.load(storage).unwrap_or_default();
+    let staked_total = snapshots.staked_total.load(storage).unwrap_or_default();
     let amount_to_stake = if staked_total == Uint128::zero() || balance == Uint128::zero() {
         amount
     } else {
@@ -143,27 +302,67 @@ pub fn execute_stake(
             .checked_div(balance)
             .map_err(StdError::divide_by_zero)?
     };
-    STAKED_BALANCES.update(
-        deps.storage,
-        sender,
-        env.block.height,
-        |bal| -> StdResult<Uint128> { Ok(bal.unwrap_or_default().checked_add(amount_to_stake)?) },
-    )?;
-    STAKED_TOTAL.update(
-        deps.storage,
-        env.block.height,
-        |total| -> StdResult<Uint128> {
+    let new =
+        snapshots
+            .staked_balances
+            .update(storage, sender, height, |bal| -> StdResult<Uint128> {
+                Ok(bal.unwrap_or_default().checked_add(amount_to_stake)?)
+            })?;
+    update_staker_count(storage, new - amount_to_stake, new)?;
+    update_power(storage, height, sender)?;
+    snapshots
+        .staked_total
+        .update(storage, height, |total| -> StdResult<Uint128> {
             Ok(total.unwrap_or_default().checked_add(amount_to_stake)?)
-        },
-    )?;
-    BALANCE.save(
-        deps.storage,
+        })?;
+    snapshots.balance.save(
+        storage,
         &balance.checked_add(amount).map_err(StdError::overflow)?,
-    )?;
+        height,
+    )
+}
+
+pub fn execute_stake_locked(
+    deps: DepsMut,
+    env: Env,
+    sender: &Addr,
+    amount: Uint128,
+    duration: Duration,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let tier = config
+        .lockup_tiers
+        .into_iter()
+        .find(|tier| tier.duration == duration)
+        .ok_or(ContractError::UnknownLockupTier {})?;
+    release_locks(deps.storage, env.block.height, sender)?;
+    let mut locks = LOCKS.may_load(deps.storage, sender)?.unwrap_or_default();
+    if locks.len() >= MAX_LOCKS as usize {
+        return Err(ContractError::TooManyLocks {});
+    }
+
+    let snapshots = Snapshots::load(deps.storage)?;
+    let old = snapshots
+        .staked_balances
+        .may_load(deps.storage, sender)?
+        .unwrap_or_default();
+    stake(deps.storage, env.block.height, sender, amount)?;
+    let new = snapshots.staked_balances.load(deps.storage, sender)?;
+    let expires = tier.duration.after(&env.block);
+    locks.push(Lock {
+        shares: new - old,
+        multiplier: tier.multiplier,
+        expires,
+    });
+    save_locks(deps.storage, sender, &locks)?;
+    update_power(deps.storage, env.block.height, sender)?;
+    let hook_msgs = stake_changed_hook_msgs(deps.as_ref(), sender.clone(), old, new)?;
     Ok(Response::new()
-        .add_attribute("action", "stake")
+        .add_submessages(hook_msgs)
+        .add_attribute("action", "stake_locked")
         .add_attribute("from", sender)
-        .add_attribute("amount", amount))
+        .add_attribute("amount", amount)
+        .add_attribute("expires", expires.to_string()))
 }
 
 pub fn execute_unstake(
@@ -172,62 +371,91 @@ pub fn execute_unstake(
     info: MessageInfo,
     amount: Uint128,
 ) -> Result<Response, ContractError> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    release_locks(deps.storage, env.block.height, &info.sender)?;
+    let locked: Uint128 = LOCKS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default()
+        .iter()
+        .map(|lock| lock.shares)
+        .sum();
+    if !locked.is_zero() {
+        let staked = snapshots
+            .staked_balances
+            .may_load(deps.storage, &info.sender)?
+            .unwrap_or_default();
+        let unlocked = staked.saturating_sub(locked);
+        if amount > unlocked {
+            return Err(ContractError::StakeLocked { unlocked });
+        }
+    }
+    update_rewards(deps.storage, env.block.height, &info.sender)?;
     let config = CONFIG.load(deps.storage)?;
-    let balance = BALANCE.load(deps.storage).unwrap_or_default();
-    let staked_total = STAKED_TOTAL.load(deps.storage)?;
+    let balance = snapshots.balance.load(deps.storage).unwrap_or_default();
+    let staked_total = snapshots.staked_total.load(deps.storage)?;
     let amount_to_claim = amount
         .checked_mul(balance)
         .map_err(StdError::overflow)?
         .checked_div(staked_total)
         .map_err(StdError::divide_by_zero)?;
-    STAKED_BALANCES.update(
+    let new = snapshots.staked_balances.update(
         deps.storage,
         &info.sender,
         env.block.height,
         |bal| -> StdResult<Uint128> { Ok(bal.unwrap_or_default().checked_sub(amount)?) },
     )?;
-    STAKED_TOTAL.update(
+    update_staker_count(deps.storage, new + amount, new)?;
+    let hook_msgs = stake_changed_hook_msgs(deps.as_ref(), info.sender.clone(), new + amount, new)?;
+    update_power(deps.storage, env.block.height, &info.sender)?;
+    snapshots.staked_total.update(
         deps.storage,
         env.block.height,
         |total| -> StdResult<Uint128> { Ok(total.unwrap_or_default().checked_sub(amount)?) },
     )?;
-    BALANCE.save(
+    snapshots.balance.save(
         deps.storage,
         &balance
             .checked_sub(amount_to_claim)
             .map_err(StdError::overflow)?,
+        env.block.height,
     )?;
     match config.unstaking_duration {
         None => {
-            let cw_send_msg = cw20::Cw20ExecuteMsg::Transfer {
-                recipient: info.sender.to_string(),
-                amount: amount_to_claim,
-            };
-            let wasm_msg = cosmwasm_std::WasmMsg::Execute {
-                contract_addr: config.token_address.to_string(),
-                msg: to_binary(&cw_send_msg)?,
-                funds: vec![],
-            };
+            let send_msg = send_tokens_msg(&config.token, &info.sender, amount_to_claim)?;
             Ok(Response::new()
-                .add_message(wasm_msg)
+                .add_message(send_msg)
+                .add_submessages(hook_msgs)
                 .add_attribute("action", "unstake")
                 .add_attribute("from", info.sender)
                 .add_attribute("amount", amount)
                 .add_attribute("claim_duration", "None"))
         }
         Some(duration) => {
-            let outstanding_claims = CLAIMS.query_claims(deps.as_ref(), &info.sender)?.claims;
-            if outstanding_claims.len() >= MAX_CLAIMS as usize {
-                return Err(ContractError::TooManyClaims {});
+            let release_at = duration.after(&env.block);
+            let mut claims = CLAIMS
+                .may_load(deps.storage, &info.sender)?
+                .unwrap_or_default();
+            // Claims released at the same time are merged
+            match claims.iter_mut().find(|c| c.release_at == release_at) {
+                Some(claim) => {
+                    claim.amount = claim
+                        .amount
+                        .checked_add(amount_to_claim)
+                        .map_err(StdError::overflow)?
+                }
+                None => {
+                    if claims.len() >= MAX_CLAIMS as usize {
+                        return Err(ContractError::TooManyClaims {});
+                    }
+                    claims.push(Claim {
+                        amount: amount_to_claim,
+                        release_at,
+                    });
+                }
             }
-
-            CLAIMS.create_claim(
-                deps.storage,
-                &info.sender,
-                amount_to_claim,
-                duration.after(&env.block),
-            )?;
+            CLAIMS.save(deps.storage, &info.sender, &claims)?;
             Ok(Response::new()
+                .add_submessages(hook_msgs)
                 .add_attribute("action", "unstake")
                 .add_attribute("from", info.sender)
                 .add_attribute("amount", amount)
@@ -238,984 +466,4168 @@ pub fn execute_unstake(
 
 pub fn execute_claim(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
+    release_at: Option<Expiration>,
+    cap: Option<Uint128>,
 ) -> Result<Response, ContractError> {
-    let release = CLAIMS.claim_tokens(deps.storage, &info.sender, &_env.block, None)?;
+    let mut claims = CLAIMS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    if let Some(release_at) = release_at {
+        if !claims.iter().any(|c| c.release_at == release_at) {
+            return Err(ContractError::ClaimNotFound {});
+        }
+    }
+    let mut release = Uint128::zero();
+    for claim in claims.iter_mut() {
+        if !claim.release_at.is_expired(&env.block)
+            || release_at.is_some_and(|release_at| claim.release_at != release_at)
+        {
+            continue;
+        }
+        // The last claim paid out may only be paid partially
+        let amount = match cap {
+            Some(cap) => claim.amount.min(cap - release),
+            None => claim.amount,
+        };
+        claim.amount -= amount;
+        release += amount;
+    }
     if release.is_zero() {
         return Err(ContractError::NothingToClaim {});
     }
+    claims.retain(|c| !c.amount.is_zero());
+    CLAIMS.save(deps.storage, &info.sender, &claims)?;
+
     let config = CONFIG.load(deps.storage)?;
-    let cw_send_msg = cw20::Cw20ExecuteMsg::Transfer {
-        recipient: info.sender.to_string(),
-        amount: release,
-    };
-    let wasm_msg = cosmwasm_std::WasmMsg::Execute {
-        contract_addr: config.token_address.to_string(),
-        msg: to_binary(&cw_send_msg)?,
-        funds: vec![],
-    };
+    let send_msg = send_tokens_msg(&config.token, &info.sender, release)?;
     Ok(Response::new()
-        .add_message(wasm_msg)
+        .add_message(send_msg)
         .add_attribute("action", "claim")
         .add_attribute("from", info.sender)
         .add_attribute("amount", release))
 }
 
-pub fn execute_fund(
+pub fn execute_restake(
     deps: DepsMut,
-    _env: Env,
-    sender: &Addr,
-    amount: Uint128,
+    env: Env,
+    info: MessageInfo,
+    release_at: Option<Expiration>,
 ) -> Result<Response, ContractError> {
-    let balance = BALANCE.load(deps.storage).unwrap_or_default();
-    BALANCE.save(
-        deps.storage,
-        &balance.checked_add(amount).map_err(StdError::overflow)?,
-    )?;
+    let snapshots = Snapshots::load(deps.storage)?;
+    let claims = CLAIMS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    let (restaked, remaining): (Vec<_>, _) = claims
+        .into_iter()
+        .partition(|c| release_at.is_none_or(|release_at| c.release_at == release_at));
+    if restaked.is_empty() {
+        return Err(ContractError::ClaimNotFound {});
+    }
+    let amount = restaked
+        .iter()
+        .try_fold(Uint128::zero(), |sum, c| sum.checked_add(c.amount))
+        .map_err(StdError::overflow)?;
+    CLAIMS.save(deps.storage, &info.sender, &remaining)?;
+    let old = snapshots
+        .staked_balances
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    stake(deps.storage, env.block.height, &info.sender, amount)?;
+    let new = snapshots.staked_balances.load(deps.storage, &info.sender)?;
+    let hook_msgs = stake_changed_hook_msgs(deps.as_ref(), info.sender.clone(), old, new)?;
+
     Ok(Response::new()
-        .add_attribute("action", "fund")
-        .add_attribute("from", sender)
+        .add_submessages(hook_msgs)
+        .add_attribute("action", "restake")
+        .add_attribute("from", info.sender)
         .add_attribute("amount", amount))
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetConfig {} => to_binary(&query_config(deps)?),
-        QueryMsg::StakedBalanceAtHeight { address, height } => {
-            to_binary(&query_staked_balance_at_height(deps, env, address, height)?)
-        }
-        QueryMsg::TotalStakedAtHeight { height } => {
-            to_binary(&query_total_staked_at_height(deps, env, height)?)
-        }
-        QueryMsg::StakedValue { address } => to_binary(&query_staked_value(deps, env, address)?),
-        QueryMsg::TotalValue {} => to_binary(&query_total_value(deps, env)?),
-        QueryMsg::Claims { address } => to_binary(&query_claims(deps, address)?),
+pub fn execute_slash(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    addr: String,
+    percentage: Decimal,
+) -> Result<Response, ContractError> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    assert_admin(deps.storage, &info.sender)?;
+    if percentage.is_zero() || percentage > Decimal::one() {
+        return Err(ContractError::InvalidSlashPercentage {});
     }
-}
-
-pub fn query_staked_balance_at_height(
-    deps: Deps,
-    _env: Env,
-    address: String,
-    height: Option<u64>,
-) -> StdResult<StakedBalanceAtHeightResponse> {
-    let address = deps.api.addr_validate(&address)?;
-    let height = height.unwrap_or(_env.block.height);
-    let balance = STAKED_BALANCES
-        .may_load_at_height(deps.storage, &address, height)?
-        .unwrap_or_default();
-    Ok(StakedBalanceAtHeightResponse { balance, height })
-}
+    let addr = deps.api.addr_validate(&addr)?;
+    release_locks(deps.storage, env.block.height, &addr)?;
+    update_rewards(deps.storage, env.block.height, &addr)?;
 
-pub fn query_total_staked_at_height(
-    deps: Deps,
-    _env: Env,
-    height: Option<u64>,
-) -> StdResult<TotalStakedAtHeightResponse> {
-    let height = height.unwrap_or(_env.block.height);
-    let total = STAKED_TOTAL
-        .may_load_at_height(deps.storage, height)?
+    // Slash the staked shares at the current share price
+    let balance = snapshots.balance.load(deps.storage).unwrap_or_default();
+    let staked_total = snapshots
+        .staked_total
+        .may_load(deps.storage)?
         .unwrap_or_default();
-    Ok(TotalStakedAtHeightResponse { total, height })
-}
-
-pub fn query_staked_value(
-    deps: Deps,
-    _env: Env,
-    address: String,
-) -> StdResult<StakedValueResponse> {
-    let address = deps.api.addr_validate(&address)?;
-    let balance = BALANCE.load(deps.storage).unwrap_or_default();
-    let staked = STAKED_BALANCES
-        .load(deps.storage, &address)
+    let old = snapshots
+        .staked_balances
+        .may_load(deps.storage, &addr)?
         .unwrap_or_default();
-    let total = STAKED_TOTAL.load(deps.storage).unwrap_or_default();
-    if balance == Uint128::zero() || staked == Uint128::zero() || total == Uint128::zero() {
-        Ok(StakedValueResponse {
-            value: Uint128::zero(),
-        })
-    } else {
-        let value = staked
-            .checked_mul(balance)
-            .map_err(StdError::overflow)?
-            .checked_div(total)
-            .map_err(StdError::divide_by_zero)?;
-        Ok(StakedValueResponse { value })
+    let slashed_shares = old * percentage;
+    let mut slashed = Uint128::zero();
+    let mut hook_msgs = vec![];
+    if !slashed_shares.is_zero() {
+        slashed = slashed_shares.multiply_ratio(balance, staked_total);
+        let new = old - slashed_shares;
+        snapshots
+            .staked_balances
+            .save(deps.storage, &addr, &new, env.block.height)?;
+        // Locked shares are slashed in the same proportion
+        let mut locks = LOCKS.may_load(deps.storage, &addr)?.unwrap_or_default();
+        for lock in locks.iter_mut() {
+            lock.shares = lock.shares.multiply_ratio(new, old);
+        }
+        locks.retain(|lock| !lock.shares.is_zero());
+        save_locks(deps.storage, &addr, &locks)?;
+        update_power(deps.storage, env.block.height, &addr)?;
+        snapshots.staked_total.save(
+            deps.storage,
+            &(staked_total - slashed_shares),
+            env.block.height,
+        )?;
+        snapshots
+            .balance
+            .save(deps.storage, &(balance - slashed), env.block.height)?;
+        update_staker_count(deps.storage, old, new)?;
+        hook_msgs = stake_changed_hook_msgs(deps.as_ref(), addr.clone(), old, new)?;
     }
-}
 
-pub fn query_total_value(deps: Deps, _env: Env) -> StdResult<TotalValueResponse> {
-    let balance = BALANCE.load(deps.storage).unwrap_or_default();
-    Ok(TotalValueResponse { total: balance })
-}
+    // Slash the claims still unbonding
+    let mut claims = CLAIMS.may_load(deps.storage, &addr)?.unwrap_or_default();
+    for claim in claims
+        .iter_mut()
+        .filter(|c| !c.release_at.is_expired(&env.block))
+    {
+        let amount = claim.amount * percentage;
+        claim.amount -= amount;
+        slashed += amount;
+    }
+    claims.retain(|c| !c.amount.is_zero());
+    CLAIMS.save(deps.storage, &addr, &claims)?;
 
-pub fn query_config(deps: Deps) -> StdResult<GetConfigResponse> {
+    if slashed.is_zero() {
+        return Err(ContractError::NothingToSlash {});
+    }
     let config = CONFIG.load(deps.storage)?;
-    Ok(GetConfigResponse {
-        admin: config.admin,
-        unstaking_duration: config.unstaking_duration,
-        token_address: config.token_address,
-    })
-}
+    let slash_msg = match (&config.slash_destination, &config.token) {
+        (Some(destination), token) => send_tokens_msg(token, destination, slashed)?,
+        (None, Denom::Cw20(token_address)) => WasmMsg::Execute {
+            contract_addr: token_address.to_string(),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Burn { amount: slashed })?,
+            funds: vec![],
+        }
+        .into(),
+        (None, Denom::Native(denom)) => BankMsg::Burn {
+            amount: coins(slashed.u128(), denom),
+        }
+        .into(),
+    };
 
-pub fn query_claims(deps: Deps, address: String) -> StdResult<ClaimsResponse> {
-    CLAIMS.query_claims(deps, &deps.api.addr_validate(&address)?)
+    Ok(Response::new()
+        .add_message(slash_msg)
+        .add_submessages(hook_msgs)
+        .add_attribute("action", "slash")
+        .add_attribute("addr", addr)
+        .add_attribute("percentage", percentage.to_string())
+        .add_attribute("amount", slashed))
 }
 
-#[cfg(test)]
-mod tests {
-    use std::borrow::BorrowMut;
+pub fn execute_delegate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    let delegate = deps.api.addr_validate(&address)?;
+    if delegate == info.sender {
+        return Err(ContractError::SelfDelegation {});
+    }
 
-    use crate::msg::{
-        ExecuteMsg, GetConfigResponse, QueryMsg, ReceiveMsg, StakedBalanceAtHeightResponse,
-        StakedValueResponse, TotalStakedAtHeightResponse, TotalValueResponse,
-    };
-    use crate::state::MAX_CLAIMS;
-    use crate::ContractError;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{to_binary, Addr, Empty, MessageInfo, Uint128};
-    use cw20::Cw20Coin;
-    use cw_utils::Duration;
+    let previous = snapshots.delegations.may_load(deps.storage, &info.sender)?;
+    let mut changed = vec![&info.sender, &delegate];
+    changed.extend(previous.as_ref());
+    track_sqrt_power(deps.storage, env.block.height, &changed, |storage| {
+        // Move the sender's power away from any previous delegate
+        remove_delegation(storage, &info.sender, env.block.height)?;
+        let power = own_power(storage, &snapshots, &info.sender, env.block.height)?;
+        snapshots.delegated_power.update(
+            storage,
+            &delegate,
+            env.block.height,
+            |delegated| -> StdResult<Uint128> {
+                Ok(delegated.unwrap_or_default().checked_add(power)?)
+            },
+        )?;
+        snapshots
+            .delegations
+            .save(storage, &info.sender, &delegate, env.block.height)
+    })?;
 
-    use cw_multi_test::{next_block, App, AppResponse, Contract, ContractWrapper, Executor};
+    Ok(Response::new()
+        .add_attribute("action", "delegate")
+        .add_attribute("from", info.sender)
+        .add_attribute("to", delegate))
+}
 
-    use anyhow::Result as AnyResult;
-    use cw_controllers::{Claim, ClaimsResponse};
-    use cw_utils::Expiration::AtHeight;
+pub fn execute_undelegate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let delegate = Snapshots::load(deps.storage)?
+        .delegations
+        .may_load(deps.storage, &info.sender)?
+        .ok_or(ContractError::NotDelegating {})?;
+    track_sqrt_power(
+        deps.storage,
+        env.block.height,
+        &[&info.sender, &delegate],
+        |storage| remove_delegation(storage, &info.sender, env.block.height),
+    )?;
 
-    const ADDR1: &str = "addr0001";
-    const ADDR2: &str = "addr0002";
-    const ADDR3: &str = "addr0003";
-    const ADDR4: &str = "addr0004";
+    Ok(Response::new()
+        .add_attribute("action", "undelegate")
+        .add_attribute("from", info.sender)
+        .add_attribute("to", delegate))
+}
 
-    pub fn contract_staking() -> Box<dyn Contract<Empty>> {
-        let contract = ContractWrapper::new(
-            crate::contract::execute,
-            crate::contract::instantiate,
-            crate::contract::query,
-        );
-        Box::new(contract)
-    }
+/// Removes the delegation of `delegator`, if any, taking its voting power
+/// out of the delegate's delegated power. Returns the previous delegate.
+fn remove_delegation(
+    storage: &mut dyn Storage,
+    delegator: &Addr,
+    height: u64,
+) -> StdResult<Option<Addr>> {
+    let snapshots = Snapshots::load(storage)?;
+    let delegate = match snapshots.delegations.may_load(storage, delegator)? {
+        Some(delegate) => delegate,
+        None => return Ok(None),
+    };
+    let power = own_power(storage, &snapshots, delegator, height)?;
+    snapshots.delegated_power.update(
+        storage,
+        &delegate,
+        height,
+        |delegated| -> StdResult<Uint128> { Ok(delegated.unwrap_or_default().checked_sub(power)?) },
+    )?;
+    snapshots.delegations.remove(storage, delegator, height)?;
+    Ok(Some(delegate))
+}
 
-    pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
-        let contract = ContractWrapper::new(
-            cw20_base::contract::execute,
-            cw20_base::contract::instantiate,
-            cw20_base::contract::query,
-        );
-        Box::new(contract)
+/// Voting power of the stake of `staker` before any change made at `height`.
+/// Stakers of contracts migrated from v0.2 have no power recorded until their
+/// stake changes, it is their staked balance until then. Migrated contracts
+/// snapshot every block.
+fn own_power(
+    storage: &dyn Storage,
+    snapshots: &Snapshots,
+    staker: &Addr,
+    height: u64,
+) -> StdResult<Uint128> {
+    if let Some(power) = snapshots.power.may_load(storage, staker)? {
+        return Ok(power);
     }
-
-    fn mock_app() -> App {
-        App::default()
+    if UNRECORDED_POWER.may_load(storage)?.is_none() {
+        return Ok(Uint128::zero());
     }
+    Ok(snapshots
+        .staked_balances
+        .may_load_at_height(storage, staker, height)?
+        .unwrap_or_default())
+}
 
-    fn get_balance<T: Into<String>, U: Into<String>>(
-        app: &App,
-        contract_addr: T,
-        address: U,
-    ) -> Uint128 {
-        let msg = cw20::Cw20QueryMsg::Balance {
-            address: address.into(),
-        };
-        let result: cw20::BalanceResponse =
-            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
-        result.balance
+/// Voting power of `address` before any change made at `height`, its own
+/// power unless delegated plus the power delegated to it
+fn voting_power(
+    storage: &dyn Storage,
+    snapshots: &Snapshots,
+    address: &Addr,
+    height: u64,
+) -> StdResult<Uint128> {
+    let delegated = snapshots
+        .delegated_power
+        .may_load(storage, address)?
+        .unwrap_or_default();
+    if snapshots.delegations.may_load(storage, address)?.is_some() {
+        return Ok(delegated);
     }
+    let own = own_power(storage, snapshots, address, height)?;
+    Ok(own.checked_add(delegated)?)
+}
 
-    fn instantiate_cw20(app: &mut App, initial_balances: Vec<Cw20Coin>) -> Addr {
-        let cw20_id = app.store_code(contract_cw20());
-        let msg = cw20_base::msg::InstantiateMsg {
-            name: String::from("Test"),
-            symbol: String::from("TEST"),
-            decimals: 6,
-            initial_balances,
-            mint: None,
-            marketing: None,
-        };
+/// Sum of the square roots of the voting power of `addresses` before any
+/// change made at `height`
+fn sum_sqrt_power(
+    storage: &dyn Storage,
+    snapshots: &Snapshots,
+    addresses: &[&Addr],
+    height: u64,
+) -> StdResult<Uint128> {
+    let mut sum = Uint128::zero();
+    for address in addresses {
+        sum = sum.checked_add(isqrt(voting_power(storage, snapshots, address, height)?))?;
+    }
+    Ok(sum)
+}
 
-        app.instantiate_contract(cw20_id, Addr::unchecked(ADDR1), &msg, &[], "cw20", None)
-            .unwrap()
+/// Runs `change`, which may only change the voting power of `addresses`,
+/// and updates the total square root power accordingly
+fn track_sqrt_power<T>(
+    storage: &mut dyn Storage,
+    height: u64,
+    addresses: &[&Addr],
+    change: impl FnOnce(&mut dyn Storage) -> StdResult<T>,
+) -> StdResult<T> {
+    let snapshots = Snapshots::load(storage)?;
+    let total = snapshots.sqrt_power.may_load(storage)?.unwrap_or_default();
+    let mut unique: Vec<&Addr> = vec![];
+    for address in addresses {
+        if !unique.contains(address) {
+            unique.push(address);
+        }
     }
+    let old = sum_sqrt_power(storage, &snapshots, &unique, height)?;
+    let result = change(storage)?;
+    let new = sum_sqrt_power(storage, &snapshots, &unique, height)?;
+    snapshots
+        .sqrt_power
+        .save(storage, &total.checked_add(new)?.checked_sub(old)?, height)?;
+    Ok(result)
+}
 
-    fn instantiate_staking(
-        app: &mut App,
-        cw20: Addr,
-        unstaking_duration: Option<Duration>,
-    ) -> Addr {
-        let staking_code_id = app.store_code(contract_staking());
-        let msg = crate::msg::InstantiateMsg {
-            admin: Some("owner".to_string()),
-            token_address: cw20.to_string(),
-            unstaking_duration,
-        };
-        app.instantiate_contract(
-            staking_code_id,
-            Addr::unchecked(ADDR1),
-            &msg,
-            &[],
-            "staking",
+pub fn execute_fund(
+    deps: DepsMut,
+    env: Env,
+    sender: &Addr,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    let balance = snapshots.balance.load(deps.storage).unwrap_or_default();
+    snapshots.balance.save(
+        deps.storage,
+        &balance.checked_add(amount).map_err(StdError::overflow)?,
+        env.block.height,
+    )?;
+    Ok(Response::new()
+        .add_attribute("action", "fund")
+        .add_attribute("from", sender)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_add_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.storage, &info.sender)?;
+    let hook = deps.api.addr_validate(&addr)?;
+    HOOKS.add_hook(deps.storage, hook)?;
+    Ok(Response::new()
+        .add_attribute("action", "add_hook")
+        .add_attribute("hook", addr))
+}
+
+pub fn execute_remove_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.storage, &info.sender)?;
+    let hook = deps.api.addr_validate(&addr)?;
+    HOOKS.remove_hook(deps.storage, hook)?;
+    Ok(Response::new()
+        .add_attribute("action", "remove_hook")
+        .add_attribute("hook", addr))
+}
+
+pub fn execute_add_checkpoint(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.storage, &info.sender)?;
+    let config = CONFIG.load(deps.storage)?;
+    if config.snapshot_strategy != (SnapshotStrategy::Selected {}) {
+        return Err(ContractError::CheckpointsNotUsed {});
+    }
+    let height = env.block.height;
+    // Several proposals may be created in the same block
+    if !CHECKPOINTS.has(deps.storage, height) {
+        Snapshots::load(deps.storage)?.add_checkpoint(deps.storage, height)?;
+        CHECKPOINTS.save(deps.storage, height, &Empty {})?;
+    }
+    Ok(Response::new()
+        .add_attribute("action", "add_checkpoint")
+        .add_attribute("height", height.to_string()))
+}
+
+/// Removes the checkpoints before `before` along with the changes recorded
+/// for them, those of addresses one page at a time
+pub fn execute_prune_checkpoints(
+    deps: DepsMut,
+    info: MessageInfo,
+    before: u64,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.storage, &info.sender)?;
+    let limit = get_and_check_limit(limit, MAX_PRUNE_LIMIT, DEFAULT_PRUNE_LIMIT)? as usize;
+    let start_after = maybe_addr(deps.api, start_after)?;
+    let config = CONFIG.load(deps.storage)?;
+    // Every past height can be queried with the other strategy, pruning its
+    // changelog would silently change their results
+    if config.snapshot_strategy != (SnapshotStrategy::Selected {}) {
+        return Err(ContractError::CheckpointsNotUsed {});
+    }
+    let snapshots = Snapshots::load(deps.storage)?;
+    let heights = CHECKPOINTS
+        .keys(
+            deps.storage,
             None,
+            Some(Bound::exclusive_int(before)),
+            Order::Ascending,
         )
-        .unwrap()
+        .collect::<StdResult<Vec<_>>>()?;
+    for height in &heights {
+        snapshots.remove_checkpoint(deps.storage, *height)?;
+        CHECKPOINTS.remove(deps.storage, *height);
     }
-
-    fn setup_test_case(
-        app: &mut App,
-        initial_balances: Vec<Cw20Coin>,
-        unstaking_duration: Option<Duration>,
-    ) -> (Addr, Addr) {
-        // Instantiate cw20 contract
-        let cw20_addr = instantiate_cw20(app, initial_balances);
-        app.update_block(next_block);
-        // Instantiate staking contract
-        let staking_addr = instantiate_staking(app, cw20_addr.clone(), unstaking_duration);
-        app.update_block(next_block);
-        (staking_addr, cw20_addr)
+    // Queries at the remaining heights only need the boosts of locks released
+    // after them
+    let released = match CHECKPOINTS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+    {
+        Some(height) => snapshots
+            .locks_released
+            .may_load_at_height(deps.storage, height?)?,
+        None => snapshots.locks_released.may_load(deps.storage)?,
     }
-
-    fn query_staked_balance<T: Into<String>, U: Into<String>>(
-        app: &App,
-        contract_addr: T,
-        address: U,
-    ) -> Uint128 {
-        let msg = QueryMsg::StakedBalanceAtHeight {
-            address: address.into(),
-            height: None,
-        };
-        let result: StakedBalanceAtHeightResponse =
-            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
-        result.balance
+    .unwrap_or_default();
+    let expiries = LOCK_EXPIRIES
+        .keys(
+            deps.storage,
+            None,
+            Some(Bound::exclusive((released + 1).joined_prefix())),
+            Order::Ascending,
+        )
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (height, staker) in &expiries {
+        LOCK_EXPIRIES.remove(deps.storage, (*height, staker));
+    }
+    let (changes, last) = Snapshots::prune_changelogs(deps.storage, before, start_after, limit)?;
+    let mut response = Response::new()
+        .add_attribute("action", "prune_checkpoints")
+        .add_attribute("before", before.to_string())
+        .add_attribute("pruned", heights.len().to_string())
+        .add_attribute("pruned_changes", changes.to_string());
+    // The changes of the addresses after `last` are left for the next page
+    if let Some(last) = last {
+        response = response.add_attribute("next_start_after", last);
     }
+    Ok(response)
+}
 
-    fn query_config<T: Into<String>>(app: &App, contract_addr: T) -> GetConfigResponse {
-        let msg = QueryMsg::GetConfig {};
-        app.wrap().query_wasm_smart(contract_addr, &msg).unwrap()
+pub fn execute_update_lockup_tiers(
+    deps: DepsMut,
+    info: MessageInfo,
+    tiers: Vec<LockupTier>,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.storage, &info.sender)?;
+    let mut config = CONFIG.load(deps.storage)?;
+    config.lockup_tiers = validate_lockup_tiers(tiers)?;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new()
+        .add_attribute("action", "update_lockup_tiers")
+        .add_attribute("tiers", config.lockup_tiers.len().to_string()))
+}
+
+fn validate_lockup_tiers(tiers: Vec<LockupTier>) -> Result<Vec<LockupTier>, ContractError> {
+    for (i, tier) in tiers.iter().enumerate() {
+        if tier.multiplier < Decimal::one()
+            || !matches!(tier.duration, Duration::Height(_))
+            || tiers[..i].iter().any(|t| t.duration == tier.duration)
+        {
+            return Err(ContractError::InvalidLockupTiers {});
+        }
     }
+    Ok(tiers)
+}
 
-    fn query_total_staked<T: Into<String>>(app: &App, contract_addr: T) -> Uint128 {
-        let msg = QueryMsg::TotalStakedAtHeight { height: None };
-        let result: TotalStakedAtHeightResponse =
-            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
-        result.total
+/// Saves the locks of `staker` that have not expired, along with the boosts
+/// they expire with
+fn save_locks(storage: &mut dyn Storage, staker: &Addr, locks: &[Lock]) -> StdResult<()> {
+    for lock in LOCKS.may_load(storage, staker)?.unwrap_or_default() {
+        LOCK_EXPIRIES.remove(storage, (lock.expires_at_height(), staker));
+    }
+    for lock in locks {
+        LOCK_EXPIRIES.update(
+            storage,
+            (lock.expires_at_height(), staker),
+            |boost| -> StdResult<Uint128> {
+                Ok(boost.unwrap_or_default().checked_add(lock.boost())?)
+            },
+        )?;
     }
+    if locks.is_empty() {
+        LOCKS.remove(storage, staker);
+        Ok(())
+    } else {
+        LOCKS.save(storage, staker, &locks.to_vec())
+    }
+}
 
-    fn query_staked_value<T: Into<String>, U: Into<String>>(
-        app: &App,
-        contract_addr: T,
-        address: U,
-    ) -> Uint128 {
-        let msg = QueryMsg::StakedValue {
-            address: address.into(),
-        };
-        let result: StakedValueResponse = app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
-        result.value
+/// Releases the locks of `staker` that expired by `height`, taking their
+/// boost out of the voting power. Their LOCK_EXPIRIES entries are kept for
+/// queries at earlier heights.
+fn release_locks(storage: &mut dyn Storage, height: u64, staker: &Addr) -> StdResult<()> {
+    let (expired, locks): (Vec<_>, Vec<_>) = LOCKS
+        .may_load(storage, staker)?
+        .unwrap_or_default()
+        .into_iter()
+        .partition(|lock| lock.expires_at_height() <= height);
+    if expired.is_empty() {
+        return Ok(());
+    }
+    if locks.is_empty() {
+        LOCKS.remove(storage, staker);
+    } else {
+        LOCKS.save(storage, staker, &locks)?;
     }
+    Snapshots::load(storage)?
+        .staker_locks_released
+        .save(storage, staker, &height, height)?;
+    update_power(storage, height, staker)
+}
 
-    fn query_total_value<T: Into<String>>(app: &App, contract_addr: T) -> Uint128 {
-        let msg = QueryMsg::TotalValue {};
-        let result: TotalValueResponse = app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
-        result.total
+/// Releases the expired locks of the stakers holding the next `limit` lock
+/// expiries, so that voting power queries don't need to subtract them
+pub fn execute_release_locks(
+    deps: DepsMut,
+    env: Env,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let limit = get_and_check_limit(limit, MAX_RELEASED_LOCKS, MAX_RELEASED_LOCKS)?.max(1) as usize;
+    let snapshots = Snapshots::load(deps.storage)?;
+    let released = snapshots
+        .locks_released
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    let start = match RELEASE_CURSOR.may_load(deps.storage)? {
+        Some((expires, staker)) => Bound::exclusive((expires, &staker).joined_key()),
+        None => Bound::inclusive((released + 1).joined_prefix()),
+    };
+    let expiries = LOCK_EXPIRIES
+        .keys(
+            deps.storage,
+            Some(start),
+            Some(Bound::exclusive((env.block.height + 1).joined_prefix())),
+            Order::Ascending,
+        )
+        .take(limit + 1)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (_, staker) in expiries.iter().take(limit) {
+        release_locks(deps.storage, env.block.height, staker)?;
     }
+    // Every lock expiring before the first entry left was released
+    let released = match expiries.get(limit) {
+        Some((expires, _)) => {
+            RELEASE_CURSOR.save(deps.storage, &expiries[limit - 1])?;
+            expires - 1
+        }
+        None => {
+            RELEASE_CURSOR.remove(deps.storage);
+            env.block.height
+        }
+    };
+    snapshots
+        .locks_released
+        .save(deps.storage, &released, env.block.height)?;
+    Ok(Response::new()
+        .add_attribute("action", "release_locks")
+        .add_attribute("expiries", expiries.len().min(limit).to_string())
+        .add_attribute("released", released.to_string()))
+}
 
-    fn query_claims<T: Into<String>, U: Into<String>>(
-        app: &App,
-        contract_addr: T,
-        address: U,
-    ) -> Vec<Claim> {
-        let msg = QueryMsg::Claims {
-            address: address.into(),
-        };
-        let result: ClaimsResponse = app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
-        result.claims
+/// Boosts of the locks expiring after `released` and by `height`, with the
+/// stakers holding them, unless `staker_released` says their staker released
+/// them already. Fails if more than MAX_RELEASED_LOCKS are left to check.
+fn expiring_boosts(
+    storage: &dyn Storage,
+    released: u64,
+    height: u64,
+    mut staker_released: impl FnMut(&Addr) -> StdResult<Option<u64>>,
+) -> StdResult<Vec<(Addr, Uint128)>> {
+    if released >= height {
+        return Ok(vec![]);
+    }
+    let expiries = LOCK_EXPIRIES
+        .range(
+            storage,
+            Some(Bound::inclusive((released + 1).joined_prefix())),
+            Some(Bound::exclusive((height + 1).joined_prefix())),
+            Order::Ascending,
+        )
+        .take(MAX_RELEASED_LOCKS as usize + 1)
+        .collect::<StdResult<Vec<_>>>()?;
+    if expiries.len() > MAX_RELEASED_LOCKS as usize {
+        return Err(StdError::GenericErr {
+            msg: ContractError::TooManyExpiredLocks {
+                max: MAX_RELEASED_LOCKS,
+            }
+            .to_string(),
+        });
+    }
+    let mut boosts = vec![];
+    for ((expires, staker), boost) in expiries {
+        if staker_released(&staker)?.unwrap_or_default() < expires {
+            boosts.push((staker, boost));
+        }
     }
+    Ok(boosts)
+}
 
-    fn stake_tokens(
-        app: &mut App,
-        staking_addr: &Addr,
-        cw20_addr: &Addr,
-        info: MessageInfo,
-        amount: Uint128,
-    ) -> AnyResult<AppResponse> {
-        let msg = cw20::Cw20ExecuteMsg::Send {
-            contract: staking_addr.to_string(),
-            amount,
-            msg: to_binary(&ReceiveMsg::Stake {}).unwrap(),
-        };
-        app.execute_contract(info.sender, cw20_addr.clone(), &msg, &[])
+/// Boosts of the locks that expired by `height` but were released after it,
+/// the voting power snapshots at `height` still include them
+fn unreleased_boosts(
+    storage: &dyn Storage,
+    snapshots: &Snapshots,
+    height: u64,
+) -> StdResult<Vec<(Addr, Uint128)>> {
+    let released = snapshots
+        .locks_released
+        .may_load_at_height(storage, height)?
+        .unwrap_or_default();
+    expiring_boosts(storage, released, height, |staker| {
+        snapshots
+            .staker_locks_released
+            .may_load_at_height(storage, staker, height)
+    })
+}
+
+/// Sum of the boosts held by `stakers` matching `filter`
+fn sum_boosts(
+    boosts: &[(Addr, Uint128)],
+    mut filter: impl FnMut(&Addr) -> StdResult<bool>,
+) -> StdResult<Uint128> {
+    let mut total = Uint128::zero();
+    for (staker, boost) in boosts {
+        if filter(staker)? {
+            total = total.checked_add(*boost)?;
+        }
     }
+    Ok(total)
+}
 
-    fn update_config(
-        app: &mut App,
-        staking_addr: &Addr,
-        info: MessageInfo,
-        admin: Option<Addr>,
-        duration: Option<Duration>,
-    ) -> AnyResult<AppResponse> {
-        let msg = ExecuteMsg::UpdateConfig {
-            admin: admin.map(|a| a.to_string()),
-            duration,
-        };
-        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+/// Brings the voting power of `staker`, of their delegate and the total
+/// voting power in line with the staker's staked balance and locks
+fn update_power(storage: &mut dyn Storage, height: u64, staker: &Addr) -> StdResult<()> {
+    // Only the voting power of the staker or of their delegate changes
+    let delegate = Snapshots::load(storage)?
+        .delegations
+        .may_load(storage, staker)?;
+    let voter = delegate.as_ref().unwrap_or(staker);
+    track_sqrt_power(storage, height, &[voter], |storage| {
+        update_staker_power(storage, height, staker)
+    })
+}
+
+fn update_staker_power(storage: &mut dyn Storage, height: u64, staker: &Addr) -> StdResult<()> {
+    let snapshots = Snapshots::load(storage)?;
+    let staked = snapshots
+        .staked_balances
+        .may_load(storage, staker)?
+        .unwrap_or_default();
+    let new = LOCKS
+        .may_load(storage, staker)?
+        .unwrap_or_default()
+        .iter()
+        .try_fold(staked, |power, lock| power.checked_add(lock.boost()))?;
+    let old = own_power(storage, &snapshots, staker, height)?;
+    if new == old {
+        return Ok(());
     }
+    snapshots.power.save(storage, staker, &new, height)?;
+    snapshots
+        .total_power
+        .update(storage, height, |total| -> StdResult<Uint128> {
+            Ok(total
+                .unwrap_or_default()
+                .checked_add(new)?
+                .checked_sub(old)?)
+        })?;
+    if let Some(delegate) = snapshots.delegations.may_load(storage, staker)? {
+        snapshots.delegated_power.update(
+            storage,
+            &delegate,
+            height,
+            |power| -> StdResult<Uint128> {
+                Ok(power
+                    .unwrap_or_default()
+                    .checked_add(new)?
+                    .checked_sub(old)?)
+            },
+        )?;
+    }
+    Ok(())
+}
 
-    fn unstake_tokens(
-        app: &mut App,
-        staking_addr: &Addr,
-        info: MessageInfo,
-        amount: Uint128,
-    ) -> AnyResult<AppResponse> {
-        let msg = ExecuteMsg::Unstake { amount };
-        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+/// Keeps the number of addresses with a staked balance up to date
+fn update_staker_count(storage: &mut dyn Storage, old: Uint128, new: Uint128) -> StdResult<()> {
+    if old.is_zero() == new.is_zero() {
+        return Ok(());
     }
+    let count = STAKER_COUNT.may_load(storage)?.unwrap_or_default();
+    let count = if new.is_zero() { count - 1 } else { count + 1 };
+    STAKER_COUNT.save(storage, &count)
+}
 
-    fn claim_tokens(
-        app: &mut App,
-        staking_addr: &Addr,
-        info: MessageInfo,
-    ) -> AnyResult<AppResponse> {
-        let msg = ExecuteMsg::Claim {};
-        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+fn assert_admin(storage: &dyn Storage, sender: &Addr) -> Result<(), ContractError> {
+    let config = CONFIG.load(storage)?;
+    let admin = config.admin.ok_or(ContractError::NoAdminConfigured {})?;
+    if *sender != admin {
+        return Err(ContractError::Unauthorized {
+            expected: admin,
+            received: sender.clone(),
+        });
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_update_config() {
-        let _deps = mock_dependencies();
+pub fn execute_add_reward_token(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: Denom,
+    reward_duration: u64,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.storage, &info.sender)?;
+    if reward_duration == 0 {
+        return Err(ContractError::InvalidRewardDuration {});
+    }
+    let denom = match denom {
+        Denom::Cw20(addr) => Denom::Cw20(deps.api.addr_validate(addr.as_str())?),
+        native => native,
+    };
 
-        let mut app = mock_app();
-        let amount1 = Uint128::from(100u128);
-        let _token_address = Addr::unchecked("token_address");
-        let initial_balances = vec![Cw20Coin {
-            address: ADDR1.to_string(),
-            amount: amount1,
-        }];
-        let (staking_addr, _cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+    let key = reward_token_key(&denom);
+    let token = match REWARD_TOKENS.may_load(deps.storage, &key)? {
+        // The current stream keeps its end height
+        Some(mut token) => {
+            token.reward_duration = reward_duration;
+            token
+        }
+        None => RewardToken::new(denom, reward_duration, env.block.height),
+    };
+    REWARD_TOKENS.save(deps.storage, &key, &token)?;
 
-        let info = mock_info("owner", &[]);
-        let _env = mock_env();
-        // Test update admin
-        update_config(
-            &mut app,
-            &staking_addr,
-            info,
-            Some(Addr::unchecked("owner2")),
-            Some(Duration::Height(100)),
-        )
-        .unwrap();
+    Ok(Response::new()
+        .add_attribute("action", "add_reward_token")
+        .add_attribute("denom", key)
+        .add_attribute("reward_duration", reward_duration.to_string()))
+}
 
-        let config = query_config(&app, &staking_addr);
-        assert_eq!(config.admin, Some(Addr::unchecked("owner2")));
-        assert_eq!(config.unstaking_duration, Some(Duration::Height(100)));
+pub fn execute_fund_rewards(
+    deps: DepsMut,
+    env: Env,
+    sender: &Addr,
+    funds: Vec<(Denom, Uint128)>,
+) -> Result<Response, ContractError> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    if funds.is_empty() {
+        return Err(ContractError::NoFunds {});
+    }
+    let total_staked = snapshots
+        .staked_total
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    let mut response = Response::new()
+        .add_attribute("action", "fund_rewards")
+        .add_attribute("from", sender);
+    for (denom, amount) in funds {
+        let key = reward_token_key(&denom);
+        let mut token = REWARD_TOKENS
+            .may_load(deps.storage, &key)?
+            .ok_or_else(|| ContractError::UnknownRewardToken { denom: key.clone() })?;
+        token.accrue(env.block.height, total_staked)?;
+        token.fund(env.block.height, amount)?;
+        REWARD_TOKENS.save(deps.storage, &key, &token)?;
+        response = response
+            .add_attribute("denom", key)
+            .add_attribute("amount", amount);
+    }
+    Ok(response)
+}
+
+pub fn execute_claim_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    update_rewards(deps.storage, env.block.height, &info.sender)?;
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    for token in reward_tokens(deps.storage)? {
+        let key = reward_token_key(&token.denom);
+        let mut reward = STAKER_REWARDS
+            .may_load(deps.storage, (&info.sender, &key))?
+            .unwrap_or_default();
+        if reward.pending.is_zero() {
+            continue;
+        }
+        msgs.push(send_tokens_msg(&token.denom, &info.sender, reward.pending)?);
+        reward.pending = Uint128::zero();
+        STAKER_REWARDS.save(deps.storage, (&info.sender, &key), &reward)?;
+    }
+    if msgs.is_empty() {
+        return Err(ContractError::NothingToClaim {});
+    }
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "claim_rewards")
+        .add_attribute("from", info.sender))
+}
+
+fn reward_tokens(storage: &dyn Storage) -> StdResult<Vec<RewardToken>> {
+    REWARD_TOKENS
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, token)| token))
+        .collect()
+}
+
+/// Accrues the rewards of every reward token and credits `staker` with their
+/// share. Must be called before the staked balance of `staker` or the total
+/// staked balance changes.
+fn update_rewards(storage: &mut dyn Storage, height: u64, staker: &Addr) -> StdResult<()> {
+    let snapshots = Snapshots::load(storage)?;
+    let total_staked = snapshots
+        .staked_total
+        .may_load(storage)?
+        .unwrap_or_default();
+    let staked = snapshots
+        .staked_balances
+        .may_load(storage, staker)?
+        .unwrap_or_default();
+    for mut token in reward_tokens(storage)? {
+        let key = reward_token_key(&token.denom);
+        token.accrue(height, total_staked)?;
+        REWARD_TOKENS.save(storage, &key, &token)?;
+
+        let mut reward = STAKER_REWARDS
+            .may_load(storage, (staker, &key))?
+            .unwrap_or_default();
+        reward.accrue(token.reward_index, staked)?;
+        STAKER_REWARDS.save(storage, (staker, &key), &reward)?;
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetConfig {} => to_binary(&query_config(deps)?),
+        QueryMsg::StakedBalanceAtHeight { address, height } => {
+            to_binary(&query_staked_balance_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::TotalStakedAtHeight { height } => {
+            to_binary(&query_total_staked_at_height(deps, env, height)?)
+        }
+        QueryMsg::StakedValue { address } => to_binary(&query_staked_value(deps, env, address)?),
+        QueryMsg::TotalValue {} => to_binary(&query_total_value(deps, env)?),
+        QueryMsg::Claims { address } => to_binary(&query_claims(deps, address)?),
+        QueryMsg::VotingPowerAtHeight { address, height } => {
+            to_binary(&query_voting_power_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::DelegatedPowerAtHeight { address, height } => to_binary(
+            &query_delegated_power_at_height(deps, env, address, height)?,
+        ),
+        QueryMsg::DelegationAtHeight { address, height } => {
+            to_binary(&query_delegation_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::PendingRewards { address } => {
+            to_binary(&query_pending_rewards(deps, env, address)?)
+        }
+        QueryMsg::GetHooks {} => to_binary(&HOOKS.query_hooks(deps)?),
+        QueryMsg::ListStakers { start_after, limit } => {
+            to_binary(&query_list_stakers(deps, env, start_after, limit)?)
+        }
+        QueryMsg::ListStakersAtHeight {
+            height,
+            start_after,
+            limit,
+        } => to_binary(&query_list_stakers_at_height(
+            deps,
+            env,
+            height,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::StakerCount {} => to_binary(&query_staker_count(deps)?),
+        QueryMsg::StakedValueAtHeight { address, height } => {
+            to_binary(&query_staked_value_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::ExchangeRateAtHeight { height } => {
+            to_binary(&query_exchange_rate_at_height(deps, env, height)?)
+        }
+        QueryMsg::Locks { address } => to_binary(&query_locks(deps, env, address)?),
+        QueryMsg::StakerPowerAtHeight { address, height } => {
+            to_binary(&query_staker_power_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::TotalPowerAtHeight { height } => {
+            to_binary(&query_total_power_at_height(deps, env, height)?)
+        }
+        QueryMsg::TotalSqrtPowerAtHeight { height } => {
+            to_binary(&query_total_sqrt_power_at_height(deps, env, height)?)
+        }
+    }
+}
+
+pub fn query_staked_balance_at_height(
+    deps: Deps,
+    _env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<StakedBalanceAtHeightResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let height = height.unwrap_or(_env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &_env.block, height)?;
+    let balance = snapshots
+        .staked_balances
+        .may_load_at_height(deps.storage, &address, height)?
+        .unwrap_or_default();
+    Ok(StakedBalanceAtHeightResponse { balance, height })
+}
+
+pub fn query_total_staked_at_height(
+    deps: Deps,
+    _env: Env,
+    height: Option<u64>,
+) -> StdResult<TotalStakedAtHeightResponse> {
+    let height = height.unwrap_or(_env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &_env.block, height)?;
+    let total = snapshots
+        .staked_total
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    Ok(TotalStakedAtHeightResponse { total, height })
+}
+
+pub fn query_voting_power_at_height(
+    deps: Deps,
+    _env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<VotingPowerAtHeightResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let height = height.unwrap_or(_env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &_env.block, height)?;
+    let boosts = unreleased_boosts(deps.storage, &snapshots, height)?;
+    let power = voting_power_at_height(deps.storage, &snapshots, &address, height, &boosts)?;
+    Ok(VotingPowerAtHeightResponse { power, height })
+}
+
+/// Voting power of `address` at `height`, less the expired `boosts`
+fn voting_power_at_height(
+    storage: &dyn Storage,
+    snapshots: &Snapshots,
+    address: &Addr,
+    height: u64,
+    boosts: &[(Addr, Uint128)],
+) -> StdResult<Uint128> {
+    let delegated = delegated_power_at_height(storage, snapshots, address, height, boosts)?;
+    // Stakers who delegate only keep the power delegated to them
+    let own = match snapshots
+        .delegations
+        .may_load_at_height(storage, address, height)?
+    {
+        Some(_) => Uint128::zero(),
+        None => staker_power_at_height(storage, snapshots, address, height, boosts)?,
+    };
+    Ok(own.checked_add(delegated)?)
+}
+
+/// Voting power of the stake of `address` at `height`. Before voting power
+/// was tracked it was the staked balance.
+fn staker_power_at_height(
+    storage: &dyn Storage,
+    snapshots: &Snapshots,
+    address: &Addr,
+    height: u64,
+    boosts: &[(Addr, Uint128)],
+) -> StdResult<Uint128> {
+    let power = match snapshots
+        .power
+        .may_load_at_height(storage, address, height)?
+    {
+        Some(power) => power,
+        None => snapshots
+            .staked_balances
+            .may_load_at_height(storage, address, height)?
+            .unwrap_or_default(),
+    };
+    let expired = sum_boosts(boosts, |staker| Ok(staker == address))?;
+    Ok(power.checked_sub(expired)?)
+}
+
+/// Voting power delegated to `address` at `height`
+fn delegated_power_at_height(
+    storage: &dyn Storage,
+    snapshots: &Snapshots,
+    address: &Addr,
+    height: u64,
+    boosts: &[(Addr, Uint128)],
+) -> StdResult<Uint128> {
+    let power = snapshots
+        .delegated_power
+        .may_load_at_height(storage, address, height)?
+        .unwrap_or_default();
+    let expired = sum_boosts(boosts, |staker| {
+        let delegate = snapshots
+            .delegations
+            .may_load_at_height(storage, staker, height)?;
+        Ok(delegate.as_ref() == Some(address))
+    })?;
+    Ok(power.checked_sub(expired)?)
+}
+
+pub fn query_staker_power_at_height(
+    deps: Deps,
+    env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<StakerPowerAtHeightResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let height = height.unwrap_or(env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &env.block, height)?;
+    let boosts = unreleased_boosts(deps.storage, &snapshots, height)?;
+    let power = staker_power_at_height(deps.storage, &snapshots, &address, height, &boosts)?;
+    Ok(StakerPowerAtHeightResponse { power, height })
+}
+
+pub fn query_total_power_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+) -> StdResult<TotalPowerAtHeightResponse> {
+    let height = height.unwrap_or(env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &env.block, height)?;
+    let power = match snapshots
+        .total_power
+        .may_load_at_height(deps.storage, height)?
+    {
+        Some(power) => power,
+        None => snapshots
+            .staked_total
+            .may_load_at_height(deps.storage, height)?
+            .unwrap_or_default(),
+    };
+    let boosts = unreleased_boosts(deps.storage, &snapshots, height)?;
+    let power = power.checked_sub(sum_boosts(&boosts, |_| Ok(true))?)?;
+    Ok(TotalPowerAtHeightResponse { power, height })
+}
+
+pub fn query_total_sqrt_power_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+) -> StdResult<TotalSqrtPowerAtHeightResponse> {
+    let height = height.unwrap_or(env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &env.block, height)?;
+    let mut power = snapshots
+        .sqrt_power
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    // The snapshot still counts the boosts of locks expired by `height`,
+    // take them out of the voting power of whoever held them
+    let boosts = unreleased_boosts(deps.storage, &snapshots, height)?;
+    let mut voters: Vec<Addr> = vec![];
+    for (staker, _) in &boosts {
+        let voter = snapshots
+            .delegations
+            .may_load_at_height(deps.storage, staker, height)?
+            .unwrap_or_else(|| staker.clone());
+        if !voters.contains(&voter) {
+            voters.push(voter);
+        }
+    }
+    for voter in &voters {
+        let snapshotted = voting_power_at_height(deps.storage, &snapshots, voter, height, &[])?;
+        let expired = voting_power_at_height(deps.storage, &snapshots, voter, height, &boosts)?;
+        power = power
+            .checked_add(isqrt(expired))?
+            .checked_sub(isqrt(snapshotted))?;
+    }
+    Ok(TotalSqrtPowerAtHeightResponse { power, height })
+}
+
+pub fn query_locks(deps: Deps, env: Env, address: String) -> StdResult<LocksResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    // Expired locks are left in storage until their staker is released
+    let locks = LOCKS
+        .may_load(deps.storage, &address)?
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|lock| !lock.expires.is_expired(&env.block))
+        .collect();
+    Ok(LocksResponse { locks })
+}
+
+pub fn query_delegated_power_at_height(
+    deps: Deps,
+    _env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<DelegatedPowerAtHeightResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let height = height.unwrap_or(_env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &_env.block, height)?;
+    let boosts = unreleased_boosts(deps.storage, &snapshots, height)?;
+    let power = delegated_power_at_height(deps.storage, &snapshots, &address, height, &boosts)?;
+    Ok(DelegatedPowerAtHeightResponse { power, height })
+}
+
+pub fn query_delegation_at_height(
+    deps: Deps,
+    _env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<DelegationAtHeightResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let height = height.unwrap_or(_env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &_env.block, height)?;
+    let delegate = snapshots
+        .delegations
+        .may_load_at_height(deps.storage, &address, height)?;
+    Ok(DelegationAtHeightResponse { delegate, height })
+}
+
+pub fn query_pending_rewards(
+    deps: Deps,
+    env: Env,
+    address: String,
+) -> StdResult<PendingRewardsResponse> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    let address = deps.api.addr_validate(&address)?;
+    let total_staked = snapshots
+        .staked_total
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    let staked = snapshots
+        .staked_balances
+        .may_load(deps.storage, &address)?
+        .unwrap_or_default();
+    let rewards = reward_tokens(deps.storage)?
+        .into_iter()
+        .map(|mut token| {
+            let key = reward_token_key(&token.denom);
+            token.accrue(env.block.height, total_staked)?;
+            let mut reward = STAKER_REWARDS
+                .may_load(deps.storage, (&address, &key))?
+                .unwrap_or_default();
+            reward.accrue(token.reward_index, staked)?;
+            Ok(PendingReward {
+                denom: token.denom,
+                amount: reward.pending,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(PendingRewardsResponse { rewards })
+}
+
+fn get_and_check_limit(limit: Option<u32>, max: u32, default: u32) -> StdResult<u32> {
+    match limit {
+        Some(l) => {
+            if l <= max {
+                Ok(l)
+            } else {
+                Err(StdError::GenericErr {
+                    msg: ContractError::OversizedRequest {
+                        size: l as u64,
+                        max: max as u64,
+                    }
+                    .to_string(),
+                })
+            }
+        }
+        None => Ok(default),
+    }
+}
+
+pub fn query_list_stakers(
+    deps: Deps,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListStakersResponse> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    let limit = get_and_check_limit(limit, MAX_LIMIT, DEFAULT_LIMIT)? as usize;
+    let start_addr = maybe_addr(deps.api, start_after)?;
+    let start = start_addr.map(|addr| Bound::exclusive(addr.as_ref()));
+
+    let balance = snapshots
+        .balance
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    let total = snapshots
+        .staked_total
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    let released = snapshots
+        .locks_released
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    let boosts = expiring_boosts(deps.storage, released, env.block.height, |staker| {
+        snapshots
+            .staker_locks_released
+            .may_load(deps.storage, staker)
+    })?;
+    // Addresses that unstaked everything are kept in storage and listed
+    // with a zero balance, so that a page never scans more than `limit` keys
+    let stakers = snapshots
+        .staked_balances
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (address, staked) = item?;
+            let power = snapshots
+                .power
+                .may_load(deps.storage, &address)?
+                .unwrap_or(staked)
+                .checked_sub(sum_boosts(&boosts, |staker| Ok(*staker == address))?)?;
+            let value = if staked.is_zero() {
+                Uint128::zero()
+            } else {
+                staked.multiply_ratio(balance, total)
+            };
+            Ok(StakerBalance {
+                address,
+                balance: staked,
+                value,
+                power,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ListStakersResponse { stakers })
+}
+
+pub fn query_list_stakers_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListStakersAtHeightResponse> {
+    let limit = get_and_check_limit(limit, MAX_LIMIT, DEFAULT_LIMIT)? as usize;
+    let start_addr = maybe_addr(deps.api, start_after)?;
+    let start = start_addr.map(|addr| Bound::exclusive(addr.as_ref()));
+    let height = height.unwrap_or(env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &env.block, height)?;
+    let balance = snapshots
+        .balance
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    let total = snapshots
+        .staked_total
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    let boosts = unreleased_boosts(deps.storage, &snapshots, height)?;
+
+    // Every address that ever staked still has a key, those without a stake
+    // at `height` are listed with a zero balance
+    let stakers = snapshots
+        .staked_balances
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|address| {
+            let address = address?;
+            let staked = snapshots
+                .staked_balances
+                .may_load_at_height(deps.storage, &address, height)?
+                .unwrap_or_default();
+            let value = if staked.is_zero() {
+                Uint128::zero()
+            } else {
+                staked.multiply_ratio(balance, total)
+            };
+            let power =
+                staker_power_at_height(deps.storage, &snapshots, &address, height, &boosts)?;
+            Ok(StakerBalance {
+                address,
+                balance: staked,
+                value,
+                power,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ListStakersAtHeightResponse { stakers, height })
+}
+
+pub fn query_staker_count(deps: Deps) -> StdResult<StakerCountResponse> {
+    let count = STAKER_COUNT.may_load(deps.storage)?.unwrap_or_default();
+    Ok(StakerCountResponse { count })
+}
+
+pub fn query_staked_value(
+    deps: Deps,
+    _env: Env,
+    address: String,
+) -> StdResult<StakedValueResponse> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    let address = deps.api.addr_validate(&address)?;
+    let balance = snapshots.balance.load(deps.storage).unwrap_or_default();
+    let staked = snapshots
+        .staked_balances
+        .load(deps.storage, &address)
+        .unwrap_or_default();
+    let total = snapshots
+        .staked_total
+        .load(deps.storage)
+        .unwrap_or_default();
+    if balance == Uint128::zero() || staked == Uint128::zero() || total == Uint128::zero() {
+        Ok(StakedValueResponse {
+            value: Uint128::zero(),
+        })
+    } else {
+        let value = staked
+            .checked_mul(balance)
+            .map_err(StdError::overflow)?
+            .checked_div(total)
+            .map_err(StdError::divide_by_zero)?;
+        Ok(StakedValueResponse { value })
+    }
+}
+
+pub fn query_staked_value_at_height(
+    deps: Deps,
+    env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<StakedValueAtHeightResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let height = height.unwrap_or(env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &env.block, height)?;
+    let balance = snapshots
+        .balance
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    let staked = snapshots
+        .staked_balances
+        .may_load_at_height(deps.storage, &address, height)?
+        .unwrap_or_default();
+    let total = snapshots
+        .staked_total
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    let value = if total.is_zero() {
+        Uint128::zero()
+    } else {
+        staked.multiply_ratio(balance, total)
+    };
+    Ok(StakedValueAtHeightResponse { value, height })
+}
+
+pub fn query_exchange_rate_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+) -> StdResult<ExchangeRateAtHeightResponse> {
+    let height = height.unwrap_or(env.block.height);
+    let snapshots = Snapshots::at_height(deps.storage, &env.block, height)?;
+    let balance = snapshots
+        .balance
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    let total = snapshots
+        .staked_total
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    // New stakers get one share per token while nothing is staked
+    let rate = if total.is_zero() || balance.is_zero() {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio(balance, total)
+    };
+    Ok(ExchangeRateAtHeightResponse { rate, height })
+}
+
+pub fn query_total_value(deps: Deps, _env: Env) -> StdResult<TotalValueResponse> {
+    let snapshots = Snapshots::load(deps.storage)?;
+    let balance = snapshots.balance.load(deps.storage).unwrap_or_default();
+    Ok(TotalValueResponse { total: balance })
+}
+
+pub fn query_config(deps: Deps) -> StdResult<GetConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(GetConfigResponse {
+        admin: config.admin,
+        unstaking_duration: config.unstaking_duration,
+        token: config.token,
+        slash_destination: config.slash_destination,
+        snapshot_strategy: config.snapshot_strategy,
+        lockup_tiers: config.lockup_tiers,
+    })
+}
+
+pub fn query_claims(deps: Deps, address: String) -> StdResult<ClaimsResponse> {
+    let claims = CLAIMS
+        .may_load(deps.storage, &deps.api.addr_validate(&address)?)?
+        .unwrap_or_default();
+    Ok(ClaimsResponse { claims })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        id if id >= STAKE_CHANGED_HOOK_REPLY_ID_OFFSET => {
+            let hook = remove_failed_hook(deps, &HOOKS, id - STAKE_CHANGED_HOOK_REPLY_ID_OFFSET)?;
+            Ok(Response::new()
+                .add_attribute("action", "remove_failed_hook")
+                .add_attribute("hook", hook.unwrap_or_default()))
+        }
+        id => Err(ContractError::UnknownReplyId { id }),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let previous_version = ensure_upgrade(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    // Storage migrations, oldest first
+    if previous_version < Version::new(0, 3, 0) {
+        migrations::migrate_v0_2(deps.storage, env.block.height)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", previous_version.to_string())
+        .add_attribute("new_version", CONTRACT_VERSION))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::BorrowMut;
+    use std::str::FromStr;
+
+    use crate::contract::{
+        execute_stake, migrate, query_staker_count, query_staker_power_at_height,
+        query_total_power_at_height, query_total_sqrt_power_at_height, CONTRACT_NAME,
+        CONTRACT_VERSION,
+    };
+    use crate::hooks::StakeChangedHookMsg;
+    use crate::migrations;
+    use crate::msg::{
+        DelegatedPowerAtHeightResponse, DelegationAtHeightResponse, ExchangeRateAtHeightResponse,
+        ExecuteMsg, GetConfigResponse, HooksResponse, ListStakersAtHeightResponse,
+        ListStakersResponse, LocksResponse, LockupTier, MigrateMsg, PendingReward,
+        PendingRewardsResponse, QueryMsg, ReceiveMsg, SnapshotStrategy,
+        StakedBalanceAtHeightResponse, StakedValueAtHeightResponse, StakedValueResponse,
+        StakerBalance, StakerCountResponse, StakerPowerAtHeightResponse,
+        TotalPowerAtHeightResponse, TotalSqrtPowerAtHeightResponse, TotalStakedAtHeightResponse,
+        TotalValueResponse, VotingPowerAtHeightResponse,
+    };
+    use crate::state::{
+        Config, Lock, Snapshots, CONFIG, MAX_CLAIMS, MAX_LOCKS, MAX_RELEASED_LOCKS,
+    };
+    use crate::ContractError;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{
+        coins, to_binary, Addr, Binary, Decimal, Empty, MessageInfo, Response, StdResult, Uint128,
+    };
+    use cw2::{get_contract_version, set_contract_version};
+    use cw20::{Cw20Coin, Denom};
+    use cw_controllers::HookError;
+    use cw_migrate::MigrateError;
+    use cw_storage_plus::{Item, Map, Strategy};
+    use cw_utils::{Duration, PaymentError};
+    use serde::{Deserialize, Serialize};
+
+    use cw_multi_test::{next_block, App, AppResponse, Contract, ContractWrapper, Executor};
+
+    use anyhow::Result as AnyResult;
+    use cw_controllers::{Claim, ClaimsResponse};
+    use cw_utils::Expiration::AtHeight;
+
+    const ADDR1: &str = "addr0001";
+    const ADDR2: &str = "addr0002";
+    const ADDR3: &str = "addr0003";
+    const ADDR4: &str = "addr0004";
+
+    pub fn contract_staking() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            crate::contract::execute,
+            crate::contract::instantiate,
+            crate::contract::query,
+        )
+        .with_reply(crate::contract::reply);
+        Box::new(contract)
+    }
+
+    pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        );
+        Box::new(contract)
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum HookReceiverMsg {
+        StakeChangeHook(StakeChangedHookMsg),
+    }
+
+    const RECEIVED_HOOKS: Item<Vec<StakeChangedHookMsg>> = Item::new("received_hooks");
+
+    /// Records every stake changed hook it receives
+    pub fn contract_hook_receiver() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            |deps, _env, _info, msg: HookReceiverMsg| -> StdResult<Response> {
+                let HookReceiverMsg::StakeChangeHook(msg) = msg;
+                let mut hooks = RECEIVED_HOOKS.may_load(deps.storage)?.unwrap_or_default();
+                hooks.push(msg);
+                RECEIVED_HOOKS.save(deps.storage, &hooks)?;
+                Ok(Response::new())
+            },
+            |_deps, _env, _info, _msg: Empty| -> StdResult<Response> { Ok(Response::new()) },
+            |deps, _env, _msg: Empty| -> StdResult<Binary> {
+                to_binary(&RECEIVED_HOOKS.may_load(deps.storage)?.unwrap_or_default())
+            },
+        );
+        Box::new(contract)
+    }
+
+    fn mock_app() -> App {
+        App::default()
+    }
+
+    fn get_balance<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+    ) -> Uint128 {
+        let msg = cw20::Cw20QueryMsg::Balance {
+            address: address.into(),
+        };
+        let result: cw20::BalanceResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.balance
+    }
+
+    fn instantiate_cw20(app: &mut App, initial_balances: Vec<Cw20Coin>) -> Addr {
+        let cw20_id = app.store_code(contract_cw20());
+        let msg = cw20_base::msg::InstantiateMsg {
+            name: String::from("Test"),
+            symbol: String::from("TEST"),
+            decimals: 6,
+            initial_balances,
+            mint: None,
+            marketing: None,
+        };
+
+        app.instantiate_contract(cw20_id, Addr::unchecked(ADDR1), &msg, &[], "cw20", None)
+            .unwrap()
+    }
+
+    fn instantiate_staking(
+        app: &mut App,
+        cw20: Addr,
+        unstaking_duration: Option<Duration>,
+    ) -> Addr {
+        let staking_code_id = app.store_code(contract_staking());
+        let msg = crate::msg::InstantiateMsg {
+            admin: Some("owner".to_string()),
+            token_address: Some(cw20.to_string()),
+            native_denom: None,
+            unstaking_duration,
+            slash_destination: None,
+            snapshot_strategy: None,
+            lockup_tiers: None,
+        };
+        app.instantiate_contract(
+            staking_code_id,
+            Addr::unchecked(ADDR1),
+            &msg,
+            &[],
+            "staking",
+            None,
+        )
+        .unwrap()
+    }
+
+    fn setup_test_case(
+        app: &mut App,
+        initial_balances: Vec<Cw20Coin>,
+        unstaking_duration: Option<Duration>,
+    ) -> (Addr, Addr) {
+        // Instantiate cw20 contract
+        let cw20_addr = instantiate_cw20(app, initial_balances);
+        app.update_block(next_block);
+        // Instantiate staking contract
+        let staking_addr = instantiate_staking(app, cw20_addr.clone(), unstaking_duration);
+        app.update_block(next_block);
+        (staking_addr, cw20_addr)
+    }
+
+    fn query_staked_balance<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+    ) -> Uint128 {
+        let msg = QueryMsg::StakedBalanceAtHeight {
+            address: address.into(),
+            height: None,
+        };
+        let result: StakedBalanceAtHeightResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.balance
+    }
+
+    fn query_sqrt_power<T: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        height: Option<u64>,
+    ) -> Uint128 {
+        let msg = QueryMsg::TotalSqrtPowerAtHeight { height };
+        let result: TotalSqrtPowerAtHeightResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.power
+    }
+
+    fn query_pending_rewards<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+    ) -> Vec<PendingReward> {
+        let msg = QueryMsg::PendingRewards {
+            address: address.into(),
+        };
+        let result: PendingRewardsResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.rewards
+    }
+
+    fn query_config<T: Into<String>>(app: &App, contract_addr: T) -> GetConfigResponse {
+        let msg = QueryMsg::GetConfig {};
+        app.wrap().query_wasm_smart(contract_addr, &msg).unwrap()
+    }
+
+    fn query_total_staked<T: Into<String>>(app: &App, contract_addr: T) -> Uint128 {
+        let msg = QueryMsg::TotalStakedAtHeight { height: None };
+        let result: TotalStakedAtHeightResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.total
+    }
+
+    fn query_staked_value<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+    ) -> Uint128 {
+        let msg = QueryMsg::StakedValue {
+            address: address.into(),
+        };
+        let result: StakedValueResponse = app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.value
+    }
+
+    fn query_total_value<T: Into<String>>(app: &App, contract_addr: T) -> Uint128 {
+        let msg = QueryMsg::TotalValue {};
+        let result: TotalValueResponse = app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.total
+    }
+
+    fn query_claims<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+    ) -> Vec<Claim> {
+        let msg = QueryMsg::Claims {
+            address: address.into(),
+        };
+        let result: ClaimsResponse = app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.claims
+    }
+
+    fn stake_tokens(
+        app: &mut App,
+        staking_addr: &Addr,
+        cw20_addr: &Addr,
+        info: MessageInfo,
+        amount: Uint128,
+    ) -> AnyResult<AppResponse> {
+        let msg = cw20::Cw20ExecuteMsg::Send {
+            contract: staking_addr.to_string(),
+            amount,
+            msg: to_binary(&ReceiveMsg::Stake {}).unwrap(),
+        };
+        app.execute_contract(info.sender, cw20_addr.clone(), &msg, &[])
+    }
+
+    fn update_config(
+        app: &mut App,
+        staking_addr: &Addr,
+        info: MessageInfo,
+        admin: Option<Addr>,
+        duration: Option<Duration>,
+    ) -> AnyResult<AppResponse> {
+        let msg = ExecuteMsg::UpdateConfig {
+            admin: admin.map(|a| a.to_string()),
+            duration,
+            slash_destination: None,
+        };
+        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+    }
+
+    fn unstake_tokens(
+        app: &mut App,
+        staking_addr: &Addr,
+        info: MessageInfo,
+        amount: Uint128,
+    ) -> AnyResult<AppResponse> {
+        let msg = ExecuteMsg::Unstake { amount };
+        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+    }
+
+    fn claim_tokens(
+        app: &mut App,
+        staking_addr: &Addr,
+        info: MessageInfo,
+    ) -> AnyResult<AppResponse> {
+        let msg = ExecuteMsg::Claim {};
+        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+    }
+
+    fn delegate(
+        app: &mut App,
+        staking_addr: &Addr,
+        info: MessageInfo,
+        address: &str,
+    ) -> AnyResult<AppResponse> {
+        let msg = ExecuteMsg::Delegate {
+            address: address.to_string(),
+        };
+        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+    }
+
+    fn undelegate(app: &mut App, staking_addr: &Addr, info: MessageInfo) -> AnyResult<AppResponse> {
+        let msg = ExecuteMsg::Undelegate {};
+        app.execute_contract(info.sender, staking_addr.clone(), &msg, &[])
+    }
+
+    fn query_voting_power<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+        height: Option<u64>,
+    ) -> Uint128 {
+        let msg = QueryMsg::VotingPowerAtHeight {
+            address: address.into(),
+            height,
+        };
+        let result: VotingPowerAtHeightResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.power
+    }
+
+    fn query_delegated_power<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+    ) -> Uint128 {
+        let msg = QueryMsg::DelegatedPowerAtHeight {
+            address: address.into(),
+            height: None,
+        };
+        let result: DelegatedPowerAtHeightResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.power
+    }
+
+    fn query_delegation<T: Into<String>, U: Into<String>>(
+        app: &App,
+        contract_addr: T,
+        address: U,
+        height: Option<u64>,
+    ) -> Option<Addr> {
+        let msg = QueryMsg::DelegationAtHeight {
+            address: address.into(),
+            height,
+        };
+        let result: DelegationAtHeightResponse =
+            app.wrap().query_wasm_smart(contract_addr, &msg).unwrap();
+        result.delegate
+    }
+
+    #[test]
+    fn test_update_config() {
+        let _deps = mock_dependencies();
+
+        let mut app = mock_app();
+        let amount1 = Uint128::from(100u128);
+        let _token_address = Addr::unchecked("token_address");
+        let initial_balances = vec![Cw20Coin {
+            address: ADDR1.to_string(),
+            amount: amount1,
+        }];
+        let (staking_addr, _cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+
+        let info = mock_info("owner", &[]);
+        let _env = mock_env();
+        // Test update admin
+        update_config(
+            &mut app,
+            &staking_addr,
+            info,
+            Some(Addr::unchecked("owner2")),
+            Some(Duration::Height(100)),
+        )
+        .unwrap();
+
+        let config = query_config(&app, &staking_addr);
+        assert_eq!(config.admin, Some(Addr::unchecked("owner2")));
+        assert_eq!(config.unstaking_duration, Some(Duration::Height(100)));
 
         // Try updating admin with original owner, which is now invalid
         let info = mock_info("owner", &[]);
         let _err = update_config(
             &mut app,
-            &staking_addr,
-            info,
-            Some(Addr::unchecked("owner3")),
-            Some(Duration::Height(100)),
-        )
-        .unwrap_err();
+            &staking_addr,
+            info,
+            Some(Addr::unchecked("owner3")),
+            Some(Duration::Height(100)),
+        )
+        .unwrap_err();
+
+        // Remove admin
+        let info = mock_info("owner2", &[]);
+        let _env = mock_env();
+        // Test update admin
+        update_config(
+            &mut app,
+            &staking_addr,
+            info,
+            None,
+            Some(Duration::Height(100)),
+        )
+        .unwrap();
+
+        // Assert no further updates can be made
+        let info = mock_info("owner2", &[]);
+        let _env = mock_env();
+        // Test update admin
+        let err: ContractError = update_config(
+            &mut app,
+            &staking_addr,
+            info,
+            None,
+            Some(Duration::Height(100)),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+        assert_eq!(err, ContractError::NoAdminConfigured {})
+    }
+
+    #[test]
+    fn test_staking() {
+        let _deps = mock_dependencies();
+
+        let mut app = mock_app();
+        let amount1 = Uint128::from(100u128);
+        let _token_address = Addr::unchecked("token_address");
+        let initial_balances = vec![Cw20Coin {
+            address: ADDR1.to_string(),
+            amount: amount1,
+        }];
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+
+        let info = mock_info(ADDR1, &[]);
+        let _env = mock_env();
+
+        // Successful bond
+        let amount = Uint128::new(50);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info.clone(), amount).unwrap();
+        app.update_block(next_block);
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(50u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(50u128)
+        );
+        assert_eq!(
+            get_balance(&app, &cw20_addr, ADDR1.to_string()),
+            Uint128::from(50u128)
+        );
+
+        // Can't transfer bonded amount
+        let msg = cw20::Cw20ExecuteMsg::Transfer {
+            recipient: ADDR2.to_string(),
+            amount: Uint128::from(51u128),
+        };
+        let _err = app
+            .borrow_mut()
+            .execute_contract(info.sender.clone(), cw20_addr.clone(), &msg, &[])
+            .unwrap_err();
+
+        // Sucessful transfer of unbonded amount
+        let msg = cw20::Cw20ExecuteMsg::Transfer {
+            recipient: ADDR2.to_string(),
+            amount: Uint128::from(20u128),
+        };
+        let _res = app
+            .borrow_mut()
+            .execute_contract(info.sender, cw20_addr.clone(), &msg, &[])
+            .unwrap();
+
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(30u128));
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(20u128));
+
+        // Addr 2 successful bond
+        let info = mock_info(ADDR2, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(20)).unwrap();
+
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR2),
+            Uint128::from(20u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(70u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::zero());
+
+        // Can't unstake more than you have staked
+        let info = mock_info(ADDR2, &[]);
+        let _err = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(100)).unwrap_err();
+
+        // Successful unstake
+        let info = mock_info(ADDR2, &[]);
+        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(10)).unwrap();
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR2),
+            Uint128::from(10u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(60u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(10u128));
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::from(50u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(30u128));
+    }
+
+    #[test]
+    fn text_max_claims() {
+        let mut app = mock_app();
+        let amount1 = Uint128::from(MAX_CLAIMS + 1);
+        let unstaking_blocks = 1000u64;
+        let _token_address = Addr::unchecked("token_address");
+        let initial_balances = vec![Cw20Coin {
+            address: ADDR1.to_string(),
+            amount: amount1,
+        }];
+        let (staking_addr, cw20_addr) = setup_test_case(
+            &mut app,
+            initial_balances,
+            Some(Duration::Height(unstaking_blocks)),
+        );
+
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info.clone(), amount1).unwrap();
+
+        // Create the max number of claims, claims released in the same
+        // block would be merged
+        for _ in 0..MAX_CLAIMS {
+            unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(1)).unwrap();
+            app.update_block(next_block);
+        }
+
+        // Additional unstaking attempts ought to fail.
+        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(1)).unwrap_err();
+
+        // Clear out the claims list.
+        app.update_block(|b| b.height += unstaking_blocks);
+        claim_tokens(&mut app, &staking_addr, info.clone()).unwrap();
+
+        // Unstaking now allowed again.
+        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(1)).unwrap();
+        app.update_block(|b| b.height += unstaking_blocks);
+        claim_tokens(&mut app, &staking_addr, info).unwrap();
+
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), amount1);
+    }
+
+    #[test]
+    fn test_unstaking_with_claims() {
+        let _deps = mock_dependencies();
+
+        let mut app = mock_app();
+        let amount1 = Uint128::from(100u128);
+        let unstaking_blocks = 10u64;
+        let _token_address = Addr::unchecked("token_address");
+        let initial_balances = vec![Cw20Coin {
+            address: ADDR1.to_string(),
+            amount: amount1,
+        }];
+        let (staking_addr, cw20_addr) = setup_test_case(
+            &mut app,
+            initial_balances,
+            Some(Duration::Height(unstaking_blocks)),
+        );
+
+        let info = mock_info(ADDR1, &[]);
+
+        // Successful bond
+        let _res =
+            stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(50)).unwrap();
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::from(50u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(50u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(50u128));
+
+        // Unstake
+        let info = mock_info(ADDR1, &[]);
+        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(10)).unwrap();
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::from(40u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(40u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(50u128));
+
+        // Cannot claim when nothing is available
+        let info = mock_info(ADDR1, &[]);
+        let _err: ContractError = claim_tokens(&mut app, &staking_addr, info)
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(_err, ContractError::NothingToClaim {});
+
+        // Successful claim
+        app.update_block(|b| b.height += unstaking_blocks);
+        let info = mock_info(ADDR1, &[]);
+        let _res = claim_tokens(&mut app, &staking_addr, info).unwrap();
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::from(40u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(40u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(60u128));
+
+        // Unstake and claim multiple
+        let _info = mock_info(ADDR1, &[]);
+        let info = mock_info(ADDR1, &[]);
+        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(5)).unwrap();
+        app.update_block(next_block);
+
+        let _info = mock_info(ADDR1, &[]);
+        let info = mock_info(ADDR1, &[]);
+        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(5)).unwrap();
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::from(30u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(30u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(60u128));
+
+        app.update_block(|b| b.height += unstaking_blocks);
+        let info = mock_info(ADDR1, &[]);
+        let _res = claim_tokens(&mut app, &staking_addr, info).unwrap();
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::from(30u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(30u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(70u128));
+    }
+
+    #[test]
+    fn multiple_address_staking() {
+        let amount1 = Uint128::from(100u128);
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: amount1,
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: amount1,
+            },
+            Cw20Coin {
+                address: ADDR3.to_string(),
+                amount: amount1,
+            },
+            Cw20Coin {
+                address: ADDR4.to_string(),
+                amount: amount1,
+            },
+        ];
+        let mut app = mock_app();
+        let amount1 = Uint128::from(100u128);
+        let unstaking_blocks = 10u64;
+        let _token_address = Addr::unchecked("token_address");
+        let (staking_addr, cw20_addr) = setup_test_case(
+            &mut app,
+            initial_balances,
+            Some(Duration::Height(unstaking_blocks)),
+        );
+
+        let info = mock_info(ADDR1, &[]);
+        // Successful bond
+        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
+        app.update_block(next_block);
+
+        let info = mock_info(ADDR2, &[]);
+        // Successful bond
+        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
+        app.update_block(next_block);
+
+        let info = mock_info(ADDR3, &[]);
+        // Successful bond
+        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
+        app.update_block(next_block);
+
+        let info = mock_info(ADDR4, &[]);
+        // Successful bond
+        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
+        app.update_block(next_block);
+
+        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR1), amount1);
+        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR2), amount1);
+        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR3), amount1);
+        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR4), amount1);
+
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            amount1.checked_mul(Uint128::new(4)).unwrap()
+        );
+
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::zero());
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::zero());
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR3), Uint128::zero());
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR4), Uint128::zero());
+    }
+
+    #[test]
+    fn test_auto_compounding_staking() {
+        let _deps = mock_dependencies();
+
+        let mut app = mock_app();
+        let amount1 = Uint128::from(1000u128);
+        let _token_address = Addr::unchecked("token_address");
+        let initial_balances = vec![Cw20Coin {
+            address: ADDR1.to_string(),
+            amount: amount1,
+        }];
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+
+        let info = mock_info(ADDR1, &[]);
+        let _env = mock_env();
+
+        // Successful bond
+        let amount = Uint128::new(100);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount).unwrap();
+        app.update_block(next_block);
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_staked_value(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_total_value(&app, &staking_addr),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            get_balance(&app, &cw20_addr, ADDR1.to_string()),
+            Uint128::from(900u128)
+        );
+
+        // Add compounding rewards
+        let msg = cw20::Cw20ExecuteMsg::Send {
+            contract: staking_addr.to_string(),
+            amount: Uint128::from(100u128),
+            msg: to_binary(&ReceiveMsg::Fund {}).unwrap(),
+        };
+        let _res = app
+            .borrow_mut()
+            .execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
+            .unwrap();
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_staked_value(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(200u128)
+        );
+        assert_eq!(
+            query_total_value(&app, &staking_addr),
+            Uint128::from(200u128)
+        );
+        assert_eq!(
+            get_balance(&app, &cw20_addr, ADDR1.to_string()),
+            Uint128::from(800u128)
+        );
+
+        // Sucessful transfer of unbonded amount
+        let msg = cw20::Cw20ExecuteMsg::Transfer {
+            recipient: ADDR2.to_string(),
+            amount: Uint128::from(100u128),
+        };
+        let _res = app
+            .borrow_mut()
+            .execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
+            .unwrap();
+
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(700u128));
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(100u128));
+
+        // Addr 2 successful bond
+        let info = mock_info(ADDR2, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
+
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR2),
+            Uint128::from(50u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(150u128)
+        );
+        assert_eq!(
+            query_staked_value(&app, &staking_addr, ADDR2.to_string()),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_total_value(&app, &staking_addr),
+            Uint128::from(300u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::zero());
 
-        // Remove admin
-        let info = mock_info("owner2", &[]);
-        let _env = mock_env();
-        // Test update admin
-        update_config(
-            &mut app,
-            &staking_addr,
-            info,
-            None,
-            Some(Duration::Height(100)),
-        )
-        .unwrap();
+        // Can't unstake more than you have staked
+        let info = mock_info(ADDR2, &[]);
+        let _err = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(51)).unwrap_err();
 
-        // Assert no further updates can be made
-        let info = mock_info("owner2", &[]);
-        let _env = mock_env();
-        // Test update admin
-        let err: ContractError = update_config(
-            &mut app,
-            &staking_addr,
-            info,
-            None,
-            Some(Duration::Height(100)),
-        )
-        .unwrap_err()
-        .downcast()
-        .unwrap();
-        assert_eq!(err, ContractError::NoAdminConfigured {})
+        // Add compounding rewards
+        let msg = cw20::Cw20ExecuteMsg::Send {
+            contract: staking_addr.to_string(),
+            amount: Uint128::from(90u128),
+            msg: to_binary(&ReceiveMsg::Fund {}).unwrap(),
+        };
+        let _res = app
+            .borrow_mut()
+            .execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
+            .unwrap();
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR2),
+            Uint128::from(50u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(150u128)
+        );
+        assert_eq!(
+            query_staked_value(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(260u128)
+        );
+        assert_eq!(
+            query_staked_value(&app, &staking_addr, ADDR2.to_string()),
+            Uint128::from(130u128)
+        );
+        assert_eq!(
+            query_total_value(&app, &staking_addr),
+            Uint128::from(390u128)
+        );
+        assert_eq!(
+            get_balance(&app, &cw20_addr, ADDR1.to_string()),
+            Uint128::from(610u128)
+        );
+
+        // Successful unstake
+        let info = mock_info(ADDR2, &[]);
+        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(25)).unwrap();
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR2),
+            Uint128::from(25u128)
+        );
+        assert_eq!(
+            query_total_staked(&app, &staking_addr),
+            Uint128::from(125u128)
+        );
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(65u128));
     }
 
     #[test]
-    fn test_staking() {
+    fn test_simple_unstaking_with_duration() {
         let _deps = mock_dependencies();
 
         let mut app = mock_app();
         let amount1 = Uint128::from(100u128);
         let _token_address = Addr::unchecked("token_address");
-        let initial_balances = vec![Cw20Coin {
-            address: ADDR1.to_string(),
-            amount: amount1,
-        }];
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: amount1,
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: amount1,
+            },
+        ];
+        let (staking_addr, cw20_addr) =
+            setup_test_case(&mut app, initial_balances, Some(Duration::Height(1)));
+
+        // Bond Address 1
+        let info = mock_info(ADDR1, &[]);
+        let _env = mock_env();
+        let amount = Uint128::new(100);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount).unwrap();
+
+        // Bond Address 2
+        let info = mock_info(ADDR2, &[]);
+        let _env = mock_env();
+        let amount = Uint128::new(100);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount).unwrap();
+        app.update_block(next_block);
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(100u128)
+        );
+
+        // Unstake Addr1
+        let info = mock_info(ADDR1, &[]);
+        let _env = mock_env();
+        let amount = Uint128::new(100);
+        unstake_tokens(&mut app, &staking_addr, info, amount).unwrap();
+
+        // Unstake Addr2
+        let info = mock_info(ADDR2, &[]);
+        let _env = mock_env();
+        let amount = Uint128::new(100);
+        unstake_tokens(&mut app, &staking_addr, info, amount).unwrap();
+
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
+            Uint128::from(0u128)
+        );
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR2.to_string()),
+            Uint128::from(0u128)
+        );
+
+        // Claim
+        assert_eq!(
+            query_claims(&app, &staking_addr, ADDR1),
+            vec![Claim {
+                amount: Uint128::new(100),
+                release_at: AtHeight(12349)
+            }]
+        );
+        assert_eq!(
+            query_claims(&app, &staking_addr, ADDR2),
+            vec![Claim {
+                amount: Uint128::new(100),
+                release_at: AtHeight(12349)
+            }]
+        );
+
+        let info = mock_info(ADDR1, &[]);
+        claim_tokens(&mut app, &staking_addr, info).unwrap();
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(100u128));
+
+        let info = mock_info(ADDR2, &[]);
+        claim_tokens(&mut app, &staking_addr, info).unwrap();
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(100u128));
+    }
+
+    #[test]
+    fn test_delegation() {
+        let mut app = mock_app();
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: Uint128::new(100),
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: Uint128::new(100),
+            },
+        ];
         let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
 
         let info = mock_info(ADDR1, &[]);
-        let _env = mock_env();
-
-        // Successful bond
-        let amount = Uint128::new(50);
-        stake_tokens(&mut app, &staking_addr, &cw20_addr, info.clone(), amount).unwrap();
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(50)).unwrap();
+        let info = mock_info(ADDR2, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(20)).unwrap();
+        app.update_block(next_block);
+
+        // Cannot delegate to yourself
+        let info = mock_info(ADDR1, &[]);
+        let err: ContractError = delegate(&mut app, &staking_addr, info, ADDR1)
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::SelfDelegation {});
+
+        // Cannot undelegate without a delegation
+        let info = mock_info(ADDR1, &[]);
+        let err: ContractError = undelegate(&mut app, &staking_addr, info)
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::NotDelegating {});
+
+        let before_delegation = app.block_info().height;
+        let info = mock_info(ADDR1, &[]);
+        delegate(&mut app, &staking_addr, info, ADDR2).unwrap();
+        app.update_block(next_block);
+
+        assert_eq!(
+            query_delegation(&app, &staking_addr, ADDR1, None),
+            Some(Addr::unchecked(ADDR2))
+        );
+        assert_eq!(
+            query_delegated_power(&app, &staking_addr, ADDR2),
+            Uint128::new(50)
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR1, None),
+            Uint128::zero()
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::new(70)
+        );
+        // Voting power is snapshotted
+        assert_eq!(
+            query_delegation(&app, &staking_addr, ADDR1, Some(before_delegation)),
+            None
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR1, Some(before_delegation)),
+            Uint128::new(50)
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR2, Some(before_delegation)),
+            Uint128::new(20)
+        );
+        // Delegated power counts towards the delegate's square root
+        assert_eq!(
+            query_sqrt_power(&app, &staking_addr, Some(before_delegation)),
+            Uint128::new(7 + 4)
+        );
+        assert_eq!(query_sqrt_power(&app, &staking_addr, None), Uint128::new(8));
+
+        // Staking and unstaking moves the delegated power along
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(30)).unwrap();
+        let info = mock_info(ADDR1, &[]);
+        unstake_tokens(&mut app, &staking_addr, info, Uint128::new(10)).unwrap();
+        app.update_block(next_block);
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::new(90)
+        );
+        assert_eq!(query_sqrt_power(&app, &staking_addr, None), Uint128::new(9));
+
+        // Delegators keep the power delegated to them
+        let info = mock_info(ADDR2, &[]);
+        delegate(&mut app, &staking_addr, info, ADDR3).unwrap();
+        app.update_block(next_block);
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::new(70)
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR3, None),
+            Uint128::new(20)
+        );
+        assert_eq!(
+            query_sqrt_power(&app, &staking_addr, None),
+            Uint128::new(8 + 4)
+        );
+
+        // Redelegating moves the power to the new delegate
+        let info = mock_info(ADDR1, &[]);
+        delegate(&mut app, &staking_addr, info, ADDR3).unwrap();
+        app.update_block(next_block);
+        assert_eq!(
+            query_delegated_power(&app, &staking_addr, ADDR2),
+            Uint128::zero()
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR3, None),
+            Uint128::new(90)
+        );
+        assert_eq!(query_sqrt_power(&app, &staking_addr, None), Uint128::new(9));
+
+        let info = mock_info(ADDR1, &[]);
+        undelegate(&mut app, &staking_addr, info).unwrap();
         app.update_block(next_block);
+        assert_eq!(query_delegation(&app, &staking_addr, ADDR1, None), None);
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(50u128)
+            query_voting_power(&app, &staking_addr, ADDR1, None),
+            Uint128::new(70)
         );
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(50u128)
+            query_voting_power(&app, &staking_addr, ADDR3, None),
+            Uint128::new(20)
         );
         assert_eq!(
-            get_balance(&app, &cw20_addr, ADDR1.to_string()),
-            Uint128::from(50u128)
+            query_sqrt_power(&app, &staking_addr, None),
+            Uint128::new(8 + 4)
         );
+    }
 
-        // Can't transfer bonded amount
-        let msg = cw20::Cw20ExecuteMsg::Transfer {
-            recipient: ADDR2.to_string(),
-            amount: Uint128::from(51u128),
-        };
-        let _err = app
-            .borrow_mut()
-            .execute_contract(info.sender.clone(), cw20_addr.clone(), &msg, &[])
-            .unwrap_err();
+    #[test]
+    fn test_rewards() {
+        let mut app = mock_app();
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: Uint128::new(1000),
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: Uint128::new(1000),
+            },
+        ];
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+        let reward_addr = instantiate_cw20(
+            &mut app,
+            vec![Cw20Coin {
+                address: ADDR3.to_string(),
+                amount: Uint128::new(1000),
+            }],
+        );
+        app.init_modules(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(ADDR3), coins(1000, "ujuno"))
+                .unwrap()
+        });
 
-        // Sucessful transfer of unbonded amount
-        let msg = cw20::Cw20ExecuteMsg::Transfer {
-            recipient: ADDR2.to_string(),
-            amount: Uint128::from(20u128),
+        // Only the admin can add reward tokens
+        let add_reward_token = |denom: Denom, reward_duration: u64| ExecuteMsg::AddRewardToken {
+            denom,
+            reward_duration,
         };
-        let _res = app
-            .borrow_mut()
-            .execute_contract(info.sender, cw20_addr.clone(), &msg, &[])
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                staking_addr.clone(),
+                &add_reward_token(Denom::Cw20(reward_addr.clone()), 100),
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err,
+            ContractError::Unauthorized {
+                expected: Addr::unchecked("owner"),
+                received: Addr::unchecked(ADDR1),
+            }
+        );
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked("owner"),
+                staking_addr.clone(),
+                &add_reward_token(Denom::Cw20(reward_addr.clone()), 0),
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
             .unwrap();
+        assert_eq!(err, ContractError::InvalidRewardDuration {});
+        for denom in [
+            Denom::Cw20(reward_addr.clone()),
+            Denom::Native("ujuno".to_string()),
+        ] {
+            app.execute_contract(
+                Addr::unchecked("owner"),
+                staking_addr.clone(),
+                &add_reward_token(denom, 100),
+                &[],
+            )
+            .unwrap();
+        }
 
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(30u128));
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(20u128));
+        // Unknown reward tokens are refused
+        app.init_modules(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(ADDR4), coins(10, "uatom"))
+                .unwrap()
+        });
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR4),
+                staking_addr.clone(),
+                &ExecuteMsg::FundRewards {},
+                &coins(10, "uatom"),
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err,
+            ContractError::UnknownRewardToken {
+                denom: "native:uatom".to_string()
+            }
+        );
 
-        // Addr 2 successful bond
+        // Stake and fund both reward tokens over 100 blocks
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
         let info = mock_info(ADDR2, &[]);
-        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(20)).unwrap();
-
-        app.update_block(next_block);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
+        app.execute_contract(
+            Addr::unchecked(ADDR3),
+            reward_addr.clone(),
+            &cw20::Cw20ExecuteMsg::Send {
+                contract: staking_addr.to_string(),
+                amount: Uint128::new(1000),
+                msg: to_binary(&ReceiveMsg::FundRewards {}).unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            Addr::unchecked(ADDR3),
+            staking_addr.clone(),
+            &ExecuteMsg::FundRewards {},
+            &coins(1000, "ujuno"),
+        )
+        .unwrap();
 
+        // Rewards are split by stake
+        app.update_block(|b| b.height += 50);
+        let rewards = query_pending_rewards(&app, &staking_addr, ADDR1);
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR2),
-            Uint128::from(20u128)
+            rewards,
+            vec![
+                PendingReward {
+                    denom: Denom::Cw20(reward_addr.clone()),
+                    amount: Uint128::new(250),
+                },
+                PendingReward {
+                    denom: Denom::Native("ujuno".to_string()),
+                    amount: Uint128::new(250),
+                },
+            ]
         );
+        app.execute_contract(
+            Addr::unchecked(ADDR2),
+            staking_addr.clone(),
+            &ExecuteMsg::ClaimRewards {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(get_balance(&app, &reward_addr, ADDR2), Uint128::new(250));
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(70u128)
+            app.wrap().query_balance(ADDR2, "ujuno").unwrap().amount,
+            Uint128::new(250)
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::zero());
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR2),
+                staking_addr.clone(),
+                &ExecuteMsg::ClaimRewards {},
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::NothingToClaim {});
 
-        // Can't unstake more than you have staked
-        let info = mock_info(ADDR2, &[]);
-        let _err = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(100)).unwrap_err();
+        // Changing stakes only affects future rewards
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(200)).unwrap();
+        app.update_block(|b| b.height += 100);
+        let rewards = query_pending_rewards(&app, &staking_addr, ADDR1);
+        assert_eq!(rewards[0].amount, Uint128::new(625));
+        let rewards = query_pending_rewards(&app, &staking_addr, ADDR2);
+        assert_eq!(rewards[0].amount, Uint128::new(125));
 
-        // Successful unstake
-        let info = mock_info(ADDR2, &[]);
-        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(10)).unwrap();
-        app.update_block(next_block);
+        app.execute_contract(
+            Addr::unchecked(ADDR1),
+            staking_addr.clone(),
+            &ExecuteMsg::ClaimRewards {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(get_balance(&app, &reward_addr, ADDR1), Uint128::new(625));
+        assert_eq!(
+            app.wrap().query_balance(ADDR1, "ujuno").unwrap().amount,
+            Uint128::new(625)
+        );
+        let rewards = query_pending_rewards(&app, &staking_addr, ADDR1);
+        assert!(rewards.iter().all(|reward| reward.amount.is_zero()));
+    }
+
+    #[test]
+    fn test_restake_and_partial_claims() {
+        let mut app = mock_app();
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: Uint128::new(100),
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: Uint128::new(100),
+            },
+        ];
+        let (staking_addr, cw20_addr) =
+            setup_test_case(&mut app, initial_balances, Some(Duration::Height(10)));
+
+        // Each share is worth two tokens after funding
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
+        let msg = cw20::Cw20ExecuteMsg::Send {
+            contract: staking_addr.to_string(),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::Fund {}).unwrap(),
+        };
+        app.execute_contract(Addr::unchecked(ADDR2), cw20_addr.clone(), &msg, &[])
+            .unwrap();
 
+        // Claims released in the same block are merged
+        let height = app.block_info().height;
+        let info = mock_info(ADDR1, &[]);
+        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(10)).unwrap();
+        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(10)).unwrap();
+        app.update_block(next_block);
+        unstake_tokens(&mut app, &staking_addr, info, Uint128::new(30)).unwrap();
+        app.update_block(next_block);
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR2),
-            Uint128::from(10u128)
+            query_claims(&app, &staking_addr, ADDR1),
+            vec![
+                Claim {
+                    amount: Uint128::new(40),
+                    release_at: AtHeight(height + 10),
+                },
+                Claim {
+                    amount: Uint128::new(60),
+                    release_at: AtHeight(height + 11),
+                },
+            ]
         );
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(60u128)
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::new(50)
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(10u128));
 
+        // Restaking a claim buys shares at the current share price
+        let restake = |release_at| ExecuteMsg::Restake { release_at };
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                staking_addr.clone(),
+                &restake(Some(AtHeight(height + 12))),
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::ClaimNotFound {});
+        app.execute_contract(
+            Addr::unchecked(ADDR1),
+            staking_addr.clone(),
+            &restake(Some(AtHeight(height + 11))),
+            &[],
+        )
+        .unwrap();
+        app.update_block(next_block);
         assert_eq!(
             query_staked_balance(&app, &staking_addr, ADDR1),
-            Uint128::from(50u128)
+            Uint128::new(80)
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(30u128));
+        assert_eq!(query_total_value(&app, &staking_addr), Uint128::new(160));
+        assert_eq!(query_claims(&app, &staking_addr, ADDR1).len(), 1);
+
+        // Claims can be paid out partially
+        app.update_block(|b| b.height += 10);
+        app.execute_contract(
+            Addr::unchecked(ADDR1),
+            staking_addr.clone(),
+            &ExecuteMsg::ClaimAmount {
+                amount: Uint128::new(15),
+            },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::new(15));
+        assert_eq!(
+            query_claims(&app, &staking_addr, ADDR1),
+            vec![Claim {
+                amount: Uint128::new(25),
+                release_at: AtHeight(height + 10),
+            }]
+        );
+
+        // Or by release time
+        let claim_at = |release_at| ExecuteMsg::ClaimAt { release_at };
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                staking_addr.clone(),
+                &claim_at(AtHeight(height + 11)),
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::ClaimNotFound {});
+        app.execute_contract(
+            Addr::unchecked(ADDR1),
+            staking_addr.clone(),
+            &claim_at(AtHeight(height + 10)),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::new(40));
+        assert!(query_claims(&app, &staking_addr, ADDR1).is_empty());
+
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                staking_addr.clone(),
+                &restake(None),
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::ClaimNotFound {});
     }
 
     #[test]
-    fn text_max_claims() {
+    fn test_hooks() {
         let mut app = mock_app();
-        let amount1 = Uint128::from(MAX_CLAIMS + 1);
-        let unstaking_blocks = 1u64;
-        let _token_address = Addr::unchecked("token_address");
         let initial_balances = vec![Cw20Coin {
             address: ADDR1.to_string(),
-            amount: amount1,
+            amount: Uint128::new(100),
         }];
-        let (staking_addr, cw20_addr) = setup_test_case(
-            &mut app,
-            initial_balances,
-            Some(Duration::Height(unstaking_blocks)),
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+        let receiver_id = app.store_code(contract_hook_receiver());
+        let receiver_addr = app
+            .instantiate_contract(
+                receiver_id,
+                Addr::unchecked(ADDR1),
+                &Empty {},
+                &[],
+                "receiver",
+                None,
+            )
+            .unwrap();
+
+        // Only the admin can manage hooks
+        let add_hook = ExecuteMsg::AddHook {
+            addr: receiver_addr.to_string(),
+        };
+        let err: ContractError = app
+            .execute_contract(Addr::unchecked(ADDR1), staking_addr.clone(), &add_hook, &[])
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err,
+            ContractError::Unauthorized {
+                expected: Addr::unchecked("owner"),
+                received: Addr::unchecked(ADDR1),
+            }
+        );
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &add_hook,
+            &[],
+        )
+        .unwrap();
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked("owner"),
+                staking_addr.clone(),
+                &add_hook,
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err,
+            ContractError::Hook(HookError::HookAlreadyRegistered {})
         );
+        let hooks: HooksResponse = app
+            .wrap()
+            .query_wasm_smart(&staking_addr, &QueryMsg::GetHooks {})
+            .unwrap();
+        assert_eq!(hooks.hooks, vec![receiver_addr.to_string()]);
 
+        // Staking and unstaking notify the hooks
         let info = mock_info(ADDR1, &[]);
-        stake_tokens(&mut app, &staking_addr, &cw20_addr, info.clone(), amount1).unwrap();
-
-        // Create the max number of claims
-        for _ in 0..MAX_CLAIMS {
-            unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(1)).unwrap();
-        }
-
-        // Additional unstaking attempts ought to fail.
-        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(1)).unwrap_err();
+        stake_tokens(
+            &mut app,
+            &staking_addr,
+            &cw20_addr,
+            info.clone(),
+            Uint128::new(100),
+        )
+        .unwrap();
+        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(40)).unwrap();
+        let received: Vec<StakeChangedHookMsg> = app
+            .wrap()
+            .query_wasm_smart(&receiver_addr, &Empty {})
+            .unwrap();
+        assert_eq!(
+            received,
+            vec![
+                StakeChangedHookMsg {
+                    addr: Addr::unchecked(ADDR1),
+                    old: Uint128::zero(),
+                    new: Uint128::new(100),
+                },
+                StakeChangedHookMsg {
+                    addr: Addr::unchecked(ADDR1),
+                    old: Uint128::new(100),
+                    new: Uint128::new(60),
+                },
+            ]
+        );
 
-        // Clear out the claims list.
-        app.update_block(next_block);
-        claim_tokens(&mut app, &staking_addr, info.clone()).unwrap();
+        // Removed hooks are no longer notified
+        let remove_hook = ExecuteMsg::RemoveHook {
+            addr: receiver_addr.to_string(),
+        };
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &remove_hook,
+            &[],
+        )
+        .unwrap();
+        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(10)).unwrap();
+        let received: Vec<StakeChangedHookMsg> = app
+            .wrap()
+            .query_wasm_smart(&receiver_addr, &Empty {})
+            .unwrap();
+        assert_eq!(received.len(), 2);
 
-        // Unstaking now allowed again.
-        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(1)).unwrap();
+        // A failing hook is removed instead of blocking staking
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &ExecuteMsg::AddHook {
+                addr: cw20_addr.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+        unstake_tokens(&mut app, &staking_addr, info, Uint128::new(10)).unwrap();
+        let hooks: HooksResponse = app
+            .wrap()
+            .query_wasm_smart(&staking_addr, &QueryMsg::GetHooks {})
+            .unwrap();
+        assert!(hooks.hooks.is_empty());
         app.update_block(next_block);
-        claim_tokens(&mut app, &staking_addr, info).unwrap();
+        let staked: StakedBalanceAtHeightResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &staking_addr,
+                &QueryMsg::StakedBalanceAtHeight {
+                    address: ADDR1.to_string(),
+                    height: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(staked.balance, Uint128::new(40));
 
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), amount1);
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked("owner"),
+                staking_addr.clone(),
+                &remove_hook,
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::Hook(HookError::HookNotRegistered {}));
     }
 
     #[test]
-    fn test_unstaking_with_claims() {
-        let _deps = mock_dependencies();
-
+    fn test_slash() {
         let mut app = mock_app();
-        let amount1 = Uint128::from(100u128);
-        let unstaking_blocks = 10u64;
-        let _token_address = Addr::unchecked("token_address");
-        let initial_balances = vec![Cw20Coin {
-            address: ADDR1.to_string(),
-            amount: amount1,
-        }];
-        let (staking_addr, cw20_addr) = setup_test_case(
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: Uint128::new(100),
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: Uint128::new(100),
+            },
+        ];
+        let (staking_addr, cw20_addr) =
+            setup_test_case(&mut app, initial_balances, Some(Duration::Height(10)));
+
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(
             &mut app,
-            initial_balances,
-            Some(Duration::Height(unstaking_blocks)),
-        );
+            &staking_addr,
+            &cw20_addr,
+            info.clone(),
+            Uint128::new(100),
+        )
+        .unwrap();
+        delegate(&mut app, &staking_addr, info.clone(), ADDR3).unwrap();
+        unstake_tokens(&mut app, &staking_addr, info, Uint128::new(40)).unwrap();
+        let info = mock_info(ADDR2, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
+        app.update_block(next_block);
+        let height_before_slash = app.block_info().height;
 
-        let info = mock_info(ADDR1, &[]);
+        // Only the admin can slash, by at most the whole stake
+        let slash = |addr: &str, percentage: &str| ExecuteMsg::Slash {
+            addr: addr.to_string(),
+            percentage: Decimal::from_str(percentage).unwrap(),
+        };
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                staking_addr.clone(),
+                &slash(ADDR1, "0.5"),
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err,
+            ContractError::Unauthorized {
+                expected: Addr::unchecked("owner"),
+                received: Addr::unchecked(ADDR1),
+            }
+        );
+        for percentage in ["0", "1.5"] {
+            let err: ContractError = app
+                .execute_contract(
+                    Addr::unchecked("owner"),
+                    staking_addr.clone(),
+                    &slash(ADDR1, percentage),
+                    &[],
+                )
+                .unwrap_err()
+                .downcast()
+                .unwrap();
+            assert_eq!(err, ContractError::InvalidSlashPercentage {});
+        }
 
-        // Successful bond
-        let _res =
-            stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(50)).unwrap();
+        // Slashed stake and unbonding claims go to the slash destination
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &ExecuteMsg::UpdateConfig {
+                admin: Some("owner".to_string()),
+                duration: Some(Duration::Height(10)),
+                slash_destination: Some(ADDR4.to_string()),
+            },
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &slash(ADDR1, "0.5"),
+            &[],
+        )
+        .unwrap();
         app.update_block(next_block);
-
+        assert_eq!(get_balance(&app, &cw20_addr, ADDR4), Uint128::new(50));
         assert_eq!(
             query_staked_balance(&app, &staking_addr, ADDR1),
-            Uint128::from(50u128)
+            Uint128::new(30)
         );
+        assert_eq!(query_total_staked(&app, &staking_addr), Uint128::new(130));
+        assert_eq!(query_total_value(&app, &staking_addr), Uint128::new(130));
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(50u128)
+            query_claims(&app, &staking_addr, ADDR1)[0].amount,
+            Uint128::new(20)
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR3, None),
+            Uint128::new(30)
+        );
+        assert_eq!(
+            query_voting_power(&app, &staking_addr, ADDR3, Some(height_before_slash)),
+            Uint128::new(60)
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(50u128));
 
-        // Unstake
-        let info = mock_info(ADDR1, &[]);
-        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(10)).unwrap();
+        // Without a slash destination slashed tokens are burned
+        let info = mock_info("owner", &[]);
+        update_config(
+            &mut app,
+            &staking_addr,
+            info,
+            Some(Addr::unchecked("owner")),
+            Some(Duration::Height(10)),
+        )
+        .unwrap();
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &slash(ADDR2, "1"),
+            &[],
+        )
+        .unwrap();
         app.update_block(next_block);
-
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1),
-            Uint128::from(40u128)
+            query_staked_balance(&app, &staking_addr, ADDR2),
+            Uint128::zero()
         );
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(40u128)
+            get_balance(&app, &cw20_addr, &staking_addr),
+            Uint128::new(50)
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(50u128));
+        let token_info: cw20::TokenInfoResponse = app
+            .wrap()
+            .query_wasm_smart(&cw20_addr, &cw20::Cw20QueryMsg::TokenInfo {})
+            .unwrap();
+        assert_eq!(token_info.total_supply, Uint128::new(100));
 
-        // Cannot claim when nothing is available
-        let info = mock_info(ADDR1, &[]);
-        let _err: ContractError = claim_tokens(&mut app, &staking_addr, info)
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked("owner"),
+                staking_addr.clone(),
+                &slash(ADDR2, "0.5"),
+                &[],
+            )
             .unwrap_err()
             .downcast()
             .unwrap();
-        assert_eq!(_err, ContractError::NothingToClaim {});
-
-        // Successful claim
-        app.update_block(|b| b.height += unstaking_blocks);
-        let info = mock_info(ADDR1, &[]);
-        let _res = claim_tokens(&mut app, &staking_addr, info).unwrap();
-        assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1),
-            Uint128::from(40u128)
-        );
-        assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(40u128)
-        );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(60u128));
+        assert_eq!(err, ContractError::NothingToSlash {});
+    }
 
-        // Unstake and claim multiple
-        let _info = mock_info(ADDR1, &[]);
-        let info = mock_info(ADDR1, &[]);
-        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(5)).unwrap();
+    #[test]
+    fn test_list_stakers() {
+        let mut app = mock_app();
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: Uint128::new(200),
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: Uint128::new(100),
+            },
+            Cw20Coin {
+                address: ADDR3.to_string(),
+                amount: Uint128::new(100),
+            },
+        ];
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+        for addr in [ADDR1, ADDR2, ADDR3] {
+            let info = mock_info(addr, &[]);
+            stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
+        }
         app.update_block(next_block);
+        let height = app.block_info().height;
 
-        let _info = mock_info(ADDR1, &[]);
-        let info = mock_info(ADDR1, &[]);
-        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(5)).unwrap();
+        // Stakers that unstaked everything are listed with a zero balance
+        let info = mock_info(ADDR2, &[]);
+        unstake_tokens(&mut app, &staking_addr, info, Uint128::new(100)).unwrap();
+        let msg = cw20::Cw20ExecuteMsg::Send {
+            contract: staking_addr.to_string(),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::Fund {}).unwrap(),
+        };
+        app.execute_contract(Addr::unchecked(ADDR1), cw20_addr, &msg, &[])
+            .unwrap();
         app.update_block(next_block);
 
+        let count: StakerCountResponse = app
+            .wrap()
+            .query_wasm_smart(&staking_addr, &QueryMsg::StakerCount {})
+            .unwrap();
+        assert_eq!(count.count, 2);
+
+        let list_stakers = |start_after: Option<&str>, limit| QueryMsg::ListStakers {
+            start_after: start_after.map(String::from),
+            limit,
+        };
+        let page: ListStakersResponse = app
+            .wrap()
+            .query_wasm_smart(&staking_addr, &list_stakers(None, Some(1)))
+            .unwrap();
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1),
-            Uint128::from(30u128)
+            page.stakers,
+            vec![StakerBalance {
+                address: Addr::unchecked(ADDR1),
+                balance: Uint128::new(100),
+                value: Uint128::new(150),
+                power: Uint128::new(100),
+            }]
         );
+        let page: ListStakersResponse = app
+            .wrap()
+            .query_wasm_smart(&staking_addr, &list_stakers(Some(ADDR1), None))
+            .unwrap();
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(30u128)
+            page.stakers,
+            vec![
+                StakerBalance {
+                    address: Addr::unchecked(ADDR2),
+                    balance: Uint128::zero(),
+                    value: Uint128::zero(),
+                    power: Uint128::zero(),
+                },
+                StakerBalance {
+                    address: Addr::unchecked(ADDR3),
+                    balance: Uint128::new(100),
+                    value: Uint128::new(150),
+                    power: Uint128::new(100),
+                }
+            ]
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(60u128));
+        let err = app
+            .wrap()
+            .query_wasm_smart::<ListStakersResponse>(&staking_addr, &list_stakers(None, Some(31)))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Request size (31) is above limit of (30)"));
 
-        app.update_block(|b| b.height += unstaking_blocks);
-        let info = mock_info(ADDR1, &[]);
-        let _res = claim_tokens(&mut app, &staking_addr, info).unwrap();
-        assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1),
-            Uint128::from(30u128)
-        );
+        // Stakers are listed as they were at the given height
+        let res: ListStakersAtHeightResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &staking_addr,
+                &QueryMsg::ListStakersAtHeight {
+                    height: Some(height),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(res.height, height);
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(30u128)
+            res.stakers
+                .iter()
+                .map(|staker| staker.address.as_str())
+                .collect::<Vec<_>>(),
+            vec![ADDR1, ADDR2, ADDR3]
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(70u128));
     }
 
     #[test]
-    fn multiple_address_staking() {
-        let amount1 = Uint128::from(100u128);
+    fn test_exchange_rate_history() {
+        let mut app = mock_app();
         let initial_balances = vec![
             Cw20Coin {
                 address: ADDR1.to_string(),
-                amount: amount1,
+                amount: Uint128::new(200),
             },
             Cw20Coin {
                 address: ADDR2.to_string(),
-                amount: amount1,
-            },
-            Cw20Coin {
-                address: ADDR3.to_string(),
-                amount: amount1,
-            },
-            Cw20Coin {
-                address: ADDR4.to_string(),
-                amount: amount1,
+                amount: Uint128::new(100),
             },
         ];
-        let mut app = mock_app();
-        let amount1 = Uint128::from(100u128);
-        let unstaking_blocks = 10u64;
-        let _token_address = Addr::unchecked("token_address");
-        let (staking_addr, cw20_addr) = setup_test_case(
-            &mut app,
-            initial_balances,
-            Some(Duration::Height(unstaking_blocks)),
-        );
-
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+        let unstaked_height = app.block_info().height;
         let info = mock_info(ADDR1, &[]);
-        // Successful bond
-        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
-        app.update_block(next_block);
-
-        let info = mock_info(ADDR2, &[]);
-        // Successful bond
-        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
         app.update_block(next_block);
+        let staked_height = app.block_info().height;
 
-        let info = mock_info(ADDR3, &[]);
-        // Successful bond
-        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
+        // Funding doubles the value of each share
+        let msg = cw20::Cw20ExecuteMsg::Send {
+            contract: staking_addr.to_string(),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::Fund {}).unwrap(),
+        };
+        app.execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
+            .unwrap();
         app.update_block(next_block);
-
-        let info = mock_info(ADDR4, &[]);
-        // Successful bond
-        let _res = stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount1).unwrap();
+        let funded_height = app.block_info().height;
+        let info = mock_info(ADDR2, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
         app.update_block(next_block);
 
-        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR1), amount1);
-        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR2), amount1);
-        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR3), amount1);
-        assert_eq!(query_staked_balance(&app, &staking_addr, ADDR4), amount1);
-
+        let exchange_rate = |height| -> Decimal {
+            let res: ExchangeRateAtHeightResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &staking_addr,
+                    &QueryMsg::ExchangeRateAtHeight {
+                        height: Some(height),
+                    },
+                )
+                .unwrap();
+            res.rate
+        };
+        assert_eq!(exchange_rate(unstaked_height), Decimal::one());
+        assert_eq!(exchange_rate(staked_height), Decimal::one());
+        assert_eq!(exchange_rate(funded_height), Decimal::percent(200));
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            amount1.checked_mul(Uint128::new(4)).unwrap()
+            exchange_rate(app.block_info().height),
+            Decimal::percent(200)
         );
 
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::zero());
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::zero());
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR3), Uint128::zero());
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR4), Uint128::zero());
+        let staked_value = |address: &str, height| -> Uint128 {
+            let res: StakedValueAtHeightResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &staking_addr,
+                    &QueryMsg::StakedValueAtHeight {
+                        address: address.to_string(),
+                        height: Some(height),
+                    },
+                )
+                .unwrap();
+            res.value
+        };
+        assert_eq!(staked_value(ADDR1, unstaked_height), Uint128::zero());
+        assert_eq!(staked_value(ADDR1, staked_height), Uint128::new(100));
+        assert_eq!(staked_value(ADDR1, funded_height), Uint128::new(200));
+        assert_eq!(staked_value(ADDR2, funded_height), Uint128::zero());
+        assert_eq!(
+            staked_value(ADDR2, app.block_info().height),
+            Uint128::new(100)
+        );
+        assert_eq!(
+            query_staked_balance(&app, &staking_addr, ADDR2),
+            Uint128::new(50)
+        );
     }
 
     #[test]
-    fn test_auto_compounding_staking() {
-        let _deps = mock_dependencies();
-
+    fn test_native_staking() {
         let mut app = mock_app();
-        let amount1 = Uint128::from(1000u128);
-        let _token_address = Addr::unchecked("token_address");
-        let initial_balances = vec![Cw20Coin {
-            address: ADDR1.to_string(),
-            amount: amount1,
-        }];
-        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+        app.init_modules(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(ADDR1), coins(1000, "ujuno"))
+                .unwrap();
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(ADDR2), coins(1000, "ujuno"))
+                .unwrap();
+        });
+        let staking_code_id = app.store_code(contract_staking());
+        let instantiate =
+            |token_address: Option<&str>, native_denom: Option<&str>| crate::msg::InstantiateMsg {
+                admin: Some("owner".to_string()),
+                token_address: token_address.map(String::from),
+                native_denom: native_denom.map(String::from),
+                unstaking_duration: Some(Duration::Height(5)),
+                slash_destination: None,
+                snapshot_strategy: None,
+                lockup_tiers: None,
+            };
 
-        let info = mock_info(ADDR1, &[]);
-        let _env = mock_env();
+        // Exactly one staked token must be configured
+        for msg in [
+            instantiate(None, None),
+            instantiate(Some("token"), Some("ujuno")),
+        ] {
+            let err: ContractError = app
+                .instantiate_contract(
+                    staking_code_id,
+                    Addr::unchecked(ADDR1),
+                    &msg,
+                    &[],
+                    "staking",
+                    None,
+                )
+                .unwrap_err()
+                .downcast()
+                .unwrap();
+            assert_eq!(err, ContractError::InvalidTokenConfig {});
+        }
+        let staking_addr = app
+            .instantiate_contract(
+                staking_code_id,
+                Addr::unchecked(ADDR1),
+                &instantiate(None, Some("ujuno")),
+                &[],
+                "staking",
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            query_config(&app, &staking_addr).token,
+            Denom::Native("ujuno".to_string())
+        );
 
-        // Successful bond
-        let amount = Uint128::new(100);
-        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount).unwrap();
+        // Only the staked denom can be staked
+        app.execute_contract(
+            Addr::unchecked(ADDR1),
+            staking_addr.clone(),
+            &ExecuteMsg::Stake {},
+            &coins(100, "ujuno"),
+        )
+        .unwrap();
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR2),
+                staking_addr.clone(),
+                &ExecuteMsg::Stake {},
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::Payment(PaymentError::NoFunds {}));
+        app.execute_contract(
+            Addr::unchecked(ADDR2),
+            staking_addr.clone(),
+            &ExecuteMsg::Fund {},
+            &coins(100, "ujuno"),
+        )
+        .unwrap();
         app.update_block(next_block);
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(100u128)
+            query_voting_power(&app, &staking_addr, ADDR1, None),
+            Uint128::new(100)
         );
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(100u128)
+            query_staked_value(&app, &staking_addr, ADDR1),
+            Uint128::new(200)
+        );
+
+        // Unstaked tokens are sent back once the claim is released
+        let info = mock_info(ADDR1, &[]);
+        unstake_tokens(&mut app, &staking_addr, info.clone(), Uint128::new(50)).unwrap();
+        app.update_block(|b| b.height += 5);
+        claim_tokens(&mut app, &staking_addr, info).unwrap();
+        let balance =
+            |app: &App, address: &Addr| app.wrap().query_balance(address, "ujuno").unwrap().amount;
+        assert_eq!(balance(&app, &Addr::unchecked(ADDR1)), Uint128::new(1000));
+
+        // Slashed tokens are burned without a slash destination
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &ExecuteMsg::Slash {
+                addr: ADDR1.to_string(),
+                percentage: Decimal::percent(50),
+            },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(balance(&app, &staking_addr), Uint128::new(50));
+        assert_eq!(query_total_value(&app, &staking_addr), Uint128::new(50));
+
+        // A cw20 staking contract does not take native denoms
+        let (cw20_staking_addr, cw20_addr) = setup_test_case(&mut app, vec![], None);
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                cw20_staking_addr,
+                &ExecuteMsg::Stake {},
+                &coins(100, "ujuno"),
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err,
+            ContractError::WrongStakingToken {
+                denom: format!("cw20:{}", cw20_addr)
+            }
+        );
+    }
+
+    #[test]
+    fn test_selected_snapshots() {
+        let mut app = mock_app();
+        let amount1 = Uint128::from(100u128);
+        let initial_balances = vec![
+            Cw20Coin {
+                address: ADDR1.to_string(),
+                amount: amount1,
+            },
+            Cw20Coin {
+                address: ADDR2.to_string(),
+                amount: amount1,
+            },
+        ];
+        let cw20_addr = instantiate_cw20(&mut app, initial_balances);
+        let staking_code_id = app.store_code(contract_staking());
+        let staking_addr = app
+            .instantiate_contract(
+                staking_code_id,
+                Addr::unchecked(ADDR1),
+                &crate::msg::InstantiateMsg {
+                    admin: Some("owner".to_string()),
+                    token_address: Some(cw20_addr.to_string()),
+                    native_denom: None,
+                    unstaking_duration: None,
+                    slash_destination: None,
+                    snapshot_strategy: Some(SnapshotStrategy::Selected {}),
+                    lockup_tiers: None,
+                },
+                &[],
+                "staking",
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            query_config(&app, &staking_addr).snapshot_strategy,
+            SnapshotStrategy::Selected {}
         );
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(60)).unwrap();
+        app.update_block(next_block);
+        let unchecked_height = app.block_info().height;
+        app.update_block(next_block);
+
+        // Only the admin can add checkpoints
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                staking_addr.clone(),
+                &ExecuteMsg::AddCheckpoint {},
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
         assert_eq!(
-            query_staked_value(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(100u128)
+            err,
+            ContractError::Unauthorized {
+                received: Addr::unchecked(ADDR1),
+                expected: Addr::unchecked("owner"),
+            }
         );
+
+        // Checkpointing the same height twice is a no-op
+        let checkpoint_height = app.block_info().height;
+        for _ in 0..2 {
+            app.execute_contract(
+                Addr::unchecked("owner"),
+                staking_addr.clone(),
+                &ExecuteMsg::AddCheckpoint {},
+                &[],
+            )
+            .unwrap();
+        }
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(40)).unwrap();
+        let info = mock_info(ADDR2, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(10)).unwrap();
+        app.update_block(next_block);
+
+        // Checkpointed heights keep the balances from the start of the block
         assert_eq!(
-            query_total_value(&app, &staking_addr),
-            Uint128::from(100u128)
+            query_voting_power(&app, &staking_addr, ADDR1, Some(checkpoint_height)),
+            Uint128::new(60)
         );
+        let total: TotalStakedAtHeightResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &staking_addr,
+                &QueryMsg::TotalStakedAtHeight {
+                    height: Some(checkpoint_height),
+                },
+            )
+            .unwrap();
+        assert_eq!(total.total, Uint128::new(60));
         assert_eq!(
-            get_balance(&app, &cw20_addr, ADDR1.to_string()),
-            Uint128::from(900u128)
+            query_voting_power(&app, &staking_addr, ADDR1, None),
+            Uint128::new(100)
         );
 
-        // Add compounding rewards
-        let msg = cw20::Cw20ExecuteMsg::Send {
-            contract: staking_addr.to_string(),
-            amount: Uint128::from(100u128),
-            msg: to_binary(&ReceiveMsg::Fund {}).unwrap(),
+        // Past heights without a checkpoint can't be queried
+        let msg = QueryMsg::VotingPowerAtHeight {
+            address: ADDR1.to_string(),
+            height: Some(unchecked_height),
         };
-        let _res = app
-            .borrow_mut()
-            .execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
+        app.wrap()
+            .query_wasm_smart::<VotingPowerAtHeightResponse>(&staking_addr, &msg)
+            .unwrap_err();
+
+        // Pruning removes checkpoints before the given height
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR1),
+                staking_addr.clone(),
+                &ExecuteMsg::PruneCheckpoints {
+                    before: checkpoint_height + 1,
+                    start_after: None,
+                    limit: None,
+                },
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
             .unwrap();
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(100u128)
-        );
-        assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(100u128)
-        );
-        assert_eq!(
-            query_staked_value(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(200u128)
-        );
-        assert_eq!(
-            query_total_value(&app, &staking_addr),
-            Uint128::from(200u128)
+            err,
+            ContractError::Unauthorized {
+                received: Addr::unchecked(ADDR1),
+                expected: Addr::unchecked("owner"),
+            }
         );
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &ExecuteMsg::PruneCheckpoints {
+                before: checkpoint_height,
+                start_after: None,
+                limit: None,
+            },
+            &[],
+        )
+        .unwrap();
         assert_eq!(
-            get_balance(&app, &cw20_addr, ADDR1.to_string()),
-            Uint128::from(800u128)
+            query_voting_power(&app, &staking_addr, ADDR1, Some(checkpoint_height)),
+            Uint128::new(60)
         );
+        let changelog: Map<(&Addr, u64), Empty> = Map::new("staked_balance__changelog");
+        let is_recorded = |app: &App, addr: &str| {
+            let change = changelog.key((&Addr::unchecked(addr), checkpoint_height));
+            app.wrap()
+                .query_wasm_raw(&staking_addr, change.to_vec())
+                .unwrap()
+                .is_some()
+        };
+        assert!(is_recorded(&app, ADDR1));
+        assert!(is_recorded(&app, ADDR2));
 
-        // Sucessful transfer of unbonded amount
-        let msg = cw20::Cw20ExecuteMsg::Transfer {
-            recipient: ADDR2.to_string(),
-            amount: Uint128::from(100u128),
+        // The changes recorded for the pruned checkpoints are deleted one
+        // page of addresses at a time
+        let prune = |app: &mut App, start_after: Option<&str>| {
+            let res = app
+                .execute_contract(
+                    Addr::unchecked("owner"),
+                    staking_addr.clone(),
+                    &ExecuteMsg::PruneCheckpoints {
+                        before: checkpoint_height + 1,
+                        start_after: start_after.map(String::from),
+                        limit: Some(1),
+                    },
+                    &[],
+                )
+                .unwrap();
+            res.custom_attrs(1)
+                .iter()
+                .find(|attr| attr.key == "next_start_after")
+                .map(|attr| attr.value.clone())
         };
-        let _res = app
-            .borrow_mut()
-            .execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
-            .unwrap();
+        assert_eq!(prune(&mut app, None), Some(ADDR1.to_string()));
+        assert!(!is_recorded(&app, ADDR1));
+        assert!(is_recorded(&app, ADDR2));
+        assert_eq!(prune(&mut app, Some(ADDR1)), Some(ADDR2.to_string()));
+        assert!(!is_recorded(&app, ADDR2));
+        assert_eq!(prune(&mut app, Some(ADDR2)), None);
+        let msg = QueryMsg::VotingPowerAtHeight {
+            address: ADDR1.to_string(),
+            height: Some(checkpoint_height),
+        };
+        app.wrap()
+            .query_wasm_smart::<VotingPowerAtHeightResponse>(&staking_addr, &msg)
+            .unwrap_err();
 
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(700u128));
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(100u128));
+        // Contracts snapshotting every block don't use checkpoints
+        let (staking_addr, _) = setup_test_case(&mut app, vec![], None);
+        for msg in [
+            ExecuteMsg::AddCheckpoint {},
+            ExecuteMsg::PruneCheckpoints {
+                before: 1,
+                start_after: None,
+                limit: None,
+            },
+        ] {
+            let err: ContractError = app
+                .execute_contract(Addr::unchecked("owner"), staking_addr.clone(), &msg, &[])
+                .unwrap_err()
+                .downcast()
+                .unwrap();
+            assert_eq!(err, ContractError::CheckpointsNotUsed {});
+        }
+    }
 
-        // Addr 2 successful bond
-        let info = mock_info(ADDR2, &[]);
-        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
+    #[test]
+    fn test_lockup_tiers() {
+        let mut app = mock_app();
+        let initial_balances = vec![Cw20Coin {
+            address: ADDR1.to_string(),
+            amount: Uint128::new(1000),
+        }];
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+        let stake_locked = |app: &mut App, amount: u128, duration: Duration| {
+            let msg = cw20::Cw20ExecuteMsg::Send {
+                contract: staking_addr.to_string(),
+                amount: Uint128::new(amount),
+                msg: to_binary(&ReceiveMsg::StakeLocked { duration }).unwrap(),
+            };
+            app.execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
+        };
+        let update_tiers = |app: &mut App, tiers: Vec<LockupTier>| {
+            app.execute_contract(
+                Addr::unchecked("owner"),
+                staking_addr.clone(),
+                &ExecuteMsg::UpdateLockupTiers { tiers },
+                &[],
+            )
+        };
+        let query_power = |app: &App| -> (Uint128, Uint128) {
+            let staker: StakerPowerAtHeightResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &staking_addr,
+                    &QueryMsg::StakerPowerAtHeight {
+                        address: ADDR1.to_string(),
+                        height: None,
+                    },
+                )
+                .unwrap();
+            let total: TotalPowerAtHeightResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &staking_addr,
+                    &QueryMsg::TotalPowerAtHeight { height: None },
+                )
+                .unwrap();
+            (staker.power, total.power)
+        };
 
-        app.update_block(next_block);
+        // Tiers need a multiplier of at least one and distinct durations in
+        // blocks
+        for tiers in [
+            vec![LockupTier {
+                duration: Duration::Height(10),
+                multiplier: Decimal::percent(50),
+            }],
+            vec![LockupTier {
+                duration: Duration::Time(10),
+                multiplier: Decimal::percent(150),
+            }],
+            vec![
+                LockupTier {
+                    duration: Duration::Height(10),
+                    multiplier: Decimal::percent(150),
+                },
+                LockupTier {
+                    duration: Duration::Height(10),
+                    multiplier: Decimal::percent(200),
+                },
+            ],
+        ] {
+            let err: ContractError = update_tiers(&mut app, tiers)
+                .unwrap_err()
+                .downcast()
+                .unwrap();
+            assert_eq!(err, ContractError::InvalidLockupTiers {});
+        }
+        let tiers = vec![
+            LockupTier {
+                duration: Duration::Height(10),
+                multiplier: Decimal::percent(150),
+            },
+            LockupTier {
+                duration: Duration::Height(100),
+                multiplier: Decimal::percent(200),
+            },
+        ];
+        update_tiers(&mut app, tiers.clone()).unwrap();
+        assert_eq!(query_config(&app, &staking_addr).lockup_tiers, tiers);
 
+        // Only configured lock periods can be chosen
+        let err: ContractError = stake_locked(&mut app, 100, Duration::Height(5))
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, ContractError::UnknownLockupTier {});
+
+        // Locked stake earns the tier's multiplier
+        let expires = app.block_info().height + 10;
+        stake_locked(&mut app, 100, Duration::Height(10)).unwrap();
+        let info = mock_info(ADDR1, &[]);
+        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, Uint128::new(100)).unwrap();
+        let locks: LocksResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &staking_addr,
+                &QueryMsg::Locks {
+                    address: ADDR1.to_string(),
+                },
+            )
+            .unwrap();
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR2),
-            Uint128::from(50u128)
-        );
-        assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(150u128)
+            locks.locks,
+            vec![Lock {
+                shares: Uint128::new(100),
+                multiplier: Decimal::percent(150),
+                expires: AtHeight(expires),
+            }]
         );
+        app.update_block(next_block);
         assert_eq!(
-            query_staked_value(&app, &staking_addr, ADDR2.to_string()),
-            Uint128::from(100u128)
+            query_staked_balance(&app, &staking_addr, ADDR1),
+            Uint128::new(200)
         );
+        assert_eq!(query_power(&app), (Uint128::new(250), Uint128::new(250)));
         assert_eq!(
-            query_total_value(&app, &staking_addr),
-            Uint128::from(300u128)
+            query_voting_power(&app, &staking_addr, ADDR1, None),
+            Uint128::new(250)
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::zero());
-
-        // Can't unstake more than you have staked
-        let info = mock_info(ADDR2, &[]);
-        let _err = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(51)).unwrap_err();
 
-        // Add compounding rewards
-        let msg = cw20::Cw20ExecuteMsg::Send {
-            contract: staking_addr.to_string(),
-            amount: Uint128::from(90u128),
-            msg: to_binary(&ReceiveMsg::Fund {}).unwrap(),
-        };
-        let _res = app
-            .borrow_mut()
-            .execute_contract(Addr::unchecked(ADDR1), cw20_addr.clone(), &msg, &[])
+        // Locked stake can't be unstaked before the lock expires
+        let info = mock_info(ADDR1, &[]);
+        let err: ContractError = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(150))
+            .unwrap_err()
+            .downcast()
             .unwrap();
+        assert_eq!(
+            err,
+            ContractError::StakeLocked {
+                unlocked: Uint128::new(100)
+            }
+        );
+        let info = mock_info(ADDR1, &[]);
+        unstake_tokens(&mut app, &staking_addr, info, Uint128::new(100)).unwrap();
 
+        // The boosted power is delegated
+        delegate(&mut app, &staking_addr, mock_info(ADDR1, &[]), ADDR2).unwrap();
+        app.update_block(next_block);
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(100u128)
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::new(150)
         );
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR2),
-            Uint128::from(50u128)
+            query_sqrt_power(&app, &staking_addr, None),
+            Uint128::new(12)
         );
+
+        // Expired locks no longer boost voting power, before anything
+        // releases them
+        app.update_block(|block| block.height = expires);
+        assert_eq!(query_power(&app), (Uint128::new(100), Uint128::new(100)));
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(150u128)
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::new(100)
         );
         assert_eq!(
-            query_staked_value(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(260u128)
+            query_sqrt_power(&app, &staking_addr, None),
+            Uint128::new(10)
         );
+
+        // Unstaking releases them, which keeps the power at past heights
+        let info = mock_info(ADDR1, &[]);
+        unstake_tokens(&mut app, &staking_addr, info, Uint128::new(100)).unwrap();
+        app.update_block(next_block);
         assert_eq!(
-            query_staked_value(&app, &staking_addr, ADDR2.to_string()),
-            Uint128::from(130u128)
+            query_voting_power(&app, &staking_addr, ADDR2, Some(expires - 1)),
+            Uint128::new(150)
         );
         assert_eq!(
-            query_total_value(&app, &staking_addr),
-            Uint128::from(390u128)
+            query_voting_power(&app, &staking_addr, ADDR2, Some(expires)),
+            Uint128::new(100)
         );
         assert_eq!(
-            get_balance(&app, &cw20_addr, ADDR1.to_string()),
-            Uint128::from(610u128)
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::zero()
         );
-
-        // Successful unstake
-        let info = mock_info(ADDR2, &[]);
-        let _res = unstake_tokens(&mut app, &staking_addr, info, Uint128::new(25)).unwrap();
-        app.update_block(next_block);
-
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR2),
-            Uint128::from(25u128)
+            query_sqrt_power(&app, &staking_addr, Some(expires - 1)),
+            Uint128::new(12)
         );
         assert_eq!(
-            query_total_staked(&app, &staking_addr),
-            Uint128::from(125u128)
+            query_sqrt_power(&app, &staking_addr, Some(expires)),
+            Uint128::new(10)
         );
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(65u128));
+        assert_eq!(query_sqrt_power(&app, &staking_addr, None), Uint128::zero());
+        let locks: LocksResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &staking_addr,
+                &QueryMsg::Locks {
+                    address: ADDR1.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(locks.locks, vec![]);
     }
 
     #[test]
-    fn test_simple_unstaking_with_duration() {
-        let _deps = mock_dependencies();
-
+    fn test_release_locks() {
         let mut app = mock_app();
-        let amount1 = Uint128::from(100u128);
-        let _token_address = Addr::unchecked("token_address");
         let initial_balances = vec![
             Cw20Coin {
                 address: ADDR1.to_string(),
-                amount: amount1,
+                amount: Uint128::new(1000),
             },
             Cw20Coin {
                 address: ADDR2.to_string(),
-                amount: amount1,
+                amount: Uint128::new(1000),
             },
         ];
-        let (staking_addr, cw20_addr) =
-            setup_test_case(&mut app, initial_balances, Some(Duration::Height(1)));
+        let (staking_addr, cw20_addr) = setup_test_case(&mut app, initial_balances, None);
+        app.execute_contract(
+            Addr::unchecked("owner"),
+            staking_addr.clone(),
+            &ExecuteMsg::UpdateLockupTiers {
+                tiers: vec![LockupTier {
+                    duration: Duration::Height(10),
+                    multiplier: Decimal::percent(200),
+                }],
+            },
+            &[],
+        )
+        .unwrap();
+        let stake_locked = |app: &mut App, staker: &str| {
+            let msg = cw20::Cw20ExecuteMsg::Send {
+                contract: staking_addr.to_string(),
+                amount: Uint128::new(1),
+                msg: to_binary(&ReceiveMsg::StakeLocked {
+                    duration: Duration::Height(10),
+                })
+                .unwrap(),
+            };
+            app.execute_contract(Addr::unchecked(staker), cw20_addr.clone(), &msg, &[])
+                .unwrap();
+        };
+        let release_locks = |app: &mut App, limit: Option<u32>| {
+            app.execute_contract(
+                Addr::unchecked(ADDR3),
+                staking_addr.clone(),
+                &ExecuteMsg::ReleaseLocks { limit },
+                &[],
+            )
+            .unwrap();
+        };
+        let query_total_power = |app: &App| -> StdResult<Uint128> {
+            let total: TotalPowerAtHeightResponse = app.wrap().query_wasm_smart(
+                &staking_addr,
+                &QueryMsg::TotalPowerAtHeight { height: None },
+            )?;
+            Ok(total.power)
+        };
 
-        // Bond Address 1
-        let info = mock_info(ADDR1, &[]);
-        let _env = mock_env();
-        let amount = Uint128::new(100);
-        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount).unwrap();
+        // One more lock than queries subtract expires, each at its own height
+        for _ in 0..MAX_LOCKS {
+            stake_locked(&mut app, ADDR1);
+            app.update_block(next_block);
+        }
+        stake_locked(&mut app, ADDR2);
+        app.update_block(|block| block.height += 10);
+        query_total_power(&app).unwrap_err();
 
-        // Bond Address 2
-        let info = mock_info(ADDR2, &[]);
-        let _env = mock_env();
-        let amount = Uint128::new(100);
-        stake_tokens(&mut app, &staking_addr, &cw20_addr, info, amount).unwrap();
+        // Releasing part of them brings the rest within the limit, locks
+        // of a released staker are no longer subtracted
+        release_locks(&mut app, Some(50));
         app.update_block(next_block);
+        assert_eq!(query_total_power(&app).unwrap(), Uint128::new(101));
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(100u128)
+            query_voting_power(&app, &staking_addr, ADDR1, None),
+            Uint128::new(100)
         );
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(100u128)
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::new(1)
         );
 
-        // Unstake Addr1
-        let info = mock_info(ADDR1, &[]);
-        let _env = mock_env();
-        let amount = Uint128::new(100);
-        unstake_tokens(&mut app, &staking_addr, info, amount).unwrap();
-
-        // Unstake Addr2
-        let info = mock_info(ADDR2, &[]);
-        let _env = mock_env();
-        let amount = Uint128::new(100);
-        unstake_tokens(&mut app, &staking_addr, info, amount).unwrap();
-
+        // The next page releases the remaining stakers
+        release_locks(&mut app, None);
         app.update_block(next_block);
-
+        assert_eq!(query_total_power(&app).unwrap(), Uint128::new(101));
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR1.to_string()),
-            Uint128::from(0u128)
+            query_voting_power(&app, &staking_addr, ADDR2, None),
+            Uint128::new(1)
         );
+        let err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ADDR3),
+                staking_addr.clone(),
+                &ExecuteMsg::ReleaseLocks {
+                    limit: Some(MAX_RELEASED_LOCKS + 1),
+                },
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert!(matches!(err, ContractError::Std(_)));
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut deps = mock_dependencies();
+
+        // Only the same contract type can be migrated
+        set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", "0.11.1").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
         assert_eq!(
-            query_staked_balance(&app, &staking_addr, ADDR2.to_string()),
-            Uint128::from(0u128)
+            err,
+            ContractError::Migrate(MigrateError::CannotMigrate {
+                previous_contract: "crates.io:cw20-base".to_string()
+            })
         );
 
-        // Claim
+        // Downgrades are refused
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
         assert_eq!(
-            query_claims(&app, &staking_addr, ADDR1),
-            vec![Claim {
-                amount: Uint128::new(100),
-                release_at: AtHeight(12349)
-            }]
+            err,
+            ContractError::Migrate(MigrateError::CannotMigrateVersion {
+                previous_version: "99.0.0".to_string(),
+                new_version: CONTRACT_VERSION.to_string(),
+            })
         );
+
+        // Upgrading migrates the config and stores the new version
+        migrations::v0_2::CONFIG
+            .save(
+                deps.as_mut().storage,
+                &migrations::v0_2::Config {
+                    admin: Some(Addr::unchecked("owner")),
+                    token_address: Addr::unchecked("token"),
+                    unstaking_duration: Some(Duration::Height(10)),
+                },
+            )
+            .unwrap();
+        for (addr, staked) in [(ADDR1, 100), (ADDR2, 0), (ADDR3, 50)] {
+            Snapshots::new(Strategy::EveryBlock)
+                .staked_balances
+                .save(
+                    deps.as_mut().storage,
+                    &Addr::unchecked(addr),
+                    &Uint128::new(staked),
+                    1,
+                )
+                .unwrap();
+        }
+        Snapshots::new(Strategy::EveryBlock)
+            .staked_total
+            .save(deps.as_mut().storage, &Uint128::new(150), 1)
+            .unwrap();
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        let version = get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, CONTRACT_VERSION);
+        // The stakers are counted and the square roots of their power summed
+        assert_eq!(query_staker_count(deps.as_ref()).unwrap().count, 2);
+        let mut next_block = mock_env();
+        next_block.block.height += 1;
+        let sqrt_power =
+            query_total_sqrt_power_at_height(deps.as_ref(), next_block.clone(), None).unwrap();
+        assert_eq!(sqrt_power.power, Uint128::new(10 + 7));
+
+        // Voting power is the staked balance, also before the migration
+        for height in [None, Some(5)] {
+            let power =
+                query_staker_power_at_height(deps.as_ref(), mock_env(), ADDR1.to_string(), height)
+                    .unwrap();
+            assert_eq!(power.power, Uint128::new(100));
+            let total = query_total_power_at_height(deps.as_ref(), mock_env(), height).unwrap();
+            assert_eq!(total.power, Uint128::new(150));
+        }
         assert_eq!(
-            query_claims(&app, &staking_addr, ADDR2),
-            vec![Claim {
-                amount: Uint128::new(100),
-                release_at: AtHeight(12349)
-            }]
+            CONFIG.load(deps.as_ref().storage).unwrap(),
+            Config {
+                admin: Some(Addr::unchecked("owner")),
+                token: Denom::Cw20(Addr::unchecked("token")),
+                unstaking_duration: Some(Duration::Height(10)),
+                slash_destination: None,
+                snapshot_strategy: SnapshotStrategy::EveryBlock {},
+                lockup_tiers: vec![],
+            }
         );
 
-        let info = mock_info(ADDR1, &[]);
-        claim_tokens(&mut app, &staking_addr, info).unwrap();
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR1), Uint128::from(100u128));
-
-        let info = mock_info(ADDR2, &[]);
-        claim_tokens(&mut app, &staking_addr, info).unwrap();
-        assert_eq!(get_balance(&app, &cw20_addr, ADDR2), Uint128::from(100u128));
+        // Staking more adds to the staked balance the power falls back to
+        execute_stake(
+            deps.as_mut(),
+            mock_env(),
+            &Addr::unchecked(ADDR1),
+            Uint128::new(21),
+        )
+        .unwrap();
+        let power = query_staker_power_at_height(
+            deps.as_ref(),
+            next_block.clone(),
+            ADDR1.to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(power.power, Uint128::new(121));
+        let total = query_total_power_at_height(deps.as_ref(), next_block.clone(), None).unwrap();
+        assert_eq!(total.power, Uint128::new(171));
+        let sqrt_power = query_total_sqrt_power_at_height(deps.as_ref(), next_block, None).unwrap();
+        assert_eq!(sqrt_power.power, Uint128::new(11 + 7));
     }
 }
@@ -1,4 +1,7 @@
-use cosmwasm_std::{Addr, StdError};
+use cosmwasm_std::{Addr, StdError, Uint128};
+use cw_controllers::HookError;
+use cw_migrate::MigrateError;
+use cw_utils::PaymentError;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -6,15 +9,57 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
     #[error("{0}")]
+    Migrate(#[from] MigrateError),
+    #[error("{0}")]
     Cw20Error(#[from] cw20_base::ContractError),
+    #[error("{0}")]
+    Hook(#[from] HookError),
     #[error("Nothing to claim")]
     NothingToClaim {},
+    #[error("No claim released at that time")]
+    ClaimNotFound {},
+    #[error("{0}")]
+    Payment(#[from] PaymentError),
+    #[error("Exactly one of token_address and native_denom must be set")]
+    InvalidTokenConfig {},
+    #[error("Only {denom} can be staked")]
+    WrongStakingToken { denom: String },
     #[error("Invalid token")]
     InvalidToken { received: Addr, expected: Addr },
     #[error("Unauthorized")]
     Unauthorized { received: Addr, expected: Addr },
     #[error("Too many outstanding claims. Claim some tokens before unstaking more.")]
     TooManyClaims {},
+    #[error("Slash percentage must be between 0 and 1")]
+    InvalidSlashPercentage {},
+    #[error("Nothing to slash")]
+    NothingToSlash {},
     #[error("No admin configured")]
     NoAdminConfigured {},
+    #[error("Cannot delegate voting power to yourself")]
+    SelfDelegation {},
+    #[error("Voting power is not delegated")]
+    NotDelegating {},
+    #[error("Not a reward token: {denom}")]
+    UnknownRewardToken { denom: String },
+    #[error("Rewards must be streamed over at least one block")]
+    InvalidRewardDuration {},
+    #[error("No funds sent")]
+    NoFunds {},
+    #[error("Request size ({size}) is above limit of ({max})")]
+    OversizedRequest { size: u64, max: u64 },
+    #[error("Checkpoints are only used by the selected snapshot strategy")]
+    CheckpointsNotUsed {},
+    #[error("No lockup tier with this duration")]
+    UnknownLockupTier {},
+    #[error("Lockup tiers need distinct durations in blocks and a multiplier of at least one")]
+    InvalidLockupTiers {},
+    #[error("Too many outstanding locks, wait for some to expire")]
+    TooManyLocks {},
+    #[error("More than {max} expired locks are left to release, release them with ReleaseLocks")]
+    TooManyExpiredLocks { max: u32 },
+    #[error("Only {unlocked} staked tokens are unlocked")]
+    StakeLocked { unlocked: Uint128 },
+    #[error("Unknown reply id: {id}")]
+    UnknownReplyId { id: u64 },
 }
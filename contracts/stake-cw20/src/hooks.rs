@@ -0,0 +1,46 @@
+use cosmwasm_std::{to_binary, Addr, Binary, Deps, StdResult, SubMsg, Uint128};
+use cw3_hooks::hook_msgs;
+pub use cw3_hooks::remove_failed_hook;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::state::HOOKS;
+
+// The index of the hook is added to this to find it again if it fails
+pub const STAKE_CHANGED_HOOK_REPLY_ID_OFFSET: u64 = 1 << 32;
+
+/// StakeChangedHookMsg should be de/serialized under `StakeChangeHook()` variant in a ExecuteMsg.
+/// `old` and `new` are the staked balances of `addr` before and after the change.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct StakeChangedHookMsg {
+    pub addr: Addr,
+    pub old: Uint128,
+    pub new: Uint128,
+}
+
+impl StakeChangedHookMsg {
+    /// serializes the message
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = StakeChangedExecuteMsg::StakeChangeHook(self);
+        to_binary(&msg)
+    }
+}
+
+// This is just a helper to properly serialize the above message
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+enum StakeChangedExecuteMsg {
+    StakeChangeHook(StakeChangedHookMsg),
+}
+
+/// Prepares a `StakeChangedHookMsg` for every registered hook
+pub fn stake_changed_hook_msgs(
+    deps: Deps,
+    addr: Addr,
+    old: Uint128,
+    new: Uint128,
+) -> StdResult<Vec<SubMsg>> {
+    let msg = StakeChangedHookMsg { addr, old, new }.into_binary()?;
+    hook_msgs(deps, &HOOKS, msg, STAKE_CHANGED_HOOK_REPLY_ID_OFFSET)
+}
@@ -0,0 +1,65 @@
+use crate::msg::SnapshotStrategy;
+use crate::state::{isqrt, Config, Snapshots, CONFIG, STAKER_COUNT, UNRECORDED_POWER};
+use cosmwasm_std::{Empty, Order, StdResult, Storage, Uint128};
+use cw20::Denom;
+use cw_storage_plus::Strategy;
+
+/// Storage layout of stake-cw20 v0.2.x
+pub(crate) mod v0_2 {
+    use cosmwasm_std::Addr;
+    use cw_storage_plus::Item;
+    use cw_utils::Duration;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+    pub struct Config {
+        pub admin: Option<Addr>,
+        pub token_address: Addr,
+        pub unstaking_duration: Option<Duration>,
+    }
+
+    pub const CONFIG: Item<Config> = Item::new("config");
+}
+
+/// Migrates storage written by v0.2.x, before native denoms could be staked
+/// and before voting power was tracked. The stakers are only read, to count
+/// them and sum the square roots of their power. Their power is not written,
+/// it falls back to their staked balance until their stake changes.
+pub fn migrate_v0_2(storage: &mut dyn Storage, height: u64) -> StdResult<()> {
+    let config = v0_2::CONFIG.load(storage)?;
+    CONFIG.save(
+        storage,
+        &Config {
+            admin: config.admin,
+            token: Denom::Cw20(config.token_address),
+            unstaking_duration: config.unstaking_duration,
+            slash_destination: None,
+            snapshot_strategy: SnapshotStrategy::EveryBlock {},
+            lockup_tiers: vec![],
+        },
+    )?;
+
+    // Without locks or delegations the voting power of a stake is its
+    // staked balance
+    let snapshots = Snapshots::new(Strategy::EveryBlock);
+    let mut count = 0u64;
+    let mut sqrt_power = Uint128::zero();
+    for item in snapshots
+        .staked_balances
+        .range(storage, None, None, Order::Ascending)
+    {
+        let (_, staked) = item?;
+        if !staked.is_zero() {
+            count += 1;
+            sqrt_power = sqrt_power.checked_add(isqrt(staked))?;
+        }
+    }
+    STAKER_COUNT.save(storage, &count)?;
+    snapshots.sqrt_power.save(storage, &sqrt_power, height)?;
+    let total = snapshots
+        .staked_total
+        .may_load(storage)?
+        .unwrap_or_default();
+    snapshots.total_power.save(storage, &total, height)?;
+    UNRECORDED_POWER.save(storage, &Empty {})
+}
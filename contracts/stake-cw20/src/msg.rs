@@ -1,37 +1,174 @@
-use cosmwasm_std::{Addr, Uint128};
-use cw20::Cw20ReceiveMsg;
+use cosmwasm_std::{Addr, Decimal, Uint128};
+use cw20::{Cw20ReceiveMsg, Denom};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cw_utils::Duration;
+use cw_storage_plus::Strategy;
+
+use crate::state::Lock;
+use cw_utils::{Duration, Expiration};
 
 pub use cw_controllers::ClaimsResponse;
 
 #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
 pub struct InstantiateMsg {
     pub admin: Option<String>,
-    pub token_address: String,
+    /// The staked cw20 token, either this or `native_denom` must be set
+    pub token_address: Option<String>,
+    /// The staked native denom, staked by sending it with `Stake`
+    pub native_denom: Option<String>,
     pub unstaking_duration: Option<Duration>,
+    pub slash_destination: Option<String>,
+    /// Defaults to `EveryBlock`
+    pub snapshot_strategy: Option<SnapshotStrategy>,
+    /// Lock periods stakers can choose, none by default
+    pub lockup_tiers: Option<Vec<LockupTier>>,
+}
+
+/// Heights at which staked balances are snapshotted
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotStrategy {
+    /// Balances can be queried at any past height
+    EveryBlock {},
+    /// Balances can only be queried at past heights registered with
+    /// `AddCheckpoint`, which keeps the changelog small
+    Selected {},
+}
+
+impl Default for SnapshotStrategy {
+    fn default() -> Self {
+        SnapshotStrategy::EveryBlock {}
+    }
+}
+
+/// A lock period stakers can choose and the multiplier it applies to the
+/// voting power of the locked stake. The period is counted in blocks, so
+/// that voting power at past heights reflects which locks had expired.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LockupTier {
+    pub duration: Duration,
+    pub multiplier: Decimal,
+}
+
+impl From<SnapshotStrategy> for Strategy {
+    fn from(strategy: SnapshotStrategy) -> Self {
+        match strategy {
+            SnapshotStrategy::EveryBlock {} => Strategy::EveryBlock,
+            SnapshotStrategy::Selected {} => Strategy::Selected,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     Receive(Cw20ReceiveMsg),
+    /// Stakes the sent native denom
+    Stake {},
+    /// Stakes the sent native denom, locked for the lockup tier with the
+    /// given duration
+    StakeLocked {
+        duration: Duration,
+    },
+    /// Adds the sent native denom to the staked balance without minting
+    /// shares, raising the value of every share
+    Fund {},
     Unstake {
         amount: Uint128,
     },
+    /// Sends the tokens of every released claim
     Claim {},
+    /// Sends the tokens of the claim released at `release_at`
+    ClaimAt {
+        release_at: Expiration,
+    },
+    /// Sends released claims up to `amount` tokens, the last claim paid out
+    /// may be paid partially
+    ClaimAmount {
+        amount: Uint128,
+    },
+    /// Stakes the tokens of an outstanding claim again at the current share
+    /// price, or of all claims if `release_at` is not set
+    Restake {
+        release_at: Option<Expiration>,
+    },
+    /// Delegates the sender's voting power to another address. Delegated
+    /// power is not passed on if the delegate delegates in turn.
+    Delegate {
+        address: String,
+    },
+    Undelegate {},
+    /// Funds the streams of native reward tokens with the sent coins
+    FundRewards {},
+    /// Sends the sender's pending rewards of every reward token
+    ClaimRewards {},
+    /// Adds a reward token, or changes how many blocks its future fundings
+    /// are streamed over (can only be called by the admin)
+    AddRewardToken {
+        denom: Denom,
+        reward_duration: u64,
+    },
     UpdateConfig {
         admin: Option<String>,
         duration: Option<Duration>,
+        slash_destination: Option<String>,
+    },
+    /// Slashes `percentage` of the staked balance and of the outstanding
+    /// claims of `addr`, sending the slashed tokens to the slash
+    /// destination (can only be called by the admin)
+    Slash {
+        addr: String,
+        percentage: Decimal,
+    },
+    /// Registers a contract to receive a `StakeChangedHookMsg` whenever a
+    /// staked balance changes (can only be called by the admin)
+    AddHook {
+        addr: String,
+    },
+    /// Unregisters a hook contract (can only be called by the admin)
+    RemoveHook {
+        addr: String,
+    },
+    /// Checkpoints staked balances at the current height under the
+    /// `Selected` snapshot strategy (can only be called by the admin)
+    AddCheckpoint {},
+    /// Removes the checkpoints of heights before `before`. Balances can no
+    /// longer be queried at those heights. The history of at most `limit`
+    /// addresses after `start_after` is deleted, the response's
+    /// `next_start_after` attribute is set when more may be left (can only
+    /// be called by the admin)
+    PruneCheckpoints {
+        before: u64,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Replaces the lockup tiers. Existing locks keep their multiplier
+    /// (can only be called by the admin)
+    UpdateLockupTiers {
+        tiers: Vec<LockupTier>,
+    },
+    /// Releases the expired locks of the stakers holding the next `limit`
+    /// lock expiries (at most 100). Locks are otherwise released when their
+    /// staker next stakes or unstakes, voting power queries fail once more
+    /// than 100 expired ones are left.
+    ReleaseLocks {
+        limit: Option<u32>,
     },
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ReceiveMsg {
     Stake {},
+    /// Stakes the sent tokens, locked for the lockup tier with the given
+    /// duration
+    StakeLocked {
+        duration: Duration,
+    },
     Fund {},
+    /// Funds the stream of a cw20 reward token
+    FundRewards {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -52,8 +189,75 @@ pub enum QueryMsg {
     Claims {
         address: String,
     },
+    /// Voting power of the stake of an address, unless delegated, plus
+    /// the power delegated to it
+    VotingPowerAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    DelegatedPowerAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    DelegationAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    /// Rewards of every reward token an address can claim
+    PendingRewards {
+        address: String,
+    },
+    /// Returns HooksResponse
+    GetHooks {},
+    /// Addresses that staked, their balance and its value. Those that
+    /// unstaked everything are listed with a zero balance.
+    ListStakers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Addresses that staked, their balance and its value at `height`.
+    /// Those without a stake at that height are listed with a zero balance.
+    ListStakersAtHeight {
+        height: Option<u64>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Number of addresses with a staked balance
+    StakerCount {},
+    /// Value of the staked balance of an address at `height`
+    StakedValueAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    /// Tokens backing each staked share at `height`
+    ExchangeRateAtHeight {
+        height: Option<u64>,
+    },
+    /// Outstanding locks of an address
+    Locks {
+        address: String,
+    },
+    /// Voting power of the stake of an address at `height`, its staked
+    /// balance plus the boost of its locks, whether delegated or not
+    StakerPowerAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    /// Voting power of all stakes at `height`
+    TotalPowerAtHeight {
+        height: Option<u64>,
+    },
+    /// Sum of the square roots of the voting power of every address at
+    /// `height`, as returned by `VotingPowerAtHeight`
+    TotalSqrtPowerAtHeight {
+        height: Option<u64>,
+    },
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrateMsg {}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct StakedBalanceAtHeightResponse {
@@ -84,6 +288,121 @@ pub struct TotalValueResponse {
 #[serde(rename_all = "snake_case")]
 pub struct GetConfigResponse {
     pub admin: Option<Addr>,
-    pub token_address: Addr,
+    pub token: Denom,
     pub unstaking_duration: Option<Duration>,
+    pub slash_destination: Option<Addr>,
+    #[serde(default)]
+    pub snapshot_strategy: SnapshotStrategy,
+    #[serde(default)]
+    pub lockup_tiers: Vec<LockupTier>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct VotingPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DelegatedPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DelegationAtHeightResponse {
+    pub delegate: Option<Addr>,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingReward {
+    pub denom: Denom,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingRewardsResponse {
+    pub rewards: Vec<PendingReward>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct HooksResponse {
+    pub hooks: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StakerBalance {
+    pub address: Addr,
+    pub balance: Uint128,
+    pub value: Uint128,
+    /// Voting power of the stake, the balance plus the boost of its locks
+    pub power: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ListStakersResponse {
+    pub stakers: Vec<StakerBalance>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ListStakersAtHeightResponse {
+    pub stakers: Vec<StakerBalance>,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StakerCountResponse {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StakedValueAtHeightResponse {
+    pub value: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ExchangeRateAtHeightResponse {
+    pub rate: Decimal,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LocksResponse {
+    pub locks: Vec<Lock>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StakerPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct TotalPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct TotalSqrtPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
 }
@@ -1,37 +1,447 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Uint128};
-use cw_controllers::Claims;
-use cw_storage_plus::{Item, SnapshotItem, SnapshotMap, Strategy};
-use cw_utils::Duration;
+use cosmwasm_std::{Addr, BlockInfo, Decimal, Empty, Order, StdResult, Storage, Uint128};
+use cw20::Denom;
+use cw_controllers::{Claim, Hooks};
+use cw_storage_plus::{Bound, Item, Map, PrimaryKey, SnapshotItem, SnapshotMap, Strategy};
+use cw_utils::{Duration, Expiration};
+
+use crate::msg::{LockupTier, SnapshotStrategy};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct Config {
     pub admin: Option<Addr>,
-    pub token_address: Addr,
+    /// The staked cw20 token or native denom
+    pub token: Denom,
     pub unstaking_duration: Option<Duration>,
+    /// Receives slashed tokens, slashed tokens are burned if not set
+    pub slash_destination: Option<Addr>,
+    /// Which heights staked balances are snapshotted at
+    pub snapshot_strategy: SnapshotStrategy,
+    /// Lock periods stakers can choose when staking
+    pub lockup_tiers: Vec<LockupTier>,
 }
 
 pub const CONFIG: Item<Config> = Item::new("config");
 
-pub const STAKED_BALANCES: SnapshotMap<&Addr, Uint128> = SnapshotMap::new(
-    "staked_balances",
-    "staked_balance__checkpoints",
-    "staked_balance__changelog",
-    Strategy::EveryBlock,
-);
-
-pub const STAKED_TOTAL: SnapshotItem<Uint128> = SnapshotItem::new(
-    "total_staked",
-    "total_staked__checkpoints",
-    "total_staked__changelog",
-    Strategy::EveryBlock,
-);
-
 /// The maximum number of claims that may be outstanding.
 pub const MAX_CLAIMS: u64 = 100;
 
-pub const CLAIMS: Claims = Claims::new("claims");
+/// Outstanding claims of each staker, at most one per release time
+pub const CLAIMS: Map<&Addr, Vec<Claim>> = Map::new("claims");
+
+const STAKED_BALANCES_CHANGELOG: &str = "staked_balance__changelog";
+const STAKED_TOTAL_CHANGELOG: &str = "total_staked__changelog";
+const DELEGATIONS_CHANGELOG: &str = "delegations__changelog";
+const DELEGATED_POWER_CHANGELOG: &str = "delegated_power__changelog";
+const BALANCE_CHANGELOG: &str = "balance__changelog";
+const POWER_CHANGELOG: &str = "power__changelog";
+const TOTAL_POWER_CHANGELOG: &str = "total_power__changelog";
+const LOCKS_RELEASED_CHANGELOG: &str = "locks_released__changelog";
+const STAKER_LOCKS_RELEASED_CHANGELOG: &str = "staker_locks_released__changelog";
+const SQRT_POWER_CHANGELOG: &str = "sqrt_power__changelog";
+
+/// Snapshotted staking state. The strategy decides at which heights changes
+/// are recorded, the storage layout is the same for every strategy.
+pub struct Snapshots<'a> {
+    pub staked_balances: SnapshotMap<'a, &'a Addr, Uint128>,
+    pub staked_total: SnapshotItem<'a, Uint128>,
+    /// The address each staker has delegated their voting power to
+    pub delegations: SnapshotMap<'a, &'a Addr, Addr>,
+    /// The total staked balance delegated to each address
+    pub delegated_power: SnapshotMap<'a, &'a Addr, Uint128>,
+    /// Tokens backing the staked shares
+    pub balance: SnapshotItem<'a, Uint128>,
+    /// Voting power of each staker's own stake, their staked balance plus
+    /// the boost of their locks
+    pub power: SnapshotMap<'a, &'a Addr, Uint128>,
+    pub total_power: SnapshotItem<'a, Uint128>,
+    /// Height up to which `ReleaseLocks` released the expired locks of every
+    /// staker. The boosts of locks expiring after it are still part of the
+    /// voting power snapshots unless their staker released them since.
+    pub locks_released: SnapshotItem<'a, u64>,
+    /// Height up to which the expired locks of each staker were released
+    pub staker_locks_released: SnapshotMap<'a, &'a Addr, u64>,
+    /// Sum of the square roots of the voting power of every address, the
+    /// power delegated to an address counting towards it
+    pub sqrt_power: SnapshotItem<'a, Uint128>,
+}
+
+impl<'a> Snapshots<'a> {
+    pub const fn new(strategy: Strategy) -> Self {
+        Snapshots {
+            staked_balances: SnapshotMap::new(
+                "staked_balances",
+                "staked_balance__checkpoints",
+                STAKED_BALANCES_CHANGELOG,
+                strategy,
+            ),
+            staked_total: SnapshotItem::new(
+                "total_staked",
+                "total_staked__checkpoints",
+                STAKED_TOTAL_CHANGELOG,
+                strategy,
+            ),
+            delegations: SnapshotMap::new(
+                "delegations",
+                "delegations__checkpoints",
+                DELEGATIONS_CHANGELOG,
+                strategy,
+            ),
+            delegated_power: SnapshotMap::new(
+                "delegated_power",
+                "delegated_power__checkpoints",
+                DELEGATED_POWER_CHANGELOG,
+                strategy,
+            ),
+            balance: SnapshotItem::new(
+                "balance",
+                "balance__checkpoints",
+                BALANCE_CHANGELOG,
+                strategy,
+            ),
+            power: SnapshotMap::new("power", "power__checkpoints", POWER_CHANGELOG, strategy),
+            total_power: SnapshotItem::new(
+                "total_power",
+                "total_power__checkpoints",
+                TOTAL_POWER_CHANGELOG,
+                strategy,
+            ),
+            locks_released: SnapshotItem::new(
+                "locks_released",
+                "locks_released__checkpoints",
+                LOCKS_RELEASED_CHANGELOG,
+                strategy,
+            ),
+            staker_locks_released: SnapshotMap::new(
+                "staker_locks_released",
+                "staker_locks_released__checkpoints",
+                STAKER_LOCKS_RELEASED_CHANGELOG,
+                strategy,
+            ),
+            sqrt_power: SnapshotItem::new(
+                "sqrt_power",
+                "sqrt_power__checkpoints",
+                SQRT_POWER_CHANGELOG,
+                strategy,
+            ),
+        }
+    }
+
+    /// Snapshots using the configured strategy
+    pub fn load(storage: &dyn Storage) -> StdResult<Self> {
+        let config = CONFIG.load(storage)?;
+        Ok(Self::new(config.snapshot_strategy.into()))
+    }
+
+    /// Snapshots to read at `height`. The current state is used from the
+    /// current block on, so no checkpoint is needed for those heights.
+    pub fn at_height(storage: &dyn Storage, block: &BlockInfo, height: u64) -> StdResult<Self> {
+        if height >= block.height {
+            Ok(Self::new(Strategy::EveryBlock))
+        } else {
+            Self::load(storage)
+        }
+    }
+
+    pub fn add_checkpoint(&self, storage: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.staked_balances.add_checkpoint(storage, height)?;
+        self.staked_total.add_checkpoint(storage, height)?;
+        self.delegations.add_checkpoint(storage, height)?;
+        self.delegated_power.add_checkpoint(storage, height)?;
+        self.balance.add_checkpoint(storage, height)?;
+        self.power.add_checkpoint(storage, height)?;
+        self.total_power.add_checkpoint(storage, height)?;
+        self.locks_released.add_checkpoint(storage, height)?;
+        self.staker_locks_released.add_checkpoint(storage, height)?;
+        self.sqrt_power.add_checkpoint(storage, height)
+    }
+
+    pub fn remove_checkpoint(&self, storage: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.staked_balances.remove_checkpoint(storage, height)?;
+        self.staked_total.remove_checkpoint(storage, height)?;
+        self.delegations.remove_checkpoint(storage, height)?;
+        self.delegated_power.remove_checkpoint(storage, height)?;
+        self.balance.remove_checkpoint(storage, height)?;
+        self.power.remove_checkpoint(storage, height)?;
+        self.total_power.remove_checkpoint(storage, height)?;
+        self.locks_released.remove_checkpoint(storage, height)?;
+        self.staker_locks_released
+            .remove_checkpoint(storage, height)?;
+        self.sqrt_power.remove_checkpoint(storage, height)
+    }
+
+    /// Deletes the changes recorded before `height` for the items and for up
+    /// to `limit` addresses after `start_after`. They are only read by queries
+    /// at earlier heights, which fail once their checkpoints are removed.
+    /// Returns the number of changes deleted and, if `limit` addresses were
+    /// visited, the last of them to continue after.
+    pub fn prune_changelogs(
+        storage: &mut dyn Storage,
+        height: u64,
+        start_after: Option<Addr>,
+        limit: usize,
+    ) -> StdResult<(u64, Option<Addr>)> {
+        let mut pruned = 0;
+        // Only the keys of the changelogs are read, so their values are not
+        // deserialized
+        for namespace in [
+            STAKED_TOTAL_CHANGELOG,
+            BALANCE_CHANGELOG,
+            TOTAL_POWER_CHANGELOG,
+            LOCKS_RELEASED_CHANGELOG,
+            SQRT_POWER_CHANGELOG,
+        ] {
+            let changelog: Map<((), u64), Empty> = Map::new(namespace);
+            let heights = changelog
+                .prefix(())
+                .keys(
+                    storage,
+                    None,
+                    Some(Bound::exclusive_int(height)),
+                    Order::Ascending,
+                )
+                .collect::<StdResult<Vec<_>>>()?;
+            for changed in &heights {
+                changelog.remove(storage, ((), *changed));
+            }
+            pruned += heights.len() as u64;
+        }
+
+        let namespaces = [
+            STAKED_BALANCES_CHANGELOG,
+            DELEGATIONS_CHANGELOG,
+            DELEGATED_POWER_CHANGELOG,
+            POWER_CHANGELOG,
+            STAKER_LOCKS_RELEASED_CHANGELOG,
+        ];
+        let mut last = start_after;
+        for _ in 0..limit {
+            // Changes are keyed by address first, the next address is the
+            // first one any changelog has after the last
+            let mut next: Option<Addr> = None;
+            for namespace in namespaces {
+                let changelog: Map<(&Addr, u64), Empty> = Map::new(namespace);
+                let min = last
+                    .as_ref()
+                    .map(|addr| Bound::exclusive((addr, u64::MAX).joined_key()));
+                if let Some(key) = changelog.keys(storage, min, None, Order::Ascending).next() {
+                    let (addr, _) = key?;
+                    if next.as_ref().is_none_or(|next| addr < *next) {
+                        next = Some(addr);
+                    }
+                }
+            }
+            let addr = match next {
+                Some(addr) => addr,
+                None => return Ok((pruned, None)),
+            };
+            for namespace in namespaces {
+                let changelog: Map<(&Addr, u64), Empty> = Map::new(namespace);
+                let heights = changelog
+                    .prefix(&addr)
+                    .keys(
+                        storage,
+                        None,
+                        Some(Bound::exclusive_int(height)),
+                        Order::Ascending,
+                    )
+                    .collect::<StdResult<Vec<_>>>()?;
+                for changed in &heights {
+                    changelog.remove(storage, (&addr, *changed));
+                }
+                pruned += heights.len() as u64;
+            }
+            last = Some(addr);
+        }
+        Ok((pruned, last))
+    }
+}
+
+/// Integer square root, rounded down
+pub fn isqrt(n: Uint128) -> Uint128 {
+    let n = n.u128();
+    if n < 2 {
+        return Uint128::new(n);
+    }
+    // Start at ceil(n / 2), which is at least the root for n >= 2
+    let mut x = n;
+    let mut y = n / 2 + n % 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    Uint128::new(x)
+}
+
+/// Heights checkpointed under the `Selected` snapshot strategy
+pub const CHECKPOINTS: Map<u64, Empty> = Map::new("checkpoints");
+
+/// Number of addresses with a staked balance
+pub const STAKER_COUNT: Item<u64> = Item::new("staker_count");
+
+/// Set by the migration from v0.2, whose stakers have no voting power
+/// recorded until their stake first changes
+pub const UNRECORDED_POWER: Item<Empty> = Item::new("unrecorded_power");
+
+/// Contracts notified when a staked balance changes
+pub const HOOKS: Hooks = Hooks::new("hooks");
+
+/// The maximum number of locks a staker may hold.
+pub const MAX_LOCKS: u64 = 100;
+
+/// Staked shares that can't be unstaked before `expires`. Their voting
+/// power is multiplied by `multiplier` until then.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Lock {
+    pub shares: Uint128,
+    pub multiplier: Decimal,
+    pub expires: Expiration,
+}
+
+impl Lock {
+    /// Voting power the lock adds on top of its shares
+    pub fn boost(&self) -> Uint128 {
+        self.shares * (self.multiplier - Decimal::one())
+    }
+
+    /// Height the lock expires at, lockup tiers are counted in blocks
+    pub fn expires_at_height(&self) -> u64 {
+        match self.expires {
+            Expiration::AtHeight(height) => height,
+            _ => u64::MAX,
+        }
+    }
+}
+
+/// Outstanding locks of each staker
+pub const LOCKS: Map<&Addr, Vec<Lock>> = Map::new("locks");
+
+/// Boost of the locks of each staker, keyed by the height they expire at.
+/// Entries are kept once the locks are released, queries at the heights
+/// before the release subtract them from the voting power snapshots.
+pub const LOCK_EXPIRIES: Map<(u64, &Addr), Uint128> = Map::new("lock_expiries");
+
+/// The most expired locks `ReleaseLocks` releases at once, and the most
+/// unreleased ones a voting power query subtracts
+pub const MAX_RELEASED_LOCKS: u32 = 100;
+
+/// The last LOCK_EXPIRIES entry released by `ReleaseLocks`, when expired
+/// entries were left after it
+pub const RELEASE_CURSOR: Item<(u64, Addr)> = Item::new("release_cursor");
+
+/// A token distributed to stakers. Each funding starts a new stream that
+/// releases the funded amount, plus whatever the previous stream had not
+/// released yet, linearly over `reward_duration` blocks.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct RewardToken {
+    pub denom: Denom,
+    /// Number of blocks a funding is streamed over
+    pub reward_duration: u64,
+    /// Amount streamed by the current stream
+    pub amount: Uint128,
+    pub start_height: u64,
+    pub end_height: u64,
+    /// Part of the current stream already added to the reward index
+    pub distributed: Uint128,
+    /// Rewards per staked token since the reward token was added
+    pub reward_index: Decimal,
+}
+
+impl RewardToken {
+    pub fn new(denom: Denom, reward_duration: u64, height: u64) -> Self {
+        RewardToken {
+            denom,
+            reward_duration,
+            amount: Uint128::zero(),
+            start_height: height,
+            end_height: height,
+            distributed: Uint128::zero(),
+            reward_index: Decimal::zero(),
+        }
+    }
+
+    /// Adds the rewards released up to `height` to the reward index. While
+    /// nothing is staked released rewards are held back for the next stakers.
+    pub fn accrue(&mut self, height: u64, total_staked: Uint128) -> StdResult<()> {
+        if total_staked.is_zero() || self.end_height <= self.start_height {
+            return Ok(());
+        }
+        let elapsed = height
+            .min(self.end_height)
+            .saturating_sub(self.start_height);
+        let released = self
+            .amount
+            .multiply_ratio(elapsed, self.end_height - self.start_height);
+        let new_rewards = released.checked_sub(self.distributed)?;
+        if !new_rewards.is_zero() {
+            self.reward_index = self.reward_index + Decimal::from_ratio(new_rewards, total_staked);
+            self.distributed = released;
+        }
+        Ok(())
+    }
 
-pub const BALANCE: Item<Uint128> = Item::new("balance");
+    /// Starts a new stream at `height`, rewards must be accrued first.
+    pub fn fund(&mut self, height: u64, amount: Uint128) -> StdResult<()> {
+        let remaining = self.amount.checked_sub(self.distributed)?;
+        self.amount = remaining.checked_add(amount)?;
+        self.start_height = height;
+        self.end_height = height + self.reward_duration;
+        self.distributed = Uint128::zero();
+        Ok(())
+    }
+}
+
+/// Rewards of a staker for one reward token
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+pub struct StakerReward {
+    /// Reward index of the token when the staker was last updated
+    pub reward_index: Decimal,
+    /// Rewards accrued but not claimed yet
+    pub pending: Uint128,
+}
+
+impl StakerReward {
+    /// Credits the rewards accrued by `staked` tokens since the last update
+    pub fn accrue(&mut self, reward_index: Decimal, staked: Uint128) -> StdResult<()> {
+        let accrued = (reward_index - self.reward_index) * staked;
+        self.pending = self.pending.checked_add(accrued)?;
+        self.reward_index = reward_index;
+        Ok(())
+    }
+}
+
+/// Storage key of a reward token
+pub fn reward_token_key(denom: &Denom) -> String {
+    match denom {
+        Denom::Cw20(addr) => format!("cw20:{}", addr),
+        Denom::Native(denom) => format!("native:{}", denom),
+    }
+}
+
+pub const REWARD_TOKENS: Map<&str, RewardToken> = Map::new("reward_tokens");
+
+pub const STAKER_REWARDS: Map<(&Addr, &str), StakerReward> = Map::new("staker_rewards");
+
+#[cfg(test)]
+mod tests {
+    use super::isqrt;
+    use cosmwasm_std::Uint128;
+
+    #[test]
+    fn test_isqrt() {
+        for (n, root) in [
+            (0, 0),
+            (1, 1),
+            (2, 1),
+            (3, 1),
+            (4, 2),
+            (8, 2),
+            (9, 3),
+            (u128::MAX, u64::MAX as u128),
+        ] {
+            assert_eq!(isqrt(Uint128::new(n)), Uint128::new(root), "isqrt({})", n);
+        }
+    }
+}
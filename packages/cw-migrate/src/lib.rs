@@ -0,0 +1,58 @@
+use cosmwasm_std::{StdError, Storage};
+use cw2::{get_contract_version, set_contract_version};
+use thiserror::Error;
+
+pub use semver::Version;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MigrateError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Cannot migrate from a different contract type ({previous_contract})")]
+    CannotMigrate { previous_contract: String },
+
+    #[error("Cannot migrate from newer version ({previous_version}) to older ({new_version})")]
+    CannotMigrateVersion {
+        previous_version: String,
+        new_version: String,
+    },
+
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+}
+
+impl From<semver::Error> for MigrateError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
+}
+
+/// Checks the stored cw2 version belongs to `contract_name` and is not newer
+/// than `version`, then stores `version`. Returns the previous version, to
+/// run the storage migrations written since.
+pub fn ensure_upgrade(
+    storage: &mut dyn Storage,
+    contract_name: &str,
+    version: &str,
+) -> Result<Version, MigrateError> {
+    let stored = get_contract_version(storage)?;
+    if stored.contract != contract_name {
+        return Err(MigrateError::CannotMigrate {
+            previous_contract: stored.contract,
+        });
+    }
+
+    // Refuse downgrades, an older version may not understand the current storage layout
+    let previous_version: Version = stored.version.parse()?;
+    let new_version: Version = version.parse()?;
+    if previous_version > new_version {
+        return Err(MigrateError::CannotMigrateVersion {
+            previous_version: stored.version,
+            new_version: version.to_string(),
+        });
+    }
+
+    set_contract_version(storage, contract_name, version)?;
+    Ok(previous_version)
+}
@@ -0,0 +1,150 @@
+use cosmwasm_std::{to_binary, Addr, Binary, Deps, DepsMut, StdResult, SubMsg, WasmMsg};
+use cw3::Vote;
+use cw_controllers::{HookError, Hooks};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// The index of the hook is added to these to find it again if it fails
+pub const PROPOSAL_HOOK_REPLY_ID_OFFSET: u64 = 2 << 32;
+pub const VOTE_HOOK_REPLY_ID_OFFSET: u64 = 3 << 32;
+
+/// ProposalHookMsg should be de/serialized under `ProposalHook()` variant in a ExecuteMsg.
+/// `S` is the proposal status type of the sending contract.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalHookMsg<S> {
+    NewProposal {
+        proposal_id: u64,
+        proposer: String,
+    },
+    /// A ballot made the proposal pass or be rejected, or the proposer
+    /// withdrew it, or it was cancelled, or its execution failed
+    ProposalStatusChanged {
+        proposal_id: u64,
+        old_status: S,
+        new_status: S,
+    },
+    ProposalExecuted {
+        proposal_id: u64,
+    },
+    ProposalClosed {
+        proposal_id: u64,
+    },
+}
+
+impl<S: Serialize> ProposalHookMsg<S> {
+    /// serializes the message
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = ProposalHookExecuteMsg::ProposalHook(self);
+        to_binary(&msg)
+    }
+}
+
+// This is just a helper to properly serialize the above message
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+enum ProposalHookExecuteMsg<S> {
+    ProposalHook(ProposalHookMsg<S>),
+}
+
+/// VoteHookMsg should be de/serialized under `VoteHook()` variant in a ExecuteMsg.
+/// `W` is the ballot weight type of the sending contract.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteHookMsg<W> {
+    NewVote {
+        proposal_id: u64,
+        voter: String,
+        vote: Vote,
+        /// The chosen option of a multiple choice proposal
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        choice: Option<u32>,
+        weight: W,
+    },
+}
+
+impl<W: Serialize> VoteHookMsg<W> {
+    /// serializes the message
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = VoteHookExecuteMsg::VoteHook(self);
+        to_binary(&msg)
+    }
+}
+
+// This is just a helper to properly serialize the above message
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+enum VoteHookExecuteMsg<W> {
+    VoteHook(VoteHookMsg<W>),
+}
+
+/// Prepares a `ProposalHookMsg` for every hook in `hooks`
+pub fn proposal_hook_msgs<S: Serialize>(
+    deps: Deps,
+    hooks: &Hooks,
+    msg: ProposalHookMsg<S>,
+) -> StdResult<Vec<SubMsg>> {
+    hook_msgs(
+        deps,
+        hooks,
+        msg.into_binary()?,
+        PROPOSAL_HOOK_REPLY_ID_OFFSET,
+    )
+}
+
+/// Prepares a `VoteHookMsg` for every hook in `hooks`
+pub fn vote_hook_msgs<W: Serialize>(
+    deps: Deps,
+    hooks: &Hooks,
+    msg: VoteHookMsg<W>,
+) -> StdResult<Vec<SubMsg>> {
+    hook_msgs(deps, hooks, msg.into_binary()?, VOTE_HOOK_REPLY_ID_OFFSET)
+}
+
+/// Prepares a submessage executing `msg` on every hook in `hooks`. A hook
+/// that fails replies with `reply_id_offset` plus its index, to be removed
+/// with `remove_failed_hook`.
+pub fn hook_msgs(
+    deps: Deps,
+    hooks: &Hooks,
+    msg: Binary,
+    reply_id_offset: u64,
+) -> StdResult<Vec<SubMsg>> {
+    // Hooks are called last to first, so that removing a failing hook
+    // doesn't change the index of the hooks whose reply is still pending
+    Ok(hooks
+        .query_hooks(deps)?
+        .hooks
+        .into_iter()
+        .enumerate()
+        .rev()
+        .map(|(index, hook)| {
+            SubMsg::reply_on_error(
+                WasmMsg::Execute {
+                    contract_addr: hook,
+                    msg: msg.clone(),
+                    funds: vec![],
+                },
+                reply_id_offset + index as u64,
+            )
+        })
+        .collect())
+}
+
+/// Removes the hook at `index` after it failed, so that a broken hook
+/// can't block the contract calling it. Returns the removed hook.
+pub fn remove_failed_hook(
+    deps: DepsMut,
+    hooks: &Hooks,
+    index: u64,
+) -> Result<Option<String>, HookError> {
+    let hook = hooks
+        .query_hooks(deps.as_ref())?
+        .hooks
+        .into_iter()
+        .nth(index as usize);
+    if let Some(hook) = &hook {
+        hooks.remove_hook(deps.storage, Addr::unchecked(hook))?;
+    }
+    Ok(hook)
+}